@@ -10,6 +10,8 @@ pub struct AuxiliaryInfo {
     pub entry: usize,
     pub base: usize,
     pub random: *const [u8; 64],
+    /// Path of the executed file, as a NUL-terminated string.
+    pub execfn: *const u8,
 }
 impl AuxiliaryInfo {
     /// Pushes all the information to a [`Vec<usize>`] stack, following the format specified in System V ABI.
@@ -34,8 +36,31 @@ impl AuxiliaryInfo {
         stack.push(AuxType::Random as usize);
         stack.push(self.random as usize);
 
-        // Push the secure flag.
+        // Push credentials and the secure flag, which is set if the program runs with credentials different from the
+        // real ones.
+        let (uid, euid) = (rtenv::security::uid(), rtenv::security::euid());
+        let (gid, egid) = (rtenv::security::gid(), rtenv::security::egid());
+        stack.push(AuxType::Uid as usize);
+        stack.push(uid as _);
+        stack.push(AuxType::EUid as usize);
+        stack.push(euid as _);
+        stack.push(AuxType::Gid as usize);
+        stack.push(gid as _);
+        stack.push(AuxType::EGid as usize);
+        stack.push(egid as _);
         stack.push(AuxType::Secure as usize);
+        stack.push((uid != euid || gid != egid) as usize);
+
+        // Push the executed file.
+        stack.push(AuxType::ExecFn as usize);
+        stack.push(self.execfn as usize);
+
+        // Push hardware capabilities.
+        stack.push(AuxType::Platform as usize);
+        stack.push(PLATFORM.as_ptr() as usize);
+        stack.push(AuxType::HwCap as usize);
+        stack.push(hwcap());
+        stack.push(AuxType::HwCap2 as usize);
         stack.push(0);
 
         // Push exec fd.
@@ -44,11 +69,11 @@ impl AuxiliaryInfo {
 
         // Push vDSO.
         stack.push(AuxType::SysinfoEhdr as usize);
-        stack.push(0);
+        stack.push(vdso());
 
         // Push clock tick.
         stack.push(AuxType::ClkTck as usize);
-        stack.push(unsafe { libc::sysconf(libc::_SC_CLK_TCK) as _ });
+        stack.push(structures::time::USER_HZ as _);
 
         // Push the terminator.
         stack.push(AuxType::Null as usize);
@@ -67,8 +92,51 @@ enum AuxType {
     PageSz = 6,
     Base = 7,
     Entry = 9,
+    Uid = 11,
+    EUid = 12,
+    Gid = 13,
+    EGid = 14,
+    Platform = 15,
+    HwCap = 16,
     ClkTck = 17,
     Secure = 23,
     Random = 25,
+    HwCap2 = 26,
+    ExecFn = 31,
     SysinfoEhdr = 33,
 }
+
+/// Name of the platform, as is pointed to by `AT_PLATFORM`.
+const PLATFORM: &std::ffi::CStr = if cfg!(target_arch = "x86_64") {
+    c"x86_64"
+} else if cfg!(target_arch = "aarch64") {
+    c"aarch64"
+} else {
+    c"unknown"
+};
+
+/// Returns address of the vDSO, or `0` if there is none.
+fn vdso() -> usize {
+    #[cfg(target_arch = "x86_64")]
+    {
+        rtenv::vdso::address()
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        0
+    }
+}
+
+/// Returns hardware capabilities of the CPU, as are reported by `AT_HWCAP`.
+///
+/// On x86_64, this is the `EDX` register of CPUID leaf 1, like on Linux.
+fn hwcap() -> usize {
+    #[cfg(target_arch = "x86_64")]
+    {
+        unsafe { core::arch::x86_64::__cpuid(1).edx as usize }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        0
+    }
+}
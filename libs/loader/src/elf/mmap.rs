@@ -1,10 +1,9 @@
 //! Memory mapping for the ELF loader.
 
 use rtenv::rust::RawRtFd;
-use std::{io::Write, os::fd::IntoRawFd};
+use std::{fs::File, io::Write};
 use structures::{
     error::LxError,
-    io::Whence,
     mm::{MmapFlags, MmapProt},
 };
 
@@ -16,15 +15,6 @@ pub struct MappedArea {
     auto_unmap: bool,
 }
 impl MappedArea {
-    /// Creates a "null" mapped area that works as a placeholder.
-    pub fn null() -> Self {
-        Self {
-            addr: std::ptr::null_mut(),
-            len: 0,
-            auto_unmap: false,
-        }
-    }
-
     /// Returns a builder.
     pub fn builder() -> MappedAreaBuilder {
         MappedAreaBuilder::new()
@@ -77,6 +67,13 @@ impl MappedAreaBuilder {
         self
     }
 
+    /// Specifies preferred address of the mapped area, which is used unless it overlaps an existing mapping.
+    pub fn hint(mut self, addr: usize) -> Self {
+        self.addr = addr;
+        self.flags &= !MmapFlags::MAP_FIXED;
+        self
+    }
+
     /// Specifies length of the mapped area.
     pub fn len(mut self, len: usize) -> Self {
         self.len = len;
@@ -116,19 +113,17 @@ impl MappedAreaBuilder {
     }
 
     /// Performs the mapping.
+    ///
+    /// Virtual file descriptors cannot be mapped, so a file specified with [`Self::file`] must be a native one, like
+    /// the ones returned by [`native_copy`].
     pub unsafe fn build(self) -> Result<MappedArea, LxError> {
-        let mut fd = self.fd.0;
-        if self.fd.is_virtual() {
-            fd = copy_vfd(fd)?;
-        }
-
         let addr = unsafe {
             rtenv::mm::map(
                 self.addr as _,
                 self.len,
                 self.prot,
                 self.flags,
-                fd,
+                self.fd.0,
                 self.offset as _,
             )?
         };
@@ -141,22 +136,26 @@ impl MappedAreaBuilder {
     }
 }
 
-fn copy_vfd(fd: i32) -> Result<i32, LxError> {
-    let mut tempfile = tempfile::Builder::new().disable_cleanup(true).tempfile()?;
-    let mut buf = vec![0u8; 4096];
-    let fd_pos = rtenv::io::lseek(fd, 0, Whence::SEEK_CUR)?;
-    rtenv::io::lseek(fd, 0, Whence::SEEK_DATA)?;
+/// Copies an image on a virtual file descriptor, like one on tmpfs, into an unlinked temporary file, so that its
+/// segments can be mapped from the copy. Native file descriptors can be mapped directly, so `None` is returned for them.
+pub fn native_copy(fd: RawRtFd) -> Result<Option<File>, LxError> {
+    if !fd.is_virtual() {
+        return Ok(None);
+    }
+
+    let mut tempfile = tempfile::tempfile()?;
+    let mut buf = vec![0u8; COPY_CHUNK_SIZE];
+    let mut off = 0;
     loop {
-        let n = rtenv::io::read(fd, &mut buf)?;
+        let n = rtenv::io::pread64(fd.0, &mut buf, off)?;
         if n == 0 {
             break;
         }
         tempfile.write_all(&buf[..n])?;
+        off += n as i64;
     }
-    let Ok(readable) = std::fs::File::open(tempfile.path()) else {
-        eprintln!("mactux: failed to reopen temporary file for reading");
-        std::process::exit(101);
-    };
-    rtenv::io::lseek(fd, fd_pos, Whence::SEEK_DATA)?;
-    Ok(readable.into_raw_fd())
+    Ok(Some(tempfile))
 }
+
+/// Size of chunks that images on virtual file descriptors are copied in.
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
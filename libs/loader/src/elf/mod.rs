@@ -2,6 +2,7 @@
 
 mod auxv;
 mod mmap;
+mod note;
 mod stack;
 
 use crate::Error;
@@ -9,18 +10,19 @@ use auxv::AuxiliaryInfo;
 use mmap::*;
 use object::{
     LittleEndian, ReadCache,
-    elf::{PT_INTERP, PT_LOAD, ProgramHeader64},
+    elf::{PF_X, PT_GNU_STACK, PT_INTERP, PT_LOAD, ProgramHeader64},
     read::elf::{ElfFile64, FileHeader, ProgramHeader},
 };
-use rand::Rng;
 use rtenv::rust::{OwnedRtFd, RawRtFd};
-use structures::error::LxError;
+use std::os::fd::AsRawFd;
+use structures::{error::LxError, mm::MmapProt};
 
 type ExecutableObject<'a> = ElfFile64<'a, LittleEndian, &'a ReadCache<RawRtFd>>;
 
 /// A loaded Linux program.
 #[derive(Debug)]
 pub struct Program {
+    path: Vec<u8>,
     exec_fd: RawRtFd,
 
     interpreter: Option<Box<Self>>,
@@ -28,53 +30,71 @@ pub struct Program {
     phent: usize,
     phnum: usize,
     entry: *const u8,
+    /// Whether the image asks for an executable stack with its `PT_GNU_STACK` segment.
+    exec_stack: bool,
 
-    base_map: MappedArea,
-    _mapped_areas: Vec<MappedArea>,
+    /// Difference between addresses in memory and virtual addresses in the image, which is zero for `ET_EXEC` images.
+    bias: usize,
+    _base_map: MappedArea,
 }
 impl Program {
-    pub const MAGIC: &[u8] = &[0x7f, 0x45, 0x4c, 0x46];
+    pub const MAGIC: &[u8] = structures::exec::ELF_MAGIC;
 
     /// Loads a Linux program from the given file descriptor.
     pub fn load(path: Vec<u8>) -> Result<Self, Error> {
-        let exec_fd = OwnedRtFd::open(path).map_err(Error::ReadImage)?.leak();
+        Self::load_image(path, false)
+    }
+
+    /// Loads a Linux program or, if `is_interp` is `true`, its interpreter.
+    fn load_image(path: Vec<u8>, is_interp: bool) -> Result<Self, Error> {
+        let exec_fd = OwnedRtFd::open(path.clone())
+            .map_err(Error::ReadImage)?
+            .leak();
         let read_cache = ReadCache::new(exec_fd);
         let main =
             ExecutableObject::parse(&read_cache).map_err(|x| Error::ImageFormat(x.to_string()))?;
+        note::check(&main, &read_cache)?;
         let mut interpreter = None;
-        let base_map = map_base(&main)?;
-        let entry = unsafe {
-            base_map
-                .addr()
-                .add(main.elf_header().e_entry(LittleEndian) as usize)
-        };
+        let (base_map, bias) = map_base(&main, is_interp)?;
+        // Mappings stay valid after the copy is closed, so it is dropped at the end of loading.
+        let native_copy = native_copy(exec_fd).map_err(Error::LoadImage)?;
+        let map_fd = native_copy
+            .as_ref()
+            .map_or(exec_fd, |x| RawRtFd(x.as_raw_fd()));
+        let entry = bias.wrapping_add(main.elf_header().e_entry(LittleEndian) as usize) as _;
 
-        let mut _mapped_areas = Vec::new();
         for phdr in main.elf_program_headers().iter() {
             match phdr.p_type(LittleEndian) {
-                PT_INTERP => {
-                    interpreter = Some(Box::new(Self::load(read_interp(phdr, &read_cache)?)?));
+                PT_INTERP if is_interp => {
+                    return Err(Error::ImageFormat(String::from(
+                        "interpreter requests an interpreter itself",
+                    )));
                 }
-                PT_LOAD => {
-                    let mapped_area =
-                        map_phdr(phdr, exec_fd, base_map.addr()).map_err(Error::LoadImage)?;
-                    if base_map.addr().is_null() {
-                        _mapped_areas.push(mapped_area);
-                    }
+                PT_INTERP => {
+                    let interp_path = read_interp(phdr, &read_cache)?;
+                    interpreter = Some(Box::new(Self::load_image(interp_path, true)?));
                 }
+                PT_LOAD => map_phdr(phdr, map_fd, bias as _).map_err(Error::LoadImage)?,
                 _ => continue,
             }
         }
 
-        let phdr = unsafe {
-            base_map
-                .addr()
-                .add(main.elf_header().e_phoff(LittleEndian) as usize)
-        };
+        if interpreter.is_none() && !is_interp && !relocates_itself(main.elf_program_headers()) {
+            protect_relro(&main, bias).map_err(Error::LoadImage)?;
+        }
+
+        let exec_stack = main
+            .elf_program_headers()
+            .iter()
+            .find(|x| x.p_type(LittleEndian) == PT_GNU_STACK)
+            .is_some_and(|x| x.p_flags(LittleEndian) & PF_X != 0);
+
+        let phdr = bias.wrapping_add(phdr_vaddr(&main)? as usize) as _;
         let phent = main.elf_header().e_phentsize(LittleEndian) as _;
         let phnum = main.elf_header().e_phnum(LittleEndian) as _;
 
         Ok(Program {
+            path,
             exec_fd,
 
             interpreter,
@@ -82,24 +102,30 @@ impl Program {
             phent,
             phnum,
             entry,
+            exec_stack,
 
-            base_map,
-            _mapped_areas,
+            bias,
+            _base_map: base_map,
         })
     }
 
     /// Runs the program.
     pub unsafe fn run(&self, args: &[&[u8]], envs: &[&[u8]]) -> ! {
         let base = match &self.interpreter {
-            Some(interp) => interp.base_map.addr() as usize,
+            Some(interp) => interp.bias,
             None => 0,
         };
         let entry = match &self.interpreter {
             Some(interp) => interp.entry,
             None => self.entry,
         };
+        // `/proc/self/exe` refers to the main program, not to the interpreter.
+        _ = rtenv::process::set_exe(&self.path);
+
         let mut random = Box::new([0u8; 64]);
-        rand::rng().fill_bytes(&mut *random);
+        rtenv::random::fill(&mut *random);
+        let mut execfn = self.path.clone();
+        execfn.push(0);
         let auxv = AuxiliaryInfo {
             exec_fd: self.exec_fd.0 as _,
             phdr_base: self.phdr as usize,
@@ -108,29 +134,139 @@ impl Program {
             entry: self.entry as usize,
             base,
             random: Box::into_raw(random),
+            execfn: Box::into_raw(execfn.into_boxed_slice()).cast(),
         };
-        stack::jump(entry, args, envs, auxv);
+        stack::jump(entry, args, envs, auxv, self.exec_stack);
     }
 }
 
-fn map_base(main: &ExecutableObject) -> Result<MappedArea, Error> {
-    if main.elf_header().e_type.get(LittleEndian) == object::elf::ET_DYN {
-        let top_phdr = main
-            .elf_program_headers()
+/// Lowest address at which position-independent executables are loaded, like `ELF_ET_DYN_BASE` on Linux.
+const ET_DYN_BASE: usize = 0x5555_5555_4000;
+
+/// Highest address at which position-independent interpreters are loaded, like the top of the `mmap` area on Linux.
+const INTERP_TOP: usize = 0x7f00_0000_0000;
+
+/// Number of bits of randomness in load addresses of position-independent images, in pages.
+const RANDOM_BITS: u32 = 28;
+
+/// Reserves address space for an image, returning the reservation and the load bias.
+///
+/// Position-independent images are placed at a random address, which stays the same across runs in deterministic mode
+/// since the random source is seeded then. Other images are mapped at their own addresses with a bias of zero, and fail
+/// to load if any part of their address range is already in use, instead of replacing existing mappings.
+fn map_base(main: &ExecutableObject, is_interp: bool) -> Result<(MappedArea, usize), Error> {
+    let loads = || {
+        main.elf_program_headers()
             .iter()
             .filter(|x| x.p_type(LittleEndian) == PT_LOAD)
-            .max_by_key(|x| x.p_vaddr(LittleEndian))
-            .ok_or_else(|| Error::ImageFormat(String::from("image has no PT_LOAD segment")))?;
-        let max_addr = top_phdr.p_vaddr(LittleEndian) + top_phdr.p_memsz(LittleEndian);
-        unsafe {
+    };
+    let min_vaddr = loads()
+        .map(|x| x.p_vaddr(LittleEndian) as usize)
+        .min()
+        .ok_or_else(|| Error::ImageFormat(String::from("image has no PT_LOAD segment")))?
+        / page_size()
+        * page_size();
+    let max_addr = loads()
+        .map(|x| (x.p_vaddr(LittleEndian) + x.p_memsz(LittleEndian)) as usize)
+        .max()
+        .unwrap_or_default()
+        .next_multiple_of(page_size());
+    let align = loads()
+        .map(|x| x.p_align(LittleEndian) as usize)
+        .filter(|x| x.is_power_of_two())
+        .max()
+        .unwrap_or_default()
+        .max(page_size());
+    let len = max_addr - min_vaddr;
+
+    if main.elf_header().e_type.get(LittleEndian) != object::elf::ET_DYN {
+        let reservation = unsafe {
             MappedArea::builder()
-                .len(max_addr as _)
+                .hint(min_vaddr)
+                .len(len)
                 .build()
-                .map_err(Error::LoadImage)
+                .map_err(Error::LoadImage)?
+        };
+        if reservation.addr() as usize != min_vaddr {
+            return Err(Error::AddressInUse(min_vaddr, max_addr));
         }
-    } else {
-        Ok(MappedArea::null())
+        return Ok((reservation, 0));
+    }
+
+    let mut random = [0u8; size_of::<usize>()];
+    rtenv::random::fill(&mut random);
+    let offset = (usize::from_ne_bytes(random) & ((1 << RANDOM_BITS) - 1)) * page_size();
+    let hint = match is_interp {
+        false => ET_DYN_BASE + offset,
+        true => INTERP_TOP - offset - len,
+    };
+
+    // Reserve more than needed, so that the image can be aligned as is requested by its segments.
+    let reservation = unsafe {
+        MappedArea::builder()
+            .hint(hint.next_multiple_of(align))
+            .len(len + align - page_size())
+            .build()
+            .map_err(Error::LoadImage)?
+    };
+    let start = (reservation.addr() as usize).next_multiple_of(align);
+    Ok((reservation, start - min_vaddr))
+}
+
+/// Returns the virtual address of the program headers of an image, which is used for `AT_PHDR`.
+fn phdr_vaddr(main: &ExecutableObject) -> Result<u64, Error> {
+    let phdrs = main.elf_program_headers();
+    if let Some(phdr) = phdrs
+        .iter()
+        .find(|x| x.p_type(LittleEndian) == object::elf::PT_PHDR)
+    {
+        return Ok(phdr.p_vaddr(LittleEndian));
+    }
+
+    // Without `PT_PHDR`, the program headers are found in the segment that maps them from the file.
+    let phoff = main.elf_header().e_phoff(LittleEndian);
+    phdrs
+        .iter()
+        .filter(|x| x.p_type(LittleEndian) == PT_LOAD)
+        .find(|x| {
+            (x.p_offset(LittleEndian)..x.p_offset(LittleEndian) + x.p_filesz(LittleEndian))
+                .contains(&phoff)
+        })
+        .map(|x| x.p_vaddr(LittleEndian) + phoff - x.p_offset(LittleEndian))
+        .ok_or_else(|| Error::ImageFormat(String::from("program headers are not mapped")))
+}
+
+/// Returns `true` if the image, given its program headers, is relocated at startup by a dynamic linker, either the one
+/// it asks for in `PT_INTERP` or its own, like static PIEs with `PT_DYNAMIC` do.
+///
+/// Such images protect their `PT_GNU_RELRO` region once relocation is done, so the loader must leave it writable.
+/// Static binaries that only apply `IRELATIVE` relocations have neither, and get their region protected by the loader.
+fn relocates_itself(phdrs: &[ProgramHeader64<LittleEndian>]) -> bool {
+    phdrs
+        .iter()
+        .any(|x| matches!(x.p_type(LittleEndian), object::elf::PT_DYNAMIC | PT_INTERP))
+}
+
+/// Makes the `PT_GNU_RELRO` region of a loaded image read-only.
+///
+/// This is normally done by the dynamic linker after relocation, so the loader only does it for images that are run
+/// without a dynamic linker and do not relocate themselves. Like in the dynamic linker, the end of the region is
+/// rounded down, since the last page may be shared with writable data.
+fn protect_relro(main: &ExecutableObject, bias: usize) -> Result<(), LxError> {
+    let Some(relro) = main
+        .elf_program_headers()
+        .iter()
+        .find(|x| x.p_type(LittleEndian) == object::elf::PT_GNU_RELRO)
+    else {
+        return Ok(());
+    };
+    let vaddr = bias + relro.p_vaddr(LittleEndian) as usize;
+    let start = vaddr / page_size() * page_size();
+    let end = (vaddr + relro.p_memsz(LittleEndian) as usize) / page_size() * page_size();
+    if end <= start {
+        return Ok(());
     }
+    unsafe { rtenv::mm::protect(start as _, end - start, MmapProt::PROT_READ) }
 }
 
 /// Reads `PT_INTERP` from a program header.
@@ -145,12 +281,12 @@ fn read_interp(
     Ok(path.into())
 }
 
-/// Maps a `PT_LOAD` program header to process memory.
+/// Maps a `PT_LOAD` program header to process memory, inside the reservation made by [`map_base`].
 fn map_phdr(
     phdr: &ProgramHeader64<LittleEndian>,
     fd: RawRtFd,
     mem_base: *mut u8,
-) -> Result<MappedArea, LxError> {
+) -> Result<(), LxError> {
     let p_filesz = phdr.p_filesz(LittleEndian);
     let p_memsz = phdr.p_memsz(LittleEndian) as usize;
     let p_vaddr = phdr.p_vaddr(LittleEndian) as usize;
@@ -161,12 +297,10 @@ fn map_phdr(
 
     let mut builder = MappedArea::builder()
         .destination(segment_base)
-        .len(p_memsz + fill_align as usize);
-    if !mem_base.is_null() {
-        builder = builder.auto_unmap(false);
-    }
+        .len(p_memsz + fill_align as usize)
+        .auto_unmap(false);
     elf_mmap_perms(&mut builder, phdr);
-    let mapped = unsafe { builder.build()? };
+    unsafe { builder.build()? };
 
     let mut builder = MappedArea::builder()
         .file(fd, p_offset - fill_align)
@@ -184,7 +318,7 @@ fn map_phdr(
         }
     }
 
-    Ok(mapped)
+    Ok(())
 }
 
 fn elf_mmap_perms(builder: &mut MappedAreaBuilder, phdr: &ProgramHeader64<LittleEndian>) {
@@ -196,10 +330,6 @@ fn elf_mmap_perms(builder: &mut MappedAreaBuilder, phdr: &ProgramHeader64<Little
     }
     if phdr.p_flags(LittleEndian) & object::elf::PF_X != 0 {
         *builder = builder.executable();
-
-        // This is a hack used to support `fs` accesses on macOS. We rewrites `fs` accesses to `gs` ones,
-        // so executable pages must be also writable.
-        *builder = builder.writable();
     }
 }
 
@@ -212,3 +342,40 @@ const fn page_size() -> usize {
         0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::{U32, elf::PT_GNU_RELRO};
+
+    fn phdrs(types: &[u32]) -> Vec<ProgramHeader64<LittleEndian>> {
+        types
+            .iter()
+            .map(|&p_type| ProgramHeader64 {
+                p_type: U32::new(LittleEndian, p_type),
+                ..unsafe { std::mem::zeroed() }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dynamic_images_relocate_themselves() {
+        assert!(relocates_itself(&phdrs(&[
+            PT_LOAD,
+            object::elf::PT_DYNAMIC,
+            PT_GNU_RELRO
+        ])));
+        assert!(relocates_itself(&phdrs(&[PT_INTERP, PT_LOAD])));
+    }
+
+    #[test]
+    fn static_images_do_not_relocate_themselves() {
+        assert!(!relocates_itself(&phdrs(&[
+            PT_LOAD,
+            PT_LOAD,
+            PT_GNU_RELRO,
+            PT_GNU_STACK
+        ])));
+        assert!(!relocates_itself(&[]));
+    }
+}
@@ -0,0 +1,192 @@
+//! Checks of ELF notes that describe requirements of an image.
+
+use super::ExecutableObject;
+use crate::Error;
+use object::{
+    LittleEndian, ReadCache,
+    elf::{
+        ELF_NOTE_GNU, ELF_NOTE_OS_LINUX, GNU_PROPERTY_X86_ISA_1_BASELINE,
+        GNU_PROPERTY_X86_ISA_1_NEEDED, GNU_PROPERTY_X86_ISA_1_V2, GNU_PROPERTY_X86_ISA_1_V3,
+        GNU_PROPERTY_X86_ISA_1_V4, NT_GNU_ABI_TAG,
+    },
+    read::elf::ProgramHeader,
+};
+use rtenv::rust::RawRtFd;
+
+/// Checks that requirements declared in `PT_NOTE` segments of an image are met.
+///
+/// This rejects images built for another operating system or for a newer kernel than the one reported by `uname`
+/// (`.note.ABI-tag`), and images needing instruction set extensions the processor lacks (`.note.gnu.property`). Other
+/// properties, like the IBT and SHSTK markers, only enable features when available, so they are ignored like on
+/// kernels without CET support.
+pub fn check(main: &ExecutableObject, read_cache: &ReadCache<RawRtFd>) -> Result<(), Error> {
+    for phdr in main.elf_program_headers() {
+        let Some(mut notes) = phdr
+            .notes(LittleEndian, read_cache)
+            .map_err(|x| Error::ImageFormat(x.to_string()))?
+        else {
+            continue;
+        };
+        while let Some(note) = notes
+            .next()
+            .map_err(|x| Error::ImageFormat(x.to_string()))?
+        {
+            if note.name() != ELF_NOTE_GNU {
+                continue;
+            }
+            if note.n_type(LittleEndian) == NT_GNU_ABI_TAG {
+                check_abi_tag(note.desc())?;
+            }
+            let Some(mut properties) = note.gnu_properties(LittleEndian) else {
+                continue;
+            };
+            while let Some(property) = properties
+                .next()
+                .map_err(|x| Error::ImageFormat(x.to_string()))?
+            {
+                if property.pr_type() == GNU_PROPERTY_X86_ISA_1_NEEDED {
+                    let needed = property
+                        .data_u32(LittleEndian)
+                        .map_err(|x| Error::ImageFormat(x.to_string()))?;
+                    check_isa_level(needed)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the descriptor of a `NT_GNU_ABI_TAG` note, which holds the operating system and the minimum kernel version.
+fn check_abi_tag(desc: &[u8]) -> Result<(), Error> {
+    let word = |n: usize| {
+        desc.get(n * 4..n * 4 + 4)
+            .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .ok_or_else(|| Error::ImageFormat(String::from("truncated ABI tag note")))
+    };
+    let os = word(0)?;
+    if os != ELF_NOTE_OS_LINUX {
+        let name = match os {
+            1 => "GNU/Hurd",
+            2 => "Solaris",
+            3 => "FreeBSD",
+            _ => "an unknown operating system",
+        };
+        return Err(Error::ImageFormat(format!("image is built for {name}")));
+    }
+
+    let required = (word(1)?, word(2)?, word(3)?);
+    let uname = rtenv::misc::uname().map_err(Error::LoadImage)?;
+    let current = kernel_version(&uname.release);
+    if required > current {
+        return Err(Error::ImageFormat(format!(
+            "image requires Linux {}.{}.{} or newer, but {}.{}.{} is emulated",
+            required.0, required.1, required.2, current.0, current.1, current.2
+        )));
+    }
+    Ok(())
+}
+
+/// Parses the leading `major.minor.patch` part of a kernel release string, like `6.8.0-11-generic`.
+fn kernel_version(release: &[u8]) -> (u32, u32, u32) {
+    let mut parts = release.split(|x| !x.is_ascii_digit()).map(|x| {
+        std::str::from_utf8(x)
+            .ok()
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(0)
+    });
+    (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    )
+}
+
+/// Checks that the processor supports the x86-64 microarchitecture levels in a `GNU_PROPERTY_X86_ISA_1_NEEDED`
+/// property.
+fn check_isa_level(needed: u32) -> Result<(), Error> {
+    const LEVELS: &[(u32, &str)] = &[
+        (GNU_PROPERTY_X86_ISA_1_BASELINE, "x86-64"),
+        (GNU_PROPERTY_X86_ISA_1_V2, "x86-64-v2"),
+        (GNU_PROPERTY_X86_ISA_1_V3, "x86-64-v3"),
+        (GNU_PROPERTY_X86_ISA_1_V4, "x86-64-v4"),
+    ];
+    let supported = supported_isa_levels();
+    match LEVELS
+        .iter()
+        .find(|(level, _)| needed & level != 0 && supported & level == 0)
+    {
+        Some((_, name)) => Err(Error::ImageFormat(format!(
+            "image requires {name} instructions, which are not supported by this processor"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Returns the x86-64 microarchitecture levels supported by the processor, as `GNU_PROPERTY_X86_ISA_1_*` bits.
+#[cfg(target_arch = "x86_64")]
+fn supported_isa_levels() -> u32 {
+    use std::arch::is_x86_feature_detected as has;
+
+    let mut levels = GNU_PROPERTY_X86_ISA_1_BASELINE;
+    let v2 = has!("cmpxchg16b")
+        && has!("popcnt")
+        && has!("sse3")
+        && has!("ssse3")
+        && has!("sse4.1")
+        && has!("sse4.2");
+    if !v2 {
+        return levels;
+    }
+    levels |= GNU_PROPERTY_X86_ISA_1_V2;
+    let v3 = has!("avx")
+        && has!("avx2")
+        && has!("bmi1")
+        && has!("bmi2")
+        && has!("f16c")
+        && has!("fma")
+        && has!("lzcnt")
+        && has!("movbe")
+        && has!("xsave");
+    if !v3 {
+        return levels;
+    }
+    levels |= GNU_PROPERTY_X86_ISA_1_V3;
+    let v4 = has!("avx512f")
+        && has!("avx512bw")
+        && has!("avx512cd")
+        && has!("avx512dq")
+        && has!("avx512vl");
+    if v4 {
+        levels |= GNU_PROPERTY_X86_ISA_1_V4;
+    }
+    levels
+}
+
+/// Returns the x86-64 microarchitecture levels supported by the processor, as `GNU_PROPERTY_X86_ISA_1_*` bits.
+///
+/// Instructions are translated on other architectures, so no level is assumed to be missing.
+#[cfg(not(target_arch = "x86_64"))]
+fn supported_isa_levels() -> u32 {
+    u32::MAX
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_version_is_parsed() {
+        assert_eq!(kernel_version(b"6.8.0-11-generic"), (6, 8, 0));
+        assert_eq!(kernel_version(b"6.1.55.1"), (6, 1, 55));
+        assert_eq!(kernel_version(b"4.19.0+"), (4, 19, 0));
+        assert_eq!(kernel_version(b"5.15"), (5, 15, 0));
+        assert_eq!(kernel_version(b"3"), (3, 0, 0));
+        assert_eq!(kernel_version(b""), (0, 0, 0));
+    }
+
+    #[test]
+    fn kernel_versions_compare_numerically() {
+        assert!(kernel_version(b"6.10.0") > kernel_version(b"6.9.12"));
+        assert!(kernel_version(b"3.2.0") >= (3, 2, 0));
+    }
+}
@@ -2,12 +2,30 @@
 
 use super::auxv::AuxiliaryInfo;
 use std::alloc::Layout;
+use structures::error::LxError;
 
 /// Jumps to a program entry with given information about the initial stack.
+///
+/// If `exec_stack` is `true`, the stack is made executable first.
 #[cfg(target_arch = "x86_64")]
-pub fn jump(entry: *const u8, args: &[&[u8]], envs: &[&[u8]], auxv: AuxiliaryInfo) -> ! {
+pub fn jump(
+    entry: *const u8,
+    args: &[&[u8]],
+    envs: &[&[u8]],
+    auxv: AuxiliaryInfo,
+    exec_stack: bool,
+) -> ! {
     unsafe {
-        let stack_info = StackInfo::new(args, envs, auxv);
+        if exec_stack {
+            rtenv::mm::make_stack_executable().unwrap_or_else(|err| {
+                eprintln!("mactux: failed to make stack executable: {err}");
+                std::process::exit(1);
+            });
+        }
+        let stack_info = StackInfo::new(args, envs, auxv).unwrap_or_else(|err| {
+            eprintln!("mactux: failed to set up initial stack: {err}");
+            std::process::exit(1);
+        });
         let stkinfo_ptr = stack_info.0.as_ptr();
         let stkinfo_len = stack_info.0.len() * size_of::<usize>();
         rtenv::emuctx::enter_emulated();
@@ -33,7 +51,11 @@ pub fn jump(entry: *const u8, args: &[&[u8]], envs: &[&[u8]], auxv: AuxiliaryInf
 pub struct StackInfo(Vec<usize>);
 impl StackInfo {
     /// Builds a [`StackInfo`] instance with given information.
-    pub fn new(args: &[&[u8]], envs: &[&[u8]], auxv: AuxiliaryInfo) -> Self {
+    ///
+    /// Fails with `E2BIG` if `args` and `envs` exceed the limits of `execve`.
+    pub fn new(args: &[&[u8]], envs: &[&[u8]], auxv: AuxiliaryInfo) -> Result<Self, LxError> {
+        rtenv::process::check_exec_args(args, envs)?;
+
         fn allocate_string(s: &[u8]) -> usize {
             let len = s.len() + 1;
             let ptr = unsafe { std::alloc::alloc(Layout::array::<u8>(len).unwrap()) };
@@ -60,7 +82,7 @@ impl StackInfo {
             vec.push(0); // align to even number of elements
         }
 
-        Self(vec)
+        Ok(Self(vec))
     }
 }
 impl Drop for StackInfo {
@@ -1,12 +1,10 @@
 //! MacTux program loader.
 
-#![feature(slice_split_once)]
-
 mod elf;
 mod shebang;
 
 use rtenv::rust::OwnedRtFd;
-use structures::error::LxError;
+use structures::{error::LxError, exec};
 
 #[derive(Debug)]
 pub enum Program {
@@ -15,22 +13,36 @@ pub enum Program {
 }
 impl Program {
     pub fn load(path: Vec<u8>) -> Result<Self, Error> {
-        let mut buf = [0; 8];
+        Self::load_at_depth(path, 0)
+    }
+
+    /// Loads an image that is run as the interpreter of `depth` nested scripts.
+    fn load_at_depth(path: Vec<u8>, depth: usize) -> Result<Self, Error> {
+        if depth > shebang::MAX_DEPTH {
+            return Err(Error::ReadImage(LxError::ELOOP));
+        }
+
+        let mut buf = [0; exec::ELF64_HEADER_SIZE];
         let fd = OwnedRtFd::open(path.clone()).map_err(Error::ReadImage)?;
-        rtenv::io::read(fd.0, &mut buf).map_err(Error::ReadImage)?;
+        let len = rtenv::io::read(fd.0, &mut buf).map_err(Error::ReadImage)?;
+        let buf = &buf[..len];
         drop(fd);
 
         if buf.starts_with(elf::Program::MAGIC) {
+            exec::check_elf_header(buf).map_err(|err| Error::ImageFormat(err.to_string()))?;
             return Ok(Self::Elf(elf::Program::load(path)?));
         }
 
         if buf.starts_with(shebang::Program::MAGIC) {
-            return Ok(Self::Shebang(shebang::Program::load(path)?));
+            return Ok(Self::Shebang(shebang::Program::load(path, depth + 1)?));
         }
 
-        Err(Error::ImageFormat(String::from(
-            "unrecognized image format",
-        )))
+        match foreign_format(buf) {
+            Some(name) => Err(Error::ImageFormat(format!("{name} is not supported"))),
+            None => Err(Error::ImageFormat(String::from(
+                "unrecognized image format",
+            ))),
+        }
     }
 
     pub unsafe fn run(&self, args: &[&[u8]], envs: &[&[u8]]) -> ! {
@@ -48,6 +60,18 @@ pub enum Error {
     ReadImage(LxError),
     ImageFormat(String),
     LoadImage(LxError),
+    /// The address range of an image that is not position-independent overlaps existing mappings.
+    AddressInUse(usize, usize),
+}
+impl Error {
+    /// Returns the error number that `execve` would fail with on Linux.
+    pub fn errno(&self) -> LxError {
+        match self {
+            Self::ReadImage(e) | Self::LoadImage(e) => *e,
+            Self::ImageFormat(_) => LxError::ENOEXEC,
+            Self::AddressInUse(..) => LxError::EEXIST,
+        }
+    }
 }
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -55,7 +79,69 @@ impl std::fmt::Display for Error {
             Self::ReadImage(e) => write!(f, "failed to read image: {e}"),
             Self::ImageFormat(e) => write!(f, "exec format error: {e}"),
             Self::LoadImage(e) => write!(f, "failed to map image segments: {e}"),
+            Self::AddressInUse(start, end) => write!(
+                f,
+                "image needs addresses {start:#x}-{end:#x}, which are already in use"
+            ),
         }
     }
 }
 impl std::error::Error for Error {}
+
+/// Recognizes image formats that are known, but not supported by the loader, from the first bytes of a file.
+fn foreign_format(buf: &[u8]) -> Option<&'static str> {
+    const FORMATS: &[(&[u8], &str)] = &[
+        (&[0xcf, 0xfa, 0xed, 0xfe], "64-bit Mach-O image"),
+        (&[0xce, 0xfa, 0xed, 0xfe], "32-bit Mach-O image"),
+        (&[0xfe, 0xed, 0xfa, 0xcf], "64-bit big-endian Mach-O image"),
+        (&[0xfe, 0xed, 0xfa, 0xce], "32-bit big-endian Mach-O image"),
+        (
+            &[0xca, 0xfe, 0xba, 0xbe],
+            "Mach-O universal binary or Java class file",
+        ),
+        (b"MZ", "PE/COFF (Windows) image"),
+        (&[0x07, 0x01], "a.out (OMAGIC) image"),
+        (&[0x08, 0x01], "a.out (NMAGIC) image"),
+        (&[0x0b, 0x01], "a.out (ZMAGIC) image"),
+        (&[0xcc, 0x00], "a.out (QMAGIC) image"),
+        (b"\xef\xbb\xbf#!", "script with a byte order mark"),
+        (b"\xff\xfe#\0!\0", "UTF-16 script"),
+        (b"\xfe\xff\0#\0!", "UTF-16 script"),
+    ];
+    FORMATS
+        .iter()
+        .find(|(magic, _)| buf.starts_with(magic))
+        .map(|(_, name)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn foreign_formats_are_named() {
+        assert_eq!(
+            foreign_format(&[0xcf, 0xfa, 0xed, 0xfe, 7, 0, 0, 1]),
+            Some("64-bit Mach-O image")
+        );
+        assert_eq!(
+            foreign_format(&[0xca, 0xfe, 0xba, 0xbe]),
+            Some("Mach-O universal binary or Java class file")
+        );
+        assert_eq!(foreign_format(b"MZ\x90\0"), Some("PE/COFF (Windows) image"));
+        assert_eq!(
+            foreign_format(b"\xef\xbb\xbf#!/bin/sh\n"),
+            Some("script with a byte order mark")
+        );
+        assert_eq!(foreign_format(b"\xff\xfe#\0!\0/\0"), Some("UTF-16 script"));
+    }
+
+    #[test]
+    fn unknown_formats_are_not_named() {
+        assert_eq!(foreign_format(b""), None);
+        assert_eq!(foreign_format(b"M"), None);
+        assert_eq!(foreign_format(b"echo hello\n"), None);
+        assert_eq!(foreign_format(exec::ELF_MAGIC), None);
+        assert_eq!(foreign_format(b"#!/bin/sh\n"), None);
+    }
+}
@@ -2,40 +2,44 @@
 
 use crate::Error;
 use rtenv::rust::OwnedRtFd;
-use std::io::{BufRead, BufReader};
-use structures::error::LxError;
+use std::io::Read;
+use structures::{exec, process::BINPRM_BUF_SIZE};
+
+/// Maximum number of interpreters that may be nested, like the recursion limit of `execve` on Linux.
+pub const MAX_DEPTH: usize = 5;
 
 #[derive(Debug)]
 pub struct Program {
     prog: Vec<u8>,
     arg: Option<Vec<u8>>,
     path: Vec<u8>,
+    interp: Box<crate::Program>,
 }
 impl Program {
-    pub const MAGIC: &[u8] = b"#!";
+    pub const MAGIC: &[u8] = exec::SHEBANG_MAGIC;
 
-    pub fn load(path: Vec<u8>) -> Result<Self, Error> {
-        let fd = OwnedRtFd::open(path.clone()).map_err(Error::ReadImage)?;
-        let mut buf_read = BufReader::new(fd);
-        let mut first_line = Vec::with_capacity(32);
-        buf_read
-            .read_until(b'\n', &mut first_line)
-            .map_err(|x| Error::ReadImage(x.into()))?;
-        if first_line.len() <= 3 {
-            return Err(Error::ImageFormat(String::from("invalid shebang line")));
-        }
-        let interp = first_line[2..].trim_ascii();
-        let (prog, arg) = match interp.split_once(|x| *x == b' ') {
-            Some((a, b)) => (a, Some(b)),
-            None => (interp, None),
-        };
-        if !prog.starts_with(b"/") {
-            return Err(Error::ReadImage(LxError::ENOENT));
+    /// Loads a script, and its interpreter at the given nesting depth.
+    pub fn load(path: Vec<u8>, depth: usize) -> Result<Self, Error> {
+        let mut fd = OwnedRtFd::open(path.clone()).map_err(Error::ReadImage)?;
+        let mut buf = [0; BINPRM_BUF_SIZE];
+        let mut len = 0;
+        while len < buf.len() {
+            match fd.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(err) => return Err(Error::ReadImage(err.into())),
+            }
         }
+        drop(fd);
+
+        let (prog, arg) =
+            exec::parse_shebang(&buf[..len]).map_err(|err| Error::ImageFormat(err.to_string()))?;
+        let interp = crate::Program::load_at_depth(prog.to_vec(), depth)?;
         Ok(Self {
             prog: prog.into(),
             arg: arg.map(|x| x.into()),
             path,
+            interp: Box::new(interp),
         })
     }
 
@@ -50,13 +54,6 @@ impl Program {
             argv.push(*i);
         }
 
-        unsafe {
-            crate::Program::load(self.prog.clone())
-                .unwrap_or_else(|e| {
-                    eprintln!("mactux: failed to load script interpreter: {e}");
-                    std::process::exit(1);
-                })
-                .run(&argv, envs);
-        }
+        unsafe { self.interp.run(&argv, envs) }
     }
 }
@@ -56,14 +56,18 @@ pub fn syscall(_: TokenStream, item: TokenStream) -> TokenStream {
                 eprint!(#strace_fmt, stringify!(#ident), #(#call_impl_inputs,)*);
             }
 
+            let start = ::rtenv::telemetry::start();
             let ret = __impl(#(#call_impl_inputs,)*);
+            ::rtenv::telemetry::finish(stringify!(#ident), start);
 
             #[cfg(debug_assertions)]
             if ::rtenv::switches::strace() {
                 eprintln!("{:?}", ret);
             }
 
-            crate::UcontextExt::ret(uctx, crate::ToSysret::to_sysret(ret));
+            let sysret = crate::ToSysret::to_sysret(ret);
+            ::rtenv::error_report::finish(stringify!(#ident), sysret);
+            crate::UcontextExt::ret(uctx, sysret);
             unsafe { ::rtenv::emuctx::enter_emulated(); }
         }
     }
@@ -42,6 +42,15 @@ pub fn x86_64_set_emulated_gsbase(new: *mut u8) {
     thread::with_context(|ctx| ctx.emulated_gsbase.set(new));
 }
 
+/// Returns `true` if a faulting `fs` access in the current thread should be rewritten to a `gs` one.
+///
+/// Without a thread pointer set with `arch_prctl(ARCH_SET_FS)` or `CLONE_SETTLS`, `fs` accesses fault on Linux too, so
+/// they are not rewritten.
+pub fn should_rewrite_fs() -> bool {
+    crate::switches::fs_rewrite()
+        && thread::with_context(|ctx| !ctx.emulated_gsbase.get().is_null())
+}
+
 /// Thread information.
 #[derive(Debug, Clone)]
 pub struct EmulatedThreadInfo {
@@ -1,3 +1,24 @@
+//! Reporting of errors, both fatal ones and the ones that system calls fail with.
+//!
+//! While [`switches::explain_errors`] is enabled, each failed system call is logged along with the reason its handler
+//! gave through [`explain`], and is recorded as the [`last_error`] of the thread.
+
+use crate::{switches, thread::with_context};
+use structures::error::LxError;
+
+/// Context of a failed system call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LastError {
+    /// Name of the system call handler.
+    pub syscall: &'static str,
+
+    /// The error the system call failed with.
+    pub error: LxError,
+
+    /// Why the system call failed, if its handler told through [`explain`].
+    pub reason: Option<&'static str>,
+}
+
 /// Makes the process fail immediately.
 #[cold]
 pub fn fast_fail() -> ! {
@@ -5,3 +26,49 @@ pub fn fast_fail() -> ! {
         libc::_exit(101);
     }
 }
+
+/// Records why the current system call fails with `err`, and returns `err`.
+///
+/// Errors that come from macOS or the server as is need no explanation, but ones that MacTux makes up itself, like
+/// permission checks and unsupported operations, should tell why they happen.
+#[inline]
+pub fn explain(err: LxError, reason: &'static str) -> LxError {
+    if switches::explain_errors() {
+        with_context(|ctx| ctx.error_reason.set(Some(reason)));
+    }
+    err
+}
+
+/// Returns context of the last failed system call of the current thread, or `None` if no system call has failed since
+/// [`switches::explain_errors`] is enabled.
+pub fn last_error() -> Option<LastError> {
+    with_context(|ctx| ctx.last_error.get())
+}
+
+/// Records the result of the system call handled by `name`, which returns `sysret`.
+#[inline]
+pub fn finish(name: &'static str, sysret: usize) {
+    if switches::explain_errors() {
+        record(name, sysret);
+    }
+}
+
+fn record(name: &'static str, sysret: usize) {
+    let reason = with_context(|ctx| ctx.error_reason.take());
+    let errno = (sysret as isize).wrapping_neg();
+    if !(1..4096).contains(&errno) {
+        return;
+    }
+    let error = LxError(errno as _);
+    log::warn!(
+        "{name} failed with {error}: {}",
+        reason.unwrap_or("no reason recorded")
+    );
+    with_context(|ctx| {
+        ctx.last_error.set(Some(LastError {
+            syscall: name,
+            error,
+            reason,
+        }))
+    });
+}
@@ -1,5 +1,7 @@
 mod vfd;
 
+pub use vfd::StatCache;
+
 use crate::{
     ipc_client::{call_server, with_client},
     posix_num, process,
@@ -13,8 +15,8 @@ use structures::{
     device::DeviceNumber,
     error::LxError,
     fs::{
-        AT_FDCWD, AccessFlags, AtFlags, Dirent64, FileMode, OpenFlags, OpenHow, OpenResolve,
-        StatFs, Statx, StatxMask, UmountFlags,
+        AT_FDCWD, AccessFlags, AtFlags, Dirent64, FileMode, FileType, MountFlags, OpenFlags,
+        OpenHow, OpenResolve, StatFs, Statx, StatxMask, UmountFlags,
     },
     internal::mactux_ipc::{Request, Response},
     time::Timespec,
@@ -60,19 +62,18 @@ pub fn openat(
         resolve,
     };
 
-    with_client(|client| {
-        match client
-            .invoke(Request::Open(at_path(dfd, path)?, how))
-            .unwrap()
-        {
+    with_client(
+        |client| match client.invoke(Request::Open(at_path(dfd, path)?, how)) {
             Response::NativePath(native) => open_native(native, oflags, atflags, mode.0 as _),
             Response::Vfd(vfd) => crate::vfd::create(vfd, oflags),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
-        }
-    })
+        },
+    )
 }
 
+/// Checks whether current process may access the file at `path` relative to `dfd`, like `faccessat2`. Permissions
+/// are checked against effective IDs of the process with `AT_EACCESS` in `flags`, or its real IDs otherwise.
 #[inline]
 pub fn faccessat2(
     dfd: c_int,
@@ -84,16 +85,13 @@ pub fn faccessat2(
         return Ok(());
     }
 
-    with_client(|client| {
-        match client
-            .invoke(Request::Access(at_path(dfd, path)?, mode))
-            .unwrap()
-        {
+    with_client(
+        |client| match client.invoke(Request::Access(at_path(dfd, path)?, mode, flags)) {
             Response::Nothing => Ok(()),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
-        }
-    })
+        },
+    )
 }
 
 #[inline]
@@ -104,6 +102,15 @@ pub fn getdents64(fd: c_int) -> Result<Option<Dirent64>, LxError> {
     }
 }
 
+/// Reads up to `count` directory entries in a single exchange with the server. See [`vfd::getdents64_batch`].
+#[inline]
+pub fn getdents64_batch(fd: c_int, count: usize) -> Result<Vec<Dirent64>, LxError> {
+    match crate::vfd::get(fd) {
+        Some(vfd) => vfd::getdents64_batch(vfd, count),
+        None => Err(LxError::EBADF),
+    }
+}
+
 #[inline]
 pub fn fstat(fd: c_int, mask: StatxMask) -> Result<Statx, LxError> {
     match crate::vfd::get(fd) {
@@ -144,18 +151,75 @@ pub unsafe fn fchmod(fd: c_int, mode: u16) -> Result<(), LxError> {
     }
 }
 
+/// Changes owner of the file at `path` relative to `dfd`. With `AT_SYMLINK_NOFOLLOW`, a symbolic link itself is
+/// changed.
+pub unsafe fn fchownat(
+    dfd: c_int,
+    path: Vec<u8>,
+    uid: u32,
+    gid: u32,
+    flags: AtFlags,
+) -> Result<(), LxError> {
+    with_path_fd(dfd, path, flags, |fd| unsafe { fchown(fd, uid, gid) })
+}
+
+/// Changes mode of the file at `path` relative to `dfd`.
+///
+/// Modes of symbolic links cannot be changed, so with `AT_SYMLINK_NOFOLLOW` this fails on symbolic links.
+pub unsafe fn fchmodat(
+    dfd: c_int,
+    path: Vec<u8>,
+    mode: u16,
+    flags: AtFlags,
+) -> Result<(), LxError> {
+    with_path_fd(dfd, path, flags, |fd| {
+        if flags.contains(AtFlags::AT_SYMLINK_NOFOLLOW)
+            && fstat(fd, StatxMask::STATX_TYPE)?.stx_mode.file_type() == FileType::Symlink
+        {
+            return Err(LxError::EOPNOTSUPP);
+        }
+        unsafe { fchmod(fd, mode) }
+    })
+}
+
+/// Executes `f` with an `O_PATH` file descriptor of `path` relative to `dfd`, honoring `AT_EMPTY_PATH` and
+/// `AT_SYMLINK_NOFOLLOW` in `flags`.
+///
+/// With `AT_EMPTY_PATH`, an empty `path` refers to `dfd` itself, or to the working directory if it is `AT_FDCWD`.
+fn with_path_fd<T>(
+    dfd: c_int,
+    mut path: Vec<u8>,
+    flags: AtFlags,
+    f: impl FnOnce(c_int) -> Result<T, LxError>,
+) -> Result<T, LxError> {
+    if path.is_empty() {
+        match (flags.contains(AtFlags::AT_EMPTY_PATH), dfd) {
+            (true, AT_FDCWD) => path = b".".to_vec(),
+            (true, _) => return f(dfd),
+            (false, _) => return Err(LxError::ENOENT),
+        }
+    }
+    let fd = openat(
+        dfd,
+        path,
+        OpenFlags::O_PATH,
+        flags & AtFlags::AT_SYMLINK_NOFOLLOW,
+        FileMode(0),
+    )?;
+    let result = f(fd);
+    _ = crate::io::close(fd);
+    result
+}
+
 #[inline]
 pub fn symlinkat(src: Vec<u8>, newdfd: c_int, dst: Vec<u8>) -> Result<(), LxError> {
-    with_client(|client| {
-        match client
-            .invoke(Request::Symlink(src, at_path(newdfd, dst)?))
-            .unwrap()
-        {
+    with_client(
+        |client| match client.invoke(Request::Symlink(src, at_path(newdfd, dst)?)) {
             Response::Nothing => Ok(()),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
-        }
-    })
+        },
+    )
 }
 
 #[inline]
@@ -167,13 +231,10 @@ pub fn renameat2(
     _flags: u32,
 ) -> Result<(), LxError> {
     with_client(|client| {
-        match client
-            .invoke(Request::Rename(
-                at_path(srcdfd, src)?,
-                at_path(dstdfd, dst)?,
-            ))
-            .unwrap()
-        {
+        match client.invoke(Request::Rename(
+            at_path(srcdfd, src)?,
+            at_path(dstdfd, dst)?,
+        )) {
             Response::Nothing => Ok(()),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
@@ -192,7 +253,7 @@ pub fn linkat(
     let full_src = at_path(sdfd, src)?;
     let full_dst = at_path(ddfd, dst)?;
     with_client(
-        |client| match client.invoke(Request::Link(full_src, full_dst)).unwrap() {
+        |client| match client.invoke(Request::Link(full_src, full_dst)) {
             Response::Nothing => Ok(()),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
@@ -208,7 +269,7 @@ pub fn unlinkat(dfd: c_int, path: Vec<u8>, flags: AtFlags) -> Result<(), LxError
     } else {
         Request::Unlink
     };
-    with_client(|client| match client.invoke(method(full_path)).unwrap() {
+    with_client(|client| match client.invoke(method(full_path)) {
         Response::Nothing => Ok(()),
         Response::Error(err) => Err(err),
         _ => ipc_fail(),
@@ -217,16 +278,13 @@ pub fn unlinkat(dfd: c_int, path: Vec<u8>, flags: AtFlags) -> Result<(), LxError
 
 #[inline]
 pub fn mkdirat(dfd: c_int, path: Vec<u8>, mode: FileMode) -> Result<(), LxError> {
-    with_client(|client| {
-        match client
-            .invoke(Request::Mkdir(at_path(dfd, path)?, mode))
-            .unwrap()
-        {
+    with_client(
+        |client| match client.invoke(Request::Mkdir(at_path(dfd, path)?, mode)) {
             Response::Nothing => Ok(()),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
-        }
-    })
+        },
+    )
 }
 
 #[inline]
@@ -236,16 +294,13 @@ pub fn mknodat(
     mode: FileMode,
     dev: DeviceNumber,
 ) -> Result<(), LxError> {
-    with_client(|client| {
-        match client
-            .invoke(Request::Mknod(at_path(dfd, path)?, mode, dev))
-            .unwrap()
-        {
+    with_client(
+        |client| match client.invoke(Request::Mknod(at_path(dfd, path)?, mode, dev)) {
             Response::Nothing => Ok(()),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
-        }
-    })
+        },
+    )
 }
 
 #[inline]
@@ -258,10 +313,9 @@ pub fn fchdir(fd: c_int) -> Result<(), LxError> {
     let Some(vfd) = crate::vfd::get(fd) else {
         return Err(LxError::ENOTDIR);
     };
-    process::context()
-        .fs
-        .cwd
-        .store(Arc::new(vfd::orig_path(vfd)?));
+    let cwd = vfd::orig_path(vfd)?;
+    call_server(Request::SetCwd(cwd.clone()))?;
+    process::context().fs.cwd.store(Arc::new(cwd));
     Ok(())
 }
 
@@ -270,6 +324,7 @@ pub fn init_cwd(new: Vec<u8>) -> Result<(), LxError> {
     if !new.starts_with(b"/") {
         return Err(LxError::EINVAL);
     }
+    call_server(Request::SetCwd(new.clone()))?;
     process::context().fs.cwd.store(Arc::new(new));
     Ok(())
 }
@@ -293,6 +348,51 @@ pub fn flistxattr(fd: c_int) -> Result<Vec<u8>, LxError> {
     }
 }
 
+/// Sets the file mode creation mask of the current process, returning the previous one.
+///
+/// The mask is kept in sync between macOS, which applies it to native creations, and the server, which applies it to
+/// creations in virtual filesystems.
+pub fn umask(mask: u16) -> u16 {
+    let mask = mask & 0o777;
+    unsafe {
+        libc::umask(mask as _);
+    }
+    with_client(|client| match client.invoke(Request::SetUmask(mask)) {
+        Response::Umask(prev) => prev,
+        _ => ipc_fail(),
+    })
+}
+
+/// Tells the server about the native file mode creation mask, which is inherited from the launching environment.
+pub fn sync_umask() {
+    let mask = unsafe {
+        let mask = libc::umask(0);
+        libc::umask(mask);
+        mask
+    };
+    umask(mask as _);
+}
+
+pub fn mount(
+    source: Vec<u8>,
+    target: Vec<u8>,
+    fs: String,
+    flags: MountFlags,
+    data: Vec<u8>,
+) -> Result<(), LxError> {
+    let source = match flags.contains(MountFlags::MS_BIND) {
+        true => at_path(AT_FDCWD, source)?,
+        false => source,
+    };
+    call_server(Request::Mount(
+        source,
+        at_path(AT_FDCWD, target)?,
+        fs,
+        flags,
+        data,
+    ))
+}
+
 #[inline]
 pub fn umount(path: Vec<u8>, flags: UmountFlags) -> Result<(), LxError> {
     call_server(Request::Umount(at_path(AT_FDCWD, path)?, flags))
@@ -325,16 +425,13 @@ pub fn futimens(fd: c_int, times: [Timespec; 2]) -> Result<(), LxError> {
 
 /// Gets path of a local socket.
 pub fn get_sock_path(path: Vec<u8>, create: bool) -> Result<Vec<u8>, LxError> {
-    with_client(|client| {
-        match client
-            .invoke(Request::GetSockPath(at_path(-100, path)?, create))
-            .unwrap()
-        {
+    with_client(
+        |client| match client.invoke(Request::GetSockPath(at_path(-100, path)?, create)) {
             Response::LxPath(path) => Ok(path),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
-        }
-    })
+        },
+    )
 }
 
 fn open_native(
@@ -360,7 +457,7 @@ fn open_native(
 }
 
 /// Returns path relative to current root directory for given path at given file descriptor.
-fn at_path(fd: c_int, mut path: Vec<u8>) -> Result<Vec<u8>, LxError> {
+pub(crate) fn at_path(fd: c_int, mut path: Vec<u8>) -> Result<Vec<u8>, LxError> {
     if path.first() == Some(&b'/') {
         return Ok(path);
     }
@@ -1,7 +1,10 @@
 use crate::{
-    ipc_client::{call_server, with_client},
+    ipc_client::{FromResponse, call_server, with_client},
+    process,
     util::ipc_fail,
 };
+use rustc_hash::FxBuildHasher;
+use std::time::{Duration, Instant};
 use structures::{
     error::LxError,
     fs::{Dirent64, StatFs, Statx, StatxMask},
@@ -13,19 +16,48 @@ pub fn getdents64(vfd: u64) -> Result<Option<Dirent64>, LxError> {
     call_server(Request::VfdGetdent(vfd))
 }
 
+/// Reads up to `count` directory entries in a single exchange with the server, stopping at the end of the directory.
+///
+/// If an error occurs after some entries are read, the entries read so far are returned.
+pub fn getdents64_batch(vfd: u64, count: usize) -> Result<Vec<Dirent64>, LxError> {
+    let reqs = vec![Request::VfdGetdent(vfd); count];
+    let resps = with_client(|client| client.invoke_batch(reqs))?;
+    let mut dirents = Vec::with_capacity(count);
+    for resp in resps {
+        match Result::<Option<Dirent64>, LxError>::from_response(resp) {
+            Some(Ok(Some(dirent))) => dirents.push(dirent),
+            Some(Ok(None)) => break,
+            Some(Err(err)) if dirents.is_empty() => return Err(err),
+            Some(Err(_)) => break,
+            None => ipc_fail(),
+        }
+    }
+    Ok(dirents)
+}
+
+/// Gets attributes of a virtual file descriptor, which may be served from [`StatCache`].
 pub fn stat(vfd: u64, mask: StatxMask) -> Result<Statx, LxError> {
-    call_server(Request::VfdStat(vfd, mask))
+    let cache = &process::context().stat_cache;
+    if let Some(stat) = cache.get(vfd, mask) {
+        return Ok(stat);
+    }
+    let stat: Statx = call_server::<Result<_, _>>(Request::VfdStat(vfd, mask))?;
+    cache.insert(vfd, mask, stat.clone());
+    Ok(stat)
 }
 
 pub fn chown(vfd: u64, uid: u32, gid: u32) -> Result<(), LxError> {
+    process::context().stat_cache.invalidate(vfd);
     call_server(Request::VfdChown(vfd, uid, gid))
 }
 
 pub fn chmod(vfd: u64, mode: u16) -> Result<(), LxError> {
+    process::context().stat_cache.invalidate(vfd);
     call_server(Request::VfdChmod(vfd, mode))
 }
 
 pub fn utimens(vfd: u64, times: [Timespec; 2]) -> Result<(), LxError> {
+    process::context().stat_cache.invalidate(vfd);
     call_server(Request::VfdUtimeNs(vfd, times))
 }
 
@@ -35,7 +67,7 @@ pub fn statfs(vfd: u64) -> Result<StatFs, LxError> {
 
 pub fn readlink(vfd: u64) -> Result<Vec<u8>, LxError> {
     with_client(|client| {
-        let response = client.invoke(Request::VfdReadlink(vfd)).unwrap();
+        let response = client.invoke(Request::VfdReadlink(vfd));
         match response {
             Response::Bytes(path) => Ok(path),
             Response::Error(err) => Err(err),
@@ -46,7 +78,7 @@ pub fn readlink(vfd: u64) -> Result<Vec<u8>, LxError> {
 
 pub fn listxattr(vfd: u64) -> Result<Vec<Vec<u8>>, LxError> {
     with_client(|client| {
-        let response = client.invoke(Request::VfdListXattr(vfd)).unwrap();
+        let response = client.invoke(Request::VfdListXattr(vfd));
         match response {
             Response::ListXattr(list) => Ok(list),
             Response::Error(err) => Err(err),
@@ -57,11 +89,58 @@ pub fn listxattr(vfd: u64) -> Result<Vec<Vec<u8>>, LxError> {
 
 /// Gets the path that we have used to originally open a virtual file descriptor.
 pub fn orig_path(vfd: u64) -> Result<Vec<u8>, LxError> {
-    with_client(
-        |client| match client.invoke(Request::VfdOrigPath(vfd)).unwrap() {
-            Response::LxPath(path) => Ok(path),
-            Response::Error(err) => Err(err),
-            _ => ipc_fail(),
-        },
-    )
+    with_client(|client| match client.invoke(Request::VfdOrigPath(vfd)) {
+        Response::LxPath(path) => Ok(path),
+        Response::Error(err) => Err(err),
+        _ => ipc_fail(),
+    })
+}
+
+/// Short-lived cache of attributes of virtual file descriptors, to avoid server round-trips on repeated `fstat()`s.
+///
+/// Entries are invalidated when the file is modified through the same virtual file descriptor in this process. Changes
+/// made through other file descriptors or by other processes are only seen once the entry expires, so they may be
+/// observed up to [`StatCache::TTL`] late.
+#[derive(Debug, Default)]
+pub struct StatCache(papaya::HashMap<u64, CachedStat, FxBuildHasher>);
+impl StatCache {
+    /// How long a cached entry stays valid.
+    pub const TTL: Duration = Duration::from_millis(10);
+
+    /// Returns cached attributes of `vfd`, if they are fresh and contain all the fields in `mask`.
+    fn get(&self, vfd: u64, mask: StatxMask) -> Option<Statx> {
+        let cache = self.0.pin();
+        let entry = cache.get(&vfd)?;
+        if entry.time.elapsed() >= Self::TTL || !entry.mask.contains(mask) {
+            return None;
+        }
+        Some(entry.stat.clone())
+    }
+
+    fn insert(&self, vfd: u64, mask: StatxMask, stat: Statx) {
+        let entry = CachedStat {
+            time: Instant::now(),
+            mask,
+            stat,
+        };
+        self.0.pin().insert(vfd, entry);
+    }
+
+    /// Invalidates cached attributes of `vfd`. This must be called before the file is modified through `vfd`, or
+    /// `vfd` is closed.
+    pub fn invalidate(&self, vfd: u64) {
+        self.0.pin().remove(&vfd);
+    }
+
+    /// Invalidates all cached attributes.
+    pub fn clear(&self) {
+        self.0.pin().clear();
+    }
+}
+
+#[derive(Debug)]
+struct CachedStat {
+    time: Instant,
+    mask: StatxMask,
+    stat: Statx,
 }
@@ -2,19 +2,24 @@ mod native_fcntl;
 mod native_ioctl;
 mod vfd;
 
-use crate::{ipc_client::with_client, posix_num, util::ipc_fail, util::posix_result};
-use rustc_hash::FxHashMap;
-use std::{
-    ffi::c_int,
-    os::fd::{AsRawFd, IntoRawFd},
-    time::Duration,
+use crate::{
+    ipc_client::with_client,
+    posix_num,
+    switches::{self, IoctlClass, UnknownIoctlAction},
+    util::ipc_fail,
+    util::posix_result,
 };
+use rustc_hash::FxHashMap;
+use std::{ffi::c_int, os::fd::AsRawFd, time::Duration};
 use structures::{
     FromApple, ToApple,
     error::LxError,
     fs::OpenFlags,
     internal::mactux_ipc::{InterruptibleRequest, Request, Response},
-    io::{EventFdFlags, FcntlCmd, FdFlags, FdSet, FlockOp, IoctlCmd, PollEvents, PollFd, Whence},
+    io::{
+        EventFdFlags, FD_SETSIZE, Fadvice, FcntlCmd, FdFlags, FdSet, FlockOp, IoctlCmd, PollEvents,
+        PollFd, RwfFlags, Whence,
+    },
 };
 
 #[inline]
@@ -41,6 +46,23 @@ pub unsafe fn ioctl(fd: c_int, cmd: IoctlCmd, arg: *mut u8) -> Result<c_int, LxE
     }
 }
 
+/// Handles an ioctl that is not implemented, as is configured by [`switches::unknown_ioctl`].
+fn unknown_ioctl(class: IoctlClass, cmd: IoctlCmd) -> Result<c_int, LxError> {
+    match switches::unknown_ioctl(class) {
+        UnknownIoctlAction::Crash => {
+            eprintln!("Unsupported ioctl");
+            log::error!("process crashed due to unsupported ioctl {cmd:?} on {class:?} file");
+            crate::error_report::fast_fail();
+        }
+        UnknownIoctlAction::Einval => Err(LxError::EINVAL),
+        UnknownIoctlAction::Notty => Err(LxError::ENOTTY),
+        UnknownIoctlAction::Success => {
+            log::warn!("ignored unsupported ioctl {cmd:?} on {class:?} file");
+            Ok(0)
+        }
+    }
+}
+
 #[inline]
 pub unsafe fn fcntl(fd: c_int, cmd: FcntlCmd, arg: usize) -> Result<c_int, LxError> {
     match crate::vfd::get(fd) {
@@ -65,6 +87,17 @@ pub unsafe fn fcntl(fd: c_int, cmd: FcntlCmd, arg: usize) -> Result<c_int, LxErr
                 crate::vfd::register(new_fd, new_vfd);
                 return Ok(new_fd);
             }
+            if cmd == FcntlCmd::F_SETFD {
+                vfd::fcntl(vfd, cmd, arg)?;
+
+                // The native file descriptor follows, so that it survives `execve` exactly when the VFD does.
+                let apple_flags = match arg as u32 & FdFlags::FD_CLOEXEC.bits() {
+                    0 => 0,
+                    _ => libc::FD_CLOEXEC,
+                };
+                unsafe { posix_result(libc::fcntl(fd, libc::F_SETFD, apple_flags))? };
+                return Ok(0);
+            }
 
             vfd::fcntl(vfd, cmd, arg)
         }
@@ -84,12 +117,16 @@ pub unsafe fn flock(fd: c_int, op: FlockOp) -> Result<(), LxError> {
 pub fn dup(fd: c_int) -> Result<c_int, LxError> {
     match crate::vfd::get(fd) {
         Some(vfd) => {
-            let fd = std::fs::File::open("/dev/null")?.into_raw_fd();
+            let fd = unsafe { posix_num!(libc::dup(fd))? };
             let new_vfd = vfd::dup(vfd);
             crate::vfd::register(fd, new_vfd);
             Ok(fd)
         }
-        None => unsafe { posix_num!(libc::dup(fd)) },
+        None => {
+            let new = unsafe { posix_num!(libc::dup(fd))? };
+            crate::io_uring::dup(fd, new);
+            Ok(new)
+        }
     }
 }
 
@@ -99,15 +136,7 @@ pub fn dup2(old: c_int, new: c_int) -> Result<c_int, LxError> {
         return Ok(new);
     }
 
-    match crate::vfd::get(old) {
-        Some(vfd) => {
-            let new_fd = unsafe { posix_num!(libc::dup2(old, new))? };
-            let new_vfd = vfd::dup(vfd);
-            crate::vfd::register(new_fd, new_vfd);
-            Ok(new)
-        }
-        None => unsafe { posix_num!(libc::dup2(old, new)) },
-    }
+    dup_to(old, new)
 }
 
 #[inline]
@@ -116,15 +145,7 @@ pub fn dup3(old: c_int, new: c_int, flags: OpenFlags) -> Result<c_int, LxError>
         return Err(LxError::EINVAL);
     }
 
-    let new = match crate::vfd::get(old) {
-        Some(vfd) => {
-            let new_fd = unsafe { posix_num!(libc::dup2(old, new))? };
-            let new_vfd = vfd::dup(vfd);
-            crate::vfd::register(new_fd, new_vfd);
-            Ok(new)
-        }
-        None => unsafe { posix_num!(libc::dup2(old, new)) },
-    }?;
+    let new = dup_to(old, new)?;
 
     if flags.contains(OpenFlags::O_CLOEXEC) {
         set_cloexec(new).inspect_err(|_| _ = close(new))?;
@@ -133,6 +154,24 @@ pub fn dup3(old: c_int, new: c_int, flags: OpenFlags) -> Result<c_int, LxError>
     Ok(new)
 }
 
+/// Duplicates `old` to `new`, closing the VFD `new` previously referred to, if any.
+fn dup_to(old: c_int, new: c_int) -> Result<c_int, LxError> {
+    let new_vfd = crate::vfd::get(old).map(vfd::dup);
+    let new_fd = unsafe { posix_num!(libc::dup2(old, new)) }.inspect_err(|_| {
+        if let Some(new_vfd) = new_vfd {
+            vfd::close(new_vfd);
+        }
+    })?;
+    if let Some(replaced) = crate::vfd::take(new_fd) {
+        vfd::close(replaced);
+    }
+    if let Some(new_vfd) = new_vfd {
+        crate::vfd::register(new_fd, new_vfd);
+    }
+    crate::io_uring::dup(old, new_fd);
+    Ok(new_fd)
+}
+
 #[inline]
 pub fn lseek(fd: c_int, off: i64, whence: Whence) -> Result<i64, LxError> {
     match crate::vfd::get(fd) {
@@ -157,6 +196,120 @@ pub fn pwrite64(fd: c_int, buf: &[u8], off: i64) -> Result<usize, LxError> {
     }
 }
 
+/// Reads into buffers described by `vec` at offset `off`, or at the file offset if `off` is `-1`.
+pub unsafe fn preadv2(
+    fd: c_int,
+    vec: &[libc::iovec],
+    off: i64,
+    flags: RwfFlags,
+) -> Result<usize, LxError> {
+    check_rwf_flags(flags)?;
+    let mut buf = vec![0u8; iovec_len(vec)?];
+    let n = match off {
+        -1 => read(fd, &mut buf)?,
+        off => pread64(fd, &mut buf, off)?,
+    };
+    let mut data = &buf[..n];
+    for vec in vec {
+        if data.is_empty() {
+            break;
+        }
+        let len = vec.iov_len.min(data.len());
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), vec.iov_base.cast(), len);
+        }
+        data = &data[len..];
+    }
+    Ok(n)
+}
+
+/// Writes buffers described by `vec` at offset `off`, or at the file offset if `off` is `-1`.
+///
+/// With `RWF_APPEND`, data is appended to the end of the file regardless of `off` and `O_APPEND`. The file offset is
+/// only updated if `off` is `-1`, like Linux.
+pub unsafe fn pwritev2(
+    fd: c_int,
+    vec: &[libc::iovec],
+    off: i64,
+    flags: RwfFlags,
+) -> Result<usize, LxError> {
+    check_rwf_flags(flags)?;
+    let mut buf = Vec::with_capacity(iovec_len(vec)?);
+    for vec in vec {
+        if vec.iov_len != 0 {
+            buf.extend_from_slice(unsafe {
+                std::slice::from_raw_parts(vec.iov_base as *const u8, vec.iov_len)
+            });
+        }
+    }
+    let n = if flags.contains(RwfFlags::RWF_APPEND) {
+        append(fd, &buf, off == -1)?
+    } else {
+        match off {
+            -1 => write(fd, &buf)?,
+            off => pwrite64(fd, &buf, off)?,
+        }
+    };
+    if flags.intersects(RwfFlags::RWF_DSYNC | RwfFlags::RWF_SYNC) {
+        fsync(fd)?;
+    }
+    Ok(n)
+}
+
+/// Appends `buf` to the end of the file, whether `fd` is opened with `O_APPEND` or not.
+///
+/// Status flags of a native open file description may be shared with other threads and processes, so they are never
+/// changed here. Instead, regular files that are not opened with `O_APPEND` are written at their size with `pwrite`.
+/// Such appends are serialized within the process, but may interleave with writes from other processes.
+fn append(fd: c_int, buf: &[u8], update_offset: bool) -> Result<usize, LxError> {
+    static APPEND: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    if let Some(vfd) = crate::vfd::get(fd) {
+        return vfd::append(vfd, buf, update_offset);
+    }
+    unsafe {
+        let flags: c_int = posix_num!(libc::fcntl(fd, libc::F_GETFL))?;
+        if flags & libc::O_APPEND != 0 && update_offset {
+            return posix_num!(libc::write(fd, buf.as_ptr().cast(), buf.len()));
+        }
+
+        let _guard = APPEND.lock().unwrap();
+        let mut stat = std::mem::zeroed();
+        posix_result(libc::fstat(fd, &mut stat))?;
+        if stat.st_mode & libc::S_IFMT != libc::S_IFREG {
+            // Files other than regular ones have no end to append to, nor offsets to update.
+            return posix_num!(libc::write(fd, buf.as_ptr().cast(), buf.len()));
+        }
+        let n: usize = posix_num!(libc::pwrite(
+            fd,
+            buf.as_ptr().cast(),
+            buf.len(),
+            stat.st_size
+        ))?;
+        if update_offset {
+            libc::lseek(fd, stat.st_size + n as i64, libc::SEEK_SET);
+        }
+        Ok(n)
+    }
+}
+
+/// Returns total length of buffers described by `vec`.
+fn iovec_len(vec: &[libc::iovec]) -> Result<usize, LxError> {
+    vec.iter()
+        .try_fold(0usize, |acc, x| acc.checked_add(x.iov_len))
+        .filter(|x| *x <= isize::MAX as usize)
+        .ok_or(LxError::EINVAL)
+}
+
+/// Checks flags of `preadv2` or `pwritev2`.
+fn check_rwf_flags(flags: RwfFlags) -> Result<(), LxError> {
+    // `RWF_NOWAIT` requires knowledge of the page cache, which is not available on macOS.
+    if flags.contains(RwfFlags::RWF_NOWAIT) || flags.bits() & !RwfFlags::all().bits() != 0 {
+        return Err(LxError::EOPNOTSUPP);
+    }
+    Ok(())
+}
+
 #[inline]
 pub fn readv(fd: c_int, vec: &[libc::iovec]) -> Result<usize, LxError> {
     match crate::vfd::get(fd) {
@@ -210,18 +363,26 @@ pub unsafe fn writev(fd: c_int, vec: &[libc::iovec]) -> Result<usize, LxError> {
     }
 }
 
-#[inline]
+/// Waits for events on native and virtual file descriptors.
+///
+/// Like Linux, negative file descriptors are ignored, and `POLLNVAL` is reported for file descriptors that are not
+/// open. macOS `poll` only takes a timeout in milliseconds, so `timeout` is rounded up to never expire early.
 pub unsafe fn poll(fds: &mut [PollFd], timeout: Option<Duration>) -> Result<u32, LxError> {
     let mut apple_fds = Vec::with_capacity(fds.len());
+    let mut apple_fd_indices = Vec::with_capacity(fds.len());
     let mut virtual_fds = Vec::new();
     let mut virtual_fd_map = FxHashMap::default();
 
     let millis = match timeout {
         None => -1,
-        Some(dur) => dur.as_millis() as _,
+        Some(dur) => dur.as_nanos().div_ceil(1_000_000).min(c_int::MAX as u128) as c_int,
     };
 
     for (n, poll_fd) in fds.iter_mut().enumerate() {
+        poll_fd.revents = PollEvents::empty();
+        if poll_fd.fd < 0 {
+            continue;
+        }
         if let Some(vfd) = crate::vfd::get(poll_fd.fd) {
             virtual_fds.push((vfd, poll_fd.events));
             virtual_fd_map.insert(vfd, n);
@@ -232,6 +393,7 @@ pub unsafe fn poll(fds: &mut [PollFd], timeout: Option<Duration>) -> Result<u32,
             events: poll_fd.events.to_apple()?,
             revents: 0,
         });
+        apple_fd_indices.push(n);
     }
 
     let client = if !virtual_fds.is_empty() {
@@ -250,96 +412,100 @@ pub unsafe fn poll(fds: &mut [PollFd], timeout: Option<Duration>) -> Result<u32,
     };
 
     unsafe {
-        match libc::poll(apple_fds.as_mut_ptr(), apple_fds.len() as _, millis) {
-            -1 => Err(LxError::last_apple_error()),
-            n => {
-                if let Some(mut client) = client
-                    && (apple_fds.last().unwrap().revents & libc::POLLIN) != 0
-                {
-                    match client.wait() {
-                        Response::Poll(Some((vfd, revent))) => {
-                            fds[virtual_fd_map[&vfd]].revents = revent;
-                        }
-                        Response::Poll(None) => (),
-                        Response::Error(err) => {
-                            return Err(err);
-                        }
-                        _ => ipc_fail(),
-                    }
-                }
-                for (n, apple_fd) in apple_fds.into_iter().enumerate() {
-                    fds[n].revents = PollEvents::from_apple(apple_fd.revents)?;
-                }
-                Ok(n as _)
+        if libc::poll(apple_fds.as_mut_ptr(), apple_fds.len() as _, millis) == -1 {
+            return Err(LxError::last_apple_error());
+        }
+    }
+    if let Some(mut client) = client
+        && (apple_fds.last().unwrap().revents & libc::POLLIN) != 0
+    {
+        match client.wait() {
+            Response::Poll(Some((vfd, revent))) => {
+                fds[virtual_fd_map[&vfd]].revents = revent;
+            }
+            Response::Poll(None) => (),
+            Response::Error(err) => {
+                return Err(err);
             }
+            _ => ipc_fail(),
         }
     }
+    for (apple_fd, n) in apple_fds.into_iter().zip(apple_fd_indices) {
+        fds[n].revents = PollEvents::from_apple(apple_fd.revents)?;
+    }
+    Ok(fds.iter().filter(|x| !x.revents.is_empty()).count() as _)
 }
 
+/// Waits for file descriptors in the given sets to become ready, like `select`.
+///
+/// A file descriptor present in several sets is polled once for all of them. On success, the sets are replaced with
+/// the ready file descriptors, and the number of them is returned, counting each set separately like Linux. The sets
+/// are left unchanged on failure.
 pub unsafe fn select(
+    nfds: c_int,
     read_fds: Option<FdSet>,
     write_fds: Option<FdSet>,
     expect_fds: Option<FdSet>,
     timeout: Option<Duration>,
 ) -> Result<u32, LxError> {
-    unsafe {
-        let mut poll_fds = Vec::new();
-        let mut fd_mapping = FxHashMap::default();
+    const READ_EVENTS: PollEvents = PollEvents::POLLIN
+        .union(PollEvents::POLLRDNORM)
+        .union(PollEvents::POLLRDBAND)
+        .union(PollEvents::POLLHUP)
+        .union(PollEvents::POLLERR);
+    const WRITE_EVENTS: PollEvents = PollEvents::POLLOUT
+        .union(PollEvents::POLLWRNORM)
+        .union(PollEvents::POLLWRBAND)
+        .union(PollEvents::POLLERR);
+    const EXPECT_EVENTS: PollEvents = PollEvents::POLLPRI;
+
+    if !(0..=FD_SETSIZE as c_int).contains(&nfds) {
+        return Err(LxError::EINVAL);
+    }
 
-        for fd in read_fds.iter().flat_map(|x| x.iter()) {
-            poll_fds.push(PollFd {
-                fd,
-                events: PollEvents::POLLIN,
-                revents: PollEvents::empty(),
-            });
-            fd_mapping.insert(poll_fds.len() - 1, (0, fd));
-        }
-        for fd in write_fds.iter().flat_map(|x| x.iter()) {
-            poll_fds.push(PollFd {
-                fd,
-                events: PollEvents::POLLOUT,
-                revents: PollEvents::empty(),
-            });
-            fd_mapping.insert(poll_fds.len() - 1, (1, fd));
+    unsafe {
+        let sets = [
+            (&read_fds, PollEvents::POLLIN, READ_EVENTS),
+            (&write_fds, PollEvents::POLLOUT, WRITE_EVENTS),
+            (&expect_fds, PollEvents::POLLPRI, EXPECT_EVENTS),
+        ];
+        let mut interests = std::collections::BTreeMap::new();
+        for (set, interest, _) in &sets {
+            for fd in set.iter().flat_map(|x| x.iter()) {
+                *interests.entry(fd).or_insert(PollEvents::empty()) |= *interest;
+            }
         }
-        for fd in expect_fds.iter().flat_map(|x| x.iter()) {
-            poll_fds.push(PollFd {
+        let mut poll_fds: Vec<PollFd> = interests
+            .into_iter()
+            .map(|(fd, events)| PollFd {
                 fd,
-                events: PollEvents::POLLPRI | PollEvents::POLLERR,
+                events,
                 revents: PollEvents::empty(),
-            });
-            fd_mapping.insert(poll_fds.len() - 1, (2, fd));
+            })
+            .collect();
+
+        poll(&mut poll_fds, timeout)?;
+        if poll_fds
+            .iter()
+            .any(|x| x.revents.contains(PollEvents::POLLNVAL))
+        {
+            return Err(LxError::EBADF);
         }
 
-        let result = poll(&mut poll_fds, timeout);
-
-        if let Some(x) = &read_fds {
-            x.clear();
-        }
-        if let Some(x) = &write_fds {
-            x.clear();
-        }
-        if let Some(x) = &expect_fds {
-            x.clear();
-        }
-
-        for (n, poll_fd) in poll_fds.into_iter().enumerate() {
-            if !poll_fd.revents.is_empty() {
-                match fd_mapping[&n] {
-                    (0, fd) => {
-                        read_fds.as_ref().unwrap().insert(fd);
-                    }
-                    (1, fd) => {
-                        write_fds.as_ref().unwrap().insert(fd);
-                    }
-                    (2, fd) => {
-                        expect_fds.as_ref().unwrap().insert(fd);
-                    }
-                    _ => unreachable!(),
+        let mut count = 0;
+        for (set, interest, ready) in &sets {
+            let Some(set) = set else {
+                continue;
+            };
+            set.clear();
+            for poll_fd in &poll_fds {
+                if poll_fd.events.intersects(*interest) && poll_fd.revents.intersects(*ready) {
+                    set.insert(poll_fd.fd);
+                    count += 1;
                 }
             }
         }
-        result
+        Ok(count)
     }
 }
 
@@ -369,15 +535,232 @@ pub fn syncfs(fd: c_int) -> Result<(), LxError> {
     fsync(fd)
 }
 
+/// Announces the intended access pattern for a range of `fd`.
+///
+/// Virtual file descriptors do not benefit from any advice, so it is only validated for them. For native ones, read-ahead
+/// and caching of the file are adjusted with `fcntl`, and `POSIX_FADV_WILLNEED` schedules reading of the range.
+pub fn fadvise(fd: c_int, off: i64, len: usize, advice: Fadvice) -> Result<(), LxError> {
+    if crate::vfd::get(fd).is_some() {
+        return match advice.is_valid() {
+            true => Ok(()),
+            false => Err(LxError::EINVAL),
+        };
+    }
+
+    let mut stat = unsafe { std::mem::zeroed() };
+    unsafe { posix_result(libc::fstat(fd, &mut stat))? };
+    if matches!(stat.st_mode & libc::S_IFMT, libc::S_IFIFO | libc::S_IFSOCK) {
+        return Err(LxError::ESPIPE);
+    }
+
+    // These are only hints, so failures of filesystems that do not support them are ignored.
+    let set = |cmd, value: c_int| unsafe { _ = libc::fcntl(fd, cmd, value) };
+    match advice {
+        Fadvice::POSIX_FADV_NORMAL => {
+            set(libc::F_RDAHEAD, 1);
+            set(libc::F_NOCACHE, 0);
+        }
+        Fadvice::POSIX_FADV_SEQUENTIAL => set(libc::F_RDAHEAD, 1),
+        Fadvice::POSIX_FADV_RANDOM => set(libc::F_RDAHEAD, 0),
+        Fadvice::POSIX_FADV_DONTNEED => set(libc::F_NOCACHE, 1),
+        Fadvice::POSIX_FADV_NOREUSE => (),
+        Fadvice::POSIX_FADV_WILLNEED => {
+            if (0..stat.st_size).contains(&off) {
+                let len = match len {
+                    0 => stat.st_size - off,
+                    n => (n as i64).min(stat.st_size - off),
+                };
+                let advisory = libc::radvisory {
+                    ra_offset: off,
+                    ra_count: len.min(c_int::MAX as i64) as c_int,
+                };
+                unsafe { _ = libc::fcntl(fd, libc::F_RDADVISE, &advisory) };
+            }
+        }
+        _ => return Err(LxError::EINVAL),
+    }
+    Ok(())
+}
+
+/// Copies up to `len` bytes from `fd_in` to `fd_out`, at `off_in` and `off_out` if given, or at the file offsets.
+///
+/// Whole-file copies between native regular files are delegated to `fcopyfile`, which may clone the data. Anything
+/// else is copied through a buffer, until `len` bytes are copied or the end of `fd_in` is reached.
+pub fn copy_file_range(
+    fd_in: c_int,
+    off_in: Option<&mut i64>,
+    fd_out: c_int,
+    off_out: Option<&mut i64>,
+    len: usize,
+) -> Result<usize, LxError> {
+    if off_in.as_deref().is_some_and(|x| *x < 0) || off_out.as_deref().is_some_and(|x| *x < 0) {
+        return Err(LxError::EINVAL);
+    }
+    if let Some(copied) =
+        copy_whole_file(fd_in, off_in.as_deref(), fd_out, off_out.as_deref(), len)?
+    {
+        if let Some(off) = off_in {
+            *off += copied as i64;
+        }
+        if let Some(off) = off_out {
+            *off += copied as i64;
+        }
+        return Ok(copied);
+    }
+
+    copy_through_buffer(fd_in, off_in, fd_out, off_out, len)
+}
+
+/// Sends up to `count` bytes from `in_fd` to `out_fd`, reading at `off_in` if given, or at the file offset.
+///
+/// Like on Linux, `in_fd` must support seeking, so pipes and sockets are rejected with `EINVAL`.
+pub fn sendfile(
+    out_fd: c_int,
+    in_fd: c_int,
+    off_in: Option<&mut i64>,
+    count: usize,
+) -> Result<usize, LxError> {
+    if off_in.as_deref().is_some_and(|x| *x < 0) {
+        return Err(LxError::EINVAL);
+    }
+    match lseek(in_fd, 0, Whence::SEEK_CUR) {
+        Ok(_) => (),
+        Err(LxError::ESPIPE) => return Err(LxError::EINVAL),
+        Err(err) => return Err(err),
+    }
+    copy_through_buffer(in_fd, off_in, out_fd, None, count.min(MAX_RW_COUNT))
+}
+
+/// Maximum number of bytes transferred by a single call, like `MAX_RW_COUNT` on Linux.
+const MAX_RW_COUNT: usize = 0x7fff_f000;
+
+/// Copies up to `len` bytes from `fd_in` to `fd_out` through a buffer, until the end of `fd_in` is reached.
+///
+/// Offsets are advanced by the number of bytes actually written. Errors are only reported if nothing was copied.
+fn copy_through_buffer(
+    fd_in: c_int,
+    mut off_in: Option<&mut i64>,
+    fd_out: c_int,
+    mut off_out: Option<&mut i64>,
+    len: usize,
+) -> Result<usize, LxError> {
+    let mut buf = vec![0u8; len.min(COPY_CHUNK_SIZE)];
+    let mut copied = 0;
+    while copied < len {
+        let chunk = (len - copied).min(buf.len());
+        let result = match off_in.as_deref() {
+            Some(&off) => pread64(fd_in, &mut buf[..chunk], off),
+            None => read(fd_in, &mut buf[..chunk]),
+        };
+        let nread = match result {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(_) if copied != 0 => break,
+            Err(err) => return Err(err),
+        };
+
+        let mut written = 0;
+        while written < nread {
+            let result = match off_out.as_deref() {
+                Some(&off) => pwrite64(fd_out, &buf[written..nread], off),
+                None => write(fd_out, &buf[written..nread]),
+            };
+            match result {
+                Ok(n) => {
+                    written += n;
+                    if let Some(off) = off_out.as_deref_mut() {
+                        *off += n as i64;
+                    }
+                }
+                Err(_) if copied + written != 0 => break,
+                Err(err) => return Err(err),
+            }
+        }
+        if let Some(off) = off_in.as_deref_mut() {
+            *off += written as i64;
+        }
+        copied += written;
+        if written != nread {
+            break;
+        }
+    }
+    Ok(copied)
+}
+
+/// Size of chunks in which [`copy_through_buffer`] copies data.
+const COPY_CHUNK_SIZE: usize = 128 * 1024;
+
+/// Copies the whole content of `fd_in` to `fd_out` with `fcopyfile`, if both are native regular files, `fd_in` is read
+/// from the beginning and `fd_out` is empty. Returns the number of bytes copied, or `None` if the fast path does not
+/// apply.
+///
+/// File offsets of both file descriptors are left as if the data was copied by `read` and `write` calls.
+fn copy_whole_file(
+    fd_in: c_int,
+    off_in: Option<&i64>,
+    fd_out: c_int,
+    off_out: Option<&i64>,
+    len: usize,
+) -> Result<Option<usize>, LxError> {
+    if crate::vfd::get(fd_in).is_some() || crate::vfd::get(fd_out).is_some() {
+        return Ok(None);
+    }
+    let fstat = |fd| unsafe {
+        let mut stat: libc::stat = std::mem::zeroed();
+        posix_result(libc::fstat(fd, &mut stat)).map(|_| stat)
+    };
+    let (stat_in, stat_out) = (fstat(fd_in)?, fstat(fd_out)?);
+    let is_reg = |stat: &libc::stat| stat.st_mode & libc::S_IFMT == libc::S_IFREG;
+    if !is_reg(&stat_in)
+        || !is_reg(&stat_out)
+        || (stat_in.st_dev, stat_in.st_ino) == (stat_out.st_dev, stat_out.st_ino)
+        || stat_in.st_size == 0
+        || stat_out.st_size != 0
+        || (len as u64) < stat_in.st_size as u64
+    {
+        return Ok(None);
+    }
+    let pos_in: i64 = unsafe { posix_num!(libc::lseek(fd_in, 0, libc::SEEK_CUR))? };
+    let pos_out: i64 = unsafe { posix_num!(libc::lseek(fd_out, 0, libc::SEEK_CUR))? };
+    if off_in.copied().unwrap_or(pos_in) != 0 || off_out.copied().unwrap_or(pos_out) != 0 {
+        return Ok(None);
+    }
+
+    // `fcopyfile` may start at the file offsets, which differ from explicit offsets.
+    let copied = stat_in.st_size;
+    unsafe {
+        libc::lseek(fd_in, 0, libc::SEEK_SET);
+        libc::lseek(fd_out, 0, libc::SEEK_SET);
+        let result = posix_result(libc::fcopyfile(
+            fd_in,
+            fd_out,
+            std::ptr::null_mut(),
+            libc::COPYFILE_DATA,
+        ));
+
+        // Explicit offsets leave file offsets unchanged, like `pread` and `pwrite` do.
+        let end_in = if off_in.is_some() { pos_in } else { copied };
+        let end_out = if off_out.is_some() { pos_out } else { copied };
+        libc::lseek(fd_in, end_in, libc::SEEK_SET);
+        libc::lseek(fd_out, end_out, libc::SEEK_SET);
+        result?;
+    }
+    Ok(Some(copied as usize))
+}
+
 #[inline]
 pub fn close(fd: c_int) -> Result<(), LxError> {
     if crate::process::context().important_fds.pin().contains(&fd) {
-        return Err(LxError::EPERM);
+        return Err(crate::error_report::explain(
+            LxError::EPERM,
+            "the file descriptor is used by MacTux itself",
+        ));
     }
 
     if let Some(vfd) = crate::vfd::take(fd) {
         vfd::close(vfd);
     }
+    crate::io_uring::forget(fd);
     unsafe { posix_result(libc::close(fd)) }
 }
 
@@ -423,7 +806,7 @@ pub fn pipe(flags: OpenFlags) -> Result<[c_int; 2], LxError> {
 #[inline]
 pub fn eventfd(count: u64, flags: EventFdFlags) -> Result<c_int, LxError> {
     with_client(
-        |client| match client.invoke(Request::EventFd(count, flags)).unwrap() {
+        |client| match client.invoke(Request::EventFd(count, flags)) {
             Response::Vfd(vfd) => crate::vfd::create(vfd, flags.open_flags()),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
@@ -433,20 +816,11 @@ pub fn eventfd(count: u64, flags: EventFdFlags) -> Result<c_int, LxError> {
 
 #[inline]
 pub fn invalidfd(flags: OpenFlags) -> Result<c_int, LxError> {
-    with_client(
-        |client| match client.invoke(Request::InvalidFd(flags)).unwrap() {
-            Response::Vfd(vfd) => crate::vfd::create(vfd, flags),
-            Response::Error(err) => Err(err),
-            _ => ipc_fail(),
-        },
-    )
-}
-
-#[inline]
-pub fn set_mnt_namespace(new: u64) {
-    with_client(|client| {
-        client.invoke(Request::SetMntNamespace(new)).unwrap();
-    });
+    with_client(|client| match client.invoke(Request::InvalidFd(flags)) {
+        Response::Vfd(vfd) => crate::vfd::create(vfd, flags),
+        Response::Error(err) => Err(err),
+        _ => ipc_fail(),
+    })
 }
 
 #[inline]
@@ -461,3 +835,44 @@ pub fn set_cloexec(fd: c_int) -> Result<(), LxError> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn native_append_leaves_status_flags_alone() {
+        crate::init_for_tests();
+        let path = std::env::temp_dir().join(format!("mactux-append-{}", std::process::id()));
+        let mut file = std::fs::File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let fd = file.as_raw_fd();
+        file.write_all(b"abc").unwrap();
+
+        // The file offset is moved past the appended data.
+        file.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(append(fd, b"de", true), Ok(2));
+        assert_eq!(file.stream_position().unwrap(), 5);
+
+        // The file offset is kept.
+        file.seek(SeekFrom::Start(1)).unwrap();
+        assert_eq!(append(fd, b"f", false), Ok(1));
+        assert_eq!(file.stream_position().unwrap(), 1);
+
+        assert_eq!(
+            unsafe { libc::fcntl(fd, libc::F_GETFL) } & libc::O_APPEND,
+            0
+        );
+        let mut content = String::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "abcdef");
+    }
+}
@@ -1,22 +1,35 @@
-use crate::{posix_num, util::posix_result};
+use crate::{
+    posix_num,
+    switches::{self, IoctlClass},
+    util::posix_result,
+};
 use libc::c_int;
 use structures::{
     FromApple, ToApple,
     error::LxError,
     io::IoctlCmd,
+    mapper::with_pid_mapper,
+    net::{IfConf, IfReq},
     terminal::{TcFlowAction, Termios, Termios2, WinSize},
 };
 
 pub fn native_ioctl(fd: c_int, cmd: IoctlCmd, arg: *mut u8) -> Result<c_int, LxError> {
     match cmd {
+        // Process groups are translated like the ones returned by `getpgid`, so that foreground checks comparing them
+        // work in PID namespaces.
         IoctlCmd::TIOCGPGRP => unsafe {
-            let apple_pid: libc::pid_t = posix_num!(libc::tcgetpgrp(fd))?;
-            arg.cast::<i32>().write(apple_pid);
+            let apple_pgid: libc::pid_t = posix_num!(libc::tcgetpgrp(fd))?;
+            let pgid = with_pid_mapper(|x| x.apple_to_linux(apple_pgid))?;
+            arg.cast::<i32>().write(pgid);
             Ok(0)
         },
         IoctlCmd::TIOCSPGRP => unsafe {
-            let apple_pid: libc::pid_t = arg.cast::<i32>().read();
-            posix_result(libc::tcsetpgrp(fd, apple_pid))?;
+            let pgid = arg.cast::<i32>().read();
+            if pgid < 0 {
+                return Err(LxError::EINVAL);
+            }
+            let apple_pgid = with_pid_mapper(|x| x.linux_to_apple(pgid))?;
+            posix_result(libc::tcsetpgrp(fd, apple_pgid))?;
             Ok(0)
         },
         IoctlCmd::TCGETS => unsafe {
@@ -27,20 +40,28 @@ pub fn native_ioctl(fd: c_int, cmd: IoctlCmd, arg: *mut u8) -> Result<c_int, LxE
             Ok(0)
         },
         IoctlCmd::TCSETS => unsafe {
-            let apple_termios = arg.cast::<Termios>().read().to_apple()?;
+            let apple_termios = legacy_termios_to_apple(fd, arg.cast::<Termios>().read())?;
             posix_result(libc::tcsetattr(fd, libc::TCSANOW, &apple_termios))?;
             Ok(0)
         },
         IoctlCmd::TCSETSW => unsafe {
-            let apple_termios = arg.cast::<Termios>().read().to_apple()?;
+            let apple_termios = legacy_termios_to_apple(fd, arg.cast::<Termios>().read())?;
             posix_result(libc::tcsetattr(fd, libc::TCSADRAIN, &apple_termios))?;
             Ok(0)
         },
         IoctlCmd::TCSETSF => unsafe {
-            let apple_termios = arg.cast::<Termios>().read().to_apple()?;
+            let apple_termios = legacy_termios_to_apple(fd, arg.cast::<Termios>().read())?;
             posix_result(libc::tcsetattr(fd, libc::TCSAFLUSH, &apple_termios))?;
             Ok(0)
         },
+        IoctlCmd::TIOCSTI => unsafe {
+            // Linux fails with `EIO` when this is disabled by `dev.tty.legacy_tiocsti`.
+            if !switches::legacy_tiocsti() {
+                return Err(LxError::EIO);
+            }
+            posix_result(libc::ioctl(fd, libc::TIOCSTI, arg))?;
+            Ok(0)
+        },
         IoctlCmd::TIOCGWINSZ => unsafe {
             let mut winsize: libc::winsize = std::mem::zeroed();
             posix_result(libc::ioctl(fd, libc::TIOCGWINSZ, &mut winsize))?;
@@ -86,10 +107,39 @@ pub fn native_ioctl(fd: c_int, cmd: IoctlCmd, arg: *mut u8) -> Result<c_int, LxE
             // and let the caller pick a default MTU.
             Ok(0)
         }
+        IoctlCmd::SIOCGIFCONF => unsafe {
+            let conf = &mut *arg.cast::<IfConf>();
+            crate::net::interface::ifconf(conf)?;
+            Ok(0)
+        },
+        IoctlCmd::SIOCGIFFLAGS => unsafe {
+            crate::net::interface::ifflags(&mut *arg.cast::<IfReq>())?;
+            Ok(0)
+        },
+        IoctlCmd::SIOCGIFADDR => unsafe {
+            crate::net::interface::ifaddr(&mut *arg.cast::<IfReq>())?;
+            Ok(0)
+        },
+        IoctlCmd::SIOCGIFNETMASK => unsafe {
+            crate::net::interface::ifnetmask(&mut *arg.cast::<IfReq>())?;
+            Ok(0)
+        },
         IoctlCmd::FIONREAD => unsafe {
             posix_result(libc::ioctl(fd, libc::FIONREAD, arg.cast::<c_int>()))?;
             Ok(0)
         },
-        _ => Err(LxError::EINVAL),
+        _ => super::unknown_ioctl(IoctlClass::Native, cmd),
+    }
+}
+
+/// Converts a legacy `termios` structure to be set on `fd`, keeping the current speeds of the terminal where it asks
+/// for a speed that only `termios2` is able to hold.
+fn legacy_termios_to_apple(fd: c_int, termios: Termios) -> Result<libc::termios, LxError> {
+    unsafe {
+        let mut current: libc::termios = std::mem::zeroed();
+        posix_result(libc::tcgetattr(fd, &mut current))?;
+        termios
+            .with_speeds(current.c_ispeed as _, current.c_ospeed as _)
+            .to_apple()
     }
 }
@@ -1,17 +1,28 @@
 use crate::{
-    ipc_client::{call_server, with_client},
+    ipc_client::{Client, call_server, with_client},
+    process,
+    switches::IoctlClass,
     util::ipc_fail,
 };
 use std::ffi::c_int;
 use structures::{
     error::LxError,
-    internal::mactux_ipc::{Request, Response},
+    internal::{
+        mactux_ipc::{Request, Response},
+        shared_buffer::{SHARED_THRESHOLD, SharedBuffer},
+    },
     io::{FcntlCmd, IoctlCmd, VfdAvailCtrl, Whence},
 };
 
 pub fn read(vfd: u64, buf: &mut [u8]) -> Result<usize, LxError> {
-    with_client(
-        |client| match client.invoke(Request::VfdRead(vfd, buf.len())).unwrap() {
+    with_client(|client| {
+        if let Some(shared) = client.shared_buffer()
+            && buf.len() > SHARED_THRESHOLD
+        {
+            let len = buf.len().min(shared.len());
+            return read_shared(client, shared, buf, Request::VfdReadShared(vfd, len));
+        }
+        match client.invoke(Request::VfdRead(vfd, buf.len())) {
             Response::Bytes(blob) => {
                 debug_assert!(blob.len() <= buf.len());
                 buf[..blob.len()].copy_from_slice(&blob);
@@ -19,16 +30,19 @@ pub fn read(vfd: u64, buf: &mut [u8]) -> Result<usize, LxError> {
             }
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
-        },
-    )
+        }
+    })
 }
 
 pub fn pread(vfd: u64, off: i64, buf: &mut [u8]) -> Result<usize, LxError> {
     with_client(|client| {
-        match client
-            .invoke(Request::VfdPread(vfd, off, buf.len()))
-            .unwrap()
+        if let Some(shared) = client.shared_buffer()
+            && buf.len() > SHARED_THRESHOLD
         {
+            let len = buf.len().min(shared.len());
+            return read_shared(client, shared, buf, Request::VfdPreadShared(vfd, off, len));
+        }
+        match client.invoke(Request::VfdPread(vfd, off, buf.len())) {
             Response::Bytes(blob) => {
                 debug_assert!(blob.len() <= buf.len());
                 buf[..blob.len()].copy_from_slice(&blob);
@@ -41,21 +55,92 @@ pub fn pread(vfd: u64, off: i64, buf: &mut [u8]) -> Result<usize, LxError> {
 }
 
 pub fn write(vfd: u64, buf: &[u8]) -> Result<usize, LxError> {
-    with_client(
-        |client| match client.invoke(Request::VfdWrite(vfd, buf.to_vec())).unwrap() {
+    process::context().stat_cache.invalidate(vfd);
+    with_client(|client| {
+        if let Some(shared) = client.shared_buffer()
+            && buf.len() > SHARED_THRESHOLD
+        {
+            return write_shared(client, shared, buf, |_, len| {
+                Request::VfdWriteShared(vfd, len)
+            });
+        }
+        match client.invoke(Request::VfdWrite(vfd, buf.to_vec())) {
             Response::Length(n) => Ok(n),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
-        },
-    )
+        }
+    })
 }
 
 pub fn pwrite(vfd: u64, off: i64, buf: &[u8]) -> Result<usize, LxError> {
+    process::context().stat_cache.invalidate(vfd);
     with_client(|client| {
-        match client
-            .invoke(Request::VfdPwrite(vfd, off, buf.to_vec()))
-            .unwrap()
+        if let Some(shared) = client.shared_buffer()
+            && buf.len() > SHARED_THRESHOLD
         {
+            return write_shared(client, shared, buf, |pos, len| {
+                Request::VfdPwriteShared(vfd, off + pos as i64, len)
+            });
+        }
+        match client.invoke(Request::VfdPwrite(vfd, off, buf.to_vec())) {
+            Response::Length(n) => Ok(n),
+            Response::Error(err) => Err(err),
+            _ => ipc_fail(),
+        }
+    })
+}
+
+/// Reads through the shared buffer with a single request, so that reading never blocks more than once, like a single
+/// `read` does. Reads longer than the shared buffer are therefore short.
+fn read_shared(
+    client: &Client,
+    shared: &SharedBuffer,
+    buf: &mut [u8],
+    req: Request,
+) -> Result<usize, LxError> {
+    match client.invoke(req) {
+        Response::Length(n) => {
+            let data = unsafe { shared.get(n) }.unwrap_or_else(|| ipc_fail());
+            buf[..n].copy_from_slice(data);
+            Ok(n)
+        }
+        Response::Error(err) => Err(err),
+        _ => ipc_fail(),
+    }
+}
+
+/// Writes through the shared buffer in chunks, stopping at the first short write. `req` makes the request for the chunk
+/// at the given position of `buf` with the given length.
+fn write_shared(
+    client: &Client,
+    shared: &SharedBuffer,
+    buf: &[u8],
+    req: impl Fn(usize, usize) -> Request,
+) -> Result<usize, LxError> {
+    let mut written = 0;
+    for chunk in buf.chunks(shared.len()) {
+        unsafe { shared.get(chunk.len()) }
+            .expect("chunks fit in the shared buffer")
+            .copy_from_slice(chunk);
+        match client.invoke(req(written, chunk.len())) {
+            Response::Length(n) => {
+                written += n;
+                if n < chunk.len() {
+                    break;
+                }
+            }
+            Response::Error(err) if written == 0 => return Err(err),
+            Response::Error(_) => break,
+            _ => ipc_fail(),
+        }
+    }
+    Ok(written)
+}
+
+pub fn append(vfd: u64, buf: &[u8], update_offset: bool) -> Result<usize, LxError> {
+    process::context().stat_cache.invalidate(vfd);
+    with_client(|client| {
+        match client.invoke(Request::VfdAppend(vfd, buf.to_vec(), update_offset)) {
             Response::Length(n) => Ok(n),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
@@ -65,7 +150,7 @@ pub fn pwrite(vfd: u64, off: i64, buf: &[u8]) -> Result<usize, LxError> {
 
 pub fn seek(vfd: u64, whence: Whence, off: i64) -> Result<i64, LxError> {
     with_client(
-        |client| match client.invoke(Request::VfdSeek(vfd, whence, off)).unwrap() {
+        |client| match client.invoke(Request::VfdSeek(vfd, whence, off)) {
             Response::Offset(n) => Ok(n),
             Response::Error(err) => Err(err),
             _ => ipc_fail(),
@@ -74,23 +159,25 @@ pub fn seek(vfd: u64, whence: Whence, off: i64) -> Result<i64, LxError> {
 }
 
 pub fn dup(vfd: u64) -> u64 {
-    with_client(
-        |client| match client.invoke(Request::VfdDup(vfd)).unwrap() {
-            Response::Vfd(x) => x,
-            _ => ipc_fail(),
-        },
-    )
+    with_client(|client| match client.invoke(Request::VfdDup(vfd)) {
+        Response::Vfd(x) => x,
+        _ => ipc_fail(),
+    })
 }
 
 pub fn ioctl(vfd: u64, cmd: IoctlCmd, arg: *mut u8) -> Result<c_int, LxError> {
-    let avail_ctrl =
-        with_client(
-            |client| match client.invoke(Request::VfdIoctlQuery(vfd, cmd)).unwrap() {
-                Response::VfdAvailCtrl(avail_ctrl) => Ok(avail_ctrl),
-                Response::Error(err) => Err(err),
-                _ => ipc_fail(),
-            },
-        )?;
+    let avail_ctrl = with_client(
+        |client| match client.invoke(Request::VfdIoctlQuery(vfd, cmd)) {
+            Response::VfdAvailCtrl(avail_ctrl) => Ok(avail_ctrl),
+            Response::Error(err) => Err(err),
+            _ => ipc_fail(),
+        },
+    );
+    let avail_ctrl = match avail_ctrl {
+        Ok(avail_ctrl) => avail_ctrl,
+        Err(LxError::ENOTTY) => return super::unknown_ioctl(IoctlClass::Vfd, cmd),
+        Err(err) => return Err(err),
+    };
 
     ctrl(vfd, cmd, arg as usize, avail_ctrl, Request::VfdIoctl)
 }
@@ -100,6 +187,7 @@ pub fn fcntl(vfd: u64, cmd: FcntlCmd, arg: usize) -> Result<c_int, LxError> {
 }
 
 pub fn truncate(vfd: u64, len: u64) -> Result<(), LxError> {
+    process::context().stat_cache.invalidate(vfd);
     call_server(Request::VfdTruncate(vfd, len))
 }
 
@@ -108,6 +196,7 @@ pub fn sync(vfd: u64) -> Result<(), LxError> {
 }
 
 pub fn close(vfd: u64) {
+    process::context().stat_cache.invalidate(vfd);
     call_server(Request::VfdClose(vfd))
 }
 
@@ -126,7 +215,7 @@ fn ctrl<C>(
             0 => Vec::new(),
             ..0 => arg.to_le_bytes().to_vec(),
         };
-        let response = client.invoke(act(vfd, cmd, in_param)).unwrap();
+        let response = client.invoke(act(vfd, cmd, in_param));
         match response {
             Response::CtrlOutput(out) => unsafe {
                 debug_assert_eq!(avail_ctrl.out_size, out.blob.len());
@@ -1 +1,879 @@
+//! Emulation of `io_uring`.
+//!
+//! A ring is backed by anonymous shared memory, which the program maps through the file descriptor returned by
+//! `io_uring_setup`. That descriptor refers to `/dev/null`, and is recognized by its number in this process. Both queue
+//! rings live in a single region, as advertised by `IORING_FEAT_SINGLE_MMAP`, and submission queue entries in another.
+//!
+//! Submissions are consumed by `io_uring_enter` only, since there is no kernel thread to poll the submission queue, and
+//! operations run on [`worker`] threads. Waiting for completions blocks on a futex, so that signals interrupt it with
+//! `EINTR`, like on Linux. Completions that do not fit in the completion queue are kept until it has
+//! room, like `IORING_FEAT_NODROP` promises.
+
 mod worker;
+
+use crate::{io, posix_num, sync::futex};
+use libc::c_int;
+use mach2::{
+    kern_return::KERN_SUCCESS,
+    traps::mach_task_self,
+    vm::mach_vm_remap,
+    vm_inherit::VM_INHERIT_SHARE,
+    vm_statistics::{VM_FLAGS_ANYWHERE, VM_FLAGS_FIXED, VM_FLAGS_OVERWRITE},
+    vm_types::mach_vm_address_t,
+};
+use rustc_hash::FxBuildHasher;
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+use structures::{
+    error::LxError,
+    io::{PollEvents, PollFd},
+    io_uring::{
+        IORING_FSYNC_DATASYNC, IORING_MAX_CQ_ENTRIES, IORING_MAX_ENTRIES, IORING_MAX_FIXED_FILES,
+        IORING_MAX_REG_BUFFERS, IORING_OFF_CQ_RING, IORING_OFF_SQ_RING, IORING_OFF_SQES,
+        IORING_SQ_CQ_OVERFLOW, IORING_SQ_NEED_WAKEUP, IoCqringOffsets, IoSqeFlags, IoSqringOffsets,
+        IoUringCqe, IoUringEnterFlags, IoUringFeatures, IoUringOp, IoUringParams,
+        IoUringRegisterOp, IoUringSetupFlags, IoUringSqe,
+    },
+    mm::MmapFlags,
+    sync::FutexOpts,
+};
+
+// Offsets of fields in the ring region. Completion queue entries follow the header, and the submission queue array
+// follows them.
+const SQ_HEAD: u32 = 0;
+const SQ_TAIL: u32 = 4;
+const CQ_HEAD: u32 = 8;
+const CQ_TAIL: u32 = 12;
+const SQ_RING_MASK: u32 = 16;
+const CQ_RING_MASK: u32 = 20;
+const SQ_RING_ENTRIES: u32 = 24;
+const CQ_RING_ENTRIES: u32 = 28;
+const SQ_DROPPED: u32 = 32;
+const SQ_FLAGS: u32 = 36;
+const CQ_FLAGS: u32 = 40;
+const CQ_OVERFLOW: u32 = 44;
+const CQES: u32 = 64;
+
+/// Maximum length of a registered buffer.
+const MAX_BUFFER_LEN: usize = 1 << 30;
+
+/// Rings of this process, keyed by file descriptors referring to them.
+static RINGS: LazyLock<papaya::HashMap<c_int, Arc<Ring>, FxBuildHasher>> =
+    LazyLock::new(|| papaya::HashMap::with_hasher(FxBuildHasher));
+
+/// An `io_uring` instance.
+#[derive(Debug)]
+pub struct Ring {
+    rings: Region,
+    sqes: Region,
+    flags: IoUringSetupFlags,
+    sq_entries: u32,
+    cq_entries: u32,
+
+    /// Offset of the submission queue array in the ring region.
+    sq_array: u32,
+
+    /// Serializes consumers of the submission queue.
+    submit_lock: Mutex<()>,
+
+    /// Completions waiting for room in the completion queue. This also serializes producers of the completion queue.
+    overflow: Mutex<VecDeque<IoUringCqe>>,
+
+    /// Incremented whenever a completion is posted. Waiters for completions wait on this as a private futex.
+    completions: AtomicU32,
+
+    /// Duplicates of registered files, with `-1` for sparse slots.
+    files: Mutex<Vec<c_int>>,
+
+    /// Addresses and lengths of registered buffers.
+    buffers: Mutex<Vec<(usize, usize)>>,
+}
+impl Ring {
+    /// Maps a region of the ring, selected by `offset` like on Linux.
+    pub unsafe fn map(
+        &self,
+        addr: *mut u8,
+        len: usize,
+        flags: MmapFlags,
+        offset: i64,
+    ) -> Result<*mut u8, LxError> {
+        let region = match offset {
+            IORING_OFF_SQ_RING | IORING_OFF_CQ_RING => &self.rings,
+            IORING_OFF_SQES => &self.sqes,
+            _ => return Err(LxError::EINVAL),
+        };
+        if len == 0 || len > region.len {
+            return Err(LxError::EINVAL);
+        }
+
+        let vm_flags = match flags.contains(MmapFlags::MAP_FIXED) {
+            true => VM_FLAGS_FIXED | VM_FLAGS_OVERWRITE,
+            false => VM_FLAGS_ANYWHERE,
+        };
+        let mut target = addr as mach_vm_address_t;
+        let (mut cur_prot, mut max_prot) = (0, 0);
+        let status = unsafe {
+            mach_vm_remap(
+                mach_task_self(),
+                &mut target,
+                len as _,
+                0,
+                vm_flags,
+                mach_task_self(),
+                region.addr as _,
+                0,
+                &mut cur_prot,
+                &mut max_prot,
+                VM_INHERIT_SHARE,
+            )
+        };
+        match status {
+            KERN_SUCCESS => Ok(target as _),
+            _ => Err(LxError::ENOMEM),
+        }
+    }
+
+    /// Consumes up to `max` entries of the submission queue, returning the number of consumed entries.
+    fn submit(self: &Arc<Self>, max: u32) -> u32 {
+        let _guard = self.submit_lock.lock().unwrap();
+        let head = self.rings.word(SQ_HEAD).load(Ordering::Relaxed);
+        let tail = self.rings.word(SQ_TAIL).load(Ordering::Acquire);
+        let count = tail.wrapping_sub(head).min(max);
+
+        let mut chain = Vec::new();
+        for n in 0..count {
+            let slot = head.wrapping_add(n) & (self.sq_entries - 1);
+            let index = self
+                .rings
+                .word(self.sq_array + slot * 4)
+                .load(Ordering::Relaxed);
+            if index >= self.sq_entries {
+                self.rings.word(SQ_DROPPED).fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            let sqe = unsafe {
+                self.sqes
+                    .addr
+                    .cast::<IoUringSqe>()
+                    .add(index as usize)
+                    .read_volatile()
+            };
+            let linked = sqe
+                .flags
+                .intersects(IoSqeFlags::IOSQE_IO_LINK | IoSqeFlags::IOSQE_IO_HARDLINK);
+            chain.push(sqe);
+            if !linked {
+                self.spawn(std::mem::take(&mut chain));
+            }
+        }
+        // Like on Linux, a chain that is not terminated in this submission ends at its last entry.
+        if !chain.is_empty() {
+            self.spawn(chain);
+        }
+
+        self.rings
+            .word(SQ_HEAD)
+            .store(head.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// Runs entries of a chain one after another on a worker thread.
+    fn spawn(self: &Arc<Self>, chain: Vec<IoUringSqe>) {
+        let ring = self.clone();
+        worker::spawn(move || {
+            let mut cancelled = false;
+            for sqe in chain {
+                let res = match cancelled {
+                    true => -(LxError::ECANCELED.bits() as i32),
+                    false => ring.execute(&sqe),
+                };
+                // A failure breaks the chain, unless it is linked by `IOSQE_IO_HARDLINK`.
+                if res < 0 && !sqe.flags.contains(IoSqeFlags::IOSQE_IO_HARDLINK) {
+                    cancelled = true;
+                }
+                if res >= 0 && sqe.flags.contains(IoSqeFlags::IOSQE_CQE_SKIP_SUCCESS) {
+                    continue;
+                }
+                ring.complete(IoUringCqe {
+                    user_data: sqe.user_data,
+                    res,
+                    flags: 0,
+                });
+            }
+        });
+    }
+
+    /// Runs an operation, returning the result to post in its completion.
+    fn execute(&self, sqe: &IoUringSqe) -> i32 {
+        match self.run(sqe) {
+            Ok(n) => n.min(i32::MAX as usize) as i32,
+            Err(err) => -(err.bits() as i32),
+        }
+    }
+
+    fn run(&self, sqe: &IoUringSqe) -> Result<usize, LxError> {
+        let supported = IoSqeFlags::IOSQE_FIXED_FILE
+            | IoSqeFlags::IOSQE_IO_LINK
+            | IoSqeFlags::IOSQE_IO_HARDLINK
+            | IoSqeFlags::IOSQE_ASYNC
+            | IoSqeFlags::IOSQE_CQE_SKIP_SUCCESS;
+        if !supported.contains(sqe.flags) {
+            return Err(LxError::EINVAL);
+        }
+        let fd = match sqe.flags.contains(IoSqeFlags::IOSQE_FIXED_FILE) {
+            true => self.fixed_file(sqe.fd)?,
+            false => sqe.fd,
+        };
+
+        match sqe.opcode {
+            IoUringOp::IORING_OP_NOP => Ok(0),
+            IoUringOp::IORING_OP_READ => unsafe {
+                let buf = user_buf(sqe.addr, sqe.len);
+                match sqe.off {
+                    u64::MAX => io::read(fd, buf),
+                    off => io::pread64(fd, buf, off as i64),
+                }
+            },
+            IoUringOp::IORING_OP_WRITE => unsafe {
+                let buf = user_buf(sqe.addr, sqe.len);
+                match sqe.off {
+                    u64::MAX => io::write(fd, buf),
+                    off => io::pwrite64(fd, buf, off as i64),
+                }
+            },
+            IoUringOp::IORING_OP_READ_FIXED => unsafe {
+                let buf = self.fixed_buf(sqe)?;
+                match sqe.off {
+                    u64::MAX => io::read(fd, buf),
+                    off => io::pread64(fd, buf, off as i64),
+                }
+            },
+            IoUringOp::IORING_OP_WRITE_FIXED => unsafe {
+                let buf = self.fixed_buf(sqe)?;
+                match sqe.off {
+                    u64::MAX => io::write(fd, buf),
+                    off => io::pwrite64(fd, buf, off as i64),
+                }
+            },
+            IoUringOp::IORING_OP_FSYNC => match sqe.op_flags {
+                0 => io::fsync(fd).map(|()| 0),
+                IORING_FSYNC_DATASYNC => io::fdatasync(fd).map(|()| 0),
+                _ => Err(LxError::EINVAL),
+            },
+            IoUringOp::IORING_OP_POLL_ADD => unsafe {
+                let mut fds = [PollFd {
+                    fd,
+                    events: PollEvents::from_bits_retain(sqe.op_flags as u16),
+                    revents: PollEvents::empty(),
+                }];
+                io::poll(&mut fds, None).map(|_| fds[0].revents.bits() as usize)
+            },
+            _ => Err(LxError::EINVAL),
+        }
+    }
+
+    /// Returns the file descriptor registered at `index`.
+    fn fixed_file(&self, index: c_int) -> Result<c_int, LxError> {
+        let files = self.files.lock().unwrap();
+        match usize::try_from(index).ok().and_then(|x| files.get(x)) {
+            Some(&fd) if fd >= 0 => Ok(fd),
+            _ => Err(LxError::EBADF),
+        }
+    }
+
+    /// Returns the part of the buffer registered at `buf_index` that a fixed read or write refers to.
+    unsafe fn fixed_buf<'a>(&self, sqe: &IoUringSqe) -> Result<&'a mut [u8], LxError> {
+        let buffers = self.buffers.lock().unwrap();
+        let &(base, len) = buffers.get(sqe.buf_index as usize).ok_or(LxError::EINVAL)?;
+        let start = sqe.addr as usize;
+        let end = start.checked_add(sqe.len as usize).ok_or(LxError::EFAULT)?;
+        if start < base || end > base + len {
+            return Err(LxError::EFAULT);
+        }
+        unsafe { Ok(user_buf(sqe.addr, sqe.len)) }
+    }
+
+    /// Registers resources with the ring, like `io_uring_register`.
+    unsafe fn register(
+        &self,
+        op: IoUringRegisterOp,
+        arg: *const u8,
+        nr_args: u32,
+    ) -> Result<(), LxError> {
+        match op {
+            IoUringRegisterOp::IORING_REGISTER_BUFFERS => {
+                if nr_args == 0 || nr_args > IORING_MAX_REG_BUFFERS {
+                    return Err(LxError::EINVAL);
+                }
+                let iovecs =
+                    unsafe { std::slice::from_raw_parts(arg.cast::<libc::iovec>(), nr_args as _) };
+                let mut buffers = self.buffers.lock().unwrap();
+                if !buffers.is_empty() {
+                    return Err(LxError::EBUSY);
+                }
+                let mut registered = Vec::with_capacity(iovecs.len());
+                for iovec in iovecs {
+                    if iovec.iov_base.is_null()
+                        || iovec.iov_len == 0
+                        || iovec.iov_len > MAX_BUFFER_LEN
+                    {
+                        return Err(LxError::EINVAL);
+                    }
+                    registered.push((iovec.iov_base as usize, iovec.iov_len));
+                }
+                *buffers = registered;
+                Ok(())
+            }
+            IoUringRegisterOp::IORING_UNREGISTER_BUFFERS => {
+                let mut buffers = self.buffers.lock().unwrap();
+                if buffers.is_empty() {
+                    return Err(LxError::ENXIO);
+                }
+                buffers.clear();
+                Ok(())
+            }
+            IoUringRegisterOp::IORING_REGISTER_FILES => {
+                if nr_args == 0 || nr_args > IORING_MAX_FIXED_FILES {
+                    return Err(LxError::EINVAL);
+                }
+                let fds = unsafe { std::slice::from_raw_parts(arg.cast::<c_int>(), nr_args as _) };
+                let mut files = self.files.lock().unwrap();
+                if !files.is_empty() {
+                    return Err(LxError::EBUSY);
+                }
+                // Like Linux, the ring holds its own references, so registered files outlive their descriptors.
+                let mut registered = Vec::with_capacity(fds.len());
+                for &fd in fds {
+                    let result = match fd {
+                        -1 => Ok(-1),
+                        fd if fd < 0 || get(fd).is_some() => Err(LxError::EBADF),
+                        fd => io::dup(fd).and_then(|x| io::set_cloexec(x).map(|()| x)),
+                    };
+                    match result {
+                        Ok(fd) => registered.push(fd),
+                        Err(err) => {
+                            close_files(&registered);
+                            return Err(err);
+                        }
+                    }
+                }
+                *files = registered;
+                Ok(())
+            }
+            IoUringRegisterOp::IORING_UNREGISTER_FILES => {
+                let mut files = self.files.lock().unwrap();
+                if files.is_empty() {
+                    return Err(LxError::ENXIO);
+                }
+                close_files(&std::mem::take(&mut *files));
+                Ok(())
+            }
+            _ => Err(LxError::EINVAL),
+        }
+    }
+
+    /// Posts a completion, keeping it aside if the completion queue is full.
+    fn complete(&self, cqe: IoUringCqe) {
+        let mut overflow = self.overflow.lock().unwrap();
+        overflow.push_back(cqe);
+        self.flush(&mut overflow);
+        drop(overflow);
+        self.completions.fetch_add(1, Ordering::SeqCst);
+        unsafe {
+            _ = futex::wake(
+                self.completions.as_ptr(),
+                u32::MAX,
+                FutexOpts::FUTEX_PRIVATE_FLAG,
+            );
+        }
+    }
+
+    /// Moves completions kept aside to the completion queue, as far as it has room.
+    fn flush(&self, overflow: &mut VecDeque<IoUringCqe>) {
+        let head = self.rings.word(CQ_HEAD).load(Ordering::Acquire);
+        let mut tail = self.rings.word(CQ_TAIL).load(Ordering::Relaxed);
+        while tail.wrapping_sub(head) < self.cq_entries
+            && let Some(cqe) = overflow.pop_front()
+        {
+            let slot = tail & (self.cq_entries - 1);
+            unsafe {
+                self.rings
+                    .addr
+                    .add(CQES as usize)
+                    .cast::<IoUringCqe>()
+                    .add(slot as usize)
+                    .write_volatile(cqe);
+            }
+            tail = tail.wrapping_add(1);
+        }
+        self.rings.word(CQ_TAIL).store(tail, Ordering::Release);
+
+        let sq_flags = self.rings.word(SQ_FLAGS);
+        match overflow.is_empty() {
+            true => sq_flags.fetch_and(!IORING_SQ_CQ_OVERFLOW, Ordering::Release),
+            false => sq_flags.fetch_or(IORING_SQ_CQ_OVERFLOW, Ordering::Release),
+        };
+    }
+
+    /// Blocks until the completion queue holds at least `count` completions.
+    ///
+    /// This fails with `EINTR` if a signal arrives while waiting.
+    fn wait(&self, count: u32) -> Result<(), LxError> {
+        let count = count.min(self.cq_entries);
+        loop {
+            // Reading the counter first makes sure that completions posted after the check below wake us up.
+            let completions = self.completions.load(Ordering::SeqCst);
+            {
+                let mut overflow = self.overflow.lock().unwrap();
+                self.flush(&mut overflow);
+            }
+            let head = self.rings.word(CQ_HEAD).load(Ordering::Acquire);
+            let tail = self.rings.word(CQ_TAIL).load(Ordering::Relaxed);
+            if tail.wrapping_sub(head) >= count {
+                return Ok(());
+            }
+            let result = unsafe {
+                futex::wait(
+                    self.completions.as_ptr(),
+                    completions,
+                    std::ptr::null_mut(),
+                    FutexOpts::FUTEX_PRIVATE_FLAG,
+                )
+            };
+            match result {
+                Ok(()) | Err(LxError::EAGAIN) => (),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        close_files(self.files.get_mut().unwrap());
+    }
+}
+
+/// Anonymous shared memory backing a ring.
+#[derive(Debug)]
+struct Region {
+    addr: *mut u8,
+    len: usize,
+}
+impl Region {
+    fn new(len: usize) -> Result<Self, LxError> {
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANON,
+                -1,
+                0,
+            )
+        };
+        match addr {
+            libc::MAP_FAILED => Err(LxError::ENOMEM),
+            addr => Ok(Self {
+                addr: addr.cast(),
+                len,
+            }),
+        }
+    }
+
+    fn word(&self, offset: u32) -> &AtomicU32 {
+        unsafe { &*self.addr.add(offset as usize).cast() }
+    }
+}
+impl Drop for Region {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.addr.cast(), self.len);
+        }
+    }
+}
+unsafe impl Send for Region {}
+unsafe impl Sync for Region {}
+
+/// Creates a ring, like `io_uring_setup`, returning a file descriptor referring to it.
+pub fn setup(entries: u32, params: &mut IoUringParams) -> Result<c_int, LxError> {
+    let supported = IoUringSetupFlags::IORING_SETUP_SQPOLL
+        | IoUringSetupFlags::IORING_SETUP_SQ_AFF
+        | IoUringSetupFlags::IORING_SETUP_CQSIZE
+        | IoUringSetupFlags::IORING_SETUP_CLAMP;
+    if !supported.contains(params.flags) || params.resv != [0; 3] {
+        return Err(LxError::EINVAL);
+    }
+
+    let clamp = params.flags.contains(IoUringSetupFlags::IORING_SETUP_CLAMP);
+    let sq_entries = ring_size(entries, IORING_MAX_ENTRIES, clamp)?;
+    let cq_entries = match params
+        .flags
+        .contains(IoUringSetupFlags::IORING_SETUP_CQSIZE)
+    {
+        true => ring_size(params.cq_entries, IORING_MAX_CQ_ENTRIES, clamp)?,
+        false => 2 * sq_entries,
+    };
+    if cq_entries < sq_entries {
+        return Err(LxError::EINVAL);
+    }
+
+    let sq_array = CQES + cq_entries * size_of::<IoUringCqe>() as u32;
+    let rings = Region::new(sq_array as usize + sq_entries as usize * 4)?;
+    let sqes = Region::new(sq_entries as usize * size_of::<IoUringSqe>())?;
+    rings
+        .word(SQ_RING_MASK)
+        .store(sq_entries - 1, Ordering::Relaxed);
+    rings
+        .word(CQ_RING_MASK)
+        .store(cq_entries - 1, Ordering::Relaxed);
+    rings
+        .word(SQ_RING_ENTRIES)
+        .store(sq_entries, Ordering::Relaxed);
+    rings
+        .word(CQ_RING_ENTRIES)
+        .store(cq_entries, Ordering::Relaxed);
+
+    // Nothing polls the submission queue, so programs asking for `IORING_SETUP_SQPOLL` are told to always wake it up.
+    if params
+        .flags
+        .contains(IoUringSetupFlags::IORING_SETUP_SQPOLL)
+    {
+        rings
+            .word(SQ_FLAGS)
+            .store(IORING_SQ_NEED_WAKEUP, Ordering::Relaxed);
+    }
+
+    let fd = unsafe {
+        posix_num!(libc::open(
+            c"/dev/null".as_ptr(),
+            libc::O_RDWR | libc::O_CLOEXEC
+        ))?
+    };
+    RINGS.pin().insert(
+        fd,
+        Arc::new(Ring {
+            rings,
+            sqes,
+            flags: params.flags,
+            sq_entries,
+            cq_entries,
+            sq_array,
+            submit_lock: Mutex::new(()),
+            overflow: Mutex::new(VecDeque::new()),
+            completions: AtomicU32::new(0),
+            files: Mutex::new(Vec::new()),
+            buffers: Mutex::new(Vec::new()),
+        }),
+    );
+
+    params.sq_entries = sq_entries;
+    params.cq_entries = cq_entries;
+    params.features = IoUringFeatures::IORING_FEAT_SINGLE_MMAP
+        | IoUringFeatures::IORING_FEAT_NODROP
+        | IoUringFeatures::IORING_FEAT_SUBMIT_STABLE
+        | IoUringFeatures::IORING_FEAT_RW_CUR_POS;
+    params.sq_off = IoSqringOffsets {
+        head: SQ_HEAD,
+        tail: SQ_TAIL,
+        ring_mask: SQ_RING_MASK,
+        ring_entries: SQ_RING_ENTRIES,
+        flags: SQ_FLAGS,
+        dropped: SQ_DROPPED,
+        array: sq_array,
+        ..Default::default()
+    };
+    params.cq_off = IoCqringOffsets {
+        head: CQ_HEAD,
+        tail: CQ_TAIL,
+        ring_mask: CQ_RING_MASK,
+        ring_entries: CQ_RING_ENTRIES,
+        overflow: CQ_OVERFLOW,
+        cqes: CQES,
+        flags: CQ_FLAGS,
+        ..Default::default()
+    };
+    Ok(fd)
+}
+
+/// Submits and waits for operations, like `io_uring_enter`, returning the number of consumed submissions.
+pub fn enter(
+    fd: c_int,
+    to_submit: u32,
+    min_complete: u32,
+    flags: IoUringEnterFlags,
+) -> Result<u32, LxError> {
+    let ring = get(fd).ok_or(LxError::EOPNOTSUPP)?;
+    let supported = IoUringEnterFlags::IORING_ENTER_GETEVENTS
+        | IoUringEnterFlags::IORING_ENTER_SQ_WAKEUP
+        | IoUringEnterFlags::IORING_ENTER_SQ_WAIT;
+    if !supported.contains(flags) {
+        return Err(LxError::EINVAL);
+    }
+
+    // With `IORING_SETUP_SQPOLL`, the submission queue is drained as a polling thread would do, and Linux reports
+    // `to_submit` as is.
+    let submitted = match ring.flags.contains(IoUringSetupFlags::IORING_SETUP_SQPOLL) {
+        true => {
+            ring.submit(u32::MAX);
+            to_submit
+        }
+        false => ring.submit(to_submit),
+    };
+    // Like on Linux, an interrupted wait is only reported if nothing was submitted.
+    if flags.contains(IoUringEnterFlags::IORING_ENTER_GETEVENTS)
+        && let Err(err) = ring.wait(min_complete)
+        && submitted == 0
+    {
+        return Err(err);
+    }
+    Ok(submitted)
+}
+
+/// Registers resources with the ring `fd` refers to, like `io_uring_register`.
+pub unsafe fn register(
+    fd: c_int,
+    op: IoUringRegisterOp,
+    arg: *const u8,
+    nr_args: u32,
+) -> Result<(), LxError> {
+    let ring = get(fd).ok_or(LxError::EOPNOTSUPP)?;
+    unsafe { ring.register(op, arg, nr_args) }
+}
+
+/// Returns the ring the file descriptor `fd` refers to, if any.
+pub fn get(fd: c_int) -> Option<Arc<Ring>> {
+    RINGS.pin().get(&fd).cloned()
+}
+
+/// Forgets the ring `fd` refers to, as the file descriptor is being closed or replaced.
+pub fn forget(fd: c_int) {
+    RINGS.pin().remove(&fd);
+}
+
+/// Makes `new` refer to the ring `old` refers to, if any, as the file descriptor is being duplicated.
+pub fn dup(old: c_int, new: c_int) {
+    let rings = RINGS.pin();
+    match rings.get(&old).cloned() {
+        Some(ring) => _ = rings.insert(new, ring),
+        None => _ = rings.remove(&new),
+    }
+}
+
+/// Forgets worker threads of the parent process in a forked child.
+pub fn after_fork() {
+    worker::after_fork();
+}
+
+/// Rounds a requested number of entries up to a power of two.
+fn ring_size(entries: u32, max: u32, clamp: bool) -> Result<u32, LxError> {
+    match entries {
+        0 => Err(LxError::EINVAL),
+        n if n > max && !clamp => Err(LxError::EINVAL),
+        n => Ok(n.min(max).next_power_of_two()),
+    }
+}
+
+fn close_files(files: &[c_int]) {
+    for &fd in files.iter().filter(|&&x| x >= 0) {
+        _ = io::close(fd);
+    }
+}
+
+unsafe fn user_buf<'a>(addr: u64, len: u32) -> &'a mut [u8] {
+    match len {
+        0 => &mut [],
+        len => unsafe { std::slice::from_raw_parts_mut(addr as *mut u8, len as usize) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(entries: u32) -> (c_int, Arc<Ring>) {
+        crate::init_for_tests();
+        let mut params: IoUringParams = unsafe { std::mem::zeroed() };
+        let fd = setup(entries, &mut params).unwrap();
+        (fd, get(fd).unwrap())
+    }
+
+    fn sqe(opcode: IoUringOp, fd: c_int, buf: &mut [u8], user_data: u64) -> IoUringSqe {
+        IoUringSqe {
+            opcode,
+            fd,
+            off: u64::MAX,
+            addr: buf.as_mut_ptr() as u64,
+            len: buf.len() as u32,
+            user_data,
+            ..unsafe { std::mem::zeroed() }
+        }
+    }
+
+    /// Places `sqe` in the submission queue, like a program does.
+    fn push(ring: &Ring, sqe: IoUringSqe) {
+        let tail = ring.rings.word(SQ_TAIL).load(Ordering::Relaxed);
+        let index = tail & (ring.sq_entries - 1);
+        unsafe {
+            ring.sqes
+                .addr
+                .cast::<IoUringSqe>()
+                .add(index as usize)
+                .write(sqe)
+        };
+        ring.rings
+            .word(ring.sq_array + index * 4)
+            .store(index, Ordering::Relaxed);
+        ring.rings
+            .word(SQ_TAIL)
+            .store(tail.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Takes all completions from the completion queue, like a program does.
+    fn reap(ring: &Ring) -> Vec<IoUringCqe> {
+        let mut head = ring.rings.word(CQ_HEAD).load(Ordering::Relaxed);
+        let tail = ring.rings.word(CQ_TAIL).load(Ordering::Acquire);
+        let mut cqes = Vec::new();
+        while head != tail {
+            let slot = head & (ring.cq_entries - 1);
+            cqes.push(unsafe {
+                ring.rings
+                    .addr
+                    .add(CQES as usize)
+                    .cast::<IoUringCqe>()
+                    .add(slot as usize)
+                    .read()
+            });
+            head = head.wrapping_add(1);
+        }
+        ring.rings.word(CQ_HEAD).store(head, Ordering::Release);
+        cqes
+    }
+
+    fn pipe() -> [c_int; 2] {
+        let mut fds = [-1; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        fds
+    }
+
+    #[test]
+    fn pipe_read_completes_after_write() {
+        let (fd, ring) = ring(4);
+        let [rx, tx] = pipe();
+        let mut buf = [0; 16];
+        push(&ring, sqe(IoUringOp::IORING_OP_READ, rx, &mut buf, 7));
+        assert_eq!(enter(fd, 1, 0, IoUringEnterFlags::empty()), Ok(1));
+
+        unsafe { libc::write(tx, b"hello".as_ptr().cast(), 5) };
+        assert_eq!(
+            enter(fd, 0, 1, IoUringEnterFlags::IORING_ENTER_GETEVENTS),
+            Ok(0)
+        );
+        let cqes = reap(&ring);
+        assert_eq!(cqes.len(), 1);
+        assert_eq!((cqes[0].user_data, cqes[0].res), (7, 5));
+        assert_eq!(&buf[..5], b"hello");
+
+        forget(fd);
+        close_files(&[fd, rx, tx]);
+    }
+
+    #[test]
+    fn blocked_operations_do_not_starve_later_ones() {
+        // More reads than the workers that are kept, each of which blocks until the write at the end runs.
+        let reads = 32;
+        let (fd, ring) = ring(64);
+        let [rx, tx] = pipe();
+        let mut bufs = vec![[0u8; 1]; reads];
+        for (n, buf) in bufs.iter_mut().enumerate() {
+            push(&ring, sqe(IoUringOp::IORING_OP_READ, rx, buf, n as u64));
+        }
+        let mut data = vec![b'x'; reads];
+        push(
+            &ring,
+            sqe(IoUringOp::IORING_OP_WRITE, tx, &mut data, u64::MAX),
+        );
+
+        let count = reads as u32 + 1;
+        assert_eq!(
+            enter(fd, count, count, IoUringEnterFlags::IORING_ENTER_GETEVENTS),
+            Ok(count)
+        );
+        let cqes = reap(&ring);
+        assert_eq!(cqes.len(), count as usize);
+        for cqe in cqes {
+            let expected = match cqe.user_data {
+                u64::MAX => reads as i32,
+                _ => 1,
+            };
+            assert_eq!(cqe.res, expected);
+        }
+
+        forget(fd);
+        close_files(&[fd, rx, tx]);
+    }
+
+    #[test]
+    fn fixed_write_then_fixed_read() {
+        let (fd, ring) = ring(4);
+        let [rx, tx] = pipe();
+        let mut output = *b"fixed data";
+        let mut input = [0; 10];
+        let iovecs = [&mut output[..], &mut input[..]].map(|x| libc::iovec {
+            iov_base: x.as_mut_ptr().cast(),
+            iov_len: x.len(),
+        });
+        let files = [rx, -1, tx];
+        unsafe {
+            ring.register(
+                IoUringRegisterOp::IORING_REGISTER_BUFFERS,
+                iovecs.as_ptr().cast(),
+                2,
+            )
+            .unwrap();
+            ring.register(
+                IoUringRegisterOp::IORING_REGISTER_FILES,
+                files.as_ptr().cast(),
+                3,
+            )
+            .unwrap();
+        }
+
+        let fixed = |opcode, file, buf: &mut [u8], buf_index| IoUringSqe {
+            flags: IoSqeFlags::IOSQE_FIXED_FILE,
+            buf_index,
+            ..sqe(opcode, file, buf, 0)
+        };
+        let write = fixed(IoUringOp::IORING_OP_WRITE_FIXED, 2, &mut output, 0);
+        assert_eq!(ring.run(&write), Ok(10));
+        let read = fixed(IoUringOp::IORING_OP_READ_FIXED, 0, &mut input, 1);
+        assert_eq!(ring.run(&read), Ok(10));
+        assert_eq!(&input, b"fixed data");
+
+        // Buffers are checked against the registered ones, and files against the registered table.
+        let unregistered = IoUringSqe {
+            buf_index: 2,
+            ..read
+        };
+        assert_eq!(ring.run(&unregistered), Err(LxError::EINVAL));
+        let outside = IoUringSqe {
+            buf_index: 0,
+            ..read
+        };
+        assert_eq!(ring.run(&outside), Err(LxError::EFAULT));
+        let overlong = IoUringSqe { len: 11, ..read };
+        assert_eq!(ring.run(&overlong), Err(LxError::EFAULT));
+        for file in [1, 3, -2] {
+            let sqe = IoUringSqe { fd: file, ..read };
+            assert_eq!(ring.run(&sqe), Err(LxError::EBADF));
+        }
+
+        forget(fd);
+        close_files(&[fd, rx, tx]);
+    }
+}
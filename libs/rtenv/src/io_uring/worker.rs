@@ -1 +1,106 @@
-//! Implementation of the `io_uring` worker thread.
+//! Implementation of the `io_uring` worker threads.
+//!
+//! Operations are queued to a pool of threads shared by all rings of the process. Workers are spawned on demand while
+//! none of them is idle, so operations that block, like reads of pipes, never keep later ones from running. Up to
+//! [`MAX_WORKERS`] workers are kept; the ones beyond that exit once they have been idle for [`IDLE_TIMEOUT`]. Workers
+//! never run Linux code, so they block all signals, and are not registered as Linux threads.
+
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender};
+use std::{
+    sync::{
+        Mutex,
+        atomic::{self, AtomicUsize},
+    },
+    time::Duration,
+};
+
+/// Number of worker threads that are kept while idle.
+const MAX_WORKERS: usize = 16;
+
+/// How long workers beyond [`MAX_WORKERS`] wait for jobs before exiting.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The worker pool, created on first use.
+static POOL: Mutex<Option<Pool>> = Mutex::new(None);
+
+/// Number of workers waiting for jobs.
+static IDLE: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+struct Pool {
+    sender: Sender<Job>,
+    receiver: Receiver<Job>,
+    workers: usize,
+}
+
+/// Runs `job` on a worker thread.
+pub fn spawn(job: impl FnOnce() + Send + 'static) {
+    let mut pool = POOL.lock().unwrap();
+    let pool = pool.get_or_insert_with(|| {
+        let (sender, receiver) = crossbeam::channel::unbounded();
+        Pool {
+            sender,
+            receiver,
+            workers: 0,
+        }
+    });
+    pool.sender
+        .send(Box::new(job))
+        .expect("the pool holds a receiver");
+    if IDLE.load(atomic::Ordering::SeqCst) == 0 {
+        let receiver = pool.receiver.clone();
+        let status = std::thread::Builder::new()
+            .name("io_uring worker".into())
+            .spawn(move || run(receiver));
+        match status {
+            Ok(_) => pool.workers += 1,
+            Err(err) => log::warn!("failed to spawn io_uring worker: {err}"),
+        }
+    }
+}
+
+/// Forgets workers of the parent process, whose threads do not exist in a forked child.
+pub fn after_fork() {
+    *POOL.lock().unwrap() = None;
+    IDLE.store(0, atomic::Ordering::SeqCst);
+}
+
+fn run(receiver: Receiver<Job>) {
+    if let Err(err) = unsafe { crate::thread::enter_worker() } {
+        log::warn!("failed to initialize io_uring worker: {err}");
+        return;
+    }
+    loop {
+        IDLE.fetch_add(1, atomic::Ordering::SeqCst);
+        let job = match receiver.recv_timeout(IDLE_TIMEOUT) {
+            Ok(job) => Some(job),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let job = match job {
+            Some(job) => job,
+            None => {
+                // Jobs are queued with the pool locked, so one that was queued while we were timing out is seen here.
+                let mut pool = POOL.lock().unwrap();
+                match receiver.try_recv() {
+                    Ok(job) => job,
+                    Err(_) => match pool.as_mut() {
+                        Some(pool) if pool.workers > MAX_WORKERS => {
+                            pool.workers -= 1;
+                            IDLE.fetch_sub(1, atomic::Ordering::SeqCst);
+                            break;
+                        }
+                        _ => {
+                            IDLE.fetch_sub(1, atomic::Ordering::SeqCst);
+                            continue;
+                        }
+                    },
+                }
+            }
+        };
+        IDLE.fetch_sub(1, atomic::Ordering::SeqCst);
+        job();
+    }
+}
@@ -1,11 +1,11 @@
 //! Client implementation of the MacTux IPC protocol.
 
 use crate::{
-    posix_num, process, thread,
+    posix_num, process, switches, thread,
     util::{ipc_fail, posix_result},
 };
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     io::{Read, Write},
     os::{
         fd::{AsRawFd, FromRawFd},
@@ -17,33 +17,42 @@ use std::{
 use structures::{
     error::LxError,
     fs::{Dirent64, StatFs, Statx},
-    internal::mactux_ipc::*,
+    internal::{mactux_ipc::*, shared_buffer::SharedBuffer},
     misc::SysInfo,
 };
 
 pub fn call_server<T: FromResponse>(req: Request) -> T {
-    with_client(
-        |client| match T::from_response(client.invoke(req).unwrap()) {
-            Some(x) => x,
-            None => ipc_fail(),
-        },
-    )
+    with_client(|client| match T::from_response(client.invoke(req)) {
+        Some(x) => x,
+        None => ipc_fail(),
+    })
 }
 
 /// A MacTux IPC client.
 #[derive(Debug)]
-pub struct Client(UnixStream);
+pub struct Client {
+    stream: UnixStream,
+
+    /// Capabilities enabled for this connection at handshake.
+    capabilities: Cell<Capabilities>,
+
+    /// The shared buffer of this connection, or `None` if the server could not map one.
+    shared: Option<SharedBuffer>,
+
+    /// Whether the connection has been reestablished, in which case the server no longer maps [`Self::shared`].
+    reconnected: Cell<bool>,
+}
 impl Client {
     /// Enables close-on-exec for this client.
     pub fn enable_cloexec(&self) -> Result<(), LxError> {
-        let fd = self.0.as_raw_fd();
+        let fd = self.stream.as_raw_fd();
         crate::io::set_cloexec(fd)?;
         Ok(())
     }
 
     /// Disables close-on-exec for this client.
     pub fn disable_cloexec(&self) -> Result<(), LxError> {
-        let fd = self.0.as_raw_fd();
+        let fd = self.stream.as_raw_fd();
         let original: i32 = unsafe { posix_num!(libc::fcntl(fd, libc::F_GETFD)) }?;
         unsafe {
             posix_result(libc::fcntl(
@@ -55,26 +64,112 @@ impl Client {
         Ok(())
     }
 
-    /// Forces a handshake message.
-    pub fn force_handshake(&self) {
+    /// Forces a handshake message, making the process fail with a readable message if the server is incompatible.
+    /// Failures of the connection itself are returned.
+    pub fn force_handshake(&self) -> std::io::Result<()> {
         let mut buf = postcard::to_stdvec(&HandshakeRequest::new())
             .expect("all handshake requests should be valid postcard");
-        self.send(&buf).unwrap();
-        self.recv(&mut buf).unwrap();
-        let response: HandshakeResponse = postcard::from_bytes(&buf).expect("forced handshake");
-        if response != HandshakeResponse::new() {
-            panic!(
-                "Server version `{}` does not match client version `{}`",
-                response.version,
-                HandshakeResponse::new().version
-            );
+        self.send(&buf)?;
+        self.recv(&mut buf)?;
+        let result = postcard::from_bytes::<HandshakeResponse>(&buf)
+            .map_err(|_| HandshakeError::BadMagic)
+            .and_then(|resp| resp.check());
+        match result {
+            Ok(capabilities) => self.capabilities.set(capabilities),
+            Err(err) => {
+                eprintln!("mactux: incompatible MacTux server: {err}");
+                crate::error_report::fast_fail();
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the capabilities enabled for this connection at handshake.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities.get()
+    }
+
+    /// Applies [`switches::ipc_timeout`] to this connection.
+    fn apply_timeout(&self) {
+        let timeout = switches::ipc_timeout();
+        _ = self.stream.set_read_timeout(timeout);
+        _ = self.stream.set_write_timeout(timeout);
+    }
+
+    /// Replaces the connection with a new one to the server, which takes over its file descriptor. Returns `false` if
+    /// the server cannot be reached.
+    ///
+    /// The new connection is reattached to the thread of this client before the old one is closed, since closing the
+    /// last connection of a thread makes the server release it, along with the PID of the process if it is the main
+    /// thread.
+    fn reconnect(&self) -> bool {
+        let Ok(stream) = UnixStream::connect(&**process::context().server_sock_path.load()) else {
+            return false;
+        };
+        let new = Client {
+            stream,
+            capabilities: Cell::new(Capabilities::empty()),
+            shared: None,
+            reconnected: Cell::new(false),
+        };
+        new.apply_timeout();
+        if new.force_handshake().is_err() {
+            return false;
+        }
+        // `invoke` cannot be used here, since it is `invoke` that reconnects, holding the IPC buffer of the thread.
+        let mut buf = postcard::to_stdvec(&Request::Reattach(thread::id()))
+            .expect("all requests should be valid postcard");
+        if new.send(&buf).is_err() || new.recv(&mut buf).is_err() {
+            return false;
+        }
+        if !matches!(postcard::from_bytes(&buf), Ok(Response::Nothing)) {
+            return false;
+        }
+
+        let fd = self.stream.as_raw_fd();
+        unsafe {
+            let cloexec = libc::fcntl(fd, libc::F_GETFD) & libc::FD_CLOEXEC;
+            if libc::dup2(new.stream.as_raw_fd(), fd) == -1 {
+                return false;
+            }
+            libc::fcntl(fd, libc::F_SETFD, cloexec);
         }
+        self.capabilities.set(new.capabilities());
+        self.reconnected.set(true);
+        true
+    }
+
+    /// Sets up a shared buffer for this connection, or leaves the connection without one if that fails, in which case
+    /// large payloads are transferred inline.
+    pub fn negotiate_shared_buffer(&mut self) {
+        if !self.capabilities().contains(Capabilities::SHARED_BUFFER) {
+            return;
+        }
+        let (buffer, name) = match SharedBuffer::create() {
+            Ok(x) => x,
+            Err(err) => {
+                log::warn!("failed to create shared buffer: {err}");
+                return;
+            }
+        };
+        let resp = self.invoke(Request::MapSharedBuffer(name.clone(), buffer.len()));
+        SharedBuffer::unlink(&name);
+        match resp {
+            Response::Nothing => self.shared = Some(buffer),
+            Response::Error(err) => log::warn!("failed to map shared buffer: {err}"),
+            _ => ipc_fail(),
+        }
+    }
+
+    /// Returns the shared buffer of this connection, if any.
+    pub fn shared_buffer(&self) -> Option<&SharedBuffer> {
+        self.shared.as_ref().filter(|_| !self.reconnected.get())
     }
 
     /// Sends a message.
     pub fn send(&self, buf: &[u8]) -> std::io::Result<()> {
-        (&self.0).write_all(&(buf.len() as u64).to_le_bytes())?;
-        (&self.0).write_all(buf)?;
+        (&self.stream).write_all(&(buf.len() as u64).to_le_bytes())?;
+        (&self.stream).write_all(buf)?;
 
         Ok(())
     }
@@ -82,37 +177,59 @@ impl Client {
     /// Receives a message.
     pub fn recv(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
         let mut len = [0u8; size_of::<u64>()];
-        (&self.0).read_exact(&mut len)?;
+        (&self.stream).read_exact(&mut len)?;
         let len = u64::from_le_bytes(len);
         buf.clear();
         buf.resize(len as usize, 0);
-        (&self.0).read_exact(buf)?;
+        (&self.stream).read_exact(buf)?;
 
         Ok(())
     }
 
     /// Makes an uninterruptible request and waits for its response.
-    pub fn invoke(&self, req: Request) -> std::io::Result<Response> {
+    ///
+    /// Failures of the connection are reported as [`LxError::EIO`] responses, rather than making the process fail. If
+    /// the request cannot be sent, the connection is reestablished once and the request is sent again. If its response
+    /// cannot be received, including when it does not arrive within [`switches::ipc_timeout`], the request may have
+    /// been handled, so it is not sent again; the connection is still reestablished, since a late response would
+    /// otherwise be taken as the response to the next request.
+    pub fn invoke(&self, req: Request) -> Response {
         crate::signal::without_signals(|| {
             thread::with_context(|ctx| {
                 let mut buf = ctx.ipc_buf.borrow_mut();
                 buf.clear();
                 postcard::to_io(&req, &mut *buf).expect("all requests should be valid postcard");
-                self.send(&buf)?;
-                self.recv(&mut buf)?;
-                postcard::from_bytes(&buf).map_err(|err| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::UnexpectedEof,
-                        format!("failed to deserialize response: {err}"),
-                    )
-                })
+                if self.send(&buf).is_err() && !(self.reconnect() && self.send(&buf).is_ok()) {
+                    return Response::Error(LxError::EIO);
+                }
+                if self.recv(&mut buf).is_err() {
+                    self.reconnect();
+                    return Response::Error(LxError::EIO);
+                }
+                postcard::from_bytes(&buf).unwrap_or_else(|_| ipc_fail())
             })
         })
     }
+
+    /// Makes several uninterruptible requests in a single exchange, and waits for their responses, which are returned
+    /// in the order of the requests.
+    ///
+    /// If the connection lacks [`Capabilities::BATCH`], the requests are made one by one instead.
+    pub fn invoke_batch(&self, reqs: Vec<Request>) -> Result<Vec<Response>, LxError> {
+        if !self.capabilities().contains(Capabilities::BATCH) {
+            return Ok(reqs.into_iter().map(|req| self.invoke(req)).collect());
+        }
+        let len = reqs.len();
+        match self.invoke(Request::Batch(reqs)) {
+            Response::Batch(resps) if resps.len() == len => Ok(resps),
+            Response::Error(err) => Err(err),
+            _ => ipc_fail(),
+        }
+    }
 }
 impl AsRawFd for Client {
     fn as_raw_fd(&self) -> std::os::fd::RawFd {
-        self.0.as_raw_fd()
+        self.stream.as_raw_fd()
     }
 }
 impl Drop for Client {
@@ -171,13 +288,26 @@ pub fn with_client<T, F: FnOnce(&Client) -> T>(f: F) -> T {
     })
 }
 
-/// Creates a client, performing the handshake.
+/// Creates a client, performing the handshake and setting up a shared buffer.
 pub fn make_client() -> Client {
-    let client = Client(
-        UnixStream::connect(&**process::context().server_sock_path.load())
+    let mut client = connect();
+    client.apply_timeout();
+    client.negotiate_shared_buffer();
+    client
+}
+
+/// Creates a client without a shared buffer or a timeout, performing the handshake.
+fn connect() -> Client {
+    let client = Client {
+        stream: UnixStream::connect(&**process::context().server_sock_path.load())
             .expect("unable to connect to MacTux server"),
-    );
-    client.force_handshake();
+        capabilities: Cell::new(Capabilities::empty()),
+        shared: None,
+        reconnected: Cell::new(false),
+    };
+    client
+        .force_handshake()
+        .expect("unable to handshake with MacTux server");
     process::context()
         .important_fds
         .pin()
@@ -187,11 +317,11 @@ pub fn make_client() -> Client {
 
 /// Begins an interruptible request.
 pub fn begin_interruptible(ireq: InterruptibleRequest) -> InterruptibleClient {
-    let client = make_client();
+    let client = connect();
     let buf = postcard::to_stdvec(&Request::CallInterruptible(ireq))
         .expect("All requests should be valid bincode");
     client.send(&buf).unwrap();
-    let stream = unsafe { (&raw const client.0).read() };
+    let stream = unsafe { (&raw const client.stream).read() };
     std::mem::forget(client);
     InterruptibleClient(stream)
 }
@@ -208,9 +338,20 @@ pub fn update_client(client: Client) {
 /// This is usually used after `execve()`, which inherits the parent client.
 pub unsafe fn set_client_fd(fd: libc::c_int) {
     unsafe {
-        let client = Client(UnixStream::from_raw_fd(fd));
+        let mut client = Client {
+            stream: UnixStream::from_raw_fd(fd),
+            capabilities: Cell::new(Capabilities::empty()),
+            shared: None,
+            reconnected: Cell::new(false),
+        };
         _ = client.enable_cloexec();
-        client.invoke(Request::AfterExec).unwrap();
+        client.apply_timeout();
+        match client.invoke(Request::AfterExec) {
+            Response::Capabilities(capabilities) => client.capabilities.set(capabilities),
+            _ => ipc_fail(),
+        }
+        // The shared buffer of the previous program image is gone, so a new one replaces it.
+        client.negotiate_shared_buffer();
         process::context()
             .important_fds
             .pin()
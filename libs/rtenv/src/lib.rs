@@ -9,18 +9,24 @@ pub mod misc;
 pub mod mm;
 pub mod net;
 pub mod process;
+pub mod random;
 pub mod rust;
 pub mod security;
 pub mod signal;
 pub mod switches;
 pub mod sync;
+pub mod telemetry;
 pub mod thread;
+pub mod time;
 pub mod vfd;
 
 #[cfg(target_arch = "x86_64")]
 #[path = "emuctx_x86_64.rs"]
 pub mod emuctx;
 
+#[cfg(target_arch = "x86_64")]
+pub mod vdso;
+
 mod util;
 
 /// Setups the environment.
@@ -29,6 +35,7 @@ pub unsafe fn install() -> std::io::Result<()> {
         process::install()?;
         thread::install()?;
         signal::install()?;
+        time::install();
         structures::mapper::set_pid_mapper(Box::new(util::RtenvPidMapper));
         log::set_logger(&util::RustLogger).expect("a rust logger is set");
         log::set_max_level(log::LevelFilter::Trace);
@@ -36,3 +43,13 @@ pub unsafe fn install() -> std::io::Result<()> {
         Ok(())
     }
 }
+
+/// Installs the process and thread contexts, with no server connection, for tests that need them.
+#[cfg(test)]
+fn init_for_tests() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| unsafe {
+        process::install().expect("cannot install process context for tests");
+        thread::install().expect("cannot install thread context for tests");
+    });
+}
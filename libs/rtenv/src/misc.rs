@@ -5,7 +5,7 @@ use crate::{
 use structures::{
     error::LxError,
     internal::mactux_ipc::{NetworkNames, Request, Response},
-    misc::{LogLevel, SysInfo, UtsName, uname_str},
+    misc::{HOST_NAME_MAX, LogLevel, SysInfo, UtsName, uname_str},
 };
 
 pub fn sysinfo() -> Result<SysInfo, LxError> {
@@ -14,8 +14,8 @@ pub fn sysinfo() -> Result<SysInfo, LxError> {
 
 pub fn uname() -> Result<UtsName, LxError> {
     let mut network_names = get_network_names()?;
-    network_names.nodename.truncate(64);
-    network_names.domainname.truncate(64);
+    network_names.nodename.truncate(HOST_NAME_MAX);
+    network_names.domainname.truncate(HOST_NAME_MAX);
     let mut nodename = [0; _];
     let mut domainname = [0; _];
     nodename[..network_names.nodename.len()].copy_from_slice(&network_names.nodename);
@@ -39,13 +39,37 @@ pub fn set_network_names(names: NetworkNames) -> Result<(), LxError> {
     call_server(Request::SetNetworkNames(names))
 }
 
+/// Sets host name of the UTS namespace, like `sethostname`.
+pub fn sethostname(name: &[u8]) -> Result<(), LxError> {
+    if name.len() > HOST_NAME_MAX {
+        return Err(LxError::EINVAL);
+    }
+    let domainname = get_network_names()?.domainname;
+    set_network_names(NetworkNames {
+        nodename: name.to_vec(),
+        domainname,
+    })
+}
+
+/// Sets domain name of the UTS namespace, like `setdomainname`.
+pub fn setdomainname(name: &[u8]) -> Result<(), LxError> {
+    if name.len() > HOST_NAME_MAX {
+        return Err(LxError::EINVAL);
+    }
+    let nodename = get_network_names()?.nodename;
+    set_network_names(NetworkNames {
+        nodename,
+        domainname: name.to_vec(),
+    })
+}
+
 pub fn write_syslog(level: LogLevel, content: Vec<u8>) {
     call_server(Request::WriteSyslog(level, content))
 }
 
 pub fn read_syslog_all(buf: &mut [u8]) -> Result<usize, LxError> {
     with_client(
-        |client| match client.invoke(Request::ReadSyslogAll(buf.len())).unwrap() {
+        |client| match client.invoke(Request::ReadSyslogAll(buf.len())) {
             Response::Bytes(blob) => {
                 debug_assert!(blob.len() <= buf.len());
                 buf[..blob.len()].copy_from_slice(&blob);
@@ -1,17 +1,33 @@
-use crate::util::posix_result;
+use crate::{switches, util::posix_result};
 use libc::c_int;
 use mach2::{
     message::mach_msg_type_number_t,
     port::mach_port_t,
+    vm_page_size::{mach_vm_trunc_page, vm_page_size},
     vm_region::{vm_region_basic_info_data_64_t, vm_region_basic_info_data_t, vm_region_info_t},
     vm_types::mach_vm_size_t,
 };
+use std::sync::atomic::{self, AtomicBool, AtomicU8, AtomicUsize};
 use structures::{
     ToApple,
     error::LxError,
     mm::{Madvice, MmapFlags, MmapProt, MremapFlags},
 };
 
+/// Top of the area where mappings without an address hint are placed in deterministic mode. Like Linux, mappings grow
+/// downwards from here.
+const DETERMINISTIC_MMAP_TOP: usize = 0x7f00_0000_0000;
+
+/// Granularity of mapping hints in deterministic mode, which is large enough for page sizes on all architectures.
+const DETERMINISTIC_MMAP_ALIGN: usize = 0x10000;
+
+/// The next address hint in deterministic mode.
+static DETERMINISTIC_MMAP_NEXT: AtomicUsize = AtomicUsize::new(DETERMINISTIC_MMAP_TOP);
+
+/// Held while a code page is temporarily writable in [`rewrite_code`], so that a thread cannot drop write permission of
+/// a page while another one is still rewriting it.
+static CODE_REWRITE_LOCK: AtomicBool = AtomicBool::new(false);
+
 pub unsafe fn map(
     addr: *mut u8,
     len: usize,
@@ -25,7 +41,14 @@ pub unsafe fn map(
         if flags.contains(MmapFlags::MAP_SYNC) {
             return Err(LxError::EOPNOTSUPP);
         }
+        if let Some(ring) = crate::io_uring::get(fd) {
+            return ring.map(addr, len, flags, offset);
+        }
 
+        let addr = match addr.is_null() && !flags.contains(MmapFlags::MAP_FIXED) {
+            true => deterministic_hint(len),
+            false => addr,
+        };
         let addr: *mut u8 = match libc::mmap(
             addr.cast(),
             len,
@@ -47,10 +70,26 @@ pub unsafe fn map(
     }
 }
 
+/// Returns the address hint for a new mapping of `len` bytes without a hint.
+///
+/// Outside of deterministic mode, this returns a null pointer and lets the system choose.
+fn deterministic_hint(len: usize) -> *mut u8 {
+    if switches::deterministic().is_none() {
+        return std::ptr::null_mut();
+    }
+    let len = len.next_multiple_of(DETERMINISTIC_MMAP_ALIGN);
+    let prev = DETERMINISTIC_MMAP_NEXT.fetch_sub(len, atomic::Ordering::Relaxed);
+    prev.wrapping_sub(len) as _
+}
+
 pub unsafe fn unmap(addr: *mut u8, len: usize) -> Result<(), LxError> {
     unsafe { posix_result(libc::munmap(addr.cast(), len)) }
 }
 
+pub unsafe fn protect(addr: *mut u8, len: usize, prot: MmapProt) -> Result<(), LxError> {
+    unsafe { posix_result(libc::mprotect(addr.cast(), len, prot.to_apple()?)) }
+}
+
 pub unsafe fn remap(
     old_addr: *mut u8,
     old_size: usize,
@@ -127,6 +166,78 @@ pub fn incore(addr: *const u8, size: usize, vec: *mut u8) -> Result<(), LxError>
     unsafe { posix_result(libc::mincore(addr.cast(), size, vec.cast())) }
 }
 
+/// Makes the stack of the calling thread executable, as is requested by a `PT_GNU_STACK` segment with `PF_X`.
+///
+/// Stacks are not executable by default, both on macOS and for Linux programs that do not ask for it.
+pub fn make_stack_executable() -> Result<(), LxError> {
+    let marker = 0u8;
+    let region = mach_vm_region(&marker).ok_or(LxError::EFAULT)?;
+    if region.addr > &raw const marker {
+        return Err(LxError::EFAULT);
+    }
+    unsafe {
+        posix_result(libc::mprotect(
+            region.addr as _,
+            region.size,
+            region.info.protection | libc::PROT_EXEC,
+        ))
+    }
+}
+
+/// Atomically replaces the byte at `addr` with `new` if it equals `old`, returning `true` on success.
+///
+/// Executable mappings are not writable, so the page is made writable only for the duration of the rewrite and its
+/// protection is restored afterwards. This is used by the `SIGSEGV` handler, so it must be async-signal-safe.
+pub(crate) unsafe fn rewrite_code(addr: *mut u8, old: u8, new: u8) -> bool {
+    while CODE_REWRITE_LOCK
+        .compare_exchange_weak(
+            false,
+            true,
+            atomic::Ordering::Acquire,
+            atomic::Ordering::Relaxed,
+        )
+        .is_err()
+    {
+        std::hint::spin_loop();
+    }
+    let result = unsafe { rewrite_code_locked(addr, old, new) };
+    CODE_REWRITE_LOCK.store(false, atomic::Ordering::Release);
+    result
+}
+
+unsafe fn rewrite_code_locked(addr: *mut u8, old: u8, new: u8) -> bool {
+    let Some(region) = mach_vm_region(addr) else {
+        return false;
+    };
+    if region.addr > addr.cast_const() {
+        return false;
+    }
+    let byte = unsafe { &*(addr as *const AtomicU8) };
+    let cmpxchg = || {
+        byte.compare_exchange(
+            old,
+            new,
+            atomic::Ordering::Relaxed,
+            atomic::Ordering::Relaxed,
+        )
+        .is_ok()
+    };
+    let prot = region.info.protection;
+    if prot & libc::VM_PROT_WRITE != 0 {
+        return cmpxchg();
+    }
+
+    unsafe {
+        let page = mach_vm_trunc_page(addr as _) as *mut libc::c_void;
+        if libc::mprotect(page, vm_page_size, prot | libc::PROT_WRITE) == -1 {
+            return false;
+        }
+        let result = cmpxchg();
+        libc::mprotect(page, vm_page_size, prot);
+        result
+    }
+}
+
 #[derive(Debug)]
 struct Region {
     addr: *const u8,
@@ -0,0 +1,149 @@
+//! Network interface queries, as are done by `SIOCGIF*` ioctls.
+
+use libc::c_int;
+use structures::{
+    FromApple,
+    error::LxError,
+    net::{IfConf, IfFlags, IfReq, SockAddrIn},
+};
+
+/// Name of the loopback interface on Linux.
+const LINUX_LOOPBACK: &[u8] = b"lo";
+
+/// Name of the loopback interface on macOS.
+const APPLE_LOOPBACK: &[u8] = b"lo0";
+
+/// A network interface address, as is reported by `getifaddrs`.
+struct IfAddr {
+    name: Vec<u8>,
+    flags: IfFlags,
+    addr: Option<SockAddrIn>,
+    netmask: Option<SockAddrIn>,
+}
+
+/// Fills `conf` with IPv4 addresses of all interfaces, like `SIOCGIFCONF`.
+///
+/// If `ifc_req` is null, only the length of the buffer needed is stored in `ifc_len`.
+///
+/// # Safety
+/// Unless null, `ifc_req` must point to `ifc_len` writable bytes.
+pub unsafe fn ifconf(conf: &mut IfConf) -> Result<(), LxError> {
+    let reqs = ifaddrs()?.into_iter().filter_map(|x| {
+        let mut req = IfReq::new(&x.name);
+        req.set_addr(x.addr?);
+        Some(req)
+    });
+    if conf.ifc_req.is_null() {
+        conf.ifc_len = (reqs.count() * size_of::<IfReq>()) as _;
+        return Ok(());
+    }
+
+    let capacity = conf.ifc_len.max(0) as usize / size_of::<IfReq>();
+    let mut n = 0;
+    for req in reqs.take(capacity) {
+        unsafe {
+            conf.ifc_req.add(n).write_unaligned(req);
+        }
+        n += 1;
+    }
+    conf.ifc_len = (n * size_of::<IfReq>()) as _;
+    Ok(())
+}
+
+/// Stores flags of the interface named in `req`, like `SIOCGIFFLAGS`.
+pub fn ifflags(req: &mut IfReq) -> Result<(), LxError> {
+    let flags = ifaddrs()?
+        .into_iter()
+        .find(|x| x.name == req.name())
+        .ok_or(LxError::ENODEV)?
+        .flags;
+    req.set_flags(flags);
+    Ok(())
+}
+
+/// Stores the IPv4 address of the interface named in `req`, like `SIOCGIFADDR`.
+pub fn ifaddr(req: &mut IfReq) -> Result<(), LxError> {
+    let addr = inet_ifaddr(req.name())?
+        .addr
+        .ok_or(LxError::EADDRNOTAVAIL)?;
+    req.set_addr(addr);
+    Ok(())
+}
+
+/// Stores the IPv4 netmask of the interface named in `req`, like `SIOCGIFNETMASK`.
+pub fn ifnetmask(req: &mut IfReq) -> Result<(), LxError> {
+    let netmask = inet_ifaddr(req.name())?
+        .netmask
+        .ok_or(LxError::EADDRNOTAVAIL)?;
+    req.set_addr(netmask);
+    Ok(())
+}
+
+/// Returns the first IPv4 address of the interface with the given name.
+fn inet_ifaddr(name: &[u8]) -> Result<IfAddr, LxError> {
+    let mut found = false;
+    for ifaddr in ifaddrs()? {
+        if ifaddr.name != name {
+            continue;
+        }
+        if ifaddr.addr.is_some() {
+            return Ok(ifaddr);
+        }
+        found = true;
+    }
+    match found {
+        true => Err(LxError::EADDRNOTAVAIL),
+        false => Err(LxError::ENODEV),
+    }
+}
+
+/// Lists addresses of all network interfaces, with interface names translated to Linux ones.
+///
+/// Only IPv4 addresses are kept, while entries of other families still tell flags of the interface.
+fn ifaddrs() -> Result<Vec<IfAddr>, LxError> {
+    unsafe {
+        let mut head = std::ptr::null_mut();
+        if libc::getifaddrs(&mut head) == -1 {
+            return Err(LxError::last_apple_error());
+        }
+        let mut ifaddrs = Vec::new();
+        let mut cur = head;
+        while let Some(ifa) = cur.as_ref() {
+            cur = ifa.ifa_next;
+            let apple_name = std::ffi::CStr::from_ptr(ifa.ifa_name).to_bytes();
+            let inet =
+                !ifa.ifa_addr.is_null() && (*ifa.ifa_addr).sa_family as c_int == libc::AF_INET;
+            ifaddrs.push(IfAddr {
+                name: linux_ifname(apple_name),
+                flags: IfFlags::from_apple(ifa.ifa_flags as _).unwrap_or(IfFlags::empty()),
+                addr: inet.then(|| inet_sockaddr(ifa.ifa_addr)).flatten(),
+                netmask: inet.then(|| inet_sockaddr(ifa.ifa_netmask)).flatten(),
+            });
+        }
+        libc::freeifaddrs(head);
+        Ok(ifaddrs)
+    }
+}
+
+/// Converts an IPv4 socket address returned by `getifaddrs`, which may be null.
+///
+/// Netmasks may be truncated after their last non-zero byte, as is told by `sa_len`, so missing bytes are zeroed.
+unsafe fn inet_sockaddr(addr: *const libc::sockaddr) -> Option<SockAddrIn> {
+    if addr.is_null() {
+        return None;
+    }
+    unsafe {
+        let mut apple = [0u8; size_of::<libc::sockaddr_in>()];
+        let len = ((*addr).sa_len as usize).min(apple.len());
+        std::ptr::copy_nonoverlapping(addr.cast::<u8>(), apple.as_mut_ptr(), len);
+        SockAddrIn::from_apple(&apple).ok()
+    }
+}
+
+/// Translates a macOS interface name into the one shown to Linux programs.
+fn linux_ifname(apple: &[u8]) -> Vec<u8> {
+    match apple {
+        APPLE_LOOPBACK => LINUX_LOOPBACK.to_vec(),
+        other => other.to_vec(),
+    }
+}
@@ -1,3 +1,4 @@
+pub(crate) mod interface;
 mod local;
 mod sockopt;
 
@@ -69,7 +70,7 @@ pub fn accept(sock: c_int, flags: SocketFlags) -> Result<(SockAddr, c_int), LxEr
         let mut buf = [0u8; size_of::<libc::sockaddr_storage>()];
         let mut size = size_of_val(&buf) as libc::socklen_t;
         let fd: c_int = posix_num!(libc::accept(sock, (&raw mut buf).cast(), &mut size))?;
-        prepare_new(fd, flags).inspect_err(|_| _ = libc::close(fd))?;
+        prepare_accepted(fd, flags).inspect_err(|_| _ = libc::close(fd))?;
         let sockaddr =
             linux_sockaddr(&buf[..(size as usize)]).inspect_err(|_| _ = libc::close(fd))?;
         Ok((sockaddr, fd))
@@ -192,6 +193,10 @@ pub unsafe fn sendmmsg(
     }
 }
 
+/// Receives a message from a socket, returning its length and the address of the sender.
+///
+/// `MSG_PEEK` and `MSG_DONTWAIT` are passed to the native `recvfrom`, so `MSG_DONTWAIT` makes only this call
+/// nonblocking and leaves `O_NONBLOCK` of the socket unchanged.
 pub fn recvfrom(
     sock: c_int,
     buf: &mut [u8],
@@ -234,6 +239,27 @@ fn prepare_new(sock: c_int, flags: SocketFlags) -> Result<(), LxError> {
     }
 }
 
+/// Prepares a socket returned by `accept`.
+///
+/// Unlike Linux, macOS makes accepted sockets inherit `O_NONBLOCK` from the listening socket, so the flag is set or
+/// cleared explicitly to match `SOCK_NONBLOCK`.
+fn prepare_accepted(sock: c_int, flags: SocketFlags) -> Result<(), LxError> {
+    unsafe {
+        let fl: c_int = posix_num!(libc::fcntl(sock, libc::F_GETFL))?;
+        let new_fl = match flags.contains(SocketFlags::SOCK_NONBLOCK) {
+            true => fl | libc::O_NONBLOCK,
+            false => fl & !libc::O_NONBLOCK,
+        };
+        if new_fl != fl {
+            posix_result(libc::fcntl(sock, libc::F_SETFL, new_fl))?;
+        }
+        if flags.contains(SocketFlags::SOCK_CLOEXEC) {
+            crate::io::set_cloexec(sock)?;
+        }
+        Ok(())
+    }
+}
+
 /// Converts from an Apple socket address to a Linux one.
 fn linux_sockaddr(apple: &[u8]) -> Result<SockAddr, LxError> {
     if apple.len() < offset_of!(libc::sockaddr, sa_data) {
@@ -1,29 +1,38 @@
 use crate::{
-    fs::FilesystemContext,
-    ipc_client::{Client, with_client},
+    error_report::explain,
+    fs::{FilesystemContext, StatCache},
+    ipc_client::{Client, call_server, with_client},
     posix_num, process,
     thread::{CloneContext, ThreadPubCtxMap, may_fork},
-    util::posix_result,
+    util::{ipc_fail, posix_result},
 };
-use arc_swap::ArcSwap;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use rustc_hash::FxBuildHasher;
 use std::{
     convert::Infallible,
-    ffi::{OsString, c_int},
+    ffi::{OsString, c_int, c_uint},
     mem::MaybeUninit,
     os::{fd::AsRawFd, unix::process::CommandExt},
     path::PathBuf,
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{self, AtomicI32},
+    },
 };
 use structures::{
-    ToApple,
+    FromApple, ToApple,
     error::LxError,
-    fs::{AT_FDCWD, AtFlags, FileMode, FileType, OpenFlags, StatxMask},
-    internal::mactux_ipc::Request,
+    exec,
+    fs::{AT_FDCWD, AccessFlags, AtFlags, FileMode, FileType, OpenFlags, StatxMask},
+    internal::mactux_ipc::{Request, Response},
     mapper::with_pid_mapper,
-    process::{ChildType, CloneFlags},
+    process::{
+        BINPRM_BUF_SIZE, ChildType, CloneFlags, MAX_ARG_STRLEN, PidFdFlags, RLimit64, RLimitable,
+        RUsage, RUsageWho, arg_max,
+    },
     signal::{SigAction, SigNum},
     thread::is_tid,
+    time::Timeval,
 };
 
 static mut PROCESS_CTX: MaybeUninit<ProcessCtx> = MaybeUninit::uninit();
@@ -37,6 +46,41 @@ pub struct ProcessCtx {
     pub vfd_table: papaya::HashMap<c_int, u64, FxBuildHasher>,
     pub server_sock_path: ArcSwap<PathBuf>,
     pub important_fds: papaya::HashSet<c_int, FxBuildHasher>,
+    pub id_cache: IdCache,
+    pub groups: ArcSwapOption<Vec<c_uint>>,
+    pub stat_cache: StatCache,
+}
+
+/// Cached Linux process IDs of current process, to avoid server round-trips on `getpid()`/`getppid()`.
+///
+/// A value of `0` indicates that the corresponding entry is not cached yet.
+#[derive(Debug)]
+pub struct IdCache {
+    pid: AtomicI32,
+    native_ppid: AtomicI32,
+    ppid: AtomicI32,
+}
+impl IdCache {
+    /// Creates a new, empty [`IdCache`] instance.
+    pub const fn new() -> Self {
+        Self {
+            pid: AtomicI32::new(0),
+            native_ppid: AtomicI32::new(0),
+            ppid: AtomicI32::new(0),
+        }
+    }
+
+    /// Invalidates all cached values. This must be called when the IDs may change, like after `fork()`.
+    pub fn invalidate(&self) {
+        self.pid.store(0, atomic::Ordering::Relaxed);
+        self.native_ppid.store(0, atomic::Ordering::Relaxed);
+        self.ppid.store(0, atomic::Ordering::Relaxed);
+    }
+}
+impl Default for IdCache {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Installs the process context.
@@ -58,6 +102,9 @@ pub unsafe fn install() -> std::io::Result<()> {
             vfd_table,
             server_sock_path,
             important_fds: papaya::HashSet::default(),
+            id_cache: IdCache::new(),
+            groups: ArcSwapOption::empty(),
+            stat_cache: StatCache::default(),
         });
     }
     Ok(())
@@ -69,13 +116,35 @@ pub fn context() -> &'static ProcessCtx {
 }
 
 pub fn pid() -> i32 {
-    let native_pid = unsafe { libc::getpid() };
-    with_pid_mapper(|x| x.apple_to_linux(native_pid)).unwrap_or(native_pid)
+    let cache = &context().id_cache;
+    match cache.pid.load(atomic::Ordering::Relaxed) {
+        0 => {
+            let native_pid = unsafe { libc::getpid() };
+            let pid = with_pid_mapper(|x| x.apple_to_linux(native_pid)).unwrap_or(native_pid);
+            cache.pid.store(pid, atomic::Ordering::Relaxed);
+            pid
+        }
+        pid => pid,
+    }
 }
 
 pub fn ppid() -> i32 {
+    // The parent process may exit and we get reparented, so the native PPID is always checked, which does not require
+    // any communication with the server.
+    let cache = &context().id_cache;
     let native_ppid = unsafe { libc::getppid() };
-    with_pid_mapper(|x| x.apple_to_linux(native_ppid)).unwrap_or(native_ppid)
+    if cache.native_ppid.load(atomic::Ordering::Relaxed) == native_ppid {
+        let ppid = cache.ppid.load(atomic::Ordering::Relaxed);
+        if ppid != 0 {
+            return ppid;
+        }
+    }
+    let ppid = with_pid_mapper(|x| x.apple_to_linux(native_ppid)).unwrap_or(native_ppid);
+    cache.ppid.store(ppid, atomic::Ordering::Relaxed);
+    cache
+        .native_ppid
+        .store(native_ppid, atomic::Ordering::Relaxed);
+    ppid
 }
 
 pub fn pgid(pid: i32) -> Result<i32, LxError> {
@@ -88,16 +157,62 @@ pub fn pgid(pid: i32) -> Result<i32, LxError> {
     with_pid_mapper(|x| x.apple_to_linux(native_pgid))
 }
 
+/// Moves the process `pid` to the process group `pgid`, where `0` refers to the current process and to `pid` itself.
+///
+/// Both IDs are translated like the ones returned by [`pgid`], so that job control of shells works in PID namespaces.
 pub fn setpgid(pid: i32, pgid: i32) -> Result<(), LxError> {
-    // TODO: support pid namespaces.
-    unsafe { posix_result(libc::setpgid(pid, pgid)) }
+    if pid < 0 || pgid < 0 {
+        return Err(LxError::EINVAL);
+    }
+    let to_native = |linux| match linux {
+        0 => Ok(0),
+        linux => with_pid_mapper(|x| x.linux_to_apple(linux)),
+    };
+    let native_pid = to_native(pid)?;
+    let native_pgid = to_native(pgid).map_err(|_| LxError::EPERM)?;
+    unsafe { posix_result(libc::setpgid(native_pid, native_pgid)) }
 }
 
+/// Returns session ID of the process `pid`, or of the current process if it is `0`.
+///
+/// Sessions belong to native processes, so session IDs are translated like process group IDs.
+pub fn sid(pid: i32) -> Result<i32, LxError> {
+    let native_pid = if pid == 0 {
+        0
+    } else {
+        with_pid_mapper(|x| x.linux_to_apple(pid))?
+    };
+    let native_sid = unsafe { posix_num!(libc::getsid(native_pid))? };
+    with_pid_mapper(|x| x.apple_to_linux(native_sid))
+}
+
+/// Creates a new session and process group with the current process as their leader, returning the new session ID.
+///
+/// Like Linux, the new session has no controlling terminal, and this fails with `EPERM` if the current process is
+/// already a process group leader.
+pub fn setsid() -> Result<i32, LxError> {
+    unsafe { posix_num!(libc::setsid())? };
+    Ok(pid())
+}
+
+/// Replaces the current program with the one at `path`, like `execve`.
+///
+/// The MacTux binary is executed again in the same native process, with the connection to the server passed on. So the
+/// PID, session, process group and controlling terminal, which belong to the native process on macOS, and the
+/// server-side process state survive, like on Linux.
 pub unsafe fn exec(
     path: &[u8],
     argv: &[*const u8],
     envp: &[*const u8],
 ) -> Result<Infallible, LxError> {
+    // Like Linux, execute permission is checked against the effective IDs. The check also refuses files on `noexec`
+    // mounts.
+    crate::fs::faccessat2(
+        AT_FDCWD,
+        path.to_vec(),
+        AccessFlags::X_OK,
+        AtFlags::AT_EACCESS,
+    )?;
     let fd = crate::fs::openat(
         AT_FDCWD,
         path.to_vec(),
@@ -109,22 +224,30 @@ pub unsafe fn exec(
         crate::fs::fstat(fd, StatxMask::STATX_MODE).inspect_err(|_| _ = crate::io::close(fd))?;
     _ = crate::io::close(fd);
     match stat.stx_mode.file_type() {
-        FileType::Directory => return Err(LxError::EISDIR),
-        _ => (),
-    }
-    if stat.stx_mode.permbits() & 0o111 == 0 {
-        return Err(LxError::EPERM);
+        FileType::RegularFile => (),
+        _ => {
+            return Err(explain(
+                LxError::EACCES,
+                "only regular files can be executed",
+            ));
+        }
     }
 
-    let mut args = Vec::with_capacity(argv.len() + 2 * envp.len() + 8);
-    let mut argv = unsafe {
+    let argv: Vec<&[u8]> = unsafe {
         argv.iter()
             .map(|&x| std::slice::from_raw_parts(x, libc::strlen(x as _)))
+            .collect()
     };
-    let envp = unsafe {
+    let envp: Vec<&[u8]> = unsafe {
         envp.iter()
             .map(|&x| std::slice::from_raw_parts(x, libc::strlen(x as _)))
+            .collect()
     };
+    check_exec_args(&argv, &envp)?;
+    check_image(path)?;
+
+    let mut args = Vec::with_capacity(argv.len() + 2 * envp.len() + 8);
+    let mut argv = argv.into_iter();
 
     let mactux_exec = std::fs::canonicalize(std::env::current_exe().map_err(LxError::from)?)
         .map_err(LxError::from)?;
@@ -178,8 +301,75 @@ pub unsafe fn exec(
         .into())
 }
 
+/// Checks that `argv` and `envp` fit in the size limits of `execve`.
+///
+/// Returns `E2BIG` if a single string is longer than [`MAX_ARG_STRLEN`] or if they take more space than allowed by
+/// `RLIMIT_STACK`.
+pub fn check_exec_args(argv: &[&[u8]], envp: &[&[u8]]) -> Result<(), LxError> {
+    let stack_limit = prlimit(0, RLimitable::RLIMIT_STACK, None)?.rlim_cur;
+    check_exec_size(argv, envp, stack_limit)
+}
+
+/// Checks that `argv` and `envp` fit in the size limits of `execve`, given the soft limit of `RLIMIT_STACK`.
+fn check_exec_size(argv: &[&[u8]], envp: &[&[u8]], stack_limit: u64) -> Result<(), LxError> {
+    let mut total = (argv.len().max(1) + envp.len()) * size_of::<usize>();
+    for s in argv.iter().chain(envp) {
+        let len = s.len() + 1;
+        if len > MAX_ARG_STRLEN {
+            return Err(LxError::E2BIG);
+        }
+        total += len;
+    }
+    if total > arg_max(stack_limit) {
+        return Err(LxError::E2BIG);
+    }
+    Ok(())
+}
+
+/// Checks that `path` is an image that the loader is able to run.
+///
+/// Returns `ENOEXEC` if it is neither an x86-64 ELF file nor an interpreter script whose `#!` line the loader accepts.
+/// Errors opening the file, like `EACCES`, are returned as is.
+fn check_image(path: &[u8]) -> Result<(), LxError> {
+    let fd = crate::fs::openat(
+        AT_FDCWD,
+        path.to_vec(),
+        OpenFlags::O_CLOEXEC | OpenFlags::O_RDONLY,
+        AtFlags::empty(),
+        FileMode(0),
+    )?;
+    let mut buf = [0; BINPRM_BUF_SIZE];
+    let mut len = 0;
+    while len < buf.len() {
+        match crate::io::read(fd, &mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(err) => {
+                _ = crate::io::close(fd);
+                return Err(err);
+            }
+        }
+    }
+    _ = crate::io::close(fd);
+
+    check_image_header(&buf[..len])
+}
+
+/// Checks that `buf`, the first [`BINPRM_BUF_SIZE`] bytes of a file, start an image that the loader is able to run.
+fn check_image_header(buf: &[u8]) -> Result<(), LxError> {
+    if buf.starts_with(exec::ELF_MAGIC) {
+        exec::check_elf_header(buf).map_err(|_| LxError::ENOEXEC)
+    } else if buf.starts_with(exec::SHEBANG_MAGIC) {
+        exec::parse_shebang(buf).map_err(|_| LxError::ENOEXEC)?;
+        Ok(())
+    } else {
+        Err(LxError::ENOEXEC)
+    }
+}
+
 pub fn fork() -> Result<i32, LxError> {
     let new_client = crate::ipc_client::make_client();
+    crate::random::before_fork();
 
     let status = may_fork(
         || unsafe {
@@ -213,6 +403,7 @@ pub fn clone(ctx: Box<CloneContext>) -> Result<i32, LxError> {
             if cl_args.flags().contains(CloneFlags::CLONE_SETTLS) {
                 crate::emuctx::x86_64_set_emulated_gsbase(cl_args.tls());
             }
+            crate::thread::set_child_tid(&cl_args, pid());
         }
         Ok(child_tid) => unsafe {
             // if we are on the original thread/process...
@@ -228,6 +419,250 @@ pub fn clone(ctx: Box<CloneContext>) -> Result<i32, LxError> {
     result
 }
 
+/// Records `path` as the executable file that the current process is running.
+pub fn set_exe(path: &[u8]) -> Result<(), LxError> {
+    let path = crate::fs::at_path(AT_FDCWD, path.to_vec())?;
+    call_server(Request::SetExe(path))
+}
+
+/// Maximum soft limit of `RLIMIT_NOFILE` accepted by macOS, from `<sys/syslimits.h>`.
+const OPEN_MAX: libc::rlim_t = 10240;
+
+/// Resource limits that macOS has analogues for.
+const NATIVE_RLIMITS: [RLimitable; 9] = [
+    RLimitable::RLIMIT_CPU,
+    RLimitable::RLIMIT_FSIZE,
+    RLimitable::RLIMIT_DATA,
+    RLimitable::RLIMIT_STACK,
+    RLimitable::RLIMIT_CORE,
+    RLimitable::RLIMIT_NOFILE,
+    RLimitable::RLIMIT_MEMLOCK,
+    RLimitable::RLIMIT_AS,
+    RLimitable::RLIMIT_NPROC,
+];
+
+/// Returns resource usage of the current process, its waited-for children or the current thread, like `getrusage`.
+///
+/// macOS has no `RUSAGE_THREAD`, so CPU times of the current thread are read from Mach instead. Other per-thread
+/// counters cannot be read, and are reported as zeroes, except for `ru_maxrss`, which is process-wide on Linux too.
+pub fn rusage(who: RUsageWho) -> Result<RUsage, LxError> {
+    let apple_who = match who {
+        RUsageWho::RUSAGE_THREAD => libc::RUSAGE_SELF,
+        _ => who.to_apple()?,
+    };
+    let mut apple = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(apple_who, &mut apple) } == -1 {
+        return Err(LxError::last_apple_error());
+    }
+    let mut rusage = RUsage::from_apple(apple)?;
+    let timeval = |micros: u64| Timeval {
+        tv_sec: (micros / 1_000_000) as _,
+        tv_usec: (micros % 1_000_000) as _,
+    };
+    match who {
+        RUsageWho::RUSAGE_THREAD => {
+            let (user, system) = crate::time::thread_times()?;
+            rusage = RUsage {
+                ru_utime: timeval(user),
+                ru_stime: timeval(system),
+                ru_maxrss: rusage.ru_maxrss,
+                ..unsafe { std::mem::zeroed() }
+            };
+        }
+        RUsageWho::RUSAGE_SELF => {
+            // Agree with `CLOCK_PROCESS_CPUTIME_ID` and `times`.
+            let (user, system) = crate::time::task_times()?;
+            rusage.ru_utime = timeval(user);
+            rusage.ru_stime = timeval(system);
+        }
+        _ => {}
+    }
+    Ok(rusage)
+}
+
+/// Gets and optionally sets a resource limit of the process `pid`, returning the previous one.
+///
+/// The server keeps the resource limits of every process. Limits of the current process that macOS has analogues for
+/// are also applied natively, so they are enforced by the kernel. Limits set on another process are applied natively
+/// when it executes a new program.
+pub fn prlimit(pid: i32, res: RLimitable, new: Option<RLimit64>) -> Result<RLimit64, LxError> {
+    let pid = if pid == process::pid() { 0 } else { pid };
+    let Some(new) = new else {
+        return with_client(|client| match client.invoke(Request::GetRLimit(pid, res)) {
+            Response::RLimit(limit) => Ok(limit),
+            Response::Error(LxError::EPERM) => Err(explain(
+                LxError::EPERM,
+                "reading resource limits of a process of another user requires effective UID 0",
+            )),
+            Response::Error(err) => Err(err),
+            _ => ipc_fail(),
+        });
+    };
+    if new.rlim_cur > new.rlim_max {
+        return Err(LxError::EINVAL);
+    }
+
+    // Permissions are checked by the server, which replaces the recorded limit atomically.
+    let set = |limit| {
+        with_client(
+            |client| match client.invoke(Request::SetRLimit(pid, res, limit)) {
+                Response::RLimit(old) => Ok(old),
+                Response::Error(LxError::EPERM) => Err(explain(
+                    LxError::EPERM,
+                    "raising a hard resource limit or changing limits of a process of another user requires \
+                     effective UID 0",
+                )),
+                Response::Error(err) => Err(err),
+                _ => ipc_fail(),
+            },
+        )
+    };
+    let old = set(new)?;
+    if pid == 0
+        && (res != RLimitable::RLIMIT_CORE || dumpable(0)?)
+        && let Err(err) = set_native_rlimit(res, new)
+    {
+        // Keep the recorded limit in line with the native one.
+        _ = set(old);
+        return Err(err);
+    }
+    Ok(old)
+}
+
+/// Synchronizes resource limits of the current process with the server.
+///
+/// Native limits are recorded for resources that the server knows nothing about, which happens when the process is
+/// not executed by another MacTux process. Otherwise, limits recorded by the server are applied natively.
+pub fn sync_rlimits() {
+    let native = NATIVE_RLIMITS
+        .into_iter()
+        .filter_map(|res| Some((res, native_rlimit(res).ok()?)))
+        .collect();
+    let rlimits = with_client(|client| match client.invoke(Request::SyncRLimits(native)) {
+        Response::RLimits(rlimits) => rlimits,
+        _ => ipc_fail(),
+    });
+    for (res, limit) in rlimits {
+        if let Err(err) = set_native_rlimit(res, limit) {
+            log::warn!("failed to apply resource limit {res:?}: {err}");
+        }
+    }
+}
+
+/// Returns `true` if the process with the given native PID, or the current process if it is `0`, is dumpable.
+pub fn dumpable(native_pid: libc::pid_t) -> Result<bool, LxError> {
+    with_client(
+        |client| match client.invoke(Request::GetDumpable(native_pid)) {
+            Response::Dumpable(dumpable) => Ok(dumpable),
+            Response::Error(err) => Err(err),
+            _ => ipc_fail(),
+        },
+    )
+}
+
+/// Sets whether the current process is dumpable, like `PR_SET_DUMPABLE`.
+///
+/// A process that is not dumpable never dumps core, which is done by lowering the native soft limit of `RLIMIT_CORE`
+/// to zero. The limit recorded by the server is kept, and applied again once the process becomes dumpable.
+pub fn set_dumpable(dumpable: bool) -> Result<(), LxError> {
+    call_server::<Result<(), LxError>>(Request::SetDumpable(dumpable))?;
+    let mut core = prlimit(0, RLimitable::RLIMIT_CORE, None)?;
+    if !dumpable {
+        core.rlim_cur = 0;
+    }
+    set_native_rlimit(RLimitable::RLIMIT_CORE, core)
+}
+
+fn native_rlimit(res: RLimitable) -> Result<RLimit64, LxError> {
+    let res = res.to_apple()?;
+    unsafe {
+        let mut buf = std::mem::zeroed();
+        if libc::getrlimit(res, &mut buf) == -1 {
+            return Err(LxError::last_apple_error());
+        }
+        Ok(RLimit64::from_apple(buf))
+    }
+}
+
+fn set_native_rlimit(res: RLimitable, limit: RLimit64) -> Result<(), LxError> {
+    let Ok(apple_res) = res.to_apple() else {
+        // Linux-only resources are only recorded by the server.
+        return Ok(());
+    };
+    let mut limit = limit.to_apple();
+    if res == RLimitable::RLIMIT_NOFILE {
+        // macOS refuses soft limits above `OPEN_MAX`, while Linux programs often raise it to the hard limit.
+        limit.rlim_cur = limit.rlim_cur.min(OPEN_MAX);
+    }
+    unsafe {
+        if libc::setrlimit(apple_res, &limit) == -1 {
+            return Err(LxError::last_apple_error());
+        }
+    }
+    Ok(())
+}
+
+/// Moves current process into new namespaces.
+pub fn unshare(flags: CloneFlags) -> Result<(), LxError> {
+    call_server(Request::Unshare(flags))
+}
+
+/// Moves current process into the namespace referred by `fd`.
+pub fn setns(fd: c_int, nstype: CloneFlags) -> Result<(), LxError> {
+    let Some(vfd) = crate::vfd::get(fd) else {
+        // Native file descriptors never refer to namespaces, but closed ones are still reported as such.
+        unsafe { posix_result(libc::fcntl(fd, libc::F_GETFD))? };
+        return Err(LxError::EINVAL);
+    };
+    call_server(Request::SetNamespace(vfd, nstype))
+}
+
+/// Opens a pidfd that refers to the process with given Linux PID.
+pub fn pidfd_open(pid: i32, flags: PidFdFlags) -> Result<c_int, LxError> {
+    if pid <= 0 || PidFdFlags::from_bits(flags.bits()).is_none() {
+        return Err(LxError::EINVAL);
+    }
+    with_client(
+        |client| match client.invoke(Request::PidFdOpen(pid, flags)) {
+            Response::Vfd(vfd) => crate::vfd::create(vfd, flags.open_flags()),
+            Response::Error(err) => Err(err),
+            _ => ipc_fail(),
+        },
+    )
+}
+
+/// Duplicates file descriptor `targetfd` of the process referred by `pidfd` into current process.
+pub fn pidfd_getfd(pidfd: c_int, targetfd: c_int, flags: u32) -> Result<c_int, LxError> {
+    if flags != 0 {
+        return Err(LxError::EINVAL);
+    }
+    let vfd = crate::vfd::get(pidfd).ok_or(LxError::EBADF)?;
+    let native_pid = with_client(|client| match client.invoke(Request::PidFdGetPid(vfd)) {
+        Response::Pid(pid) => Ok(pid),
+        Response::Error(err) => Err(err),
+        _ => ipc_fail(),
+    })?;
+
+    if native_pid == unsafe { libc::getpid() } {
+        let fd = crate::io::dup(targetfd)?;
+        crate::io::set_cloexec(fd).inspect_err(|_| _ = crate::io::close(fd))?;
+        return Ok(fd);
+    }
+
+    if !crate::security::may_access_process(native_pid) {
+        return Err(explain(
+            LxError::EPERM,
+            "access to the target process is denied by the ptrace access mode check",
+        ));
+    }
+
+    // macOS provides no way to steal a file descriptor from another task without its cooperation.
+    Err(explain(
+        LxError::EOPNOTSUPP,
+        "file descriptors of other processes cannot be taken on macOS",
+    ))
+}
+
 pub fn kill(pid: i32, signum: SigNum) -> Result<(), LxError> {
     let pid = match pid {
         0 => 0,
@@ -237,15 +672,104 @@ pub fn kill(pid: i32, signum: SigNum) -> Result<(), LxError> {
     };
     if is_tid(pid.abs()) {
         // TODO
-        return Err(LxError::EPERM);
+        return Err(explain(
+            LxError::EPERM,
+            "threads of other processes cannot be signaled",
+        ));
     }
     unsafe { posix_result(libc::kill(pid, signum.to_apple()?)) }
 }
 
 /// Does preparation work for the newly-created process.
 fn prepare_new_process(client: Client) {
-    if client.invoke(Request::AfterFork(pid())).is_err() {
+    context().id_cache.invalidate();
+    context().stat_cache.clear();
+    crate::sync::futex::after_fork();
+    crate::io_uring::after_fork();
+    crate::random::after_fork();
+    let native_pid = unsafe { libc::getpid() };
+    if let Response::Error(_) = client.invoke(Request::AfterFork(native_pid)) {
         crate::error_report::fast_fail();
     }
     crate::ipc_client::update_client(client);
+    crate::thread::set_id(pid());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elf_header(class: u8, data: u8, machine: u16) -> [u8; exec::ELF64_HEADER_SIZE] {
+        let mut buf = [0; exec::ELF64_HEADER_SIZE];
+        buf[..4].copy_from_slice(exec::ELF_MAGIC);
+        buf[4] = class;
+        buf[5] = data;
+        buf[18..20].copy_from_slice(&machine.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn long_arguments_fail_with_e2big() {
+        let long = vec![b'a'; MAX_ARG_STRLEN];
+        assert_eq!(check_exec_size(&[&long[1..]], &[], 8 << 20), Ok(()));
+        assert_eq!(check_exec_size(&[&long], &[], 8 << 20), Err(LxError::E2BIG));
+        assert_eq!(
+            check_exec_size(&[b"sh"], &[&long], 8 << 20),
+            Err(LxError::E2BIG)
+        );
+    }
+
+    #[test]
+    fn arguments_are_limited_by_the_stack() {
+        let string = vec![b'a'; 1023];
+        let strings = vec![&string[..]; 1024];
+
+        // About 1 MiB of strings, which needs a 4 MiB stack.
+        assert_eq!(check_exec_size(&strings, &[], 8 << 20), Ok(()));
+        assert_eq!(check_exec_size(&strings, &[], 4 << 20), Err(LxError::E2BIG));
+        assert_eq!(check_exec_size(&[], &strings, 4 << 20), Err(LxError::E2BIG));
+
+        // Small stack limits still allow the Linux minimum of 128 KiB.
+        assert_eq!(check_exec_size(&strings[..100], &[], 0), Ok(()));
+        assert_eq!(
+            check_exec_size(&strings[..200], &[], 0),
+            Err(LxError::E2BIG)
+        );
+    }
+
+    #[test]
+    fn text_file_is_not_executable() {
+        assert_eq!(check_image_header(b"echo hello\n"), Err(LxError::ENOEXEC));
+        assert_eq!(check_image_header(b""), Err(LxError::ENOEXEC));
+        assert_eq!(check_image_header(b" #!/bin/sh\n"), Err(LxError::ENOEXEC));
+    }
+
+    #[test]
+    fn scripts_follow_the_loader_rule() {
+        assert_eq!(check_image_header(b"#!/bin/sh\necho hello\n"), Ok(()));
+        assert_eq!(check_image_header(b"#!  \n"), Err(LxError::ENOEXEC));
+
+        let mut long = [b'a'; BINPRM_BUF_SIZE];
+        long[..3].copy_from_slice(b"#!/");
+        assert_eq!(check_image_header(&long), Err(LxError::ENOEXEC));
+        long[10] = b' ';
+        assert_eq!(check_image_header(&long), Ok(()));
+    }
+
+    #[test]
+    fn only_x86_64_elf_images_are_executable() {
+        assert_eq!(check_image_header(&elf_header(2, 1, 62)), Ok(()));
+        assert_eq!(
+            check_image_header(&elf_header(1, 1, 3)),
+            Err(LxError::ENOEXEC)
+        );
+        assert_eq!(
+            check_image_header(&elf_header(2, 1, 183)),
+            Err(LxError::ENOEXEC)
+        );
+        assert_eq!(
+            check_image_header(&elf_header(2, 1, 62)[..20]),
+            Err(LxError::ENOEXEC)
+        );
+    }
 }
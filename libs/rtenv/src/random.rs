@@ -0,0 +1,93 @@
+//! Random number sources.
+//!
+//! Randomness comes from `arc4random`, which macOS reseeds by itself in forked children, so parent and child never
+//! share a stream. The deterministic generator is forked explicitly: the child is seeded with a value that the parent
+//! draws from its own stream and skips, so that streams of both processes differ while staying reproducible.
+
+use crate::switches;
+use std::sync::atomic::{self, AtomicU64};
+use structures::{error::LxError, misc::GrndFlags};
+
+/// Number of values drawn from the deterministic generator so far.
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Seed of the deterministic generator drawn from the parent's generator on fork, or `0` if this process uses the
+/// configured seed.
+static FORKED_SEED: AtomicU64 = AtomicU64::new(0);
+
+/// Seed drawn by [`before_fork`] for the child being forked.
+static CHILD_SEED: AtomicU64 = AtomicU64::new(0);
+
+/// Fills `buf` with random bytes.
+///
+/// In deterministic mode, the bytes come from a generator seeded with the configured seed, so that the same program
+/// observes the same random stream across runs.
+pub fn fill(buf: &mut [u8]) {
+    match switches::deterministic() {
+        Some(seed) => {
+            let seed = stream_seed(seed);
+            for chunk in buf.chunks_mut(size_of::<u64>()) {
+                let value = next_deterministic(seed).to_ne_bytes();
+                chunk.copy_from_slice(&value[..chunk.len()]);
+            }
+        }
+        None => unsafe {
+            libc::arc4random_buf(buf.as_mut_ptr().cast(), buf.len());
+        },
+    }
+}
+
+/// Fills `buf` with random bytes, like `getrandom`, returning the number of filled bytes.
+///
+/// Neither source ever blocks, since macOS seeds its generator before user space starts, so `GRND_NONBLOCK` and
+/// `GRND_INSECURE` need no special treatment beyond validation.
+pub fn getrandom(buf: &mut [u8], flags: GrndFlags) -> Result<usize, LxError> {
+    if !GrndFlags::all().contains(flags)
+        || flags.contains(GrndFlags::GRND_RANDOM | GrndFlags::GRND_INSECURE)
+    {
+        return Err(LxError::EINVAL);
+    }
+    if !flags.contains(GrndFlags::GRND_RANDOM) || switches::deterministic().is_some() {
+        fill(buf);
+        return Ok(buf.len());
+    }
+
+    // Like Linux, `GRND_RANDOM` reads from the blocking pool, which may return fewer bytes than requested.
+    let file = std::fs::File::open("/dev/random")?;
+    Ok(std::io::Read::read(&mut &file, buf)?)
+}
+
+/// Draws the seed of a forked child from the deterministic generator in the parent, so that the parent never uses it.
+pub(crate) fn before_fork() {
+    if let Some(seed) = switches::deterministic() {
+        CHILD_SEED.store(
+            next_deterministic(stream_seed(seed)),
+            atomic::Ordering::Relaxed,
+        );
+    }
+}
+
+/// Reseeds the deterministic generator in a forked child with the seed drawn by [`before_fork`].
+pub(crate) fn after_fork() {
+    let seed = CHILD_SEED.load(atomic::Ordering::Relaxed);
+    FORKED_SEED.store(seed, atomic::Ordering::Relaxed);
+    COUNTER.store(0, atomic::Ordering::Relaxed);
+}
+
+fn stream_seed(configured: u64) -> u64 {
+    match FORKED_SEED.load(atomic::Ordering::Relaxed) {
+        0 => configured,
+        x => x,
+    }
+}
+
+/// Returns the next value of the deterministic generator, which is SplitMix64.
+fn next_deterministic(seed: u64) -> u64 {
+    const GAMMA: u64 = 0x9e3779b97f4a7c15;
+
+    let n = COUNTER.fetch_add(1, atomic::Ordering::Relaxed);
+    let mut z = seed.wrapping_add(n.wrapping_add(1).wrapping_mul(GAMMA));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
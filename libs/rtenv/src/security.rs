@@ -1,5 +1,13 @@
-use std::ffi::c_uint;
-use structures::{error::LxError, security::UserCap};
+use crate::{error_report::explain, rust::OwnedRtFd};
+use std::{
+    ffi::{c_int, c_uint},
+    io::Read,
+    sync::Arc,
+};
+use structures::{
+    error::LxError,
+    security::{NGROUPS_MAX, UserCap},
+};
 
 pub fn uid() -> c_uint {
     unsafe { libc::getuid() }
@@ -14,15 +22,64 @@ pub fn suid() -> c_uint {
     unsafe { libc::getuid() }
 }
 
+/// Sets user ID of current process.
+///
+/// When a privileged process switches to another user, its supplementary groups are replaced with the ones the user
+/// belongs to, so groups of the previous user are not carried over. This fails without switching if they cannot be
+/// looked up.
 pub fn setuid(target_uid: c_uint) -> Result<(), LxError> {
     if target_uid == uid() {
         return Ok(());
     }
-    Err(LxError::EPERM)
+    if euid() != 0 {
+        return Err(explain(
+            LxError::EPERM,
+            "switching to another user requires effective UID 0",
+        ));
+    }
+    let groups = group_list(target_uid)?;
+    unsafe {
+        if libc::setuid(target_uid) == -1 {
+            return Err(LxError::last_apple_error());
+        }
+    }
+    crate::process::context()
+        .groups
+        .store(Some(Arc::new(groups)));
+    Ok(())
 }
 
 pub fn setfsuid(_uid: c_uint) -> Result<(), LxError> {
-    Err(LxError::EPERM)
+    Err(explain(LxError::EPERM, "filesystem UIDs are not supported"))
+}
+
+/// Returns `true` if current process is permitted to access internals of the process with the given native PID, like
+/// what `ptrace` access mode checks do.
+///
+/// Processes that are not dumpable may only be accessed by privileged processes.
+pub fn may_access_process(native_pid: libc::pid_t) -> bool {
+    let euid = euid();
+    if euid == 0 {
+        return true;
+    }
+    if !crate::process::dumpable(native_pid).unwrap_or(false) {
+        return false;
+    }
+    unsafe {
+        let mut info: libc::proc_bsdinfo = std::mem::zeroed();
+        let size = size_of::<libc::proc_bsdinfo>() as c_int;
+        let status = libc::proc_pidinfo(
+            native_pid,
+            libc::PROC_PIDTBSDINFO,
+            0,
+            (&raw mut info).cast(),
+            size,
+        );
+        if status != size {
+            return false;
+        }
+        info.pbi_uid == euid && info.pbi_ruid == uid()
+    }
 }
 
 pub fn gid() -> c_uint {
@@ -39,14 +96,23 @@ pub fn sgid() -> c_uint {
 }
 
 pub fn setgid(_gid: c_uint) -> Result<(), LxError> {
-    Err(LxError::EPERM)
+    Err(explain(
+        LxError::EPERM,
+        "changing group ID is not supported",
+    ))
 }
 
 pub fn setfsgid(_gid: c_uint) -> Result<(), LxError> {
-    Err(LxError::EPERM)
+    Err(explain(LxError::EPERM, "filesystem GIDs are not supported"))
 }
 
+/// Returns supplementary groups of current process.
+///
+/// Groups set by [`setgroups`] are emulated, since macOS supports much fewer supplementary groups than Linux.
 pub fn groups() -> Vec<c_uint> {
+    if let Some(groups) = &*crate::process::context().groups.load() {
+        return groups.to_vec();
+    }
     loop {
         unsafe {
             let n = libc::getgroups(0, std::ptr::null_mut());
@@ -61,6 +127,69 @@ pub fn groups() -> Vec<c_uint> {
     }
 }
 
+/// Sets supplementary groups of current process.
+pub fn setgroups(groups: Vec<c_uint>) -> Result<(), LxError> {
+    if groups.len() > NGROUPS_MAX {
+        return Err(LxError::EINVAL);
+    }
+    if euid() != 0 {
+        return Err(explain(
+            LxError::EPERM,
+            "setting supplementary groups requires effective UID 0",
+        ));
+    }
+    crate::process::context()
+        .groups
+        .store(Some(Arc::new(groups)));
+    Ok(())
+}
+
+/// Returns groups that the user `uid` belongs to, according to the guest's `/etc/passwd` and `/etc/group`.
+///
+/// Like `getgrouplist()`, the primary group of the user comes first. Fails with `ENOENT` if the user is not found.
+pub fn group_list(uid: c_uint) -> Result<Vec<c_uint>, LxError> {
+    let passwd = read_guest_file(b"/etc/passwd")?;
+    let group = read_guest_file(b"/etc/group")?;
+    user_groups(uid, &passwd, &group).ok_or(LxError::ENOENT)
+}
+
+/// Returns groups that the user `uid` belongs to, given contents of `/etc/passwd` and `/etc/group`.
+fn user_groups(uid: c_uint, passwd: &[u8], group: &[u8]) -> Option<Vec<c_uint>> {
+    let (name, gid) = db_entries(passwd)
+        .filter(|fields| fields.len() >= 4 && parse_id(fields[2]) == Some(uid))
+        .find_map(|fields| Some((fields[0], parse_id(fields[3])?)))?;
+
+    let mut groups = vec![gid];
+    for fields in db_entries(&group).filter(|fields| fields.len() >= 4) {
+        let Some(gid) = parse_id(fields[2]) else {
+            continue;
+        };
+        if fields[3].split(|x| *x == b',').any(|x| x == name) && !groups.contains(&gid) {
+            groups.push(gid);
+        }
+    }
+    Some(groups)
+}
+
+fn read_guest_file(path: &[u8]) -> Result<Vec<u8>, LxError> {
+    let mut file = OwnedRtFd::open(path.to_vec())?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).map_err(|_| LxError::EIO)?;
+    Ok(buf)
+}
+
+/// Iterates over colon-separated fields of entries in a user or group database.
+fn db_entries(content: &[u8]) -> impl Iterator<Item = Vec<&[u8]>> {
+    content
+        .split(|x| *x == b'\n')
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .map(|line| line.split(|x| *x == b':').collect())
+}
+
+fn parse_id(field: &[u8]) -> Option<c_uint> {
+    std::str::from_utf8(field).ok()?.parse().ok()
+}
+
 pub fn capget(pid: i32) -> Result<UserCap, LxError> {
     if pid == 0 || pid == crate::process::pid() {
         // TODO: implement capget logic
@@ -74,3 +203,43 @@ pub fn capset(cap: UserCap) -> Result<(), LxError> {
     }
     Err(LxError::ENOSYS)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWD: &[u8] = b"# users\nroot:x:0:0:root:/root:/bin/sh\n\nalice:x:1000:100::/home/alice:/bin/sh\nbroken:x:1001\n";
+    const GROUP: &[u8] =
+        b"root:x:0:\nusers:x:100:\nwheel:x:10:root,alice\naudio:x:29:bob,alice\nbad:x:nope:alice\n";
+
+    #[test]
+    fn db_entries_skip_comments_and_blank_lines() {
+        let entries: Vec<_> = db_entries(PASSWD).collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0],
+            [
+                &b"root"[..],
+                b"x",
+                b"0",
+                b"0",
+                b"root",
+                b"/root",
+                b"/bin/sh"
+            ]
+        );
+        assert_eq!(entries[2], [&b"broken"[..], b"x", b"1001"]);
+    }
+
+    #[test]
+    fn primary_group_comes_first() {
+        assert_eq!(user_groups(1000, PASSWD, GROUP), Some(vec![100, 10, 29]));
+        assert_eq!(user_groups(0, PASSWD, GROUP), Some(vec![0, 10]));
+    }
+
+    #[test]
+    fn unknown_or_malformed_users_are_not_found() {
+        assert_eq!(user_groups(1001, PASSWD, GROUP), None);
+        assert_eq!(user_groups(2000, PASSWD, GROUP), None);
+    }
+}
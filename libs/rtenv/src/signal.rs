@@ -1,12 +1,6 @@
 use crate::{emuctx::in_emulated, process};
 use libc::c_int;
-use std::{
-    mem::offset_of,
-    sync::{
-        Arc,
-        atomic::{self, AtomicU8},
-    },
-};
+use std::{mem::offset_of, sync::Arc};
 use structures::{
     FromApple, ToApple,
     error::LxError,
@@ -48,9 +42,15 @@ const HANDLED_SIGNALS: &[c_int] = &[
 
 /// Installs signal handlers.
 pub fn install() -> std::io::Result<()> {
+    // This is read by the `SIGSEGV` handler, where the environment cannot be safely accessed.
+    crate::switches::fs_rewrite();
+
     install_for(libc::SIGSEGV, handle_sigsegv)?;
     install_for(libc::SIGABRT, handle_sigabrt)?;
     install_for(libc::SIGEMT, handle_sigemt)?;
+    if crate::switches::telemetry() {
+        install_for(libc::SIGUSR2, handle_sigusr2)?;
+    }
 
     Ok(())
 }
@@ -165,6 +165,25 @@ pub fn without_signals<T>(f: impl FnOnce() -> T) -> T {
     value
 }
 
+/// Runs `f` with the signal mask temporarily replaced with `set`, like `ppoll` and `pselect6` do.
+///
+/// The original mask is restored when `f` returns, even by unwinding. With `set` being `None`, the mask is left
+/// unchanged.
+pub fn with_mask<T>(set: Option<KernelSigSet>, f: impl FnOnce() -> T) -> Result<T, LxError> {
+    struct Restore(KernelSigSet);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            _ = mask(MaskHowto::SIG_SETMASK, Some(self.0));
+        }
+    }
+
+    let Some(set) = set else {
+        return Ok(f());
+    };
+    let _restore = Restore(mask(MaskHowto::SIG_SETMASK, Some(set))?);
+    Ok(f())
+}
+
 pub fn mask(howto: MaskHowto, set: Option<KernelSigSet>) -> Result<KernelSigSet, LxError> {
     unsafe {
         let mut old = std::mem::zeroed();
@@ -191,7 +210,7 @@ pub fn sigaction(signum: SigNum, new: Option<SigAction>) -> Result<SigAction, Lx
     };
 
     let apple_signum = signum.to_apple()?;
-    if !HANDLED_SIGNALS.contains(&apple_signum) {
+    if !HANDLED_SIGNALS.contains(&apple_signum) || is_reserved(apple_signum) {
         process::context()
             .sigactions
             .get(signum.0 as usize)
@@ -315,18 +334,10 @@ unsafe extern "C" fn handle_sigsegv(_: c_int, info: &libc::siginfo_t, ctx: &mut
     }
 
     unsafe {
-        let insc_byte = (*ctx.uc_mcontext).__ss.__rip as usize as *const AtomicU8;
-        match (*insc_byte).compare_exchange(
-            0x64,
-            0x65,
-            atomic::Ordering::Relaxed,
-            atomic::Ordering::Relaxed,
-        ) {
-            Ok(_) => (),
-            Err(_) => {
-                crate::emuctx::leave_emulated();
-                raise(SigNum::SIGSEGV, info, ctx, true);
-            }
+        let insc_byte = (*ctx.uc_mcontext).__ss.__rip as usize as *mut u8;
+        if !crate::emuctx::should_rewrite_fs() || !crate::mm::rewrite_code(insc_byte, 0x64, 0x65) {
+            crate::emuctx::leave_emulated();
+            raise(SigNum::SIGSEGV, info, ctx, true);
         }
     }
 }
@@ -362,6 +373,16 @@ unsafe extern "C" fn handle_sigemt(_: c_int, info: &libc::siginfo_t, ctx: &mut l
     }
 }
 
+/// Handles SIGUSR2 when telemetry is enabled, by requesting a latency summary.
+unsafe extern "C" fn handle_sigusr2(_: c_int, _: &libc::siginfo_t, _: &mut libc::ucontext_t) {
+    crate::telemetry::request_dump();
+}
+
+/// Returns `true` if the given macOS signal is reserved by MacTux, so handlers set by the program are only recorded.
+fn is_reserved(apple_signum: c_int) -> bool {
+    apple_signum == libc::SIGUSR2 && crate::switches::telemetry()
+}
+
 /// Reentrantly judges if we are in the emulated context.
 fn reentrant_in_emulated(info: &libc::siginfo_t) -> bool {
     if is_async(info) {
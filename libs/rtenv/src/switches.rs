@@ -1,3 +1,5 @@
+use std::{sync::OnceLock, time::Duration};
+
 #[inline]
 pub fn ignore_unsupported_syscalls() -> bool {
     matches!(
@@ -10,3 +12,117 @@ pub fn ignore_unsupported_syscalls() -> bool {
 pub fn strace() -> bool {
     matches!(std::env::var("MacTux_Strace").as_deref(), Ok("1"))
 }
+
+/// Returns `true` if latency of system calls is recorded, as is described in [`crate::telemetry`].
+///
+/// This is configured with `MacTux_Telemetry=1`. While enabled, `SIGUSR2` requests a latency summary instead of being
+/// delivered to the program. The value is cached on first use, which [`crate::signal::install`] ensures to be out of
+/// signal handlers.
+pub fn telemetry() -> bool {
+    static VALUE: OnceLock<bool> = OnceLock::new();
+    *VALUE.get_or_init(|| matches!(std::env::var("MacTux_Telemetry").as_deref(), Ok("1")))
+}
+
+/// Returns `true` if failed system calls are logged with reasons, as is described in [`crate::error_report`].
+///
+/// This is configured with `MacTux_ExplainErrors=1`. The value is cached on first use.
+pub fn explain_errors() -> bool {
+    static VALUE: OnceLock<bool> = OnceLock::new();
+    *VALUE.get_or_init(|| matches!(std::env::var("MacTux_ExplainErrors").as_deref(), Ok("1")))
+}
+
+/// Returns seed of the deterministic mode, or `None` if the mode is disabled.
+///
+/// This is configured with `MacTux_Deterministic=<seed>`. In deterministic mode, memory mappings without an address
+/// hint are placed at fixed addresses, random sources are seeded with `seed`, and `CLOCK_REALTIME` starts from a fixed
+/// point, so that two runs of the same program observe the same memory layout and random streams. The stack of the
+/// initial thread is still provided by macOS, so its address is not covered.
+pub fn deterministic() -> Option<u64> {
+    static SEED: OnceLock<Option<u64>> = OnceLock::new();
+    *SEED.get_or_init(|| std::env::var("MacTux_Deterministic").ok()?.parse().ok())
+}
+
+/// Returns `true` if `fs` accesses may be rewritten to `gs` ones.
+///
+/// This is configured with `MacTux_FsRewrite`, and is enabled unless the value is `0`. Since macOS provides no way to set
+/// the FSBASE register, instructions accessing thread-local storage through `fs` fault, and are rewritten in place to
+/// use `gs`, whose base is switched when entering the emulated context. Rewriting only happens for threads that set a
+/// thread pointer, so programs without thread-local storage never have their code pages modified. With rewriting
+/// disabled, such faults are delivered to the program as `SIGSEGV`.
+///
+/// The value is cached on first use, which [`crate::signal::install`] ensures to be out of signal handlers.
+pub fn fs_rewrite() -> bool {
+    static VALUE: OnceLock<bool> = OnceLock::new();
+    *VALUE.get_or_init(|| !matches!(std::env::var("MacTux_FsRewrite").as_deref(), Ok("0")))
+}
+
+/// Returns how long a request to the server may wait for its response, or `None` if it may wait forever.
+///
+/// This is configured with `MacTux_IpcTimeout=<milliseconds>`, and is disabled by default, since some requests, like
+/// reading an empty eventfd, block in the server by design. A request that times out fails with `EIO`, as is described
+/// in [`crate::ipc_client::Client::invoke`]. The value is cached on first use.
+pub fn ipc_timeout() -> Option<Duration> {
+    static VALUE: OnceLock<Option<Duration>> = OnceLock::new();
+    *VALUE.get_or_init(|| {
+        let millis: u64 = std::env::var("MacTux_IpcTimeout").ok()?.parse().ok()?;
+        (millis != 0).then(|| Duration::from_millis(millis))
+    })
+}
+
+/// Returns `true` if the `TIOCSTI` ioctl, which pushes bytes into the input queue of a terminal, is allowed.
+///
+/// This is configured with `MacTux_LegacyTiocsti=1`, and is disabled by default, like the `dev.tty.legacy_tiocsti`
+/// sysctl of hardened Linux kernels, since it lets a program type commands into the shell that reads the terminal.
+#[inline]
+pub fn legacy_tiocsti() -> bool {
+    matches!(std::env::var("MacTux_LegacyTiocsti").as_deref(), Ok("1"))
+}
+
+/// Action to take when a program issues an ioctl that MacTux does not implement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownIoctlAction {
+    /// Make the process crash.
+    Crash,
+
+    /// Fail with `EINVAL`.
+    Einval,
+
+    /// Fail with `ENOTTY`, which is what Linux does for unknown ioctls on most files.
+    Notty,
+
+    /// Pretend that the ioctl succeeded, returning 0.
+    Success,
+}
+
+/// Class of a file that an ioctl is issued on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoctlClass {
+    /// A native file descriptor.
+    Native,
+
+    /// A virtual file descriptor managed by the server.
+    Vfd,
+}
+impl IoctlClass {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Native => "Native",
+            Self::Vfd => "Vfd",
+        }
+    }
+}
+
+/// Returns the action to take for unknown ioctls issued on files of the given class.
+///
+/// This is configured with `MacTux_UnknownIoctl_<Class>`, falling back to `MacTux_UnknownIoctl`. Accepted values are
+/// `crash`, `einval`, `notty` and `success`, and the default is `notty`.
+pub fn unknown_ioctl(class: IoctlClass) -> UnknownIoctlAction {
+    let value = std::env::var(format!("MacTux_UnknownIoctl_{}", class.name()))
+        .or_else(|_| std::env::var("MacTux_UnknownIoctl"));
+    match value.as_deref() {
+        Ok("crash") => UnknownIoctlAction::Crash,
+        Ok("einval") => UnknownIoctlAction::Einval,
+        Ok("success") => UnknownIoctlAction::Success,
+        _ => UnknownIoctlAction::Notty,
+    }
+}
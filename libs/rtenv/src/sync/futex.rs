@@ -1,63 +1,95 @@
-use std::sync::atomic::{self, AtomicU32};
+//! Futexes.
+//!
+//! Private futexes are waited on through a wait queue of this process, which records the bitset each waiter has, so
+//! that wakeups only reach the threads they are meant for. Each waiter blocks on a word of its own, so the wait is
+//! still interruptible by signals.
+//!
+//! Shared futexes may be waited on by other processes, whose waiters cannot be known, so they are waited on directly,
+//! and their bitsets are not honored.
+
+use rustc_hash::FxHashMap;
+use std::{
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{self, AtomicU32, AtomicUsize},
+    },
+    time::Duration,
+};
 use structures::{
     error::LxError,
-    sync::{FutexOpts, FutexWakeOpVal3},
+    sync::{
+        FUTEX_BITSET_MATCH_ANY, FUTEX_OWNER_DIED, FUTEX_TID_MASK, FUTEX_WAITERS, FutexCmd, FutexOp,
+        FutexOpts, FutexWakeOpVal3, ROBUST_LIST_LIMIT, RobustList, RobustListHead,
+    },
+    time::ClockId,
 };
 
+/// Threads of this process that are blocked on private futexes, keyed by addresses of futex words, in order of arrival.
+static WAITERS: LazyLock<Mutex<FxHashMap<usize, Vec<Arc<Waiter>>>>> =
+    LazyLock::new(|| Mutex::new(FxHashMap::default()));
+
+/// A thread blocked on a private futex.
+#[derive(Debug)]
+struct Waiter {
+    bitset: u32,
+
+    /// Address of the futex word the waiter is queued on, which changes when it is requeued. This is only changed
+    /// with [`WAITERS`] locked.
+    uaddr: AtomicUsize,
+
+    /// Becomes `1` once the waiter is woken. The thread blocks on this word rather than the futex word, so that it is
+    /// only woken when it is chosen.
+    woken: AtomicU32,
+}
+impl Waiter {
+    fn wake(&self) {
+        self.woken.store(1, atomic::Ordering::SeqCst);
+        unsafe {
+            libc::os_sync_wake_by_address_any(
+                self.woken.as_ptr().cast(),
+                4,
+                libc::OS_SYNC_WAKE_BY_ADDRESS_NONE,
+            );
+        }
+    }
+}
+
+/// Forgets waiters of the parent process, whose threads do not exist in a forked child.
+pub fn after_fork() {
+    WAITERS.lock().unwrap().clear();
+}
+
+/// Checks that the clock `op` selects is supported by its command.
+///
+/// Like Linux, `FUTEX_CLOCK_REALTIME` is only accepted by commands that wait with a timeout measured by a clock, and
+/// fails with `ENOSYS` otherwise.
+pub fn check_clock(op: FutexOp) -> Result<(), LxError> {
+    if op.opts().contains(FutexOpts::FUTEX_CLOCK_REALTIME)
+        && !matches!(op.cmd(), FutexCmd::FUTEX_WAIT | FutexCmd::FUTEX_WAIT_BITSET)
+    {
+        return Err(LxError::ENOSYS);
+    }
+    Ok(())
+}
+
+/// Waits on the futex at `uaddr` if it holds `val`, like `FUTEX_WAIT`. The timeout is relative, whichever clock
+/// `opts` selects.
 pub unsafe fn wait(
     uaddr: *mut u32,
     val: u32,
     utime: *mut libc::timespec,
     opts: FutexOpts,
 ) -> Result<(), LxError> {
-    let flags = if opts.contains(FutexOpts::FUTEX_PRIVATE_FLAG) {
-        0
-    } else {
-        libc::OS_SYNC_WAIT_ON_ADDRESS_SHARED
+    let timeout = match utime.is_null() {
+        true => None,
+        false => Some(duration(unsafe { utime.read() })?),
     };
-    unsafe {
-        let result = if utime.is_null() {
-            libc::os_sync_wait_on_address(uaddr.cast(), val as _, 4, flags)
-        } else {
-            let timeout = utime.read();
-            let timeout = std::time::Duration::new(timeout.tv_sec as _, timeout.tv_nsec as _);
-            libc::os_sync_wait_on_address_with_timeout(
-                uaddr.cast(),
-                val as _,
-                4,
-                flags,
-                0,
-                timeout.as_nanos() as _,
-            )
-        };
-
-        match result {
-            -1 => Err(LxError::last_apple_error()),
-            _ => Ok(()),
-        }
-    }
+    unsafe { wait_timeout(uaddr, val, timeout, opts, FUTEX_BITSET_MATCH_ANY) }
 }
 
+/// Wakes up to `val` waiters of the futex at `uaddr`, like `FUTEX_WAKE`, returning the number of woken waiters.
 pub unsafe fn wake(uaddr: *mut u32, val: u32, opts: FutexOpts) -> Result<usize, LxError> {
-    let flags = if opts.contains(FutexOpts::FUTEX_PRIVATE_FLAG) {
-        0
-    } else {
-        libc::OS_SYNC_WAKE_BY_ADDRESS_SHARED
-    };
-    unsafe {
-        for n in 0..val {
-            match libc::os_sync_wake_by_address_any(uaddr.cast(), 4, flags) {
-                -1 => {
-                    if *libc::__error() == libc::ENOENT {
-                        return Ok(n as usize + 1);
-                    }
-                    return Err(LxError::last_apple_error());
-                }
-                _ => continue,
-            }
-        }
-        Ok(0)
-    }
+    unsafe { wake_bitset(uaddr, val, opts, FUTEX_BITSET_MATCH_ANY) }
 }
 
 pub unsafe fn wake_op(
@@ -85,6 +117,10 @@ pub unsafe fn wake_op(
     }
 }
 
+/// Waits on the futex at `uaddr` if it holds `val`, like `FUTEX_WAIT_BITSET`. The timeout is an absolute point of
+/// `CLOCK_REALTIME` if `opts` contains `FUTEX_CLOCK_REALTIME`, or `CLOCK_MONOTONIC` otherwise.
+///
+/// Only wakeups whose bitsets share a bit with `bitset` wake the waiter.
 pub unsafe fn wait_bitset(
     uaddr: *mut u32,
     val: u32,
@@ -95,34 +131,18 @@ pub unsafe fn wait_bitset(
     if bitset == 0 {
         return Err(LxError::EINVAL);
     }
-    let flags = if opts.contains(FutexOpts::FUTEX_PRIVATE_FLAG) {
-        0
-    } else {
-        libc::OS_SYNC_WAIT_ON_ADDRESS_SHARED
+    let timeout = match utime.is_null() {
+        true => None,
+        false => Some(remaining(unsafe { utime.read() }, opts)?),
     };
-    unsafe {
-        let result = if utime.is_null() {
-            libc::os_sync_wait_on_address(uaddr.cast(), val as _, 4, flags)
-        } else {
-            let timeout = utime.read();
-            let timeout = std::time::Duration::new(timeout.tv_sec as _, timeout.tv_nsec as _);
-            libc::os_sync_wait_on_address_with_timeout(
-                uaddr.cast(),
-                val as _,
-                4,
-                flags,
-                0,
-                timeout.as_nanos() as _,
-            )
-        };
-
-        match result {
-            -1 => Err(LxError::last_apple_error()),
-            _ => Ok(()),
-        }
-    }
+    unsafe { wait_timeout(uaddr, val, timeout, opts, bitset) }
 }
 
+/// Wakes up to `val` waiters of the futex at `uaddr` whose bitsets share a bit with `bitset`, like
+/// `FUTEX_WAKE_BITSET`, returning the number of woken waiters.
+///
+/// Waiters of private futexes are woken in order of arrival. Waking a shared futex also wakes waiters of the private
+/// futex at the same address, like Linux does for private mappings.
 pub unsafe fn wake_bitset(
     uaddr: *mut u32,
     val: u32,
@@ -132,23 +152,502 @@ pub unsafe fn wake_bitset(
     if bitset == 0 {
         return Err(LxError::EINVAL);
     }
-    let flags = if opts.contains(FutexOpts::FUTEX_PRIVATE_FLAG) {
-        0
-    } else {
-        libc::OS_SYNC_WAKE_BY_ADDRESS_SHARED
+    let mut count = 0;
+    {
+        let mut waiters = WAITERS.lock().unwrap();
+        if let Some(list) = waiters.get_mut(&(uaddr as usize)) {
+            list.retain(|waiter| {
+                if count >= val as usize || (waiter.bitset & bitset) == 0 {
+                    return true;
+                }
+                waiter.wake();
+                count += 1;
+                false
+            });
+            if list.is_empty() {
+                waiters.remove(&(uaddr as usize));
+            }
+        }
+    }
+    if !opts.contains(FutexOpts::FUTEX_PRIVATE_FLAG) {
+        while count < val as usize {
+            let result = unsafe {
+                libc::os_sync_wake_by_address_any(
+                    uaddr.cast(),
+                    4,
+                    libc::OS_SYNC_WAKE_BY_ADDRESS_SHARED,
+                )
+            };
+            if result == -1 {
+                if unsafe { *libc::__error() } == libc::ENOENT {
+                    break;
+                }
+                return Err(LxError::last_apple_error());
+            }
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Wakes up to `val` waiters of the futex at `uaddr`, and moves up to `val2` of the remaining ones to the futex at
+/// `uaddr2`, like `FUTEX_CMP_REQUEUE`, returning the total number of woken and moved waiters.
+///
+/// If `cmpval` is `Some`, nothing happens unless the futex at `uaddr` holds it. Moved waiters are queued after
+/// existing waiters of `uaddr2`, and are woken by wakeups of `uaddr2` from then on.
+///
+/// Waiters of shared futexes cannot be moved, so waiters that should be moved are woken instead, and wait on `uaddr2`
+/// again once they see the condition they wait for has not become true. Linux permits such spurious wakeups.
+pub unsafe fn requeue(
+    uaddr: *mut u32,
+    val: u32,
+    val2: u32,
+    uaddr2: *mut u32,
+    cmpval: Option<u32>,
+    opts: FutexOpts,
+) -> Result<usize, LxError> {
+    if uaddr.is_null() || !uaddr.is_aligned() || uaddr2.is_null() || !uaddr2.is_aligned() {
+        return Err(LxError::EINVAL);
+    }
+    if (val2 as i32) < 0 {
+        return Err(LxError::EINVAL);
+    }
+    if !opts.contains(FutexOpts::FUTEX_PRIVATE_FLAG) {
+        if let Some(cmpval) = cmpval
+            && unsafe { (*uaddr.cast::<AtomicU32>()).load(atomic::Ordering::SeqCst) } != cmpval
+        {
+            return Err(LxError::EAGAIN);
+        }
+        return unsafe { wake(uaddr, val.saturating_add(val2), opts) };
+    }
+
+    let mut waiters = WAITERS.lock().unwrap();
+    if let Some(cmpval) = cmpval
+        && unsafe { (*uaddr.cast::<AtomicU32>()).load(atomic::Ordering::SeqCst) } != cmpval
+    {
+        return Err(LxError::EAGAIN);
+    }
+    let Some(mut list) = waiters.remove(&(uaddr as usize)) else {
+        return Ok(0);
     };
+
+    let woken = list.len().min(val as usize);
+    for waiter in list.drain(..woken) {
+        waiter.wake();
+    }
+    let moved = list.len().min(val2 as usize);
+    if uaddr == uaddr2 {
+        // Requeueing onto the same futex leaves the waiters where they are.
+    } else if moved != 0 {
+        let dest = waiters.entry(uaddr2 as usize).or_default();
+        for waiter in list.drain(..moved) {
+            waiter
+                .uaddr
+                .store(uaddr2 as usize, atomic::Ordering::Relaxed);
+            dest.push(waiter);
+        }
+    }
+    if !list.is_empty() {
+        waiters.insert(uaddr as usize, list);
+    }
+    Ok(woken + moved)
+}
+
+/// Waits on the futex at `uaddr` if it holds `val`, for at most `timeout`.
+unsafe fn wait_timeout(
+    uaddr: *mut u32,
+    val: u32,
+    timeout: Option<Duration>,
+    opts: FutexOpts,
+    bitset: u32,
+) -> Result<(), LxError> {
+    if uaddr.is_null() || !uaddr.is_aligned() {
+        return Err(LxError::EINVAL);
+    }
+    if !opts.contains(FutexOpts::FUTEX_PRIVATE_FLAG) {
+        if timeout == Some(Duration::ZERO) {
+            return match unsafe { (*uaddr.cast::<AtomicU32>()).load(atomic::Ordering::SeqCst) } {
+                x if x == val => Err(LxError::ETIMEDOUT),
+                _ => Err(LxError::EAGAIN),
+            };
+        }
+        return unsafe { block(uaddr, val, timeout, libc::OS_SYNC_WAIT_ON_ADDRESS_SHARED) };
+    }
+
+    let waiter = Arc::new(Waiter {
+        bitset,
+        uaddr: AtomicUsize::new(uaddr as usize),
+        woken: AtomicU32::new(0),
+    });
+    {
+        // Wakers take the lock too, so a change of the futex word cannot slip between the check and the wait.
+        let mut waiters = WAITERS.lock().unwrap();
+        if unsafe { (*uaddr.cast::<AtomicU32>()).load(atomic::Ordering::SeqCst) } != val {
+            return Err(LxError::EAGAIN);
+        }
+        if timeout == Some(Duration::ZERO) {
+            return Err(LxError::ETIMEDOUT);
+        }
+        waiters
+            .entry(uaddr as usize)
+            .or_default()
+            .push(waiter.clone());
+    }
+
+    let result = unsafe {
+        block(
+            waiter.woken.as_ptr(),
+            0,
+            timeout,
+            libc::OS_SYNC_WAIT_ON_ADDRESS_NONE,
+        )
+    };
+
+    let mut waiters = WAITERS.lock().unwrap();
+    if waiter.woken.load(atomic::Ordering::SeqCst) != 0 {
+        return Ok(());
+    }
+    let uaddr = waiter.uaddr.load(atomic::Ordering::Relaxed);
+    if let Some(list) = waiters.get_mut(&uaddr) {
+        list.retain(|x| !Arc::ptr_eq(x, &waiter));
+        if list.is_empty() {
+            waiters.remove(&uaddr);
+        }
+    }
+    result
+}
+
+/// Blocks until the word at `addr` is woken or no longer holds `val`, or `timeout` elapses.
+unsafe fn block(
+    addr: *mut u32,
+    val: u32,
+    timeout: Option<Duration>,
+    flags: libc::os_sync_wait_on_address_flags_t,
+) -> Result<(), LxError> {
+    let result = unsafe {
+        match timeout {
+            None => libc::os_sync_wait_on_address(addr.cast(), val as _, 4, flags),
+            Some(timeout) => libc::os_sync_wait_on_address_with_timeout(
+                addr.cast(),
+                val as _,
+                4,
+                flags,
+                libc::OS_CLOCK_MACH_ABSOLUTE_TIME,
+                timeout.as_nanos().min(u64::MAX as u128) as _,
+            ),
+        }
+    };
+    match result {
+        -1 => Err(LxError::last_apple_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns how long it is until the absolute timeout `deadline` of a futex operation, whose clock is selected by
+/// `FUTEX_CLOCK_REALTIME` in `opts`.
+///
+/// The wait itself is timed by a host clock, so a `CLOCK_REALTIME` deadline does not move when the clock is set
+/// during the wait.
+fn remaining(deadline: libc::timespec, opts: FutexOpts) -> Result<Duration, LxError> {
+    let deadline = duration(deadline)?;
+    let now = match opts.contains(FutexOpts::FUTEX_CLOCK_REALTIME) {
+        true => crate::time::realtime()?,
+        false => crate::time::clock_gettime(ClockId::CLOCK_MONOTONIC)?,
+    };
+    Ok(deadline.saturating_sub(duration(now)?))
+}
+
+/// Converts a timeout of a futex operation to a [`Duration`].
+fn duration(timeout: libc::timespec) -> Result<Duration, LxError> {
+    if timeout.tv_sec < 0 || !(0..1_000_000_000).contains(&timeout.tv_nsec) {
+        return Err(LxError::EINVAL);
+    }
+    Ok(Duration::new(timeout.tv_sec as _, timeout.tv_nsec as _))
+}
+
+/// Releases robust futexes held by the exiting thread `tid`, whose robust list starts at `head`.
+///
+/// Like Linux, each futex owned by the thread is marked with `FUTEX_OWNER_DIED` and one of its waiters is woken, so
+/// that it can recover the lock instead of waiting forever. The lowest bit of list entries tells PI futexes, whose
+/// waiters are woken through [`super::pi_futex`].
+pub unsafe fn exit_robust_list(head: *const RobustListHead, tid: i32) {
+    let entry_addr =
+        |entry: Option<std::ptr::NonNull<RobustList>>| entry.map_or(0, |x| x.addr().get());
+
     unsafe {
-        for n in 0..val {
-            match libc::os_sync_wake_by_address_any(uaddr.cast(), 4, flags) {
-                -1 => {
-                    if *libc::__error() == libc::ENOENT {
-                        return Ok(n as usize + 1);
+        let end = head.addr();
+        let RobustListHead {
+            list,
+            futex_offset,
+            list_op_pending,
+        } = head.read();
+        let pending = entry_addr(list_op_pending);
+
+        let mut entry = entry_addr(list.next);
+        for _ in 0..ROBUST_LIST_LIMIT {
+            if (entry & !1) == 0 || (entry & !1) == end {
+                break;
+            }
+            let next = entry_addr(((entry & !1) as *const RobustList).read().next);
+            if (entry & !1) != (pending & !1) {
+                futex_death(entry, futex_offset, tid, false);
+            }
+            entry = next;
+        }
+        if pending != 0 {
+            futex_death(pending, futex_offset, tid, true);
+        }
+    }
+}
+
+/// Handles death of the owner `tid` of the robust futex referred to by the robust list entry `entry`, whose lowest bit
+/// tells if the futex is a PI one.
+///
+/// If `pending` is `true`, the thread might have died between acquiring or releasing the lock and updating the list,
+/// so a waiter is woken even if the futex is unowned.
+unsafe fn futex_death(entry: usize, futex_offset: i64, tid: i32, pending: bool) {
+    let pi = (entry & 1) != 0;
+    let uaddr = ((entry & !1) as i64).wrapping_add(futex_offset) as *mut u32;
+    if uaddr.is_null() || !uaddr.is_aligned() {
+        return;
+    }
+
+    unsafe {
+        let word = &*uaddr.cast::<AtomicU32>();
+        let mut uval = word.load(atomic::Ordering::SeqCst);
+        if pending && !pi && uval == 0 {
+            wake_any(uaddr);
+            return;
+        }
+        loop {
+            if (uval & FUTEX_TID_MASK) != tid as u32 {
+                return;
+            }
+            let mval = (uval & FUTEX_WAITERS) | FUTEX_OWNER_DIED;
+            match word.compare_exchange(
+                uval,
+                mval,
+                atomic::Ordering::SeqCst,
+                atomic::Ordering::SeqCst,
+            ) {
+                Ok(_) => {
+                    if pi {
+                        super::pi_futex::owner_died(uaddr);
+                    } else if (mval & FUTEX_WAITERS) != 0 {
+                        wake_any(uaddr);
                     }
-                    return Err(LxError::last_apple_error());
+                    return;
                 }
-                _ => continue,
+                Err(actual) => uval = actual,
             }
         }
-        Ok(0)
+    }
+}
+
+/// Wakes one waiter of a futex that may be either private or shared, since the robust list does not tell.
+///
+/// Waking a shared futex reaches waiters of both kinds.
+unsafe fn wake_any(uaddr: *mut u32) {
+    unsafe {
+        _ = wake(uaddr, 1, FutexOpts::empty());
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "macos")]
+mod tests {
+    use super::*;
+    use std::thread::JoinHandle;
+
+    /// Bitsets of waiters queued on the private futex `word`, in order of arrival.
+    fn queued(word: &AtomicU32) -> Vec<u32> {
+        WAITERS
+            .lock()
+            .unwrap()
+            .get(&(word.as_ptr() as usize))
+            .map_or_else(Vec::new, |list| list.iter().map(|x| x.bitset).collect())
+    }
+
+    /// Spawns a thread waiting on the private futex `word` with `bitset`, and returns once it is queued.
+    fn spawn_waiter(word: &Arc<AtomicU32>, bitset: u32) -> JoinHandle<Result<(), LxError>> {
+        let expected = queued(word).len() + 1;
+        let handle = {
+            let word = Arc::clone(word);
+            std::thread::spawn(move || unsafe {
+                wait_bitset(
+                    word.as_ptr(),
+                    0,
+                    std::ptr::null_mut(),
+                    FutexOpts::FUTEX_PRIVATE_FLAG,
+                    bitset,
+                )
+            })
+        };
+        while queued(word).len() < expected {
+            std::thread::yield_now();
+        }
+        handle
+    }
+
+    #[test]
+    fn wake_bitset_wakes_matching_waiters_only() {
+        let word = Arc::new(AtomicU32::new(0));
+        let first = spawn_waiter(&word, 0b01);
+        let second = spawn_waiter(&word, 0b10);
+        let both = spawn_waiter(&word, 0b11);
+
+        let woken =
+            unsafe { wake_bitset(word.as_ptr(), u32::MAX, FutexOpts::FUTEX_PRIVATE_FLAG, 0b10) };
+        assert_eq!(woken, Ok(2));
+        second.join().unwrap().unwrap();
+        both.join().unwrap().unwrap();
+        assert_eq!(queued(&word), [0b01]);
+
+        let woken = unsafe {
+            wake_bitset(
+                word.as_ptr(),
+                u32::MAX,
+                FutexOpts::FUTEX_PRIVATE_FLAG,
+                0b100,
+            )
+        };
+        assert_eq!(woken, Ok(0));
+        assert_eq!(
+            unsafe { wake_bitset(word.as_ptr(), 1, FutexOpts::FUTEX_PRIVATE_FLAG, 0) },
+            Err(LxError::EINVAL)
+        );
+
+        let woken = unsafe { wake(word.as_ptr(), u32::MAX, FutexOpts::FUTEX_PRIVATE_FLAG) };
+        assert_eq!(woken, Ok(1));
+        first.join().unwrap().unwrap();
+        assert!(queued(&word).is_empty());
+    }
+
+    #[test]
+    fn cmp_requeue_wakes_and_moves_waiters() {
+        let word = Arc::new(AtomicU32::new(0));
+        let dest = Arc::new(AtomicU32::new(0));
+        let first = spawn_waiter(&word, 0b001);
+        let second = spawn_waiter(&word, 0b010);
+        let third = spawn_waiter(&word, 0b100);
+        let opts = FutexOpts::FUTEX_PRIVATE_FLAG;
+
+        let result = unsafe { requeue(word.as_ptr(), 1, 1, dest.as_ptr(), Some(1), opts) };
+        assert_eq!(result, Err(LxError::EAGAIN));
+        assert_eq!(queued(&word), [0b001, 0b010, 0b100]);
+        let result = unsafe { requeue(word.as_ptr(), 1, u32::MAX, dest.as_ptr(), Some(0), opts) };
+        assert_eq!(result, Err(LxError::EINVAL));
+
+        let result = unsafe { requeue(word.as_ptr(), 1, 1, dest.as_ptr(), Some(0), opts) };
+        assert_eq!(result, Ok(2));
+        first.join().unwrap().unwrap();
+        assert_eq!(queued(&word), [0b100]);
+        assert_eq!(queued(&dest), [0b010]);
+
+        // The moved waiter is only woken through the futex it was moved to.
+        assert_eq!(
+            unsafe { wake_bitset(word.as_ptr(), u32::MAX, opts, 0b010) },
+            Ok(0)
+        );
+        assert_eq!(unsafe { wake(dest.as_ptr(), u32::MAX, opts) }, Ok(1));
+        second.join().unwrap().unwrap();
+        assert_eq!(unsafe { wake(word.as_ptr(), u32::MAX, opts) }, Ok(1));
+        third.join().unwrap().unwrap();
+        assert!(queued(&word).is_empty() && queued(&dest).is_empty());
+    }
+
+    #[test]
+    fn absolute_timeouts_use_selected_clock() {
+        let later = |now: libc::timespec| libc::timespec {
+            tv_sec: now.tv_sec + 10,
+            ..now
+        };
+        let realtime = later(crate::time::realtime().unwrap());
+        let monotonic = later(crate::time::clock_gettime(ClockId::CLOCK_MONOTONIC).unwrap());
+        let within = |x: Duration| Duration::from_secs(9) < x && x <= Duration::from_secs(10);
+
+        assert!(within(remaining(monotonic, FutexOpts::empty()).unwrap()));
+        assert!(within(
+            remaining(realtime, FutexOpts::FUTEX_CLOCK_REALTIME).unwrap()
+        ));
+
+        // The clocks are decades apart, so a deadline of one clock is measured wrongly by the other.
+        assert!(remaining(realtime, FutexOpts::empty()).unwrap() > Duration::from_secs(3600));
+        assert_eq!(
+            remaining(monotonic, FutexOpts::FUTEX_CLOCK_REALTIME),
+            Ok(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn realtime_clock_is_only_accepted_by_waits() {
+        let op = |cmd: FutexCmd, opts: FutexOpts| FutexOp(cmd.0 | opts.bits());
+        for cmd in [FutexCmd::FUTEX_WAIT, FutexCmd::FUTEX_WAIT_BITSET] {
+            assert_eq!(
+                check_clock(op(cmd, FutexOpts::FUTEX_CLOCK_REALTIME)),
+                Ok(())
+            );
+        }
+        for cmd in [
+            FutexCmd::FUTEX_WAKE,
+            FutexCmd::FUTEX_CMP_REQUEUE,
+            FutexCmd::FUTEX_LOCK_PI,
+            FutexCmd::FUTEX_WAKE_BITSET,
+        ] {
+            assert_eq!(check_clock(op(cmd, FutexOpts::empty())), Ok(()));
+            assert_eq!(
+                check_clock(op(cmd, FutexOpts::FUTEX_CLOCK_REALTIME)),
+                Err(LxError::ENOSYS)
+            );
+        }
+    }
+
+    /// A lock that is linked into a robust list, with its futex word at a fixed offset from the entry.
+    #[repr(C)]
+    struct RobustMutex {
+        list: RobustList,
+        word: AtomicU32,
+    }
+
+    #[test]
+    fn exiting_owner_of_robust_lock_wakes_waiter() {
+        const TID: u32 = 201;
+        let mut held = RobustMutex {
+            list: RobustList { next: None },
+            word: AtomicU32::new(TID | FUTEX_WAITERS),
+        };
+        let mut other = RobustMutex {
+            list: RobustList { next: None },
+            word: AtomicU32::new(TID + 1),
+        };
+        let mut head = RobustListHead {
+            list: RobustList { next: None },
+            futex_offset: std::mem::offset_of!(RobustMutex, word) as i64,
+            list_op_pending: None,
+        };
+        head.list.next = std::ptr::NonNull::new(&raw mut held.list);
+        held.list.next = std::ptr::NonNull::new(&raw mut other.list);
+        other.list.next = std::ptr::NonNull::new(&raw mut head.list);
+
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| unsafe {
+                wait(
+                    held.word.as_ptr(),
+                    TID | FUTEX_WAITERS,
+                    std::ptr::null_mut(),
+                    FutexOpts::FUTEX_PRIVATE_FLAG,
+                )
+            });
+            while queued(&held.word).is_empty() {
+                std::thread::yield_now();
+            }
+            unsafe { exit_robust_list(&head, TID as i32) };
+            waiter.join().unwrap().unwrap();
+        });
+        assert_eq!(
+            held.word.load(atomic::Ordering::SeqCst),
+            FUTEX_WAITERS | FUTEX_OWNER_DIED
+        );
+        assert_eq!(other.word.load(atomic::Ordering::SeqCst), TID + 1);
     }
 }
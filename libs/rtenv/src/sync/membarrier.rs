@@ -0,0 +1,123 @@
+//! Implementation of the `membarrier` system call.
+
+use std::{
+    ffi::c_int,
+    sync::{
+        Mutex,
+        atomic::{self, AtomicI32},
+    },
+};
+use structures::{error::LxError, sync::MembarrierCmd};
+
+/// Commands that are supported by this implementation, as is returned by `MEMBARRIER_CMD_QUERY`.
+const SUPPORTED: c_int = MembarrierCmd::MEMBARRIER_CMD_GLOBAL.0
+    | MembarrierCmd::MEMBARRIER_CMD_GLOBAL_EXPEDITED.0
+    | MembarrierCmd::MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED.0
+    | MembarrierCmd::MEMBARRIER_CMD_PRIVATE_EXPEDITED.0
+    | MembarrierCmd::MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED.0
+    | MembarrierCmd::MEMBARRIER_CMD_PRIVATE_EXPEDITED_SYNC_CORE.0
+    | MembarrierCmd::MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED_SYNC_CORE.0
+    | MembarrierCmd::MEMBARRIER_CMD_GET_REGISTRATIONS.0;
+
+/// Registration commands that current process has issued.
+static REGISTRATIONS: AtomicI32 = AtomicI32::new(0);
+
+/// Performs a `membarrier` command.
+pub fn membarrier(cmd: MembarrierCmd, flags: u32) -> Result<c_int, LxError> {
+    if flags != 0 {
+        return Err(LxError::EINVAL);
+    }
+
+    match cmd {
+        MembarrierCmd::MEMBARRIER_CMD_QUERY => Ok(SUPPORTED),
+        MembarrierCmd::MEMBARRIER_CMD_GLOBAL | MembarrierCmd::MEMBARRIER_CMD_GLOBAL_EXPEDITED => {
+            barrier();
+            Ok(0)
+        }
+        MembarrierCmd::MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED
+        | MembarrierCmd::MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED
+        | MembarrierCmd::MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED_SYNC_CORE => {
+            REGISTRATIONS.fetch_or(cmd.0, atomic::Ordering::SeqCst);
+            Ok(0)
+        }
+        MembarrierCmd::MEMBARRIER_CMD_PRIVATE_EXPEDITED => {
+            require(MembarrierCmd::MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED)?;
+            barrier();
+            Ok(0)
+        }
+        MembarrierCmd::MEMBARRIER_CMD_PRIVATE_EXPEDITED_SYNC_CORE => {
+            // The barrier interrupts all running threads of current process, which is serializing and thus also
+            // synchronizes their instruction streams.
+            require(MembarrierCmd::MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED_SYNC_CORE)?;
+            barrier();
+            Ok(0)
+        }
+        MembarrierCmd::MEMBARRIER_CMD_GET_REGISTRATIONS => {
+            Ok(REGISTRATIONS.load(atomic::Ordering::SeqCst))
+        }
+        _ => Err(LxError::EINVAL),
+    }
+}
+
+/// Fails with [`LxError::EPERM`] if the registration command `register` has not been issued by current process.
+fn require(register: MembarrierCmd) -> Result<(), LxError> {
+    match REGISTRATIONS.load(atomic::Ordering::SeqCst) & register.0 {
+        0 => Err(LxError::EPERM),
+        _ => Ok(()),
+    }
+}
+
+/// Issues a memory barrier on all running threads of current process.
+///
+/// macOS does not provide a `membarrier` equivalent, so we change protection of a dirty page, which forces a TLB
+/// shootdown on all processors that are running threads of current process.
+fn barrier() {
+    static PAGE: Mutex<usize> = Mutex::new(0);
+
+    let mut page = PAGE.lock().unwrap();
+    atomic::fence(atomic::Ordering::SeqCst);
+    unsafe {
+        let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        if *page == 0 {
+            match libc::mmap(
+                std::ptr::null_mut(),
+                page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANON,
+                -1,
+                0,
+            ) {
+                libc::MAP_FAILED => return,
+                addr => *page = addr as usize,
+            }
+        }
+        libc::mprotect(*page as _, page_size, libc::PROT_READ | libc::PROT_WRITE);
+        (*page as *mut u8).write_volatile(0);
+        libc::mprotect(*page as _, page_size, libc::PROT_NONE);
+    }
+    atomic::fence(atomic::Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_expedited_requires_registration() {
+        let private = MembarrierCmd::MEMBARRIER_CMD_PRIVATE_EXPEDITED;
+        let sync_core = MembarrierCmd::MEMBARRIER_CMD_PRIVATE_EXPEDITED_SYNC_CORE;
+        let register = MembarrierCmd::MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED;
+
+        assert_eq!(membarrier(private, 0), Err(LxError::EPERM));
+        assert_eq!(membarrier(register, 1), Err(LxError::EINVAL));
+        assert_eq!(membarrier(register, 0), Ok(0));
+        assert_eq!(membarrier(private, 0), Ok(0));
+        assert_eq!(
+            membarrier(MembarrierCmd::MEMBARRIER_CMD_GET_REGISTRATIONS, 0),
+            Ok(register.0)
+        );
+
+        // Registrations are separate for each command.
+        assert_eq!(membarrier(sync_core, 0), Err(LxError::EPERM));
+    }
+}
@@ -1,3 +1,4 @@
 pub mod futex;
+pub mod membarrier;
 pub mod pi_futex;
 pub mod rseq;
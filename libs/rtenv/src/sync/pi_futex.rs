@@ -1,43 +1,347 @@
-use std::sync::atomic::{AtomicU32, Ordering};
-use structures::{error::LxError, sync::FUTEX_WAITERS};
+//! Priority-inheritance futexes.
+//!
+//! Like Linux, the futex word holds TID of the owner, with `FUTEX_WAITERS` set while threads are blocked on it, so that
+//! the owner enters the kernel to unlock it. Threads of this process that block on a futex are recorded, and unlocking
+//! hands the lock directly over to the waiter with the highest priority. macOS provides no way to boost priority of
+//! the owner, so priorities only decide the order of handoffs.
+//!
+//! Waiters in other processes are not known to the unlocking thread, and neither is the death of an owner that has no
+//! robust list, so blocked threads also recheck the futex word periodically, and take it over once it becomes free.
 
-pub unsafe fn lock(word: &mut AtomicU32) {
+use crate::thread::with_context;
+use rustc_hash::FxHashMap;
+use std::{
+    sync::{
+        Arc, Condvar, LazyLock, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use structures::{
+    error::LxError,
+    sync::{FUTEX_OWNER_DIED, FUTEX_TID_MASK, FUTEX_WAITERS},
+    thread::SchedPolicy,
+};
+
+/// How often blocked threads recheck the futex word.
+const RECHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Threads of this process that are blocked on PI futexes, keyed by addresses of futex words, in order of arrival.
+static WAITERS: LazyLock<Mutex<FxHashMap<usize, Vec<Arc<Waiter>>>>> =
+    LazyLock::new(|| Mutex::new(FxHashMap::default()));
+
+/// A thread blocked on a PI futex.
+#[derive(Debug)]
+struct Waiter {
+    tid: u32,
+    priority: i32,
+    state: Mutex<WaiterState>,
+    cond: Condvar,
+}
+impl Waiter {
+    fn new(tid: u32) -> Self {
+        let (policy, priority) =
+            with_context(|ctx| (ctx.sched_policy.get(), ctx.sched_priority.get()));
+        let priority = match policy {
+            SchedPolicy::SCHED_FIFO | SchedPolicy::SCHED_RR => priority,
+            _ => 0,
+        };
+        Self {
+            tid,
+            priority,
+            state: Mutex::new(WaiterState::Waiting),
+            cond: Condvar::new(),
+        }
+    }
+
+    /// Blocks until the waiter is woken, or until the next recheck or `deadline` is due.
+    fn wait(&self, deadline: Option<Instant>) {
+        let mut timeout = RECHECK_INTERVAL;
+        if let Some(deadline) = deadline {
+            timeout = timeout.min(deadline.saturating_duration_since(Instant::now()));
+        }
+        let state = self.state.lock().unwrap();
+        if *state == WaiterState::Waiting {
+            drop(self.cond.wait_timeout(state, timeout).unwrap());
+        }
+    }
+
+    fn wake(&self, state: WaiterState) {
+        *self.state.lock().unwrap() = state;
+        self.cond.notify_one();
+    }
+
+    fn state(&self) -> WaiterState {
+        *self.state.lock().unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WaiterState {
+    /// The thread is blocked.
+    Waiting,
+
+    /// The lock has been handed over to the thread.
+    Granted,
+
+    /// The thread should recheck the futex word.
+    Woken,
+}
+
+/// Acquires the PI futex at `uaddr` for the current thread, like `FUTEX_LOCK_PI`, blocking until `deadline` if it is
+/// owned by another thread.
+///
+/// If the previous owner died, `FUTEX_OWNER_DIED` is kept in the futex word for the caller to notice.
+pub unsafe fn lock(uaddr: *mut u32, deadline: Option<Instant>) -> Result<(), LxError> {
+    let word = unsafe { futex_word(uaddr)? };
     let tid = crate::thread::id() as u32;
     loop {
-        if word
-            .compare_exchange(0, tid, Ordering::SeqCst, Ordering::SeqCst)
-            .is_ok()
-        {
-            break;
+        let mut waiters = WAITERS.lock().unwrap();
+        let has_waiters = waiters
+            .get(&(uaddr as usize))
+            .is_some_and(|x| !x.is_empty());
+        if try_acquire(word, tid, has_waiters)? {
+            return Ok(());
+        }
+        if deadline.is_some_and(|x| x <= Instant::now()) {
+            return Err(LxError::ETIMEDOUT);
+        }
+        if word.fetch_or(FUTEX_WAITERS, Ordering::SeqCst) & FUTEX_TID_MASK == 0 {
+            // The lock was released in the meantime.
+            continue;
+        }
+        let waiter = Arc::new(Waiter::new(tid));
+        waiters
+            .entry(uaddr as usize)
+            .or_default()
+            .push(waiter.clone());
+        drop(waiters);
+
+        waiter.wait(deadline);
+
+        let mut waiters = WAITERS.lock().unwrap();
+        if waiter.state() == WaiterState::Granted {
+            return Ok(());
+        }
+        if let Some(list) = waiters.get_mut(&(uaddr as usize)) {
+            list.retain(|x| !Arc::ptr_eq(x, &waiter));
+            if list.is_empty() {
+                waiters.remove(&(uaddr as usize));
+            }
         }
-        word.fetch_or(FUTEX_WAITERS, Ordering::SeqCst);
     }
 }
 
-pub unsafe fn try_lock(word: &mut AtomicU32) -> Result<(), LxError> {
+/// Acquires the PI futex at `uaddr` for the current thread if it is not owned by another thread, like
+/// `FUTEX_TRYLOCK_PI`.
+pub unsafe fn try_lock(uaddr: *mut u32) -> Result<(), LxError> {
+    let word = unsafe { futex_word(uaddr)? };
     let tid = crate::thread::id() as u32;
-    if word
-        .compare_exchange(0, tid, Ordering::SeqCst, Ordering::SeqCst)
-        .is_ok()
-    {
-        Ok(())
-    } else {
-        Err(LxError::EAGAIN)
+    let waiters = WAITERS.lock().unwrap();
+    let has_waiters = waiters
+        .get(&(uaddr as usize))
+        .is_some_and(|x| !x.is_empty());
+    match try_acquire(word, tid, has_waiters)? {
+        true => Ok(()),
+        false => Err(LxError::EAGAIN),
     }
 }
 
-pub unsafe fn unlock(word: &mut AtomicU32) -> Result<(), LxError> {
+/// Releases the PI futex at `uaddr`, which must be owned by the current thread, like `FUTEX_UNLOCK_PI`.
+///
+/// The lock is handed over to the blocked thread with the highest priority, or the earliest one among those with the
+/// same priority. Without blocked threads in this process, the futex becomes free.
+pub unsafe fn unlock(uaddr: *mut u32) -> Result<(), LxError> {
+    let word = unsafe { futex_word(uaddr)? };
     let tid = crate::thread::id() as u32;
-    let result = word.try_update(Ordering::SeqCst, Ordering::SeqCst, |x| {
-        if (x & !FUTEX_WAITERS) == tid {
-            Some(0)
-        } else {
-            None
-        }
-    });
-    if result.is_ok() {
-        Ok(())
-    } else {
-        Err(LxError::EPERM)
+    let mut waiters = WAITERS.lock().unwrap();
+    if word.load(Ordering::SeqCst) & FUTEX_TID_MASK != tid {
+        return Err(LxError::EPERM);
+    }
+
+    if let Some(list) = waiters.get_mut(&(uaddr as usize))
+        && let Some(next) = top_waiter(list)
+    {
+        let next = list.remove(next);
+        let remaining = match list.is_empty() {
+            true => {
+                waiters.remove(&(uaddr as usize));
+                0
+            }
+            false => FUTEX_WAITERS,
+        };
+        word.store(next.tid | remaining, Ordering::SeqCst);
+        next.wake(WaiterState::Granted);
+        return Ok(());
+    }
+
+    // Waiters in other processes may set `FUTEX_WAITERS` concurrently, which does not change the owner.
+    let mut uval = word.load(Ordering::SeqCst);
+    while let Err(actual) = word.compare_exchange(uval, 0, Ordering::SeqCst, Ordering::SeqCst) {
+        if actual & FUTEX_TID_MASK != tid {
+            return Err(LxError::EPERM);
+        }
+        uval = actual;
+    }
+    Ok(())
+}
+
+/// Wakes the blocked thread with the highest priority after the owner of the PI futex at `uaddr` died, so that it
+/// takes the lock over.
+pub fn owner_died(uaddr: *mut u32) {
+    let waiters = WAITERS.lock().unwrap();
+    if let Some(list) = waiters.get(&(uaddr as usize))
+        && let Some(next) = top_waiter(list)
+    {
+        list[next].wake(WaiterState::Woken);
+    }
+}
+
+/// Converts an absolute `CLOCK_REALTIME` timeout of `FUTEX_LOCK_PI` to a deadline.
+pub fn deadline(timeout: libc::timespec) -> Result<Instant, LxError> {
+    if timeout.tv_sec < 0 || !(0..1_000_000_000).contains(&timeout.tv_nsec) {
+        return Err(LxError::EINVAL);
+    }
+    let now = crate::time::realtime()?;
+    let timeout = Duration::new(timeout.tv_sec as _, timeout.tv_nsec as _);
+    let now = Duration::new(now.tv_sec.max(0) as _, now.tv_nsec as _);
+    Ok(Instant::now() + timeout.saturating_sub(now))
+}
+
+/// Tries to take over the futex word if it has no owner, keeping `FUTEX_OWNER_DIED`.
+fn try_acquire(word: &AtomicU32, tid: u32, has_waiters: bool) -> Result<bool, LxError> {
+    let mut uval = word.load(Ordering::SeqCst);
+    loop {
+        match uval & FUTEX_TID_MASK {
+            0 => {}
+            owner if owner == tid => return Err(LxError::EDEADLK),
+            _ => return Ok(false),
+        }
+        let mut newval = tid | (uval & FUTEX_OWNER_DIED);
+        if has_waiters {
+            newval |= FUTEX_WAITERS;
+        }
+        match word.compare_exchange(uval, newval, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => return Ok(true),
+            Err(actual) => uval = actual,
+        }
+    }
+}
+
+/// Returns index of the waiter with the highest priority, preferring earlier ones.
+fn top_waiter(list: &[Arc<Waiter>]) -> Option<usize> {
+    let mut top: Option<usize> = None;
+    for (n, waiter) in list.iter().enumerate() {
+        if top.is_none_or(|x| waiter.priority > list[x].priority) {
+            top = Some(n);
+        }
+    }
+    top
+}
+
+unsafe fn futex_word<'a>(uaddr: *mut u32) -> Result<&'a AtomicU32, LxError> {
+    if uaddr.is_null() || !uaddr.is_aligned() {
+        return Err(LxError::EINVAL);
+    }
+    unsafe { Ok(&*uaddr.cast::<AtomicU32>()) }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "macos")]
+mod tests {
+    use super::*;
+    use std::thread::JoinHandle;
+
+    /// Runs `f` on a new thread whose TID is `tid`, as is seen by PI futexes.
+    fn run_as<T: Send + 'static>(
+        tid: i32,
+        f: impl FnOnce() -> T + Send + 'static,
+    ) -> JoinHandle<T> {
+        std::thread::spawn(move || {
+            unsafe { crate::thread::enter().unwrap() };
+            crate::thread::set_id(tid);
+            f()
+        })
+    }
+
+    fn queued(word: &AtomicU32) -> usize {
+        WAITERS
+            .lock()
+            .unwrap()
+            .get(&(word.as_ptr() as usize))
+            .map_or(0, Vec::len)
+    }
+
+    #[test]
+    fn unlocking_hands_the_lock_over() {
+        crate::init_for_tests();
+        let word = Arc::new(AtomicU32::new(0));
+
+        run_as(101, {
+            let word = word.clone();
+            move || unsafe {
+                assert_eq!(lock(word.as_ptr(), None), Ok(()));
+                assert_eq!(word.load(Ordering::SeqCst), 101);
+                assert_eq!(lock(word.as_ptr(), None), Err(LxError::EDEADLK));
+                assert_eq!(try_lock(word.as_ptr()), Err(LxError::EDEADLK));
+            }
+        })
+        .join()
+        .unwrap();
+
+        run_as(102, {
+            let word = word.clone();
+            move || unsafe {
+                assert_eq!(try_lock(word.as_ptr()), Err(LxError::EAGAIN));
+                assert_eq!(unlock(word.as_ptr()), Err(LxError::EPERM));
+                let deadline = Instant::now() + Duration::from_millis(50);
+                assert_eq!(lock(word.as_ptr(), Some(deadline)), Err(LxError::ETIMEDOUT));
+            }
+        })
+        .join()
+        .unwrap();
+        assert_eq!(queued(&word), 0);
+
+        let waiter = run_as(102, {
+            let word = word.clone();
+            move || unsafe {
+                lock(word.as_ptr(), None).unwrap();
+                let uval = word.load(Ordering::SeqCst);
+                unlock(word.as_ptr()).unwrap();
+                uval
+            }
+        });
+        while queued(&word) == 0 {
+            std::thread::yield_now();
+        }
+        assert_eq!(word.load(Ordering::SeqCst), 101 | FUTEX_WAITERS);
+
+        run_as(101, {
+            let word = word.clone();
+            move || unsafe { unlock(word.as_ptr()) }
+        })
+        .join()
+        .unwrap()
+        .unwrap();
+        assert_eq!(waiter.join().unwrap(), 102);
+        assert_eq!(word.load(Ordering::SeqCst), 0);
+        assert_eq!(queued(&word), 0);
+    }
+
+    #[test]
+    fn lock_of_dead_owner_keeps_owner_died() {
+        crate::init_for_tests();
+        let word = Arc::new(AtomicU32::new(FUTEX_OWNER_DIED));
+        run_as(103, {
+            let word = word.clone();
+            move || unsafe {
+                assert_eq!(try_lock(word.as_ptr()), Ok(()));
+                assert_eq!(word.load(Ordering::SeqCst), 103 | FUTEX_OWNER_DIED);
+                assert_eq!(unlock(word.as_ptr()), Ok(()));
+            }
+        })
+        .join()
+        .unwrap();
+        assert_eq!(word.load(Ordering::SeqCst), 0);
     }
 }
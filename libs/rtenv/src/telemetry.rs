@@ -0,0 +1,195 @@
+//! System call latency telemetry, which is enabled by [`crate::switches::telemetry`].
+//!
+//! Each thread records durations of the system calls it handles into its own histograms, so recording never contends
+//! with other threads. Histograms of all threads are merged when a summary is dumped through the logger, which happens
+//! when the process exits with `exit_group`, or at the next system call after `SIGUSR2` is received.
+
+use crate::switches;
+use rustc_hash::FxHashMap;
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{self, AtomicBool},
+    },
+    time::{Duration, Instant},
+};
+
+/// Number of buckets of a histogram. Bucket `n` counts durations shorter than `2^n` nanoseconds that do not fit in
+/// previous buckets, and the last bucket counts everything longer.
+const BUCKETS: usize = 40;
+
+/// Histograms of all threads that have ever handled a system call with telemetry enabled.
+static THREADS: Mutex<Vec<Arc<ThreadHistograms>>> = Mutex::new(Vec::new());
+
+/// Set by `SIGUSR2` to request a dump at the next system call.
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Latency histograms of system calls handled by a thread, keyed by names of system call handlers.
+#[derive(Debug, Default)]
+pub struct ThreadHistograms(Mutex<FxHashMap<&'static str, Histogram>>);
+impl ThreadHistograms {
+    /// Creates histograms for the current thread, and registers them so they are included in dumps.
+    fn register() -> Arc<Self> {
+        let histograms = Arc::new(Self::default());
+        THREADS.lock().unwrap().push(histograms.clone());
+        histograms
+    }
+
+    fn record(&self, name: &'static str, elapsed: Duration) {
+        self.0
+            .lock()
+            .unwrap()
+            .entry(name)
+            .or_default()
+            .record(elapsed);
+    }
+}
+
+/// A histogram of system call durations with power-of-two buckets.
+#[derive(Debug, Clone)]
+struct Histogram {
+    count: u64,
+    buckets: [u64; BUCKETS],
+}
+impl Histogram {
+    fn record(&mut self, elapsed: Duration) {
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        let bucket = (u64::BITS - nanos.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.count += 1;
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.count += other.count;
+        for (x, y) in self.buckets.iter_mut().zip(other.buckets) {
+            *x += y;
+        }
+    }
+
+    /// Returns upper bound of the bucket that the given percentile falls in.
+    fn percentile(&self, p: u64) -> Duration {
+        let rank = (self.count * p).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (n, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(1 << n);
+            }
+        }
+        Duration::from_nanos(1 << (BUCKETS - 1))
+    }
+}
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            buckets: [0; BUCKETS],
+        }
+    }
+}
+
+/// Returns the time a system call starts at, or `None` if telemetry is disabled.
+#[inline]
+pub fn start() -> Option<Instant> {
+    switches::telemetry().then(Instant::now)
+}
+
+/// Records duration of the system call handled by `name`, which started at `start`.
+#[inline]
+pub fn finish(name: &'static str, start: Option<Instant>) {
+    if let Some(start) = start {
+        record(name, start.elapsed());
+    }
+}
+
+fn record(name: &'static str, elapsed: Duration) {
+    crate::thread::with_context(|ctx| {
+        ctx.syscall_latency
+            .get_or_init(ThreadHistograms::register)
+            .record(name, elapsed)
+    });
+    if DUMP_REQUESTED.swap(false, atomic::Ordering::Relaxed) {
+        dump();
+    }
+}
+
+/// Requests a dump at the next system call. This is async-signal-safe.
+pub fn request_dump() {
+    DUMP_REQUESTED.store(true, atomic::Ordering::Relaxed);
+}
+
+/// Merges histograms of all threads, and logs a latency summary for each system call, the most frequent first.
+///
+/// Percentiles are reported as upper bounds of histogram buckets, so they are accurate to a factor of two.
+pub fn dump() {
+    let mut merged = FxHashMap::<&'static str, Histogram>::default();
+    for thread in THREADS.lock().unwrap().iter() {
+        for (name, histogram) in thread.0.lock().unwrap().iter() {
+            merged.entry(*name).or_default().merge(histogram);
+        }
+    }
+    let mut merged: Vec<_> = merged.into_iter().collect();
+    merged.sort_by_key(|(name, histogram)| (std::cmp::Reverse(histogram.count), *name));
+
+    log::info!("system call latency summary of {} handlers", merged.len());
+    for (name, histogram) in merged {
+        log::info!(
+            "{name}: count={}, p50<={:?}, p99<={:?}",
+            histogram.count,
+            histogram.percentile(50),
+            histogram.percentile(99),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(samples: &[(Duration, usize)]) -> Histogram {
+        let mut histogram = Histogram::default();
+        for (elapsed, n) in samples {
+            for _ in 0..*n {
+                histogram.record(*elapsed);
+            }
+        }
+        histogram
+    }
+
+    #[test]
+    fn percentile_is_bucket_upper_bound() {
+        let histogram = histogram(&[
+            (Duration::from_micros(1), 99),
+            (Duration::from_millis(1), 1),
+        ]);
+        assert_eq!(histogram.percentile(50), Duration::from_nanos(1 << 10));
+        assert_eq!(histogram.percentile(99), Duration::from_nanos(1 << 10));
+        assert_eq!(histogram.percentile(100), Duration::from_nanos(1 << 20));
+        assert_eq!(histogram.percentile(0), Duration::from_nanos(1 << 10));
+    }
+
+    #[test]
+    fn percentile_rounds_rank_up() {
+        let histogram = histogram(&[(Duration::from_nanos(3), 1), (Duration::from_nanos(5), 1)]);
+        assert_eq!(histogram.percentile(50), Duration::from_nanos(1 << 2));
+        assert_eq!(histogram.percentile(51), Duration::from_nanos(1 << 3));
+    }
+
+    #[test]
+    fn long_durations_fall_in_last_bucket() {
+        let histogram = histogram(&[(Duration::MAX, 1)]);
+        assert_eq!(
+            histogram.percentile(50),
+            Duration::from_nanos(1 << (BUCKETS - 1))
+        );
+    }
+
+    #[test]
+    fn merged_histograms_add_up() {
+        let mut merged = histogram(&[(Duration::from_nanos(1), 3)]);
+        merged.merge(&histogram(&[(Duration::from_secs(1), 1)]));
+        assert_eq!(merged.count, 4);
+        assert_eq!(merged.percentile(75), Duration::from_nanos(1 << 1));
+        assert_eq!(merged.percentile(76), Duration::from_nanos(1 << 30));
+    }
+}
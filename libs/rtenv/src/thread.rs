@@ -1,28 +1,30 @@
 use crate::{
     emuctx::EmulatedThreadInfo,
+    error_report::LastError,
     ipc_client::{Client, with_client},
     process,
+    telemetry::ThreadHistograms,
     util::ipc_fail,
 };
 use crossbeam::queue::SegQueue;
 use rustc_hash::FxHashMap;
 use std::{
     cell::{Cell, OnceCell, RefCell, UnsafeCell},
-    ffi::c_void,
+    ffi::{c_int, c_void},
     ptr::NonNull,
     sync::{
         Arc, RwLock,
-        atomic::{self, AtomicI64, AtomicPtr, AtomicUsize},
+        atomic::{self, AtomicI32, AtomicI64, AtomicPtr, AtomicU32, AtomicUsize},
     },
 };
 use structures::{
-    FromApple,
+    ToApple,
     error::LxError,
     internal::mactux_ipc::{Request, Response},
     process::{CloneArgs, CloneFlags},
     signal::{SigAltStack, SigNum},
     sync::{FutexOpts, RobustListHead},
-    thread::TID_MIN,
+    thread::{SchedPolicy, TID_MIN},
 };
 
 static mut THREAD_CTX: libc::pthread_key_t = unsafe { std::mem::zeroed() };
@@ -36,9 +38,7 @@ pub unsafe fn install() -> std::io::Result<()> {
         enter()?;
     }
 
-    with_context(|ctx| unsafe {
-        ctx.tid.set(libc::getpid());
-    });
+    set_id(unsafe { libc::getpid() });
 
     Ok(())
 }
@@ -53,7 +53,12 @@ pub struct ThreadCtx {
     pub ipc_buf: RefCell<Vec<u8>>,
     pub clear_tid: Cell<Option<NonNull<u32>>>,
     pub sigaltstack: Cell<SigAltStack>,
+    pub sched_policy: Cell<SchedPolicy>,
+    pub sched_priority: Cell<c_int>,
     pub parent_thread: Option<libc::pid_t>,
+    pub syscall_latency: OnceCell<Arc<ThreadHistograms>>,
+    pub error_reason: Cell<Option<&'static str>>,
+    pub last_error: Cell<Option<LastError>>,
 }
 impl ThreadCtx {
     /// Creates a new thread context. All fields are initialized to the "empty" values.
@@ -66,7 +71,12 @@ impl ThreadCtx {
             ipc_buf: RefCell::new(Vec::with_capacity(256)),
             clear_tid: Cell::new(None),
             sigaltstack: Cell::new(SigAltStack::default()),
+            sched_policy: Cell::new(SchedPolicy::SCHED_OTHER),
+            sched_priority: Cell::new(0),
             parent_thread: None,
+            syscall_latency: OnceCell::new(),
+            error_reason: Cell::new(None),
+            last_error: Cell::new(None),
         }
     }
 
@@ -125,6 +135,18 @@ impl ThreadPubCtxMap {
         unsafe { f((*self.0.get()).read().unwrap().get(&thread_id).unwrap()) }
     }
 
+    /// Executes a closure with [`ThreadPubCtx`] of the thread with the given Linux TID, if it is in current process.
+    pub fn with_tid<T>(&self, tid: i32, f: impl FnOnce(&ThreadPubCtx) -> T) -> Option<T> {
+        unsafe {
+            (*self.0.get())
+                .read()
+                .unwrap()
+                .values()
+                .find(|x| x.tid.load(atomic::Ordering::Relaxed) == tid)
+                .map(|x| f(x))
+        }
+    }
+
     /// This is called on the new process after `fork()`.
     pub fn after_fork(&self, current: Box<ThreadPubCtx>) {
         unsafe {
@@ -165,6 +187,7 @@ pub fn may_fork<T>(fork: impl FnOnce() -> T, is_new: impl FnOnce(&T) -> bool) ->
 #[derive(Debug)]
 pub struct ThreadPubCtx {
     pub pthread: libc::pthread_t,
+    pub tid: AtomicI32,
     pub emulation: EmulatedThreadInfo,
     pub robust_list_head: AtomicPtr<RobustListHead>,
     pub robust_list_head_size: AtomicUsize,
@@ -175,6 +198,7 @@ impl ThreadPubCtx {
     pub fn new() -> Self {
         Self {
             pthread: unsafe { libc::pthread_self() },
+            tid: AtomicI32::new(0),
             emulation: EmulatedThreadInfo::new(),
             robust_list_head: AtomicPtr::new(std::ptr::null_mut()),
             robust_list_head_size: AtomicUsize::new(0),
@@ -186,6 +210,7 @@ impl Clone for ThreadPubCtx {
     fn clone(&self) -> Self {
         Self {
             pthread: unsafe { libc::pthread_self() },
+            tid: AtomicI32::new(self.tid.load(atomic::Ordering::Relaxed)),
             emulation: self.emulation.clone(),
             robust_list_head: AtomicPtr::new(self.robust_list_head.load(atomic::Ordering::Relaxed)),
             robust_list_head_size: AtomicUsize::new(
@@ -206,14 +231,69 @@ pub fn id() -> i32 {
     with_context(|ctx| ctx.tid.get())
 }
 
-/// Kills a thread.
+/// Records TID of this thread, so that it can be found by other threads.
+pub(crate) fn set_id(tid: i32) {
+    with_context(|ctx| ctx.tid.set(tid));
+    process::context()
+        .thread_pubctx_map
+        .with_current(|ctx| ctx.tid.store(tid, atomic::Ordering::Relaxed));
+}
+
+/// Sends a signal to the thread `tid`, like `tkill`.
+///
+/// macOS provides no way to signal a specific thread of another process, so threads of other processes can only be
+/// signaled if they are the main thread, in which case the signal is sent to the whole process.
 pub fn kill(tid: i32, signum: SigNum) -> Result<(), LxError> {
-    if tid < TID_MIN {
-        return crate::process::kill(tid, signum);
+    if tid <= 0 {
+        return Err(LxError::EINVAL);
+    }
+    let result = process::context()
+        .thread_pubctx_map
+        .with_tid(tid, |ctx| send_signal(ctx, signum));
+    match result {
+        Some(result) => result,
+        None if tid < TID_MIN => crate::process::kill(tid, signum),
+        None => Err(LxError::ESRCH),
     }
+}
 
-    // TODO
-    Err(LxError::ESRCH)
+/// Sends a signal to the thread `tid` of the thread group `tgid`, like `tgkill`.
+///
+/// This fails with `ESRCH` if the thread does not belong to the thread group, so a signal is never sent to another
+/// thread that reused the TID.
+pub fn tgkill(tgid: i32, tid: i32, signum: SigNum) -> Result<(), LxError> {
+    if tgid <= 0 || tid <= 0 {
+        return Err(LxError::EINVAL);
+    }
+    let actual_tgid = with_client(|client| match client.invoke(Request::GetThreadGroup(tid)) {
+        Response::Pid(pid) => Ok(pid),
+        Response::Error(err) => Err(err),
+        _ => ipc_fail(),
+    })?;
+    if actual_tgid != tgid {
+        return Err(LxError::ESRCH);
+    }
+    kill(tid, signum)
+}
+
+/// Delivers a signal to a thread of current process.
+///
+/// Signals that macOS has no counterparts of are queued and raised by the `SIGEMT` handler of the target thread.
+fn send_signal(ctx: &ThreadPubCtx, signum: SigNum) -> Result<(), LxError> {
+    if signum.0 == 0 {
+        return Ok(());
+    }
+    let status = match signum.to_apple() {
+        Ok(apple_signum) => unsafe { libc::pthread_kill(ctx.pthread, apple_signum) },
+        Err(_) => {
+            ctx.signal_queue.push(signum);
+            unsafe { libc::pthread_kill(ctx.pthread, libc::SIGEMT) }
+        }
+    };
+    match status {
+        0 => Ok(()),
+        err => Err(LxError::from_apple_errno(err)),
+    }
 }
 
 /// Gets `clear_child_tid` value of current thread.
@@ -228,6 +308,84 @@ pub fn set_clear_tid(value: Option<NonNull<u32>>) {
     with_context(|ctx| ctx.clear_tid.set(value));
 }
 
+/// Applies `CLONE_CHILD_SETTID` and `CLONE_CHILD_CLEARTID` of `args` in the newly created thread or process.
+pub fn set_child_tid(args: &CloneArgs, tid: i32) {
+    let flags = args.flags();
+    if flags.contains(CloneFlags::CLONE_CHILD_SETTID) {
+        unsafe {
+            args.child_tid().write(tid);
+        }
+    }
+    if flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
+        set_clear_tid(NonNull::new(args.child_tid().cast()));
+    }
+}
+
+/// Clears the `CLONE_CHILD_CLEARTID` word of an exiting thread and wakes a waiter of it, like Linux, so that joiners
+/// waiting for the word to become zero see the thread is gone.
+unsafe fn clear_child_tid(ptr: NonNull<u32>) {
+    unsafe {
+        (*ptr.as_ptr().cast::<AtomicU32>()).store(0, atomic::Ordering::SeqCst);
+        _ = crate::sync::futex::wake(ptr.as_ptr(), 1, FutexOpts::empty());
+    }
+}
+
+/// Returns scheduling policy and priority of the thread `tid`.
+pub fn scheduler(tid: i32) -> Result<(SchedPolicy, c_int), LxError> {
+    check_sched_target(tid)?;
+    Ok(with_context(|ctx| {
+        (ctx.sched_policy.get(), ctx.sched_priority.get())
+    }))
+}
+
+/// Sets scheduling policy and priority of the thread `tid`.
+///
+/// Real-time policies are mapped to their macOS counterparts, with priorities scaled to the native range. Other
+/// policies run natively as `SCHED_OTHER` with the default priority.
+pub fn set_scheduler(tid: i32, policy: SchedPolicy, priority: c_int) -> Result<(), LxError> {
+    check_sched_target(tid)?;
+    let (min, max) = policy.priority_range()?;
+    if !(min..=max).contains(&priority) {
+        return Err(LxError::EINVAL);
+    }
+    if policy.is_realtime() && crate::security::euid() != 0 {
+        return Err(LxError::EPERM);
+    }
+
+    let native_policy = match policy.is_realtime() {
+        true => policy.to_apple()?,
+        false => libc::SCHED_OTHER,
+    };
+    unsafe {
+        let native_min = libc::sched_get_priority_min(native_policy);
+        let native_max = libc::sched_get_priority_max(native_policy);
+        let mut param: libc::sched_param = std::mem::zeroed();
+        param.sched_priority = match policy.is_realtime() {
+            true => native_min + (priority - min) * (native_max - native_min) / (max - min),
+            false => (native_min + native_max) / 2,
+        };
+        let status = libc::pthread_setschedparam(libc::pthread_self(), native_policy, &param);
+        if status != 0 {
+            return Err(LxError::from_apple_errno(status));
+        }
+    }
+    with_context(|ctx| {
+        ctx.sched_policy.set(policy);
+        ctx.sched_priority.set(priority);
+    });
+    Ok(())
+}
+
+/// Scheduling parameters are kept in thread-local storage, so only those of current thread are accessible.
+fn check_sched_target(tid: i32) -> Result<(), LxError> {
+    match tid {
+        ..0 => Err(LxError::EINVAL),
+        0 => Ok(()),
+        tid if tid == id() => Ok(()),
+        _ => Err(LxError::EPERM),
+    }
+}
+
 /// Spawns a thread.
 pub fn clone(ctx: Box<CloneContext>) -> Result<i32, LxError> {
     let tid = ctx.tid.clone();
@@ -239,7 +397,7 @@ pub fn clone(ctx: Box<CloneContext>) -> Result<i32, LxError> {
             libc::pthread_create(&mut native, std::ptr::null(), setup_thread_lx, data as _);
         if status != 0 {
             drop(Box::from_raw(data));
-            return Err(LxError::from_apple(status)?);
+            return Err(LxError::from_apple_errno(status));
         }
         libc::pthread_detach(native);
     }
@@ -270,24 +428,55 @@ pub fn set_robust_list(ptr: *mut u8, size: usize) -> Result<(), LxError> {
 
 pub fn get_name() -> [u8; 16] {
     let mut result = [0u8; 16];
-    let buf = with_client(
-        |client| match client.invoke(Request::GetThreadName).unwrap() {
-            Response::Bytes(name) => name,
-            _ => ipc_fail(),
-        },
-    );
-    result.copy_from_slice(&buf);
+    let buf = with_client(|client| match client.invoke(Request::GetThreadName) {
+        Response::Bytes(name) => name,
+        _ => ipc_fail(),
+    });
+    let len = buf.len().min(result.len() - 1);
+    result[..len].copy_from_slice(&buf[..len]);
     result
 }
 
+/// Sets name of current thread.
+///
+/// The name is also propagated to the native thread, so that it is visible in native debugging tools.
 pub fn set_name(name: [u8; 16]) {
+    let len = name.iter().position(|x| *x == 0).unwrap_or(name.len() - 1);
+    let name = &name[..len];
+
+    let mut native_name = [0u8; 16];
+    native_name[..len].copy_from_slice(name);
+    unsafe {
+        libc::pthread_setname_np(native_name.as_ptr().cast());
+    }
+
     with_client(|client| {
-        client
-            .invoke(Request::SetThreadName(name.to_vec()))
-            .unwrap();
+        client.invoke(Request::SetThreadName(name.to_vec()));
     });
 }
 
+/// Initializes context of a thread that MacTux spawns for itself, which never runs Linux code.
+///
+/// Such threads are not registered as Linux threads, and block all signals, so that signals of the program are never
+/// delivered to them.
+pub(crate) unsafe fn enter_worker() -> std::io::Result<()> {
+    unsafe {
+        let mut set = std::mem::zeroed();
+        libc::sigfillset(&mut set);
+        if libc::pthread_sigmask(libc::SIG_SETMASK, &set, std::ptr::null_mut()) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if libc::pthread_setspecific(
+            (&raw const THREAD_CTX).read(),
+            Box::into_raw(Box::new(ThreadCtx::new())).cast(),
+        ) == -1
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
 /// This is called when entering a MacTux thread.
 pub unsafe fn enter() -> std::io::Result<()> {
     unsafe {
@@ -321,8 +510,14 @@ pub unsafe fn enter() -> std::io::Result<()> {
 /// This function may cause UB.
 pub unsafe fn exit(code: i32) -> ! {
     unsafe {
+        let robust_list_head = process::context()
+            .thread_pubctx_map
+            .with_current(|ctx| ctx.robust_list_head.load(atomic::Ordering::Relaxed));
+        if !robust_list_head.is_null() {
+            crate::sync::futex::exit_robust_list(robust_list_head, id());
+        }
         if let Some(ptr) = with_context(|ctx| ctx.clear_tid.get()) {
-            _ = crate::sync::futex::wake(ptr.as_ptr(), 0, FutexOpts::empty());
+            clear_child_tid(ptr);
         }
         process::context().thread_pubctx_map.unregister();
         if let Some(parent_thread) = with_context(|x| x.parent_thread) {
@@ -344,6 +539,7 @@ pub struct CloneContext {
     pub args: CloneArgs,
     pub cpu: libc::__darwin_x86_thread_state64,
     pub tid: Arc<AtomicI64>,
+    pub sched: (SchedPolicy, c_int),
 }
 impl CloneContext {
     pub fn new(args: CloneArgs, cpu: libc::__darwin_x86_thread_state64) -> Box<Self> {
@@ -351,6 +547,7 @@ impl CloneContext {
             args,
             cpu,
             tid: Arc::new(AtomicI64::new(0)),
+            sched: with_context(|ctx| (ctx.sched_policy.get(), ctx.sched_priority.get())),
         })
     }
 }
@@ -359,7 +556,12 @@ impl CloneContext {
 extern "C" fn setup_thread_lx(data: *mut c_void) -> *mut c_void {
     unsafe {
         // Get inherited data
-        let CloneContext { args, mut cpu, tid } = *Box::from_raw(data as *mut CloneContext);
+        let CloneContext {
+            args,
+            mut cpu,
+            tid,
+            sched,
+        } = *Box::from_raw(data as *mut CloneContext);
 
         // When `CLONE_VM` is specified (the only case this function is called), the Linux man pages says that a stack must be
         // explicitly specified. So no NULL-checking is done here.
@@ -378,7 +580,7 @@ extern "C" fn setup_thread_lx(data: *mut c_void) -> *mut c_void {
 
         // Return thread id to parent thread
         let current_tid = match with_client(|client| client.invoke(Request::GetThreadId)) {
-            Ok(Response::Pid(pid)) => pid,
+            Response::Pid(pid) => pid,
             _ => {
                 log::warn!("Failed to get tid for new thread");
                 tid.store(-1, atomic::Ordering::Relaxed);
@@ -388,9 +590,15 @@ extern "C" fn setup_thread_lx(data: *mut c_void) -> *mut c_void {
         assert!(current_tid > 0);
         tid.store(current_tid as _, atomic::Ordering::Relaxed);
         drop(tid);
-        with_context(|ctx| {
-            ctx.tid.set(current_tid);
-        });
+        set_id(current_tid);
+        set_child_tid(&args, current_tid);
+
+        // Inherit scheduling parameters of the parent thread
+        if sched.0 != SchedPolicy::SCHED_OTHER
+            && let Err(err) = set_scheduler(0, sched.0, sched.1)
+        {
+            log::warn!("Failed to inherit scheduling parameters: {err}");
+        }
 
         // Reset necessary registers
         cpu.__rax = 0;
@@ -422,3 +630,40 @@ fn thread_selfid() -> libc::pid_t {
         macos_tid as _
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Waits on `word` like `pthread_join`, until it becomes zero.
+    fn join(word: &AtomicU32, opts: FutexOpts) {
+        loop {
+            let tid = word.load(atomic::Ordering::SeqCst);
+            if tid == 0 {
+                return;
+            }
+            match unsafe {
+                crate::sync::futex::wait(word.as_ptr(), tid, std::ptr::null_mut(), opts)
+            } {
+                Ok(()) | Err(LxError::EAGAIN) | Err(LxError::EINTR) => (),
+                Err(err) => panic!("futex wait failed: {err:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn exit_clears_child_tid_and_wakes_joiner() {
+        for opts in [FutexOpts::empty(), FutexOpts::FUTEX_PRIVATE_FLAG] {
+            let word = Arc::new(AtomicU32::new(1234));
+            let joiner = std::thread::spawn({
+                let word = word.clone();
+                move || join(&word, opts)
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            unsafe { clear_child_tid(NonNull::from(&*word).cast()) };
+            joiner.join().unwrap();
+            assert_eq!(word.load(atomic::Ordering::SeqCst), 0);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Time sources.
+
+use crate::switches;
+use std::sync::{
+    OnceLock,
+    atomic::{self, AtomicI64},
+};
+use structures::{
+    ToApple,
+    error::LxError,
+    process::RUsageWho,
+    time::{ClockId, Timeval, Timezone, Tms, USER_HZ},
+};
+
+/// The point `CLOCK_REALTIME` starts from in deterministic mode, which is `2000-01-01T00:00:00Z`.
+const DETERMINISTIC_EPOCH: i128 = 946_684_800 * NANOS_PER_SEC;
+
+/// Name of the environment variable that records when the deterministic clock started, in nanoseconds since the
+/// native epoch. It is inherited by children and new program images, so the clock does not jump backwards on them.
+const DETERMINISTIC_START: &str = "MacTux_DeterministicStart";
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// Initializes time sources of the current process.
+///
+/// # Safety
+/// This modifies the process environment, so it must be called before any other thread is spawned.
+pub unsafe fn install() {
+    if switches::deterministic().is_some() && std::env::var_os(DETERMINISTIC_START).is_none() {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        unsafe {
+            std::env::set_var(DETERMINISTIC_START, now.to_string());
+        }
+    }
+}
+
+/// Reads `CLOCK_REALTIME` as is seen by Linux programs.
+///
+/// This is the single source of wall-clock time for `clock_gettime`, `gettimeofday` and `time`, so that they agree with
+/// each other, including in deterministic mode.
+pub fn realtime() -> Result<libc::timespec, LxError> {
+    let mut now = unsafe { std::mem::zeroed() };
+    if unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut now) } == -1 {
+        return Err(LxError::last_apple_error());
+    }
+    adjust_realtime(&mut now);
+    Ok(now)
+}
+
+/// Reads a clock as is seen by Linux programs, like `clock_gettime`.
+pub fn clock_gettime(clock: ClockId) -> Result<libc::timespec, LxError> {
+    if let Some(cpu_time) = cpu_clock_gettime(clock) {
+        return cpu_time;
+    }
+    if clock == ClockId::CLOCK_REALTIME {
+        return realtime();
+    }
+    let mut now = unsafe { std::mem::zeroed() };
+    if unsafe { libc::clock_gettime(clock.to_apple()?, &mut now) } == -1 {
+        return Err(LxError::last_apple_error());
+    }
+    Ok(now)
+}
+
+/// Reads `CLOCK_REALTIME` in microseconds, like `gettimeofday`.
+pub fn timeofday() -> Result<Timeval, LxError> {
+    let now = realtime()?;
+    Ok(Timeval {
+        tv_sec: now.tv_sec,
+        tv_usec: now.tv_nsec / 1000,
+    })
+}
+
+/// Returns the obsolete timezone information reported by `gettimeofday`.
+pub fn timezone() -> Result<Timezone, LxError> {
+    unsafe {
+        let mut tv = std::mem::zeroed();
+        let mut tz: Timezone = std::mem::zeroed();
+        if libc::gettimeofday(&mut tv, (&raw mut tz).cast()) == -1 {
+            return Err(LxError::last_apple_error());
+        }
+        Ok(tz)
+    }
+}
+
+/// Adjusts a native `CLOCK_REALTIME` reading to the time seen by Linux programs.
+///
+/// Outside of deterministic mode, this is a no-op.
+fn adjust_realtime(tp: &mut libc::timespec) {
+    let Some(offset) = realtime_offset() else {
+        return;
+    };
+    let nanos = timespec_nanos(tp) + offset.load(atomic::Ordering::Relaxed) as i128;
+    tp.tv_sec = nanos.div_euclid(NANOS_PER_SEC) as _;
+    tp.tv_nsec = nanos.rem_euclid(NANOS_PER_SEC) as _;
+}
+
+/// Sets `CLOCK_REALTIME` as is seen by Linux programs.
+///
+/// The host clock is never changed, so this fails with `EPERM` unless the clock is offset from the host one, as in
+/// deterministic mode. The new offset applies to the calling process only.
+pub fn set_realtime(tp: &libc::timespec) -> Result<(), LxError> {
+    if tp.tv_sec < 0 || !(0..NANOS_PER_SEC as i64).contains(&tp.tv_nsec) {
+        return Err(LxError::EINVAL);
+    }
+    let Some(offset) = realtime_offset() else {
+        return Err(crate::error_report::explain(
+            LxError::EPERM,
+            "the host clock is never changed",
+        ));
+    };
+    let mut now = unsafe { std::mem::zeroed() };
+    if unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut now) } == -1 {
+        return Err(LxError::last_apple_error());
+    }
+    let new = timespec_nanos(tp) - timespec_nanos(&now);
+    offset.store(
+        new.try_into().map_err(|_| LxError::EINVAL)?,
+        atomic::Ordering::Relaxed,
+    );
+    Ok(())
+}
+
+/// Returns the offset between the Linux `CLOCK_REALTIME` and the native one, in nanoseconds.
+fn realtime_offset() -> Option<&'static AtomicI64> {
+    static OFFSET: OnceLock<Option<AtomicI64>> = OnceLock::new();
+    OFFSET
+        .get_or_init(|| {
+            switches::deterministic()?;
+            let start: i128 = std::env::var(DETERMINISTIC_START).ok()?.parse().ok()?;
+            Some(AtomicI64::new((DETERMINISTIC_EPOCH - start) as i64))
+        })
+        .as_ref()
+}
+
+fn timespec_nanos(tp: &libc::timespec) -> i128 {
+    tp.tv_sec as i128 * NANOS_PER_SEC + tp.tv_nsec as i128
+}
+
+/// Kind of a CPU-time clock, as is encoded in the low bits of dynamic clock IDs on Linux.
+const CPUCLOCK_PROF: i32 = 0;
+const CPUCLOCK_VIRT: i32 = 1;
+const CPUCLOCK_SCHED: i32 = 2;
+const CPUCLOCK_PERTHREAD_MASK: i32 = 4;
+
+/// Reads a CPU-time clock, or returns `None` if `clock` is not one.
+///
+/// Besides `CLOCK_PROCESS_CPUTIME_ID` and `CLOCK_THREAD_CPUTIME_ID`, this accepts the clock IDs made by
+/// `clock_getcpuclockid` and `pthread_getcpuclockid` for the calling process and thread. Clocks of other processes and
+/// threads cannot be read, and fail with `EINVAL`.
+pub fn cpu_clock_gettime(clock: ClockId) -> Option<Result<libc::timespec, LxError>> {
+    let (kind, per_thread, target) = match clock {
+        ClockId::CLOCK_PROCESS_CPUTIME_ID => (CPUCLOCK_SCHED, false, 0),
+        ClockId::CLOCK_THREAD_CPUTIME_ID => (CPUCLOCK_SCHED, true, 0),
+        ClockId(id) if (id as i32) < 0 => {
+            let id = id as i32;
+            (id & 3, id & CPUCLOCK_PERTHREAD_MASK != 0, !(id >> 3))
+        }
+        _ => return None,
+    };
+    Some(cpu_time(kind, per_thread, target))
+}
+
+fn cpu_time(kind: i32, per_thread: bool, target: i32) -> Result<libc::timespec, LxError> {
+    let (user, system) = match per_thread {
+        true if target == 0 || target == crate::thread::id() => thread_times()?,
+        false if target == 0 || target == crate::process::pid() => task_times()?,
+        _ => return Err(LxError::EINVAL),
+    };
+    let micros = match kind {
+        CPUCLOCK_PROF | CPUCLOCK_SCHED => user + system,
+        CPUCLOCK_VIRT => user,
+        _ => return Err(LxError::EINVAL),
+    };
+    Ok(libc::timespec {
+        tv_sec: (micros / 1_000_000) as _,
+        tv_nsec: (micros % 1_000_000 * 1000) as _,
+    })
+}
+
+/// Returns user and system time consumed by the calling thread, in microseconds.
+pub(crate) fn thread_times() -> Result<(u64, u64), LxError> {
+    unsafe {
+        let mut info: libc::thread_basic_info = std::mem::zeroed();
+        let mut count = libc::THREAD_BASIC_INFO_COUNT;
+        let thread = mach2::mach_init::mach_thread_self();
+        let kr = libc::thread_info(
+            thread,
+            libc::THREAD_BASIC_INFO as _,
+            (&raw mut info).cast(),
+            &mut count,
+        );
+        mach2::mach_port::mach_port_deallocate(mach2::traps::mach_task_self(), thread);
+        if kr != libc::KERN_SUCCESS {
+            return Err(LxError::EINVAL);
+        }
+        Ok((
+            time_value_micros(info.user_time),
+            time_value_micros(info.system_time),
+        ))
+    }
+}
+
+/// Reports CPU time consumed by the calling process and its reaped children in clock ticks, like `times`, returning
+/// the time elapsed since boot in clock ticks.
+///
+/// Times of the process come from the same source as `CLOCK_PROCESS_CPUTIME_ID` and `getrusage`, and times of children
+/// from `getrusage`, so that all of them agree.
+pub fn times() -> Result<(Tms, i64), LxError> {
+    let (user, system) = task_times()?;
+    let children = crate::process::rusage(RUsageWho::RUSAGE_CHILDREN)?;
+    let timeval_micros = |x: Timeval| x.tv_sec as u64 * 1_000_000 + x.tv_usec as u64;
+    let tms = Tms {
+        tms_utime: micros_to_ticks(user),
+        tms_stime: micros_to_ticks(system),
+        tms_cutime: micros_to_ticks(timeval_micros(children.ru_utime)),
+        tms_cstime: micros_to_ticks(timeval_micros(children.ru_stime)),
+    };
+
+    let mut uptime = unsafe { std::mem::zeroed() };
+    if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut uptime) } == -1 {
+        return Err(LxError::last_apple_error());
+    }
+    let elapsed = uptime.tv_sec * USER_HZ + uptime.tv_nsec * USER_HZ / NANOS_PER_SEC as i64;
+    Ok((tms, elapsed))
+}
+
+/// Returns user and system time consumed by the calling process, in microseconds.
+///
+/// `MACH_TASK_BASIC_INFO` only accounts for terminated threads, so times of live threads are added from
+/// `TASK_THREAD_TIMES_INFO`.
+pub(crate) fn task_times() -> Result<(u64, u64), LxError> {
+    unsafe {
+        let task = mach2::traps::mach_task_self();
+        let mut basic: libc::mach_task_basic_info = std::mem::zeroed();
+        let mut count = libc::MACH_TASK_BASIC_INFO_COUNT;
+        if libc::task_info(
+            task,
+            libc::MACH_TASK_BASIC_INFO,
+            (&raw mut basic).cast(),
+            &mut count,
+        ) != libc::KERN_SUCCESS
+        {
+            return Err(LxError::EINVAL);
+        }
+        let mut live: libc::task_thread_times_info = std::mem::zeroed();
+        let mut count = libc::TASK_THREAD_TIMES_INFO_COUNT;
+        if libc::task_info(
+            task,
+            libc::TASK_THREAD_TIMES_INFO,
+            (&raw mut live).cast(),
+            &mut count,
+        ) != libc::KERN_SUCCESS
+        {
+            return Err(LxError::EINVAL);
+        }
+        Ok((
+            time_value_micros(basic.user_time) + time_value_micros(live.user_time),
+            time_value_micros(basic.system_time) + time_value_micros(live.system_time),
+        ))
+    }
+}
+
+fn micros_to_ticks(micros: u64) -> i64 {
+    (micros * USER_HZ as u64 / 1_000_000) as i64
+}
+
+fn time_value_micros(value: libc::time_value_t) -> u64 {
+    value.seconds as u64 * 1_000_000 + value.microseconds as u64
+}
@@ -42,7 +42,7 @@ pub struct RtenvPidMapper;
 impl PidMapper for RtenvPidMapper {
     fn apple_to_linux(&self, apple: libc::pid_t) -> Result<i32, LxError> {
         with_client(
-            |client| match client.invoke(Request::PidNativeToLinux(apple)).unwrap() {
+            |client| match client.invoke(Request::PidNativeToLinux(apple)) {
                 Response::Pid(pid) => Ok(pid),
                 Response::Error(err) => Err(err),
                 _ => ipc_fail(),
@@ -52,7 +52,7 @@ impl PidMapper for RtenvPidMapper {
 
     fn linux_to_apple(&self, linux: i32) -> Result<libc::pid_t, LxError> {
         with_client(
-            |client| match client.invoke(Request::PidLinuxToNative(linux)).unwrap() {
+            |client| match client.invoke(Request::PidLinuxToNative(linux)) {
                 Response::Pid(pid) => Ok(pid),
                 Response::Error(err) => Err(err),
                 _ => ipc_fail(),
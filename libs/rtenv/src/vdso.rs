@@ -0,0 +1,347 @@
+//! A minimal vDSO, which lets Linux programs read clocks without trapping into system call emulation.
+//!
+//! The image only contains ELF headers and a dynamic symbol table, which is enough for the dynamic linker and libc to
+//! find vDSO functions. Its symbols point to functions of MacTux itself, which leave the emulated context for the duration
+//! of the call, like system call handlers do.
+
+use crate::emuctx;
+use libc::{c_int, c_long, c_uint, c_void};
+use std::sync::OnceLock;
+use structures::{
+    FromApple,
+    error::LxError,
+    time::{ClockId, Timespec, Timeval, Timezone},
+};
+
+/// Size of the memory the image is mapped in.
+const IMAGE_SIZE: usize = 0x1000;
+
+/// Name of the vDSO, as is recorded in `DT_SONAME`.
+const SONAME: &[u8] = b"linux-vdso.so.1";
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const DYN_SIZE: usize = 16;
+const SYM_SIZE: usize = 24;
+
+/// Returns address of the vDSO image, as is passed to programs with `AT_SYSINFO_EHDR`, or `0` if it cannot be set up.
+pub fn address() -> usize {
+    static ADDRESS: OnceLock<usize> = OnceLock::new();
+    *ADDRESS.get_or_init(|| map_image().unwrap_or(0))
+}
+
+/// Functions exported by the vDSO, with their names.
+fn symbols() -> [(&'static [u8], usize); 4] {
+    [
+        (
+            b"__vdso_clock_gettime",
+            vdso_clock_gettime as *const () as usize,
+        ),
+        (
+            b"__vdso_gettimeofday",
+            vdso_gettimeofday as *const () as usize,
+        ),
+        (b"__vdso_getcpu", vdso_getcpu as *const () as usize),
+        (b"__vdso_time", vdso_time as *const () as usize),
+    ]
+}
+
+/// Maps the vDSO image into read-only memory.
+fn map_image() -> Result<usize, LxError> {
+    unsafe {
+        let addr = libc::mmap(
+            std::ptr::null_mut(),
+            IMAGE_SIZE,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        );
+        if addr == libc::MAP_FAILED {
+            return Err(LxError::last_apple_error());
+        }
+        let image = build_image(addr as usize);
+        if image.len() > IMAGE_SIZE {
+            libc::munmap(addr, IMAGE_SIZE);
+            return Err(LxError::ENOMEM);
+        }
+        std::ptr::copy_nonoverlapping(image.as_ptr(), addr.cast(), image.len());
+        libc::mprotect(addr, IMAGE_SIZE, libc::PROT_READ);
+        Ok(addr as usize)
+    }
+}
+
+/// Builds the vDSO image to be placed at `base`.
+///
+/// The image is laid out as the ELF header, program headers, the dynamic section, the symbol table, the hash table and
+/// the string table, with virtual addresses equal to file offsets.
+fn build_image(base: usize) -> Vec<u8> {
+    let symbols = symbols();
+    let nsyms = symbols.len() + 1;
+
+    let mut strtab = vec![0];
+    let mut push_str = |s: &[u8]| {
+        let off = strtab.len();
+        strtab.extend_from_slice(s);
+        strtab.push(0);
+        off
+    };
+    let soname = push_str(SONAME);
+    let names = symbols.map(|(name, _)| push_str(name));
+
+    let dynamic_off = EHDR_SIZE + 2 * PHDR_SIZE;
+    let dynamic_len = 7;
+    let symtab_off = dynamic_off + dynamic_len * DYN_SIZE;
+    let hash_off = symtab_off + nsyms * SYM_SIZE;
+    let strtab_off = hash_off + (2 + 1 + nsyms) * size_of::<u32>();
+    let size = strtab_off + strtab.len();
+
+    let mut image = Vec::with_capacity(size);
+
+    // ELF header.
+    image.extend_from_slice(b"\x7fELF");
+    image.extend_from_slice(&[2, 1, 1, 0]); // ELFCLASS64, ELFDATA2LSB, EV_CURRENT, ELFOSABI_NONE
+    image.extend_from_slice(&[0; 8]);
+    image.extend_from_slice(&3u16.to_le_bytes()); // ET_DYN
+    image.extend_from_slice(&machine().to_le_bytes());
+    image.extend_from_slice(&1u32.to_le_bytes()); // EV_CURRENT
+    image.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+    image.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes()); // e_phoff
+    image.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    image.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    image.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    image.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+    image.extend_from_slice(&2u16.to_le_bytes()); // e_phnum
+    image.extend_from_slice(&64u16.to_le_bytes()); // e_shentsize
+    image.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    image.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    // Program headers.
+    let mut push_phdr = |p_type: u32, off: usize, len: usize, align: u64| {
+        image.extend_from_slice(&p_type.to_le_bytes());
+        image.extend_from_slice(&4u32.to_le_bytes()); // PF_R
+        image.extend_from_slice(&(off as u64).to_le_bytes()); // p_offset
+        image.extend_from_slice(&(off as u64).to_le_bytes()); // p_vaddr
+        image.extend_from_slice(&(off as u64).to_le_bytes()); // p_paddr
+        image.extend_from_slice(&(len as u64).to_le_bytes()); // p_filesz
+        image.extend_from_slice(&(len as u64).to_le_bytes()); // p_memsz
+        image.extend_from_slice(&align.to_le_bytes());
+    };
+    push_phdr(1, 0, size, IMAGE_SIZE as _); // PT_LOAD
+    push_phdr(2, dynamic_off, dynamic_len * DYN_SIZE, 8); // PT_DYNAMIC
+
+    // Dynamic section.
+    let dynamic: [(i64, usize); 7] = [
+        (4, hash_off),      // DT_HASH
+        (5, strtab_off),    // DT_STRTAB
+        (6, symtab_off),    // DT_SYMTAB
+        (10, strtab.len()), // DT_STRSZ
+        (11, SYM_SIZE),     // DT_SYMENT
+        (14, soname),       // DT_SONAME
+        (0, 0),             // DT_NULL
+    ];
+    for (tag, val) in dynamic {
+        image.extend_from_slice(&tag.to_le_bytes());
+        image.extend_from_slice(&(val as u64).to_le_bytes());
+    }
+
+    // Symbol table. Values are relative to the image, so they wrap around to reach functions outside of it. There are no
+    // section headers, but the section index must not be `SHN_UNDEF` for the symbols to be seen as defined.
+    image.extend_from_slice(&[0; SYM_SIZE]);
+    for (name, (_, addr)) in names.into_iter().zip(symbols) {
+        image.extend_from_slice(&(name as u32).to_le_bytes());
+        image.push(0x12); // STB_GLOBAL, STT_FUNC
+        image.push(0); // STV_DEFAULT
+        image.extend_from_slice(&1u16.to_le_bytes()); // st_shndx
+        image.extend_from_slice(&(addr.wrapping_sub(base) as u64).to_le_bytes());
+        image.extend_from_slice(&0u64.to_le_bytes()); // st_size
+    }
+
+    // Hash table, with a single bucket chaining all the symbols.
+    image.extend_from_slice(&1u32.to_le_bytes()); // nbucket
+    image.extend_from_slice(&(nsyms as u32).to_le_bytes()); // nchain
+    image.extend_from_slice(&1u32.to_le_bytes()); // bucket[0]
+    for i in 0..nsyms {
+        let next = match i {
+            0 => 0,
+            i if i + 1 == nsyms => 0,
+            i => i + 1,
+        };
+        image.extend_from_slice(&(next as u32).to_le_bytes());
+    }
+
+    // String table.
+    image.extend_from_slice(&strtab);
+
+    debug_assert_eq!(image.len(), size);
+    image
+}
+
+/// Returns the ELF machine type of the vDSO.
+const fn machine() -> u16 {
+    if cfg!(target_arch = "x86_64") {
+        62 // EM_X86_64
+    } else if cfg!(target_arch = "aarch64") {
+        183 // EM_AARCH64
+    } else {
+        0
+    }
+}
+
+/// Runs `f` out of the emulated context, and returns its result the way a system call does.
+fn with_native(f: impl FnOnce() -> Result<(), LxError>) -> c_long {
+    unsafe {
+        emuctx::leave_emulated();
+    }
+    let result = f();
+    unsafe {
+        emuctx::enter_emulated();
+    }
+    match result {
+        Ok(()) => 0,
+        Err(err) => -(err.0 as c_long),
+    }
+}
+
+/// Implementation of `__vdso_clock_gettime`.
+unsafe extern "C" fn vdso_clock_gettime(clock: c_int, tp: *mut Timespec) -> c_long {
+    with_native(|| unsafe {
+        let now = crate::time::clock_gettime(ClockId(clock as _))?;
+        tp.write(Timespec::from_apple(now)?);
+        Ok(())
+    })
+}
+
+/// Implementation of `__vdso_gettimeofday`.
+unsafe extern "C" fn vdso_gettimeofday(tv: *mut Timeval, tz: *mut Timezone) -> c_long {
+    with_native(|| unsafe {
+        if !tv.is_null() {
+            tv.write(crate::time::timeofday()?);
+        }
+        if !tz.is_null() {
+            tz.write(crate::time::timezone()?);
+        }
+        Ok(())
+    })
+}
+
+/// Implementation of `__vdso_time`.
+unsafe extern "C" fn vdso_time(tloc: *mut i64) -> c_long {
+    let mut now = 0;
+    let status = with_native(|| {
+        now = crate::time::realtime()?.tv_sec;
+        Ok(())
+    });
+    if status != 0 {
+        return status;
+    }
+    if !tloc.is_null() {
+        unsafe {
+            tloc.write(now);
+        }
+    }
+    now
+}
+
+/// Implementation of `__vdso_getcpu`.
+///
+/// The CPU a thread runs on cannot be queried on macOS, so this always reports CPU 0 on node 0.
+unsafe extern "C" fn vdso_getcpu(cpu: *mut c_uint, node: *mut c_uint, _: *mut c_void) -> c_long {
+    unsafe {
+        if !cpu.is_null() {
+            cpu.write(0);
+        }
+        if !node.is_null() {
+            node.write(0);
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(image: &[u8], off: usize) -> u16 {
+        u16::from_le_bytes(image[off..off + 2].try_into().unwrap())
+    }
+
+    fn u32_at(image: &[u8], off: usize) -> u32 {
+        u32::from_le_bytes(image[off..off + 4].try_into().unwrap())
+    }
+
+    fn u64_at(image: &[u8], off: usize) -> u64 {
+        u64::from_le_bytes(image[off..off + 8].try_into().unwrap())
+    }
+
+    /// Hashes a symbol name for lookup through `DT_HASH`.
+    fn elf_hash(name: &[u8]) -> u32 {
+        let mut h = 0u32;
+        for &c in name {
+            h = (h << 4).wrapping_add(c as u32);
+            let g = h & 0xf000_0000;
+            h ^= g >> 24;
+            h &= !g;
+        }
+        h
+    }
+
+    /// Finds the value of the symbol `name` in `image`, like the dynamic linker does.
+    fn lookup(image: &[u8], name: &[u8]) -> Option<u64> {
+        let phoff = u64_at(image, 32) as usize;
+        let phnum = u16_at(image, 56) as usize;
+        let dynamic = (0..phnum)
+            .map(|n| phoff + n * PHDR_SIZE)
+            .find(|&phdr| u32_at(image, phdr) == 2)
+            .map(|phdr| u64_at(image, phdr + 8) as usize)?;
+
+        let (mut hash, mut symtab, mut strtab) = (None, None, None);
+        for entry in (dynamic..).step_by(DYN_SIZE) {
+            let val = Some(u64_at(image, entry + 8) as usize);
+            match u64_at(image, entry) {
+                0 => break,
+                4 => hash = val,
+                5 => strtab = val,
+                6 => symtab = val,
+                _ => {}
+            }
+        }
+        let (hash, symtab, strtab) = (hash?, symtab?, strtab?);
+
+        let nbucket = u32_at(image, hash);
+        let chain = hash + 8 + nbucket as usize * 4;
+        let mut index = u32_at(image, hash + 8 + (elf_hash(name) % nbucket) as usize * 4);
+        while index != 0 {
+            let sym = symtab + index as usize * SYM_SIZE;
+            let name_off = strtab + u32_at(image, sym) as usize;
+            let sym_name = image[name_off..].split(|&c| c == 0).next().unwrap();
+            if sym_name == name && u16_at(image, sym + 6) != 0 {
+                return Some(u64_at(image, sym + 8));
+            }
+            index = u32_at(image, chain + index as usize * 4);
+        }
+        None
+    }
+
+    #[test]
+    fn image_is_elf_shared_object() {
+        let image = build_image(0x7000_0000);
+        assert!(image.len() <= IMAGE_SIZE);
+        assert_eq!(&image[..4], b"\x7fELF");
+        assert_eq!(u16_at(&image, 16), 3);
+        assert_eq!(u16_at(&image, 18), machine());
+        assert_eq!(u16_at(&image, 54) as usize, PHDR_SIZE);
+    }
+
+    #[test]
+    fn symbols_are_found_through_hash_table() {
+        let base = 0x7000_0000;
+        let image = build_image(base);
+        for (name, addr) in symbols() {
+            let value = lookup(&image, name).unwrap();
+            assert_eq!(base.wrapping_add(value as usize), addr);
+        }
+        assert_eq!(lookup(&image, b"__vdso_nonexistent"), None);
+        assert_eq!(lookup(&image, SONAME), None);
+    }
+}
@@ -11,6 +11,8 @@ unixvariants! {
         const ESRCH = 3;
         const EINTR = 4;
         const EIO = 5;
+        const ENXIO = 6;
+        const E2BIG = 7;
         const ENOEXEC = 8;
         const EBADF = 9;
         const ECHILD = 10;
@@ -18,6 +20,7 @@ unixvariants! {
         const ENOMEM = 12;
         const EACCES = 13;
         const EFAULT = 14;
+        const ENOTBLK = 15;
         const EBUSY = 16;
         const EEXIST = 17;
         const EXDEV = 18;
@@ -25,34 +28,92 @@ unixvariants! {
         const ENOTDIR = 20;
         const EISDIR = 21;
         const EINVAL = 22;
+        const ENFILE = 23;
         const EMFILE = 24;
         const ENOTTY = 25;
         const ETXTBSY = 26;
+        const EFBIG = 27;
         const ENOSPC = 28;
         const ESPIPE = 29;
         const EROFS = 30;
+        const EMLINK = 31;
         const EPIPE = 32;
+        const EDOM = 33;
         const ERANGE = 34;
+        const EDEADLK = 35;
+        const ENAMETOOLONG = 36;
+        const ENOLCK = 37;
         const ENOSYS = 38;
         const ENOTEMPTY = 39;
         const ELOOP = 40;
+        const ENOMSG = 42;
+        const EIDRM = 43;
+        const ENOSTR = 60;
+        const ENODATA = 61;
+        const ETIME = 62;
+        const ENOSR = 63;
+        const EREMOTE = 66;
+        const ENOLINK = 67;
+        const EPROTO = 71;
+        const EMULTIHOP = 72;
+        const EBADMSG = 74;
+        const EOVERFLOW = 75;
+        const EILSEQ = 84;
+        const EUSERS = 87;
         const ENOTSOCK = 88;
+        const EDESTADDRREQ = 89;
+        const EMSGSIZE = 90;
         const EPROTOTYPE = 91;
+        const ENOPROTOOPT = 92;
+        const EPROTONOSUPPORT = 93;
+        const ESOCKTNOSUPPORT = 94;
         const EOPNOTSUPP = 95;
+        const EPFNOSUPPORT = 96;
         const EAFNOSUPPORT = 97;
         const EADDRINUSE = 98;
         const EADDRNOTAVAIL = 99;
         const ENETDOWN = 100;
         const ENETUNREACH = 101;
+        const ENETRESET = 102;
+        const ECONNABORTED = 103;
         const ECONNRESET = 104;
         const ENOBUFS = 105;
         const EISCONN = 106;
+        const ENOTCONN = 107;
+        const ESHUTDOWN = 108;
+        const ETOOMANYREFS = 109;
         const ETIMEDOUT = 110;
         const ECONNREFUSED = 111;
+        const EHOSTDOWN = 112;
         const EHOSTUNREACH = 113;
         const EALREADY = 114;
         const EINPROGRESS = 115;
+        const ESTALE = 116;
+        const EDQUOT = 122;
+        const ECANCELED = 125;
+        const EOWNERDEAD = 130;
+        const ENOTRECOVERABLE = 131;
         #[linux_only] const EBADFD = 77;
+        #[apple_only = ENOTSUP] use EOPNOTSUPP;
+        #[apple_only = ENOATTR] use ENODATA;
+        #[apple_only = EPROCLIM] use EAGAIN;
+        #[apple_only = EBADRPC] use EIO;
+        #[apple_only = ERPCMISMATCH] use EIO;
+        #[apple_only = EPROGUNAVAIL] use EIO;
+        #[apple_only = EPROGMISMATCH] use EIO;
+        #[apple_only = EPROCUNAVAIL] use EIO;
+        #[apple_only = EFTYPE] use EINVAL;
+        #[apple_only = EAUTH] use EACCES;
+        #[apple_only = ENEEDAUTH] use EACCES;
+        #[apple_only = EPWROFF] use EIO;
+        #[apple_only = EDEVERR] use EIO;
+        #[apple_only = EBADEXEC] use ENOEXEC;
+        #[apple_only = EBADARCH] use ENOEXEC;
+        #[apple_only = ESHLIBVERS] use ENOEXEC;
+        #[apple_only = EBADMACHO] use ENOEXEC;
+        #[apple_only = ENOPOLICY] use EINVAL;
+        #[apple_only = EQFULL] use ENOBUFS;
+        #[apple_only = ENOTCAPABLE] use EPERM;
         #[reserve] const NONE = 0;
         fn from_apple(apple: c_int) -> Result<Self, LxError>;
         fn to_apple(self) -> Result<libc::c_int, LxError>;
@@ -62,16 +123,24 @@ impl LxError {
     /// Returns the [`LxError`] instance converted from last macOS error.
     #[inline]
     pub fn last_apple_error() -> Self {
-        Self::from_apple(std::io::Error::last_os_error().raw_os_error().expect(
+        Self::from_apple_errno(std::io::Error::last_os_error().raw_os_error().expect(
             "`std::io::Error::last_os_error` should always return an error that has raw OS error",
         ))
-        .unwrap_or(Self::EIO)
+    }
+
+    /// Returns the [`LxError`] instance converted from macOS error `errno`.
+    ///
+    /// Every macOS error has a Linux counterpart, so this falls back to [`LxError::EIO`] only for values that macOS
+    /// does not define.
+    #[inline]
+    pub fn from_apple_errno(errno: c_int) -> Self {
+        Self::from_apple(errno).unwrap_or(Self::EIO)
     }
 }
 impl From<std::io::Error> for LxError {
     fn from(value: std::io::Error) -> Self {
         match value.raw_os_error() {
-            Some(x) => Self::from_apple(x).unwrap_or(Self::EIO),
+            Some(x) => Self::from_apple_errno(x),
             None => Self::EIO,
         }
     }
@@ -82,3 +151,50 @@ impl Display for LxError {
     }
 }
 impl std::error::Error for LxError {}
+
+#[cfg(test)]
+#[cfg(target_os = "macos")]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_errnos_are_renumbered() {
+        let cases = [
+            (35, LxError::EAGAIN),
+            (11, LxError::EDEADLK),
+            (36, LxError::EINPROGRESS),
+            (62, LxError::ELOOP),
+            (63, LxError::ENAMETOOLONG),
+            (60, LxError::ETIMEDOUT),
+            (96, LxError::ENODATA),
+            (102, LxError::EOPNOTSUPP),
+            (2, LxError::ENOENT),
+        ];
+        for (apple, linux) in cases {
+            assert_eq!(LxError::from_apple_errno(apple), linux, "errno {apple}");
+        }
+    }
+
+    #[test]
+    fn apple_only_errnos_have_counterparts() {
+        let cases = [
+            (libc::ENOTSUP, LxError::EOPNOTSUPP),
+            (libc::ENOATTR, LxError::ENODATA),
+            (libc::EPROCLIM, LxError::EAGAIN),
+            (libc::EBADARCH, LxError::ENOEXEC),
+            (libc::EAUTH, LxError::EACCES),
+            (libc::EQFULL, LxError::ENOBUFS),
+        ];
+        for (apple, linux) in cases {
+            assert_eq!(LxError::from_apple_errno(apple), linux, "errno {apple}");
+        }
+        assert_eq!(libc::ENOTSUP, 45);
+        assert_eq!(libc::ENOATTR, 93);
+    }
+
+    #[test]
+    fn unknown_errno_is_eio() {
+        assert_eq!(LxError::from_apple_errno(1000), LxError::EIO);
+        assert_eq!(LxError::from_apple_errno(-1), LxError::EIO);
+    }
+}
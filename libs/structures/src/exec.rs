@@ -0,0 +1,222 @@
+//! Recognition of images that `execve` runs, shared by the loader and the checks `execve` makes before replacing the
+//! program.
+
+use crate::process::BINPRM_BUF_SIZE;
+use std::fmt::Display;
+
+/// Magic number of ELF images.
+pub const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+/// Size of the header of 64-bit ELF images.
+pub const ELF64_HEADER_SIZE: usize = 64;
+
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EM_X86_64: u16 = 62;
+
+/// Magic number of interpreter scripts.
+pub const SHEBANG_MAGIC: &[u8] = b"#!";
+
+/// Reasons an image cannot be run, which make `execve` fail with `ENOEXEC`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecFormatError {
+    TruncatedElfHeader,
+    Elf32,
+    ElfBigEndian,
+    ElfMachine(u16),
+    InterpreterTooLong,
+    NoInterpreter,
+}
+impl Display for ExecFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TruncatedElfHeader => write!(f, "truncated ELF header"),
+            Self::Elf32 => write!(f, "32-bit ELF images are not supported"),
+            Self::ElfBigEndian => write!(f, "big-endian ELF images are not supported"),
+            Self::ElfMachine(machine) => {
+                write!(f, "ELF image is built for machine {machine}, not x86-64")
+            }
+            Self::InterpreterTooLong => write!(f, "interpreter path is too long"),
+            Self::NoInterpreter => write!(f, "no interpreter in shebang line"),
+        }
+    }
+}
+impl std::error::Error for ExecFormatError {}
+
+/// Checks that the ELF header at the start of `buf` describes a little-endian x86-64 image, so that images built for
+/// other machines are reported as such instead of failing later with a confusing parse error.
+pub fn check_elf_header(buf: &[u8]) -> Result<(), ExecFormatError> {
+    if buf.len() < ELF64_HEADER_SIZE {
+        return Err(ExecFormatError::TruncatedElfHeader);
+    }
+    match (buf[4], buf[5]) {
+        (ELFCLASS64, ELFDATA2LSB) => (),
+        (ELFCLASS32, _) => return Err(ExecFormatError::Elf32),
+        _ => return Err(ExecFormatError::ElfBigEndian),
+    }
+    let machine = u16::from_le_bytes([buf[18], buf[19]]);
+    if machine != EM_X86_64 {
+        return Err(ExecFormatError::ElfMachine(machine));
+    }
+    Ok(())
+}
+
+/// Splits the `#!` line at the start of `buf` into the interpreter path and its optional argument, like Linux. Only the
+/// first [`BINPRM_BUF_SIZE`] bytes of `buf` are looked at.
+///
+/// Leading and trailing spaces and tabs are skipped. The interpreter ends at the first space, tab or NUL, and everything
+/// after the following spaces and tabs, up to a NUL, is passed as a single argument. Other characters, including the
+/// carriage return of CRLF line endings, belong to the path or the argument, so `#!/bin/sh\r` names an interpreter
+/// that does not exist. If the line does not fit in the buffer, it is only accepted if the interpreter path is known
+/// to be complete, in which case the argument is truncated.
+pub fn parse_shebang(buf: &[u8]) -> Result<(&[u8], Option<&[u8]>), ExecFormatError> {
+    let is_spacetab = |x: &u8| matches!(x, b' ' | b'\t');
+    let is_terminator = |x: &u8| matches!(x, b' ' | b'\t' | b'\0');
+
+    let buf = &buf[..buf.len().min(BINPRM_BUF_SIZE)];
+    let line = &buf[SHEBANG_MAGIC.len()..];
+    let line = match line.iter().position(|x| *x == b'\n') {
+        Some(end) => &line[..end],
+        None if buf.len() < BINPRM_BUF_SIZE => line,
+        None => {
+            // The last byte of the buffer is reserved for the terminating NUL on Linux.
+            let line = &line[..line.len() - 1];
+            let start = line
+                .iter()
+                .position(|x| !is_spacetab(x))
+                .unwrap_or(line.len());
+            if !line[start..].iter().any(is_terminator) {
+                return Err(ExecFormatError::InterpreterTooLong);
+            }
+            line
+        }
+    };
+
+    let start = line
+        .iter()
+        .position(|x| !is_spacetab(x))
+        .unwrap_or(line.len());
+    let end = line
+        .iter()
+        .rposition(|x| !is_spacetab(x))
+        .map_or(0, |x| x + 1);
+    if start >= end {
+        return Err(ExecFormatError::NoInterpreter);
+    }
+    let line = &line[start..end];
+    let line = match line.iter().position(|x| *x == b'\0') {
+        Some(nul) => &line[..nul],
+        None => line,
+    };
+    if line.is_empty() {
+        return Err(ExecFormatError::NoInterpreter);
+    }
+
+    match line.iter().position(is_terminator) {
+        Some(sep) => {
+            let arg = &line[sep..];
+            let arg = &arg[arg
+                .iter()
+                .position(|x| !is_spacetab(x))
+                .unwrap_or(arg.len())..];
+            Ok((&line[..sep], (!arg.is_empty()).then_some(arg)))
+        }
+        None => Ok((line, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elf_header(class: u8, data: u8, machine: u16) -> [u8; ELF64_HEADER_SIZE] {
+        let mut buf = [0; ELF64_HEADER_SIZE];
+        buf[..4].copy_from_slice(ELF_MAGIC);
+        buf[4] = class;
+        buf[5] = data;
+        buf[18..20].copy_from_slice(&machine.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn elf_header_is_checked() {
+        assert_eq!(
+            check_elf_header(&elf_header(ELFCLASS64, ELFDATA2LSB, EM_X86_64)),
+            Ok(())
+        );
+        assert_eq!(
+            check_elf_header(&elf_header(ELFCLASS32, ELFDATA2LSB, 3)),
+            Err(ExecFormatError::Elf32)
+        );
+        assert_eq!(
+            check_elf_header(&elf_header(ELFCLASS64, 2, EM_X86_64)),
+            Err(ExecFormatError::ElfBigEndian)
+        );
+        assert_eq!(
+            check_elf_header(&elf_header(ELFCLASS64, ELFDATA2LSB, 183)),
+            Err(ExecFormatError::ElfMachine(183))
+        );
+        assert_eq!(
+            check_elf_header(&elf_header(ELFCLASS64, ELFDATA2LSB, EM_X86_64)[..20]),
+            Err(ExecFormatError::TruncatedElfHeader)
+        );
+    }
+
+    #[test]
+    fn shebang_line_is_split_like_linux() {
+        assert_eq!(
+            parse_shebang(b"#!/bin/sh\necho"),
+            Ok((&b"/bin/sh"[..], None))
+        );
+        assert_eq!(parse_shebang(b"#!/bin/sh"), Ok((&b"/bin/sh"[..], None)));
+        assert_eq!(
+            parse_shebang(b"#! /usr/bin/env \tpython3 -u \n"),
+            Ok((&b"/usr/bin/env"[..], Some(&b"python3 -u"[..])))
+        );
+        assert_eq!(
+            parse_shebang(b"#!/bin/sh\r\n"),
+            Ok((&b"/bin/sh\r"[..], None))
+        );
+        assert_eq!(
+            parse_shebang(b"#!/bin/sh\0 -x\n"),
+            Ok((&b"/bin/sh"[..], None))
+        );
+        assert_eq!(
+            parse_shebang(b"#! \t\n"),
+            Err(ExecFormatError::NoInterpreter)
+        );
+        assert_eq!(
+            parse_shebang(b"#!\0/bin/sh\n"),
+            Err(ExecFormatError::NoInterpreter)
+        );
+    }
+
+    #[test]
+    fn truncated_shebang_argument_is_accepted() {
+        let mut buf = b"#!/bin/sh ".to_vec();
+        buf.resize(BINPRM_BUF_SIZE * 2, b'x');
+        let (interp, arg) = parse_shebang(&buf).unwrap();
+        assert_eq!(interp, b"/bin/sh");
+        assert_eq!(arg.unwrap(), &buf[10..BINPRM_BUF_SIZE - 1]);
+    }
+
+    #[test]
+    fn truncated_shebang_interpreter_is_rejected() {
+        let mut buf = b"#!   /".to_vec();
+        buf.resize(BINPRM_BUF_SIZE * 2, b'a');
+        assert_eq!(
+            parse_shebang(&buf),
+            Err(ExecFormatError::InterpreterTooLong)
+        );
+
+        // The byte reserved for the terminating NUL does not end the path.
+        buf[BINPRM_BUF_SIZE - 1] = b' ';
+        assert_eq!(
+            parse_shebang(&buf),
+            Err(ExecFormatError::InterpreterTooLong)
+        );
+        buf[BINPRM_BUF_SIZE - 2] = b' ';
+        assert_eq!(parse_shebang(&buf).map(|x| x.1), Ok(None));
+    }
+}
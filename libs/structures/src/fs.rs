@@ -1,4 +1,4 @@
-use crate::{FromApple, ToApple, error::LxError, time::Timespec};
+use crate::{FromApple, ToApple, error::LxError, internal::extensible::Extensible, time::Timespec};
 use bitflags::bitflags;
 use libc::c_int;
 use serde::{Deserialize, Serialize};
@@ -19,6 +19,12 @@ pub const XATTR_NAMESPACE_PREFIXES: &[&[u8]] = &[
 
 pub const AT_FDCWD: c_int = -100;
 
+/// Maximum length of a file name, in bytes.
+pub const NAME_MAX: usize = 255;
+
+pub const MS_MGC_VAL: u64 = 0xc0ed0000;
+pub const MS_MGC_MSK: u64 = 0xffff0000;
+
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
     #[repr(transparent)]
@@ -46,6 +52,18 @@ bitflags! {
     }
 }
 impl OpenFlags {
+    /// Flags that only affect opening a file, which are not kept as status of the open file description.
+    pub const CREATION: Self = Self::O_CREAT
+        .union(Self::O_EXCL)
+        .union(Self::O_NOCTTY)
+        .union(Self::O_TRUNC);
+
+    /// Status flags that can be changed by `F_SETFL`.
+    pub const SETFL: Self = Self::O_APPEND
+        .union(Self::O_NONBLOCK)
+        .union(Self::O_DIRECT)
+        .union(Self::O_NOATIME);
+
     pub fn is_readable(self) -> bool {
         let path_only = self.contains(Self::O_PATH);
         let write_only = self.contains(Self::O_WRONLY);
@@ -80,12 +98,13 @@ OpenFlags;
 );
 
 bitflags! {
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
     #[repr(transparent)]
     pub struct AtFlags: u32 {
         const AT_EMPTY_PATH = 0x1000;
         const AT_SYMLINK_NOFOLLOW = 0x100;
         const AT_REMOVEDIR = 0x200;
+        const AT_EACCESS = 0x200;
     }
 }
 
@@ -125,7 +144,95 @@ bitflags! {
         const MS_NODEV = 4;
         const MS_NOEXEC = 8;
         const MS_REMOUNT = 32;
+        const MS_NOATIME = 1024;
+        const MS_BIND = 4096;
+        const MS_MOVE = 8192;
+        const MS_REC = 16384;
         const MS_SILENT = 32768;
+        const MS_UNBINDABLE = 1 << 17;
+        const MS_PRIVATE = 1 << 18;
+        const MS_SLAVE = 1 << 19;
+        const MS_SHARED = 1 << 20;
+    }
+}
+impl MountFlags {
+    /// Flags that are recorded per mount, instead of affecting the mount operation only.
+    pub const PER_MOUNT: Self = Self::MS_RDONLY
+        .union(Self::MS_NOSUID)
+        .union(Self::MS_NODEV)
+        .union(Self::MS_NOEXEC)
+        .union(Self::MS_NOATIME);
+
+    /// Flags that change propagation type of a mount.
+    pub const PROPAGATION: Self = Self::MS_UNBINDABLE
+        .union(Self::MS_PRIVATE)
+        .union(Self::MS_SLAVE)
+        .union(Self::MS_SHARED);
+
+    /// Parses a comma-separated mount option string, like the options column of `/etc/fstab`, returning the parsed mount
+    /// flags and the filesystem-specific data string consisting of unrecognized options.
+    pub fn parse_options(options: &str) -> (Self, String) {
+        let mut flags = Self::empty();
+        let mut data = Vec::new();
+        for option in options.split(',') {
+            match option {
+                "" | "defaults" | "auto" | "noauto" | "nouser" => (),
+                "ro" => flags |= Self::MS_RDONLY,
+                "rw" => flags -= Self::MS_RDONLY,
+                "nosuid" => flags |= Self::MS_NOSUID,
+                "suid" => flags -= Self::MS_NOSUID,
+                "nodev" => flags |= Self::MS_NODEV,
+                "dev" => flags -= Self::MS_NODEV,
+                "noexec" => flags |= Self::MS_NOEXEC,
+                "exec" => flags -= Self::MS_NOEXEC,
+                "noatime" => flags |= Self::MS_NOATIME,
+                "atime" => flags -= Self::MS_NOATIME,
+                other => data.push(other),
+            }
+        }
+        (flags, data.join(","))
+    }
+
+    /// Formats the mount flags as a comma-separated option string, as is represented in `/proc/mounts`.
+    pub fn options(self) -> String {
+        let mut options = vec![match self.contains(Self::MS_RDONLY) {
+            true => "ro",
+            false => "rw",
+        }];
+        if self.contains(Self::MS_NOSUID) {
+            options.push("nosuid");
+        }
+        if self.contains(Self::MS_NODEV) {
+            options.push("nodev");
+        }
+        if self.contains(Self::MS_NOEXEC) {
+            options.push("noexec");
+        }
+        if self.contains(Self::MS_NOATIME) {
+            options.push("noatime");
+        }
+        options.join(",")
+    }
+
+    /// Converts the mount flags to flags reported by `statfs`.
+    pub fn statfs_flags(self) -> StatFsFlags {
+        let mut result = StatFsFlags::empty();
+        if self.contains(Self::MS_RDONLY) {
+            result |= StatFsFlags::ST_RDONLY;
+        }
+        if self.contains(Self::MS_NOSUID) {
+            result |= StatFsFlags::ST_NOSUID;
+        }
+        if self.contains(Self::MS_NODEV) {
+            result |= StatFsFlags::ST_NODEV;
+        }
+        if self.contains(Self::MS_NOEXEC) {
+            result |= StatFsFlags::ST_NOEXEC;
+        }
+        if self.contains(Self::MS_NOATIME) {
+            result |= StatFsFlags::ST_NOATIME;
+        }
+        result
     }
 }
 
@@ -145,6 +252,9 @@ pub struct Dirent64 {
     name: Vec<u8>,
 }
 impl Dirent64 {
+    /// Maximum size of an entry, whose name is at most `NAME_MAX` bytes long.
+    pub const MAX_SIZE: usize = size_of::<Dirent64Hdr>() + NAME_MAX + 1;
+
     pub fn new(mut hdr: Dirent64Hdr, name: Vec<u8>) -> Self {
         hdr.d_reclen = (size_of::<Dirent64Hdr>() + name.len() + 1) as _;
         Self { hdr, name }
@@ -193,6 +303,7 @@ impl FromApple for Dirent64 {
         ))
     }
 }
+impl Extensible for Dirent64 {}
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(transparent)]
@@ -361,6 +472,7 @@ impl Statx {
         }
     }
 }
+impl Extensible for Statx {}
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[repr(C)]
@@ -574,5 +686,59 @@ impl FsMagic {
 bitflags! {
     #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
     #[repr(transparent)]
-    pub struct StatFsFlags: u64 {}
+    pub struct StatFsFlags: u64 {
+        const ST_RDONLY = 1;
+        const ST_NOSUID = 2;
+        const ST_NODEV = 4;
+        const ST_NOEXEC = 8;
+        const ST_NOATIME = 1024;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(options: &str) -> (u64, String) {
+        let (flags, data) = MountFlags::parse_options(options);
+        (flags.bits(), data)
+    }
+
+    #[test]
+    fn mount_options_are_parsed() {
+        assert_eq!(parse(""), (0, String::new()));
+        assert_eq!(parse("defaults"), (0, String::new()));
+        assert_eq!(
+            parse("ro,nosuid,nodev,noexec,noatime"),
+            (MountFlags::PER_MOUNT.bits(), String::new())
+        );
+        assert_eq!(
+            parse("rw,size=64m,noexec,mode=1777"),
+            (
+                MountFlags::MS_NOEXEC.bits(),
+                String::from("size=64m,mode=1777")
+            )
+        );
+    }
+
+    #[test]
+    fn later_mount_options_win() {
+        assert_eq!(parse("ro,rw"), (0, String::new()));
+        assert_eq!(
+            parse("rw,ro"),
+            (MountFlags::MS_RDONLY.bits(), String::new())
+        );
+        assert_eq!(
+            parse("noexec,nosuid,exec"),
+            (MountFlags::MS_NOSUID.bits(), String::new())
+        );
+    }
+
+    #[test]
+    fn mount_options_round_trip() {
+        let (flags, _) = MountFlags::parse_options("nodev,ro,noatime");
+        assert_eq!(flags.options(), "ro,nodev,noatime");
+        let (reparsed, _) = MountFlags::parse_options(&flags.options());
+        assert_eq!(reparsed.bits(), flags.bits());
+    }
 }
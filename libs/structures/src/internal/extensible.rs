@@ -0,0 +1,114 @@
+//! Encoding of IPC structures that may grow new fields.
+//!
+//! An extensible structure is encoded as a length-prefixed byte string that holds its postcard encoding, so a receiver
+//! always knows where the structure ends, regardless of which fields the sender knows about. When decoding, bytes left
+//! over by fields that are unknown to the receiver are ignored, and fields that are missing from the encoding of an
+//! older sender are decoded from zeroes.
+//!
+//! New fields must therefore only be appended to extensible structures, and must have meaningful zero values. For
+//! example, a new field of [`Statx`](crate::fs::Statx) is zeroed along with its bit in `stx_mask`.
+//!
+//! Use it with `#[serde(with = "crate::internal::extensible")]`.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwned};
+
+/// A structure that is encoded with [this module](self) in IPC messages.
+pub trait Extensible: Serialize + DeserializeOwned {
+    /// Maximum number of zeroes needed to decode fields that may be missing from the encoding.
+    ///
+    /// Every field takes at most its in-memory size when encoded from zeroes, so the default is always enough.
+    const MAX_PADDING: usize = size_of::<Self>();
+}
+impl<T: Extensible> Extensible for Box<T> {
+    const MAX_PADDING: usize = T::MAX_PADDING;
+}
+
+pub fn serialize<T: Extensible, S: Serializer>(val: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    postcard::to_stdvec(val)
+        .map_err(serde::ser::Error::custom)?
+        .serialize(serializer)
+}
+
+pub fn deserialize<'de, T: Extensible, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    let mut buf = Vec::<u8>::deserialize(deserializer)?;
+    buf.resize(buf.len() + T::MAX_PADDING, 0);
+    postcard::take_from_bytes(&buf)
+        .map(|(val, _)| val)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Old {
+        a: u32,
+        b: i64,
+    }
+    impl Extensible for Old {}
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct New {
+        a: u32,
+        b: i64,
+        c: u64,
+        d: Option<u16>,
+    }
+    impl Extensible for New {}
+
+    /// A message carrying an extensible structure, followed by a field that must stay in place.
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Message<T: Extensible> {
+        #[serde(with = "super")]
+        #[serde(bound = "T: Extensible")]
+        inner: T,
+        tail: u32,
+    }
+
+    #[test]
+    fn older_receiver_ignores_new_fields() {
+        let sent = Message {
+            inner: New {
+                a: 1,
+                b: -2,
+                c: u64::MAX,
+                d: Some(4),
+            },
+            tail: 0xdead,
+        };
+        let buf = postcard::to_stdvec(&sent).unwrap();
+        let received: Message<Old> = postcard::from_bytes(&buf).unwrap();
+        assert_eq!(
+            received,
+            Message {
+                inner: Old { a: 1, b: -2 },
+                tail: 0xdead,
+            }
+        );
+    }
+
+    #[test]
+    fn newer_receiver_zeroes_missing_fields() {
+        let sent = Message {
+            inner: Old { a: 1, b: -2 },
+            tail: 0xdead,
+        };
+        let buf = postcard::to_stdvec(&sent).unwrap();
+        let received: Message<New> = postcard::from_bytes(&buf).unwrap();
+        assert_eq!(
+            received,
+            Message {
+                inner: New {
+                    a: 1,
+                    b: -2,
+                    c: 0,
+                    d: None,
+                },
+                tail: 0xdead,
+            }
+        );
+    }
+}
@@ -2,30 +2,71 @@ use crate::{
     device::DeviceNumber,
     error::LxError,
     fs::{
-        AccessFlags, Dirent64, FileMode, OpenFlags, OpenHow, StatFs, Statx, StatxMask, UmountFlags,
+        AccessFlags, AtFlags, Dirent64, FileMode, MountFlags, OpenFlags, OpenHow, StatFs, Statx,
+        StatxMask, UmountFlags,
     },
     io::{EventFdFlags, FcntlCmd, IoctlCmd, PollEvents, VfdAvailCtrl, Whence},
     misc::{LogLevel, SysInfo},
+    process::{CloneFlags, PidFdFlags, RLimit64, RLimitable},
     time::Timespec,
 };
+use bitflags::bitflags;
 use libc::c_int;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
-pub const PROTOCOL_VERSION: &str = "9999";
+/// Version of the MacTux IPC protocol, which is bumped whenever requests or responses change incompatibly.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+bitflags! {
+    /// Optional features of the MacTux IPC protocol, which are used on a connection only if both sides support them.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    #[repr(transparent)]
+    pub struct Capabilities: u64 {
+        /// Large payloads may be transferred through a shared buffer, set up by [`Request::MapSharedBuffer`].
+        const SHARED_BUFFER = 1;
+
+        /// Several requests may be made in a single exchange with [`Request::Batch`].
+        const BATCH = 2;
+    }
+}
 
 /// A handshake request.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+///
+/// `magic` and `version` must lead both handshake messages in every protocol version, so that peers of different
+/// versions can always tell each other apart.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct HandshakeRequest {
     pub magic: [u8; 8],
+    pub version: u32,
+    pub capabilities: Capabilities,
 }
 impl HandshakeRequest {
     /// The magic number.
     pub const MAGIC: [u8; 8] = *b"MACTUXHQ";
 
-    /// Creates a new [`HandshakeRequest`] instance, in its only valid form.
+    /// Creates a new [`HandshakeRequest`] instance that fits current library version, offering all capabilities it
+    /// supports.
     pub fn new() -> Self {
-        Self { magic: Self::MAGIC }
+        Self {
+            magic: Self::MAGIC,
+            version: PROTOCOL_VERSION,
+            capabilities: Capabilities::all(),
+        }
+    }
+
+    /// Checks whether the request comes from a client that speaks the current protocol version.
+    pub fn check(&self) -> Result<(), HandshakeError> {
+        if self.magic != Self::MAGIC {
+            return Err(HandshakeError::BadMagic);
+        }
+        if self.version != PROTOCOL_VERSION {
+            return Err(HandshakeError::VersionMismatch {
+                server: PROTOCOL_VERSION,
+                client: self.version,
+            });
+        }
+        Ok(())
     }
 }
 impl Default for HandshakeRequest {
@@ -35,40 +76,81 @@ impl Default for HandshakeRequest {
 }
 
 /// A handshake response.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+///
+/// The server answers with its own version even if it rejects the client, and closes the connection afterwards.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct HandshakeResponse {
     pub magic: [u8; 8],
-    pub version: String,
+    pub version: u32,
+    pub capabilities: Capabilities,
 }
 impl HandshakeResponse {
     /// The magic number.
     pub const MAGIC: [u8; 8] = *b"MACTUXHS";
 
-    /// Creates a new [`HandshakeResponse`] instance that fits current library version.
-    pub fn new() -> Self {
+    /// Creates a new [`HandshakeResponse`] instance that fits current library version, enabling capabilities that
+    /// are supported by both this version and the client that sent `req`.
+    pub fn new(req: &HandshakeRequest) -> Self {
         Self {
             magic: Self::MAGIC,
-            version: PROTOCOL_VERSION.into(),
+            version: PROTOCOL_VERSION,
+            capabilities: req.capabilities & Capabilities::all(),
+        }
+    }
+
+    /// Checks whether the response comes from a server that speaks the current protocol version, returning the
+    /// capabilities enabled for the connection.
+    pub fn check(&self) -> Result<Capabilities, HandshakeError> {
+        if self.magic != Self::MAGIC {
+            return Err(HandshakeError::BadMagic);
+        }
+        if self.version != PROTOCOL_VERSION {
+            return Err(HandshakeError::VersionMismatch {
+                server: self.version,
+                client: PROTOCOL_VERSION,
+            });
         }
+        Ok(self.capabilities & Capabilities::all())
     }
 }
-impl Default for HandshakeResponse {
-    fn default() -> Self {
-        Self::new()
+
+/// Reasons a handshake fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HandshakeError {
+    /// The peer does not speak the MacTux IPC protocol, or speaks a version too old to be told apart.
+    BadMagic,
+
+    /// The peers speak different versions of the MacTux IPC protocol.
+    VersionMismatch { server: u32, client: u32 },
+}
+impl std::fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadMagic => write!(
+                f,
+                "peer does not speak a known version of the MacTux IPC protocol"
+            ),
+            Self::VersionMismatch { server, client } => write!(
+                f,
+                "server speaks MacTux IPC protocol version {server}, but client speaks version {client}; make sure \
+                 both come from the same MacTux installation"
+            ),
+        }
     }
 }
+impl std::error::Error for HandshakeError {}
 
 /// An uninterruptible MacTux IPC request.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Request {
-    SetMntNamespace(u64),
-    SetPidNamespace(u64),
-    SetUtsNamespace(u64),
+    SetNamespace(u64, CloneFlags),
+    Unshare(CloneFlags),
 
+    Mount(Vec<u8>, Vec<u8>, String, MountFlags, Vec<u8>),
     Umount(Vec<u8>, UmountFlags),
 
     Open(Vec<u8>, OpenHow),
-    Access(Vec<u8>, AccessFlags),
+    Access(Vec<u8>, AccessFlags, AtFlags),
     Unlink(Vec<u8>),
     Rmdir(Vec<u8>),
     Symlink(Vec<u8>, Vec<u8>),
@@ -82,6 +164,17 @@ pub enum Request {
     VfdPread(u64, i64, usize),
     VfdWrite(u64, Vec<u8>),
     VfdPwrite(u64, i64, Vec<u8>),
+    VfdAppend(u64, Vec<u8>, bool),
+
+    /// Like [`Request::VfdRead`], but the data is placed in the shared buffer, and its length is returned.
+    VfdReadShared(u64, usize),
+    /// Like [`Request::VfdPread`], but the data is placed in the shared buffer, and its length is returned.
+    VfdPreadShared(u64, i64, usize),
+    /// Like [`Request::VfdWrite`], but the data is taken from the shared buffer.
+    VfdWriteShared(u64, usize),
+    /// Like [`Request::VfdPwrite`], but the data is taken from the shared buffer.
+    VfdPwriteShared(u64, i64, usize),
+
     VfdSeek(u64, Whence, i64),
     VfdIoctlQuery(u64, IoctlCmd),
     VfdIoctl(u64, IoctlCmd, Vec<u8>),
@@ -102,6 +195,8 @@ pub enum Request {
 
     EventFd(u64, EventFdFlags),
     InvalidFd(OpenFlags),
+    PidFdOpen(i32, PidFdFlags),
+    PidFdGetPid(u64),
 
     GetNetworkNames,
     SetNetworkNames(NetworkNames),
@@ -111,16 +206,44 @@ pub enum Request {
     ReadSyslogAll(usize),
 
     AfterFork(i32),
+
+    /// Notifies that the client has executed a new program, which is answered by [`Response::Capabilities`] holding
+    /// the capabilities enabled for the connection at handshake.
     AfterExec,
+    SetExe(Vec<u8>),
+    SetCwd(Vec<u8>),
 
     GetThreadName,
     SetThreadName(Vec<u8>),
 
     GetThreadId,
 
+    /// Binds this connection to the thread of the given TID in the calling process, in place of the thread created for
+    /// the connection, so that a client reestablishing its connection keeps its identity.
+    Reattach(i32),
+    GetThreadGroup(i32),
+
+    GetUmask,
+    SetUmask(u16),
+
+    GetDumpable(i32),
+    SetDumpable(bool),
+
+    GetRLimit(i32, RLimitable),
+    SetRLimit(i32, RLimitable, RLimit64),
+    SyncRLimits(Vec<(RLimitable, RLimit64)>),
+
     PidNativeToLinux(i32),
     PidLinuxToNative(i32),
 
+    /// Maps the shared buffer of the given name and length for this connection, replacing the previous one. See
+    /// [`super::shared_buffer`]. Requires [`Capabilities::SHARED_BUFFER`].
+    MapSharedBuffer(Vec<u8>, usize),
+
+    /// Several requests handled in a single exchange, which is answered by [`Response::Batch`] holding their responses
+    /// in order. Batches and interruptible requests cannot be nested. Requires [`Capabilities::BATCH`].
+    Batch(Vec<Request>),
+
     CallInterruptible(InterruptibleRequest),
 }
 
@@ -143,13 +266,19 @@ pub enum Response {
     Offset(i64),
     CtrlOutput(CtrlOutput),
     VfdAvailCtrl(VfdAvailCtrl),
-    Stat(Box<Statx>),
-    Dirent64(Dirent64),
+    Stat(#[serde(with = "super::extensible")] Box<Statx>),
+    Dirent64(#[serde(with = "super::extensible")] Dirent64),
     NetworkNames(NetworkNames),
     SysInfo(Box<SysInfo>),
     StatFs(Box<StatFs>),
     Poll(Option<(u64, PollEvents)>),
     ListXattr(Vec<Vec<u8>>),
+    Umask(u16),
+    Dumpable(bool),
+    RLimit(RLimit64),
+    RLimits(Vec<(RLimitable, RLimit64)>),
+    Batch(Vec<Response>),
+    Capabilities(Capabilities),
     Error(LxError),
 }
 
@@ -165,3 +294,83 @@ pub struct NetworkNames {
     pub nodename: Vec<u8>,
     pub domainname: Vec<u8>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake_of_same_version_succeeds() {
+        let req = HandshakeRequest::new();
+        assert_eq!(req.check(), Ok(()));
+        let resp = HandshakeResponse::new(&req);
+        assert_eq!(resp.check(), Ok(Capabilities::all()));
+    }
+
+    #[test]
+    fn handshake_of_other_version_fails() {
+        let req = HandshakeRequest {
+            version: PROTOCOL_VERSION + 1,
+            ..HandshakeRequest::new()
+        };
+        assert_eq!(
+            req.check(),
+            Err(HandshakeError::VersionMismatch {
+                server: PROTOCOL_VERSION,
+                client: PROTOCOL_VERSION + 1,
+            })
+        );
+
+        let resp = HandshakeResponse {
+            version: PROTOCOL_VERSION + 1,
+            ..HandshakeResponse::new(&HandshakeRequest::new())
+        };
+        assert_eq!(
+            resp.check(),
+            Err(HandshakeError::VersionMismatch {
+                server: PROTOCOL_VERSION + 1,
+                client: PROTOCOL_VERSION,
+            })
+        );
+    }
+
+    #[test]
+    fn handshake_with_bad_magic_fails() {
+        let req = HandshakeRequest {
+            magic: HandshakeResponse::MAGIC,
+            ..HandshakeRequest::new()
+        };
+        assert_eq!(req.check(), Err(HandshakeError::BadMagic));
+        let resp = HandshakeResponse {
+            magic: HandshakeRequest::MAGIC,
+            ..HandshakeResponse::new(&HandshakeRequest::new())
+        };
+        assert_eq!(resp.check(), Err(HandshakeError::BadMagic));
+    }
+
+    #[test]
+    fn only_common_capabilities_are_enabled() {
+        let req = HandshakeRequest {
+            capabilities: Capabilities::BATCH,
+            ..HandshakeRequest::new()
+        };
+        assert_eq!(
+            HandshakeResponse::new(&req).check(),
+            Ok(Capabilities::BATCH)
+        );
+
+        // Capabilities unknown to this version, offered by a newer client or enabled by a newer server, are dropped.
+        let unknown = Capabilities::from_bits_retain(1 << 63);
+        let req = HandshakeRequest {
+            capabilities: Capabilities::SHARED_BUFFER | unknown,
+            ..HandshakeRequest::new()
+        };
+        let resp = HandshakeResponse::new(&req);
+        assert_eq!(resp.capabilities, Capabilities::SHARED_BUFFER);
+        let resp = HandshakeResponse {
+            capabilities: Capabilities::all() | unknown,
+            ..resp
+        };
+        assert_eq!(resp.check(), Ok(Capabilities::all()));
+    }
+}
@@ -1,2 +1,4 @@
+pub mod extensible;
 pub mod mactux_gui_abi;
 pub mod mactux_ipc;
+pub mod shared_buffer;
@@ -0,0 +1,127 @@
+//! Shared memory that carries large payloads of the MacTux IPC protocol.
+//!
+//! A client creates a POSIX shared memory object, and announces its name with [`Request::MapSharedBuffer`]. Once the
+//! server has mapped it, the client unlinks the name, so the object lives exactly as long as both mappings. Payloads
+//! larger than [`SHARED_THRESHOLD`] are then placed in the buffer, and requests only carry their lengths.
+//!
+//! [`Request::MapSharedBuffer`]: super::mactux_ipc::Request::MapSharedBuffer
+
+use std::{
+    ffi::CString,
+    sync::atomic::{self, AtomicU32},
+};
+
+/// Length of a shared buffer.
+pub const SHARED_BUFFER_LEN: usize = 4 * 1024 * 1024;
+
+/// Payloads larger than this are transferred through the shared buffer, if there is one.
+pub const SHARED_THRESHOLD: usize = 64 * 1024;
+
+/// A mapping of a shared buffer.
+#[derive(Debug)]
+pub struct SharedBuffer {
+    ptr: *mut u8,
+    len: usize,
+}
+impl SharedBuffer {
+    /// Creates a shared buffer of [`SHARED_BUFFER_LEN`] bytes, returning it and its name.
+    pub fn create() -> std::io::Result<(Self, Vec<u8>)> {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+        // Names are limited to 31 bytes on macOS.
+        let name = format!(
+            "/mactux.{}.{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, atomic::Ordering::Relaxed)
+        );
+        let cname = CString::new(name.clone()).expect("names contain no nul bytes");
+        unsafe {
+            let fd = libc::shm_open(
+                cname.as_ptr(),
+                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC,
+                0o600 as libc::c_uint,
+            );
+            if fd == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let result = match libc::ftruncate(fd, SHARED_BUFFER_LEN as _) {
+                -1 => Err(std::io::Error::last_os_error()),
+                _ => Self::map(fd, SHARED_BUFFER_LEN),
+            };
+            libc::close(fd);
+            if result.is_err() {
+                libc::shm_unlink(cname.as_ptr());
+            }
+            Ok((result?, name.into_bytes()))
+        }
+    }
+
+    /// Maps the shared buffer named `name`, which must be `len` bytes long.
+    pub fn open(name: &[u8], len: usize) -> std::io::Result<Self> {
+        let cname = CString::new(name)?;
+        unsafe {
+            let fd = libc::shm_open(cname.as_ptr(), libc::O_RDWR | libc::O_CLOEXEC);
+            if fd == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let result = Self::map(fd, len);
+            libc::close(fd);
+            result
+        }
+    }
+
+    /// Removes the name of a shared buffer, once both sides have mapped it.
+    pub fn unlink(name: &[u8]) {
+        if let Ok(cname) = CString::new(name) {
+            unsafe {
+                libc::shm_unlink(cname.as_ptr());
+            }
+        }
+    }
+
+    unsafe fn map(fd: libc::c_int, len: usize) -> std::io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+        match ptr {
+            libc::MAP_FAILED => Err(std::io::Error::last_os_error()),
+            ptr => Ok(Self {
+                ptr: ptr.cast(),
+                len,
+            }),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the first `len` bytes of the buffer, or `None` if the buffer is shorter.
+    ///
+    /// # Safety
+    /// The other side must not access the buffer while the returned slice is alive, which holds as long as it is only
+    /// used while handling a request.
+    pub unsafe fn get(&self, len: usize) -> Option<&mut [u8]> {
+        (len <= self.len).then(|| unsafe { std::slice::from_raw_parts_mut(self.ptr, len) })
+    }
+}
+impl Drop for SharedBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr.cast(), self.len);
+        }
+    }
+}
+unsafe impl Send for SharedBuffer {}
+unsafe impl Sync for SharedBuffer {}
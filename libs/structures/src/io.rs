@@ -1,6 +1,6 @@
 use crate::{
     FromApple, ToApple, error::LxError, mapper, signal::KernelSigSet, terminal::Termios2,
-    unixvariants,
+    time::RtcTime, unixvariants,
 };
 use bitflags::bitflags;
 use libc::c_int;
@@ -70,10 +70,15 @@ impl IoctlCmd {
     pub const TCXONC: Self = Self(0x540A);
     pub const TIOCGPGRP: Self = Self(0x540F);
     pub const TIOCSPGRP: Self = Self(0x5410);
+    pub const TIOCSTI: Self = Self(0x5412);
     pub const TIOCGWINSZ: Self = Self(0x5413);
     pub const TIOCSWINSZ: Self = Self(0x5414);
 
     pub const SIOCGSTAMP: Self = Self(0x8906);
+    pub const SIOCGIFCONF: Self = Self(0x8912);
+    pub const SIOCGIFFLAGS: Self = Self(0x8913);
+    pub const SIOCGIFADDR: Self = Self(0x8915);
+    pub const SIOCGIFNETMASK: Self = Self(0x891b);
 
     pub const TCGETS2: Self = Self::_ior::<Termios2>(b'T' as _, 42);
     pub const TCSETS2: Self = Self::_iow::<Termios2>(b'T' as _, 43);
@@ -87,6 +92,19 @@ impl IoctlCmd {
     pub const SNDCTL_DSP_SETFMT: Self = Self::_iowr::<c_int>(b'P' as _, 5);
     pub const SNDCTL_DSP_SETFRAGMENT: Self = Self::_iowr::<c_int>(b'P' as _, 10);
     pub const SNDCTL_DSP_STEREO: Self = Self::_iowr::<c_int>(b'P' as _, 3);
+    pub const SNDCTL_DSP_SYNC: Self = Self::_ioc(0, b'P' as _, 1, 0);
+    pub const SNDCTL_DSP_GETBLKSIZE: Self = Self::_iowr::<c_int>(b'P' as _, 4);
+    pub const SNDCTL_DSP_GETOSPACE: Self = Self::_ior::<AudioBufInfo>(b'P' as _, 12);
+    pub const SNDCTL_DSP_GETISPACE: Self = Self::_ior::<AudioBufInfo>(b'P' as _, 13);
+
+    pub const RTC_AIE_ON: Self = Self::_ioc(0, b'p' as _, 0x01, 0);
+    pub const RTC_AIE_OFF: Self = Self::_ioc(0, b'p' as _, 0x02, 0);
+    pub const RTC_UIE_ON: Self = Self::_ioc(0, b'p' as _, 0x03, 0);
+    pub const RTC_UIE_OFF: Self = Self::_ioc(0, b'p' as _, 0x04, 0);
+    pub const RTC_ALM_SET: Self = Self::_iow::<RtcTime>(b'p' as _, 0x07);
+    pub const RTC_ALM_READ: Self = Self::_ior::<RtcTime>(b'p' as _, 0x08);
+    pub const RTC_RD_TIME: Self = Self::_ior::<RtcTime>(b'p' as _, 0x09);
+    pub const RTC_SET_TIME: Self = Self::_iow::<RtcTime>(b'p' as _, 0x0a);
 
     pub const _IOC_READ: u32 = 2;
     pub const _IOC_WRITE: u32 = 1;
@@ -185,6 +203,19 @@ crate::bitflags_impl_from_to_apple!(
     values = LOCK_SH, LOCK_EX, LOCK_NB, LOCK_UN
 );
 
+bitflags! {
+    /// Per-call flags of `preadv2` and `pwritev2`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(transparent)]
+    pub struct RwfFlags: u32 {
+        const RWF_HIPRI = 1;
+        const RWF_DSYNC = 2;
+        const RWF_SYNC = 4;
+        const RWF_NOWAIT = 8;
+        const RWF_APPEND = 16;
+    }
+}
+
 #[derive(Debug, Clone)]
 #[repr(C)]
 pub struct PollFd {
@@ -216,6 +247,9 @@ crate::bitflags_impl_from_to_apple!(
     values = POLLIN, POLLPRI, POLLOUT, POLLERR, POLLHUP, POLLNVAL, POLLRDNORM, POLLRDBAND, POLLWRNORM, POLLWRBAND
 );
 
+/// Maximum number of file descriptors in a [`FdSet`].
+pub const FD_SETSIZE: usize = 1024;
+
 #[derive(Debug)]
 pub struct FdSet {
     ptr: NonNull<u64>,
@@ -387,6 +421,42 @@ bitflags! {
     }
 }
 
+/// Advice given to `fadvise64` about the access pattern of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Fadvice(pub c_int);
+impl Fadvice {
+    pub const POSIX_FADV_NORMAL: Self = Self(0);
+    pub const POSIX_FADV_RANDOM: Self = Self(1);
+    pub const POSIX_FADV_SEQUENTIAL: Self = Self(2);
+    pub const POSIX_FADV_WILLNEED: Self = Self(3);
+    pub const POSIX_FADV_DONTNEED: Self = Self(4);
+    pub const POSIX_FADV_NOREUSE: Self = Self(5);
+
+    /// Returns `true` if this is a known advice value.
+    pub fn is_valid(self) -> bool {
+        (0..=5).contains(&self.0)
+    }
+}
+
+/// The `audio_buf_info` structure, used by OSS `SNDCTL_DSP_GET[IO]SPACE` ioctls.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct AudioBufInfo {
+    pub fragments: c_int,
+    pub fragstotal: c_int,
+    pub fragsize: c_int,
+    pub bytes: c_int,
+}
+impl AudioBufInfo {
+    pub fn to_bytes(self) -> Vec<u8> {
+        [self.fragments, self.fragstotal, self.fragsize, self.bytes]
+            .iter()
+            .flat_map(|x| x.to_ne_bytes())
+            .collect()
+    }
+}
+
 /// Information about a virtual file descriptor's specific "ioctl" availability.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VfdAvailCtrl {
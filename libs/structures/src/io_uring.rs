@@ -0,0 +1,187 @@
+//! Structures of `io_uring`.
+
+use bitflags::bitflags;
+
+/// Offset to map the submission queue ring at.
+pub const IORING_OFF_SQ_RING: i64 = 0;
+
+/// Offset to map the completion queue ring at.
+pub const IORING_OFF_CQ_RING: i64 = 0x8000000;
+
+/// Offset to map the submission queue entries at.
+pub const IORING_OFF_SQES: i64 = 0x10000000;
+
+/// Maximum number of submission queue entries of a ring.
+pub const IORING_MAX_ENTRIES: u32 = 32768;
+
+/// Maximum number of completion queue entries of a ring.
+pub const IORING_MAX_CQ_ENTRIES: u32 = 2 * IORING_MAX_ENTRIES;
+
+/// Maximum number of buffers registered with a ring.
+pub const IORING_MAX_REG_BUFFERS: u32 = 1 << 14;
+
+/// Maximum number of files registered with a ring.
+pub const IORING_MAX_FIXED_FILES: u32 = 1 << 15;
+
+/// Set in `flags` of the submission queue ring if `io_uring_enter` must be called for submissions to be consumed.
+pub const IORING_SQ_NEED_WAKEUP: u32 = 1;
+
+/// Set in `flags` of the submission queue ring if completions are waiting for space in the completion queue.
+pub const IORING_SQ_CQ_OVERFLOW: u32 = 2;
+
+/// Makes `IORING_OP_FSYNC` behave like `fdatasync`.
+pub const IORING_FSYNC_DATASYNC: u32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct IoUringParams {
+    pub sq_entries: u32,
+    pub cq_entries: u32,
+    pub flags: IoUringSetupFlags,
+    pub sq_thread_cpu: u32,
+    pub sq_thread_idle: u32,
+    pub features: IoUringFeatures,
+    pub wq_fd: u32,
+    pub resv: [u32; 3],
+    pub sq_off: IoSqringOffsets,
+    pub cq_off: IoCqringOffsets,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct IoSqringOffsets {
+    pub head: u32,
+    pub tail: u32,
+    pub ring_mask: u32,
+    pub ring_entries: u32,
+    pub flags: u32,
+    pub dropped: u32,
+    pub array: u32,
+    pub resv1: u32,
+    pub user_addr: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct IoCqringOffsets {
+    pub head: u32,
+    pub tail: u32,
+    pub ring_mask: u32,
+    pub ring_entries: u32,
+    pub overflow: u32,
+    pub cqes: u32,
+    pub flags: u32,
+    pub resv1: u32,
+    pub user_addr: u64,
+}
+
+/// A submission queue entry.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct IoUringSqe {
+    pub opcode: IoUringOp,
+    pub flags: IoSqeFlags,
+    pub ioprio: u16,
+    pub fd: i32,
+    pub off: u64,
+    pub addr: u64,
+    pub len: u32,
+
+    /// Flags specific to the operation, like `rw_flags`, `fsync_flags` and `poll_events`.
+    pub op_flags: u32,
+    pub user_data: u64,
+    pub buf_index: u16,
+    pub personality: u16,
+    pub file_index: u32,
+    pub addr3: u64,
+    pub _pad2: u64,
+}
+
+/// A completion queue entry.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct IoUringCqe {
+    pub user_data: u64,
+    pub res: i32,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct IoUringOp(pub u8);
+impl IoUringOp {
+    pub const IORING_OP_NOP: Self = Self(0);
+    pub const IORING_OP_READV: Self = Self(1);
+    pub const IORING_OP_WRITEV: Self = Self(2);
+    pub const IORING_OP_FSYNC: Self = Self(3);
+    pub const IORING_OP_READ_FIXED: Self = Self(4);
+    pub const IORING_OP_WRITE_FIXED: Self = Self(5);
+    pub const IORING_OP_POLL_ADD: Self = Self(6);
+    pub const IORING_OP_POLL_REMOVE: Self = Self(7);
+    pub const IORING_OP_READ: Self = Self(22);
+    pub const IORING_OP_WRITE: Self = Self(23);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct IoUringRegisterOp(pub u32);
+impl IoUringRegisterOp {
+    pub const IORING_REGISTER_BUFFERS: Self = Self(0);
+    pub const IORING_UNREGISTER_BUFFERS: Self = Self(1);
+    pub const IORING_REGISTER_FILES: Self = Self(2);
+    pub const IORING_UNREGISTER_FILES: Self = Self(3);
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(transparent)]
+    pub struct IoUringSetupFlags: u32 {
+        const IORING_SETUP_IOPOLL = 1;
+        const IORING_SETUP_SQPOLL = 2;
+        const IORING_SETUP_SQ_AFF = 4;
+        const IORING_SETUP_CQSIZE = 8;
+        const IORING_SETUP_CLAMP = 16;
+        const IORING_SETUP_ATTACH_WQ = 32;
+        const IORING_SETUP_R_DISABLED = 64;
+        const IORING_SETUP_SUBMIT_ALL = 128;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(transparent)]
+    pub struct IoUringFeatures: u32 {
+        const IORING_FEAT_SINGLE_MMAP = 1;
+        const IORING_FEAT_NODROP = 2;
+        const IORING_FEAT_SUBMIT_STABLE = 4;
+        const IORING_FEAT_RW_CUR_POS = 8;
+        const IORING_FEAT_CUR_PERSONALITY = 16;
+        const IORING_FEAT_FAST_POLL = 32;
+        const IORING_FEAT_POLL_32BITS = 64;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(transparent)]
+    pub struct IoUringEnterFlags: u32 {
+        const IORING_ENTER_GETEVENTS = 1;
+        const IORING_ENTER_SQ_WAKEUP = 2;
+        const IORING_ENTER_SQ_WAIT = 4;
+        const IORING_ENTER_EXT_ARG = 8;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(transparent)]
+    pub struct IoSqeFlags: u8 {
+        const IOSQE_FIXED_FILE = 1;
+        const IOSQE_IO_DRAIN = 2;
+        const IOSQE_IO_LINK = 4;
+        const IOSQE_IO_HARDLINK = 8;
+        const IOSQE_ASYNC = 16;
+        const IOSQE_BUFFER_SELECT = 32;
+        const IOSQE_CQE_SKIP_SUCCESS = 64;
+    }
+}
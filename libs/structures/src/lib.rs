@@ -2,10 +2,12 @@
 
 pub mod device;
 pub mod error;
+pub mod exec;
 pub mod files;
 pub mod fs;
 pub mod internal;
 pub mod io;
+pub mod io_uring;
 pub mod mapper;
 pub mod misc;
 pub mod mm;
@@ -96,6 +98,7 @@ macro_rules! unixvariants {
             $(const $j:ident = $k:expr;)*
             $(#[linux_only] const $h:ident = $i:expr;)*
             $(#[apple = $an:ident] const $l:ident = $m:expr;)*
+            $(#[apple_only = $ao:ident] use $u:ident;)*
             $(#[reserve] const $r:ident = $x:expr;)*
 
             fn from_apple($_:ident: $ati:ty) -> Result<Self, LxError>;
@@ -128,6 +131,7 @@ macro_rules! unixvariants {
                 match apple {
                     $(libc::$j => Ok(Self::$j),)*
                     $(libc::$an => Ok(Self::$l),)*
+                    $(libc::$ao => Ok(Self::$u),)*
                     $($x => Ok(Self::$r),)*
                     _ => Err($crate::error::LxError::EINVAL),
                 }
@@ -13,6 +13,9 @@ pub struct UtsName {
     pub domainname: [u8; 65],
 }
 
+/// Maximum length of host names and domain names, excluding the terminating NUL.
+pub const HOST_NAME_MAX: usize = 64;
+
 /// Converts a byte string to the format that fits [`UtsName`].
 #[inline]
 pub fn uname_str(s: &[u8]) -> Result<[u8; 65], LxError> {
@@ -25,6 +28,9 @@ pub fn uname_str(s: &[u8]) -> Result<[u8; 65], LxError> {
     Ok(data)
 }
 
+/// Load averages in [`SysInfo`] are fixed-point numbers with this many fractional bits.
+pub const SI_LOAD_SHIFT: u32 = 16;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[repr(C)]
 pub struct SysInfo {
@@ -48,6 +54,7 @@ bitflags! {
     pub struct GrndFlags: u32 {
         const GRND_NONBLOCK = 1;
         const GRND_RANDOM = 2;
+        const GRND_INSECURE = 4;
     }
 }
 
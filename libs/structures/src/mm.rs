@@ -23,7 +23,7 @@ impl ToApple for MmapProt {
             apple |= libc::PROT_WRITE;
         }
         if self.contains(Self::PROT_EXEC) {
-            apple |= libc::PROT_EXEC | libc::PROT_WRITE;
+            apple |= libc::PROT_EXEC;
         }
         Ok(apple)
     }
@@ -133,6 +133,7 @@ bitflags! {
         const MSG_OOB = 0x1;
         const MSG_PEEK = 0x2;
         const MSG_DONTROUTE = 0x4;
+        const MSG_DONTWAIT = 0x40;
         const MSG_WAITALL = 0x100;
         const MSG_NOSIGNAL = 0x4000;
     }
@@ -140,7 +141,7 @@ bitflags! {
 crate::bitflags_impl_from_to_apple!(
     MsgFlags;
     type Apple = i32;
-    values = MSG_OOB, MSG_PEEK, MSG_DONTROUTE, MSG_WAITALL, MSG_NOSIGNAL
+    values = MSG_OOB, MSG_PEEK, MSG_DONTROUTE, MSG_DONTWAIT, MSG_WAITALL, MSG_NOSIGNAL
 );
 
 #[derive(Debug, Clone)]
@@ -277,6 +278,83 @@ impl From<libc::in_addr> for InAddr {
     }
 }
 
+/// Maximum length of an interface name, including the terminating NUL.
+pub const IFNAMSIZ: usize = 16;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    #[repr(transparent)]
+    pub struct IfFlags: u16 {
+        const IFF_UP = 0x1;
+        const IFF_BROADCAST = 0x2;
+        const IFF_DEBUG = 0x4;
+        const IFF_LOOPBACK = 0x8;
+        const IFF_POINTOPOINT = 0x10;
+        const IFF_NOTRAILERS = 0x20;
+        const IFF_RUNNING = 0x40;
+        const IFF_NOARP = 0x80;
+        const IFF_PROMISC = 0x100;
+        const IFF_ALLMULTI = 0x200;
+        const IFF_MULTICAST = 0x1000;
+    }
+}
+crate::bitflags_impl_from_to_apple!(
+    IfFlags;
+    type Apple = c_int;
+    values = IFF_UP, IFF_BROADCAST, IFF_DEBUG, IFF_LOOPBACK, IFF_POINTOPOINT, IFF_NOTRAILERS,
+        IFF_RUNNING, IFF_NOARP, IFF_PROMISC, IFF_ALLMULTI, IFF_MULTICAST
+);
+
+/// The `ifreq` structure, used by `SIOCGIF*` ioctls.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct IfReq {
+    pub ifr_name: [u8; IFNAMSIZ],
+    pub ifr_ifru: [u8; 24],
+}
+impl IfReq {
+    /// Creates a request about the interface with the given name, which is truncated to fit in [`IFNAMSIZ`].
+    pub fn new(name: &[u8]) -> Self {
+        let mut ifr_name = [0; IFNAMSIZ];
+        let len = name.len().min(IFNAMSIZ - 1);
+        ifr_name[..len].copy_from_slice(&name[..len]);
+        Self {
+            ifr_name,
+            ifr_ifru: [0; _],
+        }
+    }
+
+    /// Returns the interface name, without the terminating NUL.
+    pub fn name(&self) -> &[u8] {
+        let len = self
+            .ifr_name
+            .iter()
+            .position(|x| *x == 0)
+            .unwrap_or(IFNAMSIZ);
+        &self.ifr_name[..len]
+    }
+
+    /// Sets the `ifr_addr`, `ifr_netmask` or another address member.
+    pub fn set_addr(&mut self, addr: SockAddrIn) {
+        self.ifr_ifru = [0; _];
+        addr.write_to(&mut self.ifr_ifru).unwrap();
+    }
+
+    /// Sets the `ifr_flags` member.
+    pub fn set_flags(&mut self, flags: IfFlags) {
+        self.ifr_ifru = [0; _];
+        self.ifr_ifru[..2].copy_from_slice(&flags.bits().to_ne_bytes());
+    }
+}
+
+/// The `ifconf` structure, used by the `SIOCGIFCONF` ioctl.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct IfConf {
+    pub ifc_len: c_int,
+    pub ifc_req: *mut IfReq,
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct Linger {
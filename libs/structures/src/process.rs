@@ -1,15 +1,35 @@
 use crate::{FromApple, error::LxError, signal::SigNum, time::Timeval, unixvariants};
 use bitflags::bitflags;
-use std::{ffi::c_int, fmt::Debug};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::{c_int, c_ulong},
+    fmt::Debug,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct WaitStatus(pub c_int);
 impl WaitStatus {
+    /// Status of a child continued by `SIGCONT`.
+    pub const CONTINUED: Self = Self(0xffff);
+
+    /// Status of a child stopped by the given signal.
+    pub const fn stopped(signum: SigNum) -> Self {
+        Self(0x7f | (signum.0 as c_int) << 8)
+    }
+
     pub fn from_apple(apple: c_int) -> Self {
-        if libc::WTERMSIG(apple) != 0 {
+        if libc::WIFCONTINUED(apple) {
+            Self::CONTINUED
+        } else if libc::WIFSTOPPED(apple) {
+            Self::stopped(SigNum::from_apple(libc::WSTOPSIG(apple)).unwrap_or(SigNum::SIGSTOP))
+        } else if libc::WIFSIGNALED(apple) {
             let signum = SigNum::from_apple(libc::WTERMSIG(apple)).unwrap_or(SigNum::SIGKILL);
-            Self(signum.0 as _)
+            let core_dump = match libc::WCOREDUMP(apple) {
+                true => 0x80,
+                false => 0,
+            };
+            Self(signum.0 as c_int | core_dump)
         } else {
             Self(libc::WEXITSTATUS(apple) << 8)
         }
@@ -17,7 +37,7 @@ impl WaitStatus {
 }
 
 bitflags! {
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
     #[repr(transparent)]
     pub struct CloneFlags: u32 {
         const CLONE_VM = 0x100;
@@ -27,11 +47,18 @@ bitflags! {
         const CLONE_VFORK = 0x4000;
         const CLONE_PARENT = 0x8000;
         const CLONE_THREAD = 0x10000;
+        const CLONE_NEWNS = 0x20000;
         const CLONE_SYSVSEM = 0x40000;
         const CLONE_SETTLS = 0x80000;
         const CLONE_PARENT_SETTID = 0x100000;
         const CLONE_CHILD_CLEARTID = 0x200000;
         const CLONE_CHILD_SETTID = 0x1000000;
+        const CLONE_NEWCGROUP = 0x2000000;
+        const CLONE_NEWUTS = 0x4000000;
+        const CLONE_NEWIPC = 0x8000000;
+        const CLONE_NEWUSER = 0x10000000;
+        const CLONE_NEWPID = 0x20000000;
+        const CLONE_NEWNET = 0x40000000;
         const CLONE_IO = 0x80000000;
     }
 }
@@ -55,6 +82,36 @@ impl CloneFlags {
     }
 }
 
+/// Kind of a namespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NamespaceKind {
+    Mount,
+    Pid,
+    Uts,
+    Net,
+}
+impl NamespaceKind {
+    /// Returns the `CLONE_NEW*` flag that refers to this kind of namespace.
+    pub fn clone_flag(self) -> CloneFlags {
+        match self {
+            Self::Mount => CloneFlags::CLONE_NEWNS,
+            Self::Pid => CloneFlags::CLONE_NEWPID,
+            Self::Uts => CloneFlags::CLONE_NEWUTS,
+            Self::Net => CloneFlags::CLONE_NEWNET,
+        }
+    }
+
+    /// Returns name of this kind of namespace, as is represented in `/proc/[pid]/ns`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mount => "mnt",
+            Self::Pid => "pid",
+            Self::Uts => "uts",
+            Self::Net => "net",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ChildType {
     Process,
@@ -67,14 +124,34 @@ bitflags! {
     #[repr(transparent)]
     pub struct WaitOptions: u32 {
         const WNOHANG = 1;
+        const WUNTRACED = 2;
+        const WCONTINUED = 8;
     }
 }
 crate::bitflags_impl_from_to_apple!(
     WaitOptions;
     type Apple = c_int;
-    values = WNOHANG
+    values = WNOHANG, WUNTRACED, WCONTINUED
 );
 
+bitflags! {
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+    #[repr(transparent)]
+    pub struct PidFdFlags: u32 {
+        const PIDFD_THREAD = 0o200;
+        const PIDFD_NONBLOCK = 0o4000;
+    }
+}
+impl PidFdFlags {
+    pub fn open_flags(self) -> crate::fs::OpenFlags {
+        let mut result = crate::fs::OpenFlags::O_RDWR | crate::fs::OpenFlags::O_CLOEXEC;
+        if self.contains(Self::PIDFD_NONBLOCK) {
+            result |= crate::fs::OpenFlags::O_NONBLOCK;
+        }
+        result
+    }
+}
+
 #[derive(Clone)]
 #[repr(C)]
 pub struct CloneArgs {
@@ -206,7 +283,7 @@ impl FromApple for RUsage {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(C)]
 pub struct RLimit64 {
     pub rlim_cur: u64,
@@ -215,6 +292,12 @@ pub struct RLimit64 {
 impl RLimit64 {
     pub const RLIM_INFINITY: u64 = !0;
 
+    /// A limit with both soft and hard values unlimited.
+    pub const UNLIMITED: Self = Self {
+        rlim_cur: Self::RLIM_INFINITY,
+        rlim_max: Self::RLIM_INFINITY,
+    };
+
     pub fn from_apple(apple: libc::rlimit) -> Self {
         let map_value = |apple| match apple {
             libc::RLIM_INFINITY => Self::RLIM_INFINITY,
@@ -238,7 +321,63 @@ impl RLimit64 {
     }
 }
 
+/// Maximum length of a single argument or environment string passed to `execve`, including the terminating NUL.
+pub const MAX_ARG_STRLEN: usize = 32 * 4096;
+
+/// Maximum length of the `#!` line of an interpreter script, including the `#!` itself.
+pub const BINPRM_BUF_SIZE: usize = 128;
+
+/// Returns the maximum total size of arguments and environment strings that `execve` accepts, given the soft limit of
+/// `RLIMIT_STACK`.
+///
+/// As on Linux, this is a quarter of the stack limit, capped at 6 MiB and never less than 128 KiB. The space taken by
+/// the `argv` and `envp` pointer arrays counts towards this limit too.
+pub fn arg_max(stack_limit: u64) -> usize {
+    const MIN: u64 = 32 * 4096;
+    const MAX: u64 = 6 * 1024 * 1024;
+    (stack_limit / 4).clamp(MIN, MAX) as usize
+}
+
+/// The legacy `struct rlimit`, used by `getrlimit` and `setrlimit`.
+///
+/// Its fields are as wide as `unsigned long`, so on 32-bit ABIs `RLIM_INFINITY` differs from the one of
+/// [`RLimit64`] and limits too large to be represented are reported as unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct RLimit {
+    pub rlim_cur: c_ulong,
+    pub rlim_max: c_ulong,
+}
+impl RLimit {
+    pub const RLIM_INFINITY: c_ulong = !0;
+}
+impl From<RLimit64> for RLimit {
+    fn from(value: RLimit64) -> Self {
+        let map_value = |x| match x {
+            RLimit64::RLIM_INFINITY => Self::RLIM_INFINITY,
+            other => c_ulong::try_from(other).unwrap_or(Self::RLIM_INFINITY),
+        };
+        Self {
+            rlim_cur: map_value(value.rlim_cur),
+            rlim_max: map_value(value.rlim_max),
+        }
+    }
+}
+impl From<RLimit> for RLimit64 {
+    fn from(value: RLimit) -> Self {
+        let map_value = |x| match x {
+            RLimit::RLIM_INFINITY => Self::RLIM_INFINITY,
+            other => other as u64,
+        };
+        Self {
+            rlim_cur: map_value(value.rlim_cur),
+            rlim_max: map_value(value.rlim_max),
+        }
+    }
+}
+
 unixvariants! {
+    #[derive(Serialize, Deserialize)]
     pub struct RLimitable: u32 {
         const RLIMIT_CPU = 0;
         const RLIMIT_FSIZE = 1;
@@ -248,6 +387,7 @@ unixvariants! {
         const RLIMIT_NOFILE = 7;
         const RLIMIT_MEMLOCK = 8;
         const RLIMIT_AS = 9;
+        const RLIMIT_NPROC = 6;
         #[linux_only] const RLIMIT_LOCKS = 10;
         #[linux_only] const RLIMIT_SIGPENDING = 11;
         #[linux_only] const RLIMIT_MSGQUEUE = 12;
@@ -255,7 +395,6 @@ unixvariants! {
         #[linux_only] const RLIMIT_RTPRIO = 14;
         #[linux_only] const RLIMIT_RTTIME = 15;
         #[linux_only] const RLIMIT_RSS = 5;
-        #[linux_only] const RLIMIT_NRPOC = 6;
         fn from_apple(apple: c_int) -> Result<Self, LxError>;
         fn to_apple(self) -> Result<c_int, LxError>;
     }
@@ -265,7 +404,7 @@ unixvariants! {
     pub struct RUsageWho: u32 {
         const RUSAGE_SELF = 0;
         const RUSAGE_CHILDREN = u32::MAX;
-        #[apple = RUSAGE_SELF] const RUSAGE_THREAD = 1;
+        #[linux_only] const RUSAGE_THREAD = 1;
         fn from_apple(apple: c_int) -> Result<Self, LxError>;
         fn to_apple(self) -> Result<c_int, LxError>;
     }
@@ -275,8 +414,87 @@ unixvariants! {
 #[repr(transparent)]
 pub struct PrctlOp(pub u32);
 impl PrctlOp {
+    pub const PR_GET_DUMPABLE: Self = Self(3);
+    pub const PR_SET_DUMPABLE: Self = Self(4);
     pub const PR_SET_KEEPCAPS: Self = Self(8);
     pub const PR_SET_NAME: Self = Self(15);
     pub const PR_GET_NAME: Self = Self(16);
     pub const PR_GET_TID_ADDRESS: Self = Self(40);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arg_max_is_a_quarter_of_the_stack() {
+        assert_eq!(arg_max(8 << 20), 2 << 20);
+        assert_eq!(arg_max(1 << 20), 256 << 10);
+    }
+
+    #[test]
+    fn arg_max_is_clamped() {
+        assert_eq!(arg_max(0), 128 << 10);
+        assert_eq!(arg_max(64 << 10), 128 << 10);
+        assert_eq!(arg_max(1 << 30), 6 << 20);
+        assert_eq!(arg_max(u64::MAX), 6 << 20);
+    }
+
+    #[test]
+    fn rlimit_round_trips_through_legacy_struct() {
+        let limits = [
+            RLimit64 {
+                rlim_cur: 1024,
+                rlim_max: 4096,
+            },
+            RLimit64 {
+                rlim_cur: 8 << 20,
+                rlim_max: RLimit64::RLIM_INFINITY,
+            },
+            RLimit64::UNLIMITED,
+        ];
+        for limit in limits {
+            assert_eq!(RLimit64::from(RLimit::from(limit)), limit);
+        }
+
+        let legacy = RLimit::from(RLimit64::UNLIMITED);
+        assert_eq!(legacy.rlim_cur, RLimit::RLIM_INFINITY);
+        assert_eq!(legacy.rlim_max, RLimit::RLIM_INFINITY);
+    }
+
+    #[test]
+    fn rlimit_infinity_maps_to_native_one() {
+        let limit = RLimit64 {
+            rlim_cur: 256,
+            rlim_max: RLimit64::RLIM_INFINITY,
+        };
+        let native = limit.to_apple();
+        assert_eq!(native.rlim_cur, 256);
+        assert_eq!(native.rlim_max, libc::RLIM_INFINITY);
+        assert_eq!(RLimit64::from_apple(native), limit);
+    }
+
+    #[test]
+    fn stopped_and_continued_statuses_are_encoded_like_linux() {
+        assert_eq!(WaitStatus::stopped(SigNum::SIGSTOP), WaitStatus(0x137f));
+        assert_eq!(WaitStatus::stopped(SigNum::SIGTTIN), WaitStatus(0x157f));
+        assert_eq!(WaitStatus::CONTINUED, WaitStatus(0xffff));
+    }
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn native_statuses_are_translated() {
+        let stopped = |signum: c_int| 0x7f | signum << 8;
+        assert_eq!(
+            WaitStatus::from_apple(stopped(libc::SIGTSTP)),
+            WaitStatus(0x147f)
+        );
+        assert_eq!(
+            WaitStatus::from_apple(stopped(libc::SIGSTOP)),
+            WaitStatus(0x137f)
+        );
+        // macOS reports continued children as stopped by signal 0x13, which is `SIGCONT` there.
+        assert_eq!(WaitStatus::from_apple(stopped(0x13)), WaitStatus::CONTINUED);
+        assert_eq!(WaitStatus::from_apple(3 << 8), WaitStatus(3 << 8));
+    }
+}
@@ -1,5 +1,8 @@
 use crate::error::LxError;
 
+/// Maximum number of supplementary groups of a process.
+pub const NGROUPS_MAX: usize = 65536;
+
 #[derive(Debug, Clone)]
 pub struct UserCap {
     pub version: UserCapVersion,
@@ -4,6 +4,15 @@ use libc::c_int;
 use std::{fmt::Debug, ptr::NonNull};
 
 pub const FUTEX_WAITERS: u32 = 0x80000000;
+pub const FUTEX_OWNER_DIED: u32 = 0x40000000;
+pub const FUTEX_TID_MASK: u32 = 0x3fffffff;
+
+/// The bitset that `FUTEX_WAIT` and `FUTEX_WAKE` use, which matches all waiters.
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// Maximum number of entries of a robust list that are processed when a thread exits, so that a corrupted or circular
+/// list does not hang the exiting thread.
+pub const ROBUST_LIST_LIMIT: usize = 2048;
 
 #[derive(Clone, Copy)]
 #[repr(transparent)]
@@ -157,3 +166,20 @@ pub struct RSeqCs {
     pub post_commit_offset: u64,
     pub abort_ip: u64,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct MembarrierCmd(pub c_int);
+impl MembarrierCmd {
+    pub const MEMBARRIER_CMD_QUERY: Self = Self(0);
+    pub const MEMBARRIER_CMD_GLOBAL: Self = Self(1 << 0);
+    pub const MEMBARRIER_CMD_GLOBAL_EXPEDITED: Self = Self(1 << 1);
+    pub const MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED: Self = Self(1 << 2);
+    pub const MEMBARRIER_CMD_PRIVATE_EXPEDITED: Self = Self(1 << 3);
+    pub const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED: Self = Self(1 << 4);
+    pub const MEMBARRIER_CMD_PRIVATE_EXPEDITED_SYNC_CORE: Self = Self(1 << 5);
+    pub const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED_SYNC_CORE: Self = Self(1 << 6);
+    pub const MEMBARRIER_CMD_PRIVATE_EXPEDITED_RSEQ: Self = Self(1 << 7);
+    pub const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED_RSEQ: Self = Self(1 << 8);
+    pub const MEMBARRIER_CMD_GET_REGISTRATIONS: Self = Self(1 << 9);
+}
@@ -1,6 +1,11 @@
 use crate::{FromApple, ToApple, bitflags_impl_from_to_apple, error::LxError, unixvariants};
 use bitflags::bitflags;
 
+/// The legacy `termios` structure.
+///
+/// Terminals are native ones, so line discipline, like canonical-mode line buffering, echoing and sending signals to
+/// the foreground process group for `VINTR`, `VQUIT` and `VSUSP`, is done by macOS as the translated settings say.
+/// Native signals reach programs through the signal handlers of the runtime environment.
 #[derive(Debug, Clone)]
 #[repr(C)]
 pub struct Termios {
@@ -24,6 +29,24 @@ impl From<Termios2> for Termios {
         }
     }
 }
+impl Termios {
+    /// Converts to a [`Termios2`], using `c_ispeed` and `c_ospeed` for speeds set to `BOTHER` in `c_cflag`.
+    ///
+    /// The legacy structure has no room for such speeds, which [`Termios2::from_apple`] reports for rates missing
+    /// from the table of speed codes, so callers pass the current speeds of the terminal to keep them unchanged.
+    pub fn with_speeds(self, c_ispeed: u32, c_ospeed: u32) -> Termios2 {
+        Termios2 {
+            c_iflag: self.c_iflag,
+            c_oflag: self.c_oflag,
+            c_cflag: self.c_cflag,
+            c_lflag: self.c_lflag,
+            c_line: self.c_line,
+            c_cc: self.c_cc,
+            c_ispeed,
+            c_ospeed,
+        }
+    }
+}
 impl FromApple for Termios {
     type Apple = libc::termios;
 
@@ -34,16 +57,16 @@ impl FromApple for Termios {
 impl ToApple for Termios {
     type Apple = libc::termios;
 
+    /// Fails with `EINVAL` if a speed is `BOTHER`, since the structure has no field holding it; see
+    /// [`Termios::with_speeds`].
     fn to_apple(self) -> Result<libc::termios, LxError> {
-        Ok(libc::termios {
-            c_iflag: self.c_iflag.to_apple()?,
-            c_oflag: self.c_oflag.to_apple()?,
-            c_cflag: self.c_cflag.to_apple()?,
-            c_lflag: self.c_lflag.to_apple()?,
-            c_cc: self.c_cc.to_apple(),
-            c_ispeed: 0,
-            c_ospeed: 0,
-        })
+        let cflag = self.c_cflag.bits();
+        let ocode = cflag & ControlFlags::CBAUD.bits();
+        let icode = (cflag & ControlFlags::CIBAUD.bits()) >> IBSHIFT;
+        if ocode == BOTHER || icode == BOTHER {
+            return Err(LxError::EINVAL);
+        }
+        self.with_speeds(0, 0).to_apple()
     }
 }
 
@@ -63,14 +86,15 @@ impl ToApple for Termios2 {
     type Apple = libc::termios;
 
     fn to_apple(self) -> Result<libc::termios, LxError> {
+        let (ispeed, ospeed) = apple_speeds(self.c_cflag, self.c_ispeed, self.c_ospeed)?;
         Ok(libc::termios {
             c_iflag: self.c_iflag.to_apple()?,
             c_oflag: self.c_oflag.to_apple()?,
             c_cflag: self.c_cflag.to_apple()?,
             c_lflag: self.c_lflag.to_apple()?,
             c_cc: self.c_cc.to_apple(),
-            c_ispeed: self.c_ispeed as _,
-            c_ospeed: self.c_ospeed as _,
+            c_ispeed: ispeed,
+            c_ospeed: ospeed,
         })
     }
 }
@@ -78,19 +102,104 @@ impl FromApple for Termios2 {
     type Apple = libc::termios;
 
     fn from_apple(value: libc::termios) -> Result<Self, LxError> {
+        let (ispeed, ospeed) = (value.c_ispeed as u32, value.c_ospeed as u32);
+        let mut speed_bits = baud_code(ospeed);
+        if ispeed != ospeed {
+            speed_bits |= baud_code(ispeed) << IBSHIFT;
+        }
         Ok(Self {
             c_iflag: InputFlags::from_apple(value.c_iflag)?,
             c_oflag: OutputFlags::from_apple(value.c_oflag)?,
-            c_cflag: ControlFlags::from_apple(value.c_cflag)?,
+            c_cflag: ControlFlags::from_apple(value.c_cflag)?
+                | ControlFlags::from_bits_retain(speed_bits),
             c_lflag: LocalFlags::from_apple(value.c_lflag)?,
             c_line: 0,
             c_cc: ControlCharacters::from_apple(value.c_cc),
-            c_ispeed: value.c_ispeed as _,
-            c_ospeed: value.c_ospeed as _,
+            c_ispeed: ispeed,
+            c_ospeed: ospeed,
         })
     }
 }
 
+/// Shift of the input speed in `c_cflag`, from `CBAUD` to `CIBAUD`.
+const IBSHIFT: u32 = 16;
+
+/// The `BOTHER` speed code, telling that the speed is given by `c_ispeed` or `c_ospeed` of `termios2`.
+const BOTHER: u32 = 0o10000;
+
+/// Speed codes of `c_cflag` and the speeds in bits per second they stand for.
+const BAUD_RATES: &[(u32, u32)] = &[
+    (0o0, 0),
+    (0o1, 50),
+    (0o2, 75),
+    (0o3, 110),
+    (0o4, 134),
+    (0o5, 150),
+    (0o6, 200),
+    (0o7, 300),
+    (0o10, 600),
+    (0o11, 1200),
+    (0o12, 1800),
+    (0o13, 2400),
+    (0o14, 4800),
+    (0o15, 9600),
+    (0o16, 19200),
+    (0o17, 38400),
+    (0o10001, 57600),
+    (0o10002, 115200),
+    (0o10003, 230400),
+    (0o10004, 460800),
+    (0o10005, 500000),
+    (0o10006, 576000),
+    (0o10007, 921600),
+    (0o10010, 1000000),
+    (0o10011, 1152000),
+    (0o10012, 1500000),
+    (0o10013, 2000000),
+    (0o10014, 2500000),
+    (0o10015, 3000000),
+    (0o10016, 3500000),
+    (0o10017, 4000000),
+];
+
+/// Returns the speed code of `c_cflag` for a speed in bits per second, or `BOTHER` if there is no such code.
+fn baud_code(speed: u32) -> u32 {
+    BAUD_RATES
+        .iter()
+        .find(|(_, rate)| *rate == speed)
+        .map_or(BOTHER, |(code, _)| *code)
+}
+
+/// Returns the speed in bits per second of a speed code of `c_cflag`, using `other` for `BOTHER`.
+fn baud_rate(code: u32, other: u32) -> Result<u32, LxError> {
+    if code == BOTHER {
+        return Ok(other);
+    }
+    BAUD_RATES
+        .iter()
+        .find(|(x, _)| *x == code)
+        .map(|(_, rate)| *rate)
+        .ok_or(LxError::EINVAL)
+}
+
+/// Returns the input and output speeds that macOS expects in `c_ispeed` and `c_ospeed`.
+///
+/// Linux encodes speeds in `c_cflag`, where an input speed of zero means the same speed as the output.
+fn apple_speeds(
+    cflag: ControlFlags,
+    c_ispeed: u32,
+    c_ospeed: u32,
+) -> Result<(libc::speed_t, libc::speed_t), LxError> {
+    let ocode = cflag.bits() & ControlFlags::CBAUD.bits();
+    let icode = (cflag.bits() & ControlFlags::CIBAUD.bits()) >> IBSHIFT;
+    let ospeed = baud_rate(ocode, c_ospeed)?;
+    let ispeed = match icode {
+        0 => ospeed,
+        _ => baud_rate(icode, c_ispeed)?,
+    };
+    Ok((ispeed as _, ospeed as _))
+}
+
 bitflags! {
     #[derive(Debug, Clone, Copy)]
     #[repr(transparent)]
@@ -195,7 +304,8 @@ bitflags! {
 bitflags_impl_from_to_apple!(
     LocalFlags;
     type Apple = u64;
-    values = ISIG, ICANON, ECHO, ECHOE, ECHOK, ECHONL, NOFLSH, TOSTOP, ECHOCTL, ECHOPRT, ECHOKE, IEXTEN
+    values = ISIG, ICANON, ECHO, ECHOE, ECHOK, ECHONL, NOFLSH, TOSTOP, ECHOCTL, ECHOPRT, ECHOKE, FLUSHO,
+             PENDIN, IEXTEN
 );
 
 #[derive(Debug, Clone, Copy)]
@@ -220,6 +330,10 @@ impl ControlCharacters {
     pub const VEOL2: usize = 16;
 }
 impl ControlCharacters {
+    /// Converts control characters of macOS.
+    ///
+    /// `VMIN` and `VTIME` are counts rather than characters, so they are copied as is instead of being treated as
+    /// disabled when zero.
     pub fn from_apple(apple: [u8; 20]) -> Self {
         let mut linux = [ControlCharacter::DISABLED; _];
         linux[Self::VINTR] = ControlCharacter::from_apple(apple[libc::VINTR]);
@@ -227,13 +341,14 @@ impl ControlCharacters {
         linux[Self::VERASE] = ControlCharacter::from_apple(apple[libc::VERASE]);
         linux[Self::VKILL] = ControlCharacter::from_apple(apple[libc::VKILL]);
         linux[Self::VEOF] = ControlCharacter::from_apple(apple[libc::VEOF]);
-        linux[Self::VTIME] = ControlCharacter::from_apple(apple[libc::VTIME]);
-        linux[Self::VMIN] = ControlCharacter::from_apple(apple[libc::VMIN]);
+        linux[Self::VTIME] = ControlCharacter(apple[libc::VTIME]);
+        linux[Self::VMIN] = ControlCharacter(apple[libc::VMIN]);
         linux[Self::VSTART] = ControlCharacter::from_apple(apple[libc::VSTART]);
         linux[Self::VSTOP] = ControlCharacter::from_apple(apple[libc::VSTOP]);
         linux[Self::VSUSP] = ControlCharacter::from_apple(apple[libc::VSUSP]);
         linux[Self::VEOL] = ControlCharacter::from_apple(apple[libc::VEOL]);
         linux[Self::VREPRINT] = ControlCharacter::from_apple(apple[libc::VREPRINT]);
+        linux[Self::VDISCARD] = ControlCharacter::from_apple(apple[libc::VDISCARD]);
         linux[Self::VWERASE] = ControlCharacter::from_apple(apple[libc::VWERASE]);
         linux[Self::VLNEXT] = ControlCharacter::from_apple(apple[libc::VLNEXT]);
         linux[Self::VEOL2] = ControlCharacter::from_apple(apple[libc::VEOL2]);
@@ -248,13 +363,14 @@ impl ControlCharacters {
         apple[libc::VERASE] = linux[Self::VERASE].to_apple();
         apple[libc::VKILL] = linux[Self::VKILL].to_apple();
         apple[libc::VEOF] = linux[Self::VEOF].to_apple();
-        apple[libc::VTIME] = linux[Self::VTIME].to_apple();
-        apple[libc::VMIN] = linux[Self::VMIN].to_apple();
+        apple[libc::VTIME] = linux[Self::VTIME].0;
+        apple[libc::VMIN] = linux[Self::VMIN].0;
         apple[libc::VSTART] = linux[Self::VSTART].to_apple();
         apple[libc::VSTOP] = linux[Self::VSTOP].to_apple();
         apple[libc::VSUSP] = linux[Self::VSUSP].to_apple();
         apple[libc::VEOL] = linux[Self::VEOL].to_apple();
         apple[libc::VREPRINT] = linux[Self::VREPRINT].to_apple();
+        apple[libc::VDISCARD] = linux[Self::VDISCARD].to_apple();
         apple[libc::VWERASE] = linux[Self::VWERASE].to_apple();
         apple[libc::VLNEXT] = linux[Self::VLNEXT].to_apple();
         apple[libc::VEOL2] = linux[Self::VEOL2].to_apple();
@@ -284,7 +400,7 @@ impl ControlCharacter {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[repr(C)]
 pub struct WinSize {
     pub ws_row: u16,
@@ -293,6 +409,26 @@ pub struct WinSize {
     pub ws_ypixel: u16,
 }
 impl WinSize {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LxError> {
+        if bytes.len() != size_of::<Self>() {
+            return Err(LxError::EINVAL);
+        }
+        let field = |n: usize| u16::from_ne_bytes([bytes[2 * n], bytes[2 * n + 1]]);
+        Ok(Self {
+            ws_row: field(0),
+            ws_col: field(1),
+            ws_xpixel: field(2),
+            ws_ypixel: field(3),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.ws_row, self.ws_col, self.ws_xpixel, self.ws_ypixel]
+            .iter()
+            .flat_map(|x| x.to_ne_bytes())
+            .collect()
+    }
+
     pub fn to_apple(self) -> libc::winsize {
         libc::winsize {
             ws_row: self.ws_row,
@@ -324,3 +460,132 @@ unixvariants! {
         fn to_apple(self) -> Result<libc::c_int, LxError>;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apple_termios(speed: libc::speed_t) -> libc::termios {
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        termios.c_ispeed = speed;
+        termios.c_ospeed = speed;
+        termios
+    }
+
+    #[test]
+    fn legacy_termios_keeps_table_speeds() {
+        let termios = Termios::from_apple(apple_termios(38400)).unwrap();
+        let apple = termios.to_apple().unwrap();
+        assert_eq!((apple.c_ispeed, apple.c_ospeed), (38400, 38400));
+    }
+
+    #[test]
+    fn legacy_termios_never_hangs_up_on_other_speeds() {
+        let termios = Termios::from_apple(apple_termios(12345)).unwrap();
+        assert!(matches!(termios.clone().to_apple(), Err(LxError::EINVAL)));
+
+        let apple = termios.with_speeds(12345, 12345).to_apple().unwrap();
+        assert_eq!((apple.c_ispeed, apple.c_ospeed), (12345, 12345));
+    }
+
+    #[cfg(target_os = "macos")]
+    fn open_pty() -> (std::os::fd::OwnedFd, std::os::fd::OwnedFd) {
+        use std::os::fd::FromRawFd;
+
+        let (mut master, mut slave) = (-1, -1);
+        unsafe {
+            let status = libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            assert_eq!(status, 0);
+            (
+                std::os::fd::OwnedFd::from_raw_fd(master),
+                std::os::fd::OwnedFd::from_raw_fd(slave),
+            )
+        }
+    }
+
+    /// Applies Linux terminal settings, changed by `f`, to the native terminal `fd`.
+    #[cfg(target_os = "macos")]
+    fn set_linux_termios(fd: libc::c_int, f: impl FnOnce(&mut Termios2)) {
+        unsafe {
+            let mut apple: libc::termios = std::mem::zeroed();
+            assert_eq!(libc::tcgetattr(fd, &mut apple), 0);
+            let mut linux = Termios2::from_apple(apple).unwrap();
+            f(&mut linux);
+            assert_eq!(
+                libc::tcsetattr(fd, libc::TCSANOW, &linux.to_apple().unwrap()),
+                0
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn canonical_reads_return_whole_lines_and_echo() {
+        use std::os::fd::AsRawFd;
+
+        let (master, slave) = open_pty();
+        set_linux_termios(slave.as_raw_fd(), |termios| {
+            termios.c_lflag = LocalFlags::ICANON | LocalFlags::ECHO;
+            termios.c_cc.0[ControlCharacters::VERASE] = ControlCharacter(0x7f);
+        });
+        unsafe {
+            let flags = libc::fcntl(slave.as_raw_fd(), libc::F_GETFL);
+            libc::fcntl(slave.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK);
+        }
+        let write =
+            |buf: &[u8]| unsafe { libc::write(master.as_raw_fd(), buf.as_ptr().cast(), buf.len()) };
+        let read = |fd: libc::c_int| {
+            let mut buf = [0; 64];
+            let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+            (n >= 0).then(|| buf[..n as usize].to_vec())
+        };
+
+        write(b"ab\x7fc");
+        assert_eq!(read(slave.as_raw_fd()), None);
+        write(b"\n");
+        assert_eq!(read(slave.as_raw_fd()).unwrap(), b"ac\n");
+        assert!(read(master.as_raw_fd()).unwrap().starts_with(b"ab"));
+    }
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn signal_characters_signal_foreground_group() {
+        use std::os::{
+            fd::AsRawFd,
+            unix::process::{CommandExt, ExitStatusExt},
+        };
+
+        for (index, ch, signal) in [
+            (ControlCharacters::VINTR, 0x3, libc::SIGINT),
+            (ControlCharacters::VQUIT, 0x1c, libc::SIGQUIT),
+        ] {
+            let (master, slave) = open_pty();
+            set_linux_termios(slave.as_raw_fd(), |termios| {
+                termios.c_lflag = LocalFlags::ISIG | LocalFlags::ICANON;
+                termios.c_cc.0[index] = ControlCharacter(ch);
+            });
+
+            // The child becomes the session leader with the terminal as its controlling one, so it is in the
+            // foreground process group of the terminal.
+            let tty = slave.as_raw_fd();
+            let mut command = std::process::Command::new("/bin/sleep");
+            command.arg("30");
+            unsafe {
+                command.pre_exec(move || {
+                    libc::setsid();
+                    libc::ioctl(tty, libc::TIOCSCTTY, 0);
+                    Ok(())
+                });
+            }
+            let mut child = command.spawn().unwrap();
+            unsafe { libc::write(master.as_raw_fd(), [ch].as_ptr().cast(), 1) };
+            assert_eq!(child.wait().unwrap().signal(), Some(signal));
+        }
+    }
+}
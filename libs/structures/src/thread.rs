@@ -1,3 +1,6 @@
+use crate::{error::LxError, unixvariants};
+use std::ffi::c_int;
+
 /// Minimal TID that indicates a non-main thread rather than a process (or, the "main thread").
 pub const TID_MIN: i32 = 0x10000000;
 
@@ -7,3 +10,42 @@ pub const TID_MAX: i32 = 0x3ffff000;
 pub fn is_tid(pid: i32) -> bool {
     (TID_MIN..=TID_MAX).contains(&pid)
 }
+
+unixvariants! {
+    /// A scheduling policy.
+    pub struct SchedPolicy: u32 {
+        const SCHED_OTHER = 0;
+        const SCHED_FIFO = 1;
+        const SCHED_RR = 2;
+        #[linux_only] const SCHED_BATCH = 3;
+        #[linux_only] const SCHED_IDLE = 5;
+        #[linux_only] const SCHED_DEADLINE = 6;
+        fn from_apple(apple: c_int) -> Result<Self, LxError>;
+        fn to_apple(self) -> Result<c_int, LxError>;
+    }
+}
+impl SchedPolicy {
+    /// Flag that may be ORed into a policy, making children of the thread use the default policy.
+    pub const SCHED_RESET_ON_FORK: u32 = 0x40000000;
+
+    /// Returns `true` if this is a real-time policy.
+    pub fn is_realtime(self) -> bool {
+        matches!(self, Self::SCHED_FIFO | Self::SCHED_RR)
+    }
+
+    /// Returns the minimum and maximum priorities of the policy.
+    pub fn priority_range(self) -> Result<(c_int, c_int), LxError> {
+        match self {
+            Self::SCHED_FIFO | Self::SCHED_RR => Ok((1, 99)),
+            Self::SCHED_OTHER | Self::SCHED_BATCH | Self::SCHED_IDLE => Ok((0, 0)),
+            _ => Err(LxError::EINVAL),
+        }
+    }
+}
+
+/// The `struct sched_param` structure.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct SchedParam {
+    pub sched_priority: c_int,
+}
@@ -4,6 +4,9 @@ use libc::c_int;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+/// Frequency of clock ticks reported to programs, like `times` and `AT_CLKTCK`.
+pub const USER_HZ: i64 = 100;
+
 unixvariants! {
     #[derive(Default)]
     pub struct ClockId: u32 {
@@ -45,6 +48,14 @@ impl Timespec {
     pub fn to_duration(self) -> Duration {
         Duration::new(self.tv_sec as _, self.tv_nsec as _)
     }
+
+    /// Converts the timespec to a [`Duration`], failing with `EINVAL` if it is negative or not normalized.
+    pub fn try_to_duration(self) -> Result<Duration, LxError> {
+        if self.tv_sec < 0 || !(0..1_000_000_000).contains(&self.tv_nsec) {
+            return Err(LxError::EINVAL);
+        }
+        Ok(self.to_duration())
+    }
 }
 impl FromApple for Timespec {
     type Apple = libc::timespec;
@@ -129,3 +140,53 @@ impl FromApple for Tms {
         })
     }
 }
+
+/// The `rtc_time` structure, used by `RTC_RD_TIME` and `RTC_SET_TIME` ioctls of RTC devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(C)]
+pub struct RtcTime {
+    pub tm_sec: c_int,
+    pub tm_min: c_int,
+    pub tm_hour: c_int,
+    pub tm_mday: c_int,
+    pub tm_mon: c_int,
+    pub tm_year: c_int,
+    pub tm_wday: c_int,
+    pub tm_yday: c_int,
+    pub tm_isdst: c_int,
+}
+impl RtcTime {
+    pub fn to_bytes(self) -> Vec<u8> {
+        [
+            self.tm_sec,
+            self.tm_min,
+            self.tm_hour,
+            self.tm_mday,
+            self.tm_mon,
+            self.tm_year,
+            self.tm_wday,
+            self.tm_yday,
+            self.tm_isdst,
+        ]
+        .iter()
+        .flat_map(|x| x.to_ne_bytes())
+        .collect()
+    }
+}
+impl FromApple for RtcTime {
+    type Apple = libc::tm;
+
+    fn from_apple(apple: libc::tm) -> Result<Self, LxError> {
+        Ok(Self {
+            tm_sec: apple.tm_sec,
+            tm_min: apple.tm_min,
+            tm_hour: apple.tm_hour,
+            tm_mday: apple.tm_mday,
+            tm_mon: apple.tm_mon,
+            tm_year: apple.tm_year,
+            tm_wday: apple.tm_wday,
+            tm_yday: apple.tm_yday,
+            tm_isdst: 0,
+        })
+    }
+}
@@ -4,30 +4,33 @@ use super::UcontextExt;
 use crate::util::with_openat;
 use libc::{c_char, c_int, c_uint};
 use macros::syscall;
-use rtenv::posix_num;
 use std::{ffi::CStr, num::NonZero, ptr::NonNull, time::Duration};
 use structures::{
     FromApple, ToApple,
     device::DeviceNumber,
     error::LxError,
     fs::{
-        AT_FDCWD, AccessFlags, AtFlags, FileMode, OpenFlags, Stat, StatFs, Statx, StatxMask,
-        UmountFlags,
+        AT_FDCWD, AccessFlags, AtFlags, Dirent64, FileMode, MS_MGC_MSK, MS_MGC_VAL, MountFlags,
+        OpenFlags, Stat, StatFs, Statx, StatxMask, UmountFlags,
     },
-    internal::mactux_ipc::NetworkNames,
     io::{
-        CloseRangeFlags, EventFdFlags, FcntlCmd, FdSet, FlockOp, IoctlCmd, PSelectSigMask, PollFd,
-        Whence,
+        CloseRangeFlags, EventFdFlags, Fadvice, FcntlCmd, FdSet, FlockOp, IoctlCmd, PSelectSigMask,
+        PollFd, RwfFlags, Whence,
     },
-    misc::{GrndFlags, SysInfo, SyslogAction, UtsName},
+    io_uring::{IoUringEnterFlags, IoUringParams, IoUringRegisterOp},
+    misc::{GrndFlags, HOST_NAME_MAX, SysInfo, SyslogAction, UtsName},
     mm::{Madvice, MmapFlags, MmapProt, MremapFlags, MsyncFlags},
     net::{
         Domain, MmsgHdr, MsgFlags, MsgHdr, Protocol, ShutdownHow, SockAddr, SockOptLevel,
         SocketFlags, SocketType,
     },
-    process::{PrctlOp, RLimit64, RLimitable, RUsage, RUsageWho, WaitOptions, WaitStatus},
+    process::{
+        CloneFlags, PidFdFlags, PrctlOp, RLimit, RLimit64, RLimitable, RUsage, RUsageWho,
+        WaitOptions, WaitStatus,
+    },
     signal::{KernelSigSet, MaskHowto, SigAction, SigAltStack, SigNum},
-    sync::{FutexCmd, FutexOp, RSeq},
+    sync::{FutexCmd, FutexOp, MembarrierCmd, RSeq},
+    thread::{SchedParam, SchedPolicy},
     time::{ClockId, TimerFlags, Timespec, Timeval, Timezone, Tms},
 };
 
@@ -252,13 +255,45 @@ pub unsafe fn sys_readlinkat(
 
 #[syscall]
 pub unsafe fn sys_getdents64(fd: c_int, dp: *mut u8, count: c_int) -> Result<usize, LxError> {
+    /// Maximum number of entries read in a single exchange with the server.
+    const MAX_BATCH: usize = 64;
+
+    if count < 0 {
+        return Err(LxError::EINVAL);
+    }
     let mut dp = dp;
     let mut count = count;
     let mut total_written = 0;
 
     loop {
-        let Some(dirent) = rtenv::fs::getdents64(fd)? else {
-            return Ok(total_written);
+        // Entries that surely fit in the remaining buffer are read in a single exchange with the server.
+        let batch = (count as usize / Dirent64::MAX_SIZE).min(MAX_BATCH);
+        if batch > 1 {
+            let dirents = match rtenv::fs::getdents64_batch(fd, batch) {
+                Ok(dirents) => dirents,
+                Err(_) if total_written > 0 => return Ok(total_written),
+                Err(err) => return Err(err),
+            };
+            let exhausted = dirents.len() < batch;
+            for dirent in dirents {
+                unsafe {
+                    dirent.write_to(dp);
+                    total_written += dirent.size();
+                    dp = dp.add(dirent.size());
+                    count -= dirent.size() as c_int;
+                }
+            }
+            if exhausted {
+                return Ok(total_written);
+            }
+            continue;
+        }
+
+        let dirent = match rtenv::fs::getdents64(fd) {
+            Ok(Some(dirent)) => dirent,
+            Ok(None) => return Ok(total_written),
+            Err(_) if total_written > 0 => return Ok(total_written),
+            Err(err) => return Err(err),
         };
         if dirent.size() > count as _ {
             break;
@@ -308,7 +343,7 @@ pub unsafe fn sys_fchdir(fd: c_int) -> Result<(), LxError> {
 
 #[syscall]
 pub unsafe fn sys_umask(mask: c_int) -> c_int {
-    unsafe { libc::umask(mask as _) as _ }
+    rtenv::fs::umask(mask as _) as _
 }
 
 #[syscall]
@@ -422,13 +457,12 @@ pub unsafe fn sys_rmdir(path: &CStr) -> Result<(), LxError> {
 #[syscall]
 pub unsafe fn sys_chown(path: &CStr, uid: u32, gid: u32) -> Result<(), LxError> {
     unsafe {
-        with_openat(
+        rtenv::fs::fchownat(
             AT_FDCWD,
             path.to_bytes().to_vec(),
-            OpenFlags::O_PATH,
+            uid,
+            gid,
             AtFlags::empty(),
-            0,
-            |fd| rtenv::fs::fchown(fd, uid, gid),
         )
     }
 }
@@ -436,17 +470,27 @@ pub unsafe fn sys_chown(path: &CStr, uid: u32, gid: u32) -> Result<(), LxError>
 #[syscall]
 pub unsafe fn sys_lchown(path: &CStr, uid: u32, gid: u32) -> Result<(), LxError> {
     unsafe {
-        with_openat(
+        rtenv::fs::fchownat(
             AT_FDCWD,
             path.to_bytes().to_vec(),
-            OpenFlags::O_PATH,
+            uid,
+            gid,
             AtFlags::AT_SYMLINK_NOFOLLOW,
-            0,
-            |fd| rtenv::fs::fchown(fd, uid, gid),
         )
     }
 }
 
+#[syscall]
+pub unsafe fn sys_fchownat(
+    dfd: c_int,
+    filename: &CStr,
+    uid: u32,
+    gid: u32,
+    flags: AtFlags,
+) -> Result<(), LxError> {
+    unsafe { rtenv::fs::fchownat(dfd, filename.to_bytes().to_vec(), uid, gid, flags) }
+}
+
 #[syscall]
 pub unsafe fn sys_fchown(fd: c_int, uid: u32, gid: u32) -> Result<(), LxError> {
     unsafe { rtenv::fs::fchown(fd, uid, gid) }
@@ -454,30 +498,22 @@ pub unsafe fn sys_fchown(fd: c_int, uid: u32, gid: u32) -> Result<(), LxError> {
 
 #[syscall]
 pub unsafe fn sys_chmod(path: &CStr, mode: u16) -> Result<(), LxError> {
-    unsafe {
-        with_openat(
-            AT_FDCWD,
-            path.to_bytes().to_vec(),
-            OpenFlags::O_PATH,
-            AtFlags::empty(),
-            0,
-            |fd| rtenv::fs::fchmod(fd, mode),
-        )
-    }
+    unsafe { rtenv::fs::fchmodat(AT_FDCWD, path.to_bytes().to_vec(), mode, AtFlags::empty()) }
 }
 
 #[syscall]
 pub unsafe fn sys_fchmodat(dfd: c_int, filename: &CStr, mode: u16) -> Result<(), LxError> {
-    unsafe {
-        with_openat(
-            dfd,
-            filename.to_bytes().to_vec(),
-            OpenFlags::O_PATH,
-            AtFlags::empty(),
-            0,
-            |fd| rtenv::fs::fchmod(fd, mode),
-        )
-    }
+    unsafe { rtenv::fs::fchmodat(dfd, filename.to_bytes().to_vec(), mode, AtFlags::empty()) }
+}
+
+#[syscall]
+pub unsafe fn sys_fchmodat2(
+    dfd: c_int,
+    filename: &CStr,
+    mode: u16,
+    flags: AtFlags,
+) -> Result<(), LxError> {
+    unsafe { rtenv::fs::fchmodat(dfd, filename.to_bytes().to_vec(), mode, flags) }
 }
 
 #[syscall]
@@ -549,6 +585,28 @@ pub unsafe fn sys_flistxattr(fd: c_int, list: *mut u8, size: usize) -> Result<us
     unsafe { crate::util::ret_buf(&rtenv::fs::flistxattr(fd)?, list, size) }
 }
 
+#[syscall]
+pub unsafe fn sys_mount(
+    source: Option<&CStr>,
+    target: &CStr,
+    fs: Option<&CStr>,
+    flags: MountFlags,
+    data: Option<&CStr>,
+) -> Result<(), LxError> {
+    let flags = match flags.bits() & MS_MGC_MSK {
+        MS_MGC_VAL => MountFlags::from_bits_retain(flags.bits() & !MS_MGC_MSK),
+        _ => flags,
+    };
+    rtenv::fs::mount(
+        source.map(|x| x.to_bytes().to_vec()).unwrap_or_default(),
+        target.to_bytes().to_vec(),
+        fs.map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        flags,
+        data.map(|x| x.to_bytes().to_vec()).unwrap_or_default(),
+    )
+}
+
 #[syscall]
 pub unsafe fn sys_umount2(path: &CStr, flags: UmountFlags) -> Result<(), LxError> {
     rtenv::fs::umount(path.to_bytes().to_vec(), flags)
@@ -600,6 +658,76 @@ pub unsafe fn sys_writev(
     unsafe { rtenv::io::writev(fd, std::slice::from_raw_parts(vec, vlen)) }
 }
 
+#[syscall]
+pub unsafe fn sys_preadv(
+    fd: c_int,
+    vec: *const libc::iovec,
+    vlen: usize,
+    pos: i64,
+) -> Result<usize, LxError> {
+    if pos < 0 {
+        return Err(LxError::EINVAL);
+    }
+    unsafe {
+        rtenv::io::preadv2(
+            fd,
+            crate::util::iovec_slice(vec, vlen)?,
+            pos,
+            RwfFlags::empty(),
+        )
+    }
+}
+
+#[syscall]
+pub unsafe fn sys_pwritev(
+    fd: c_int,
+    vec: *const libc::iovec,
+    vlen: usize,
+    pos: i64,
+) -> Result<usize, LxError> {
+    if pos < 0 {
+        return Err(LxError::EINVAL);
+    }
+    unsafe {
+        rtenv::io::pwritev2(
+            fd,
+            crate::util::iovec_slice(vec, vlen)?,
+            pos,
+            RwfFlags::empty(),
+        )
+    }
+}
+
+#[syscall]
+pub unsafe fn sys_preadv2(
+    fd: c_int,
+    vec: *const libc::iovec,
+    vlen: usize,
+    pos: i64,
+    _pos_h: usize,
+    flags: RwfFlags,
+) -> Result<usize, LxError> {
+    if pos < -1 {
+        return Err(LxError::EINVAL);
+    }
+    unsafe { rtenv::io::preadv2(fd, crate::util::iovec_slice(vec, vlen)?, pos, flags) }
+}
+
+#[syscall]
+pub unsafe fn sys_pwritev2(
+    fd: c_int,
+    vec: *const libc::iovec,
+    vlen: usize,
+    pos: i64,
+    _pos_h: usize,
+    flags: RwfFlags,
+) -> Result<usize, LxError> {
+    if pos < -1 {
+        return Err(LxError::EINVAL);
+    }
+    unsafe { rtenv::io::pwritev2(fd, crate::util::iovec_slice(vec, vlen)?, pos, flags) }
+}
+
 #[syscall]
 pub unsafe fn sys_lseek(fd: c_int, off: i64, whence: Whence) -> Result<i64, LxError> {
     rtenv::io::lseek(fd, off, whence)
@@ -722,12 +850,12 @@ pub unsafe fn sys_pipe2(fildes: *mut [c_int; 2], flags: OpenFlags) -> Result<(),
 
 #[syscall]
 pub unsafe fn sys_fadvise64(
-    _fd: c_int,
-    _off: i64,
-    _len: usize,
-    _advice: c_int,
+    fd: c_int,
+    off: i64,
+    len: usize,
+    advice: Fadvice,
 ) -> Result<(), LxError> {
-    Ok(())
+    rtenv::io::fadvise(fd, off, len, advice)
 }
 
 // -== Zero-copy IO Operations ==-
@@ -746,24 +874,15 @@ pub unsafe fn sys_copy_file_range(
         return Err(LxError::EINVAL);
     }
 
-    let mut buf = vec![0u8; len.min(4096)];
-    let bytes_read = match off_in {
-        Some(ptr) => unsafe {
-            let n = rtenv::io::pread64(fd_in, &mut buf, ptr.read())?;
-            ptr.write(ptr.read() + n as i64);
-            n
-        },
-        None => rtenv::io::read(fd_in, &mut buf)?,
-    };
-    let bytes_written = match off_out {
-        Some(ptr) => unsafe {
-            let n = rtenv::io::pwrite64(fd_in, &buf[..bytes_read], ptr.read())?;
-            ptr.write(ptr.read() + n as i64);
-            n
-        },
-        None => rtenv::io::write(fd_out, &buf[..bytes_read])?,
-    };
-    Ok(bytes_written)
+    unsafe {
+        rtenv::io::copy_file_range(
+            fd_in,
+            off_in.map(|mut x| x.as_mut()),
+            fd_out,
+            off_out.map(|mut x| x.as_mut()),
+            len,
+        )
+    }
 }
 
 #[syscall]
@@ -773,17 +892,7 @@ pub unsafe fn sys_sendfile(
     off_in: Option<NonNull<i64>>,
     count: usize,
 ) -> Result<usize, LxError> {
-    let mut buf = vec![0u8; count.min(4096)];
-    let bytes_read = match off_in {
-        Some(ptr) => unsafe {
-            let n = rtenv::io::pread64(in_fd, &mut buf, ptr.read())?;
-            ptr.write(ptr.read() + n as i64);
-            n
-        },
-        None => rtenv::io::read(in_fd, &mut buf)?,
-    };
-    let bytes_written = rtenv::io::write(out_fd, &buf[..bytes_read])?;
-    Ok(bytes_written)
+    unsafe { rtenv::io::sendfile(out_fd, in_fd, off_in.map(|mut x| x.as_mut()), count) }
 }
 
 // -== POSIX Traditional IO Multiplexers ==-
@@ -811,12 +920,12 @@ pub unsafe fn sys_ppoll(
         }
 
         let sigmask = sigset.map(|x| x.read());
-        let timeout = timeout.map(|x| x.read().to_duration());
-
-        let orig_mask = rtenv::signal::mask(MaskHowto::SIG_SETMASK, sigmask)?;
-        let result = rtenv::io::poll(std::slice::from_raw_parts_mut(fds, nfds as _), timeout);
-        rtenv::signal::mask(MaskHowto::SIG_SETMASK, Some(orig_mask))?;
-        result
+        let timeout = match timeout {
+            Some(x) => Some(x.read().try_to_duration()?),
+            None => None,
+        };
+        let fds = std::slice::from_raw_parts_mut(fds, nfds as _);
+        rtenv::signal::with_mask(sigmask, || rtenv::io::poll(fds, timeout))?
     }
 }
 
@@ -833,7 +942,7 @@ pub unsafe fn sys_select(
         let write_fds = write_fds.map(|x| FdSet::new(x, nfds));
         let expect_fds = expect_fds.map(|x| FdSet::new(x, nfds));
         let timeout = timeout.map(|x| x.read().to_timespec().to_duration());
-        rtenv::io::select(read_fds, write_fds, expect_fds, timeout)
+        rtenv::io::select(nfds as _, read_fds, write_fds, expect_fds, timeout)
     }
 }
 
@@ -851,16 +960,17 @@ pub unsafe fn sys_pselect6(
             Some(x) => Some(x.read().into_sigset()?),
             None => None,
         };
-        let timeout = timeout.map(|x| x.read().to_duration());
+        let timeout = match timeout {
+            Some(x) => Some(x.read().try_to_duration()?),
+            None => None,
+        };
         let read_fds = read_fds.map(|x| FdSet::new(x, nfds));
         let write_fds = write_fds.map(|x| FdSet::new(x, nfds));
         let expect_fds = expect_fds.map(|x| FdSet::new(x, nfds));
 
-        let orig_mask = rtenv::signal::mask(MaskHowto::SIG_SETMASK, sigmask)?;
-        let result = rtenv::io::select(read_fds, write_fds, expect_fds, timeout);
-        rtenv::signal::mask(MaskHowto::SIG_SETMASK, Some(orig_mask))?;
-
-        result
+        rtenv::signal::with_mask(sigmask, || {
+            rtenv::io::select(nfds as _, read_fds, write_fds, expect_fds, timeout)
+        })?
     }
 }
 
@@ -876,6 +986,41 @@ pub unsafe fn sys_eventfd2(initval: u64, flags: EventFdFlags) -> Result<c_int, L
     rtenv::io::eventfd(initval, flags)
 }
 
+#[syscall]
+pub unsafe fn sys_io_uring_setup(
+    entries: u32,
+    params: *mut IoUringParams,
+) -> Result<c_int, LxError> {
+    unsafe {
+        let mut buf = params.read();
+        let fd = rtenv::io_uring::setup(entries, &mut buf)?;
+        params.write(buf);
+        Ok(fd)
+    }
+}
+
+#[syscall]
+pub unsafe fn sys_io_uring_enter(
+    fd: c_int,
+    to_submit: u32,
+    min_complete: u32,
+    flags: IoUringEnterFlags,
+    _sig: *const u8,
+    _sigsz: usize,
+) -> Result<u32, LxError> {
+    rtenv::io_uring::enter(fd, to_submit, min_complete, flags)
+}
+
+#[syscall]
+pub unsafe fn sys_io_uring_register(
+    fd: c_int,
+    opcode: IoUringRegisterOp,
+    arg: *const u8,
+    nr_args: u32,
+) -> Result<(), LxError> {
+    unsafe { rtenv::io_uring::register(fd, opcode, arg, nr_args) }
+}
+
 // -== System Information Functions ==-
 
 #[syscall]
@@ -911,57 +1056,26 @@ pub unsafe fn sys_syslog(
 
 #[syscall]
 pub unsafe fn sys_sethostname(name: *const c_char, len: usize) -> Result<(), LxError> {
-    let domainname = rtenv::misc::get_network_names()?.domainname;
-    unsafe {
-        let nodename = std::slice::from_raw_parts(name.cast::<u8>(), len).to_vec();
-        rtenv::misc::set_network_names(NetworkNames {
-            nodename,
-            domainname,
-        })?;
-        Ok(())
+    // Like Linux, oversized and negative lengths are rejected before the name is read.
+    if len > HOST_NAME_MAX {
+        return Err(LxError::EINVAL);
     }
+    unsafe { rtenv::misc::sethostname(std::slice::from_raw_parts(name.cast(), len)) }
 }
 
 #[syscall]
 pub unsafe fn sys_setdomainname(name: *const c_char, len: usize) -> Result<(), LxError> {
-    let nodename = rtenv::misc::get_network_names()?.nodename;
-    unsafe {
-        let domainname = std::slice::from_raw_parts(name.cast::<u8>(), len).to_vec();
-        rtenv::misc::set_network_names(NetworkNames {
-            nodename,
-            domainname,
-        })?;
-        Ok(())
+    if len > HOST_NAME_MAX {
+        return Err(LxError::EINVAL);
     }
+    unsafe { rtenv::misc::setdomainname(std::slice::from_raw_parts(name.cast(), len)) }
 }
 
 // -== Cryptography Functions ==-
 
 #[syscall]
 pub unsafe fn sys_getrandom(buf: *mut u8, len: usize, flags: GrndFlags) -> Result<usize, LxError> {
-    if flags.contains(GrndFlags::GRND_NONBLOCK) {
-        // TODO
-    }
-
-    if flags.contains(GrndFlags::GRND_RANDOM) {
-        unsafe {
-            let fd = libc::open(
-                (&raw const *b"/dev/random\0").cast(),
-                libc::O_RDONLY | libc::O_CLOEXEC,
-            );
-            if fd == -1 {
-                return Err(LxError::last_apple_error());
-            }
-            let read_result = libc::read(fd, buf.cast(), len);
-            libc::close(fd);
-            posix_num!(read_result)
-        }
-    } else {
-        unsafe {
-            libc::arc4random_buf(buf.cast(), len);
-        }
-        Ok(len)
-    }
+    unsafe { rtenv::random::getrandom(std::slice::from_raw_parts_mut(buf, len), flags) }
 }
 
 // -== Network Communication ==-
@@ -1327,16 +1441,11 @@ pub unsafe fn sys_alarm(secs: c_uint) -> c_uint {
 
 #[syscall]
 pub unsafe fn sys_times(tms: *mut Tms) -> Result<i64, LxError> {
-    unsafe {
-        let mut apple = std::mem::zeroed();
-        match libc::times(&mut apple) as i64 {
-            -1 => Err(LxError::last_apple_error()),
-            other => {
-                tms.write(Tms::from_apple(apple)?);
-                Ok(other)
-            }
-        }
+    let (times, elapsed) = rtenv::time::times()?;
+    if !tms.is_null() {
+        unsafe { tms.write(times) };
     }
+    Ok(elapsed)
 }
 
 #[syscall]
@@ -1364,10 +1473,16 @@ pub unsafe fn sys_clock_nanosleep(
     unsafe {
         let mut rqtp = rqtp.read().to_apple()?;
         if flags.contains(TimerFlags::TIMER_ABSTIME) {
-            let mut now = std::mem::zeroed();
-            if libc::clock_gettime(clock.to_apple()?, &mut now) == -1 {
-                return Err(LxError::last_apple_error());
-            }
+            let now = match clock {
+                ClockId::CLOCK_REALTIME => rtenv::time::realtime()?,
+                _ => {
+                    let mut now = std::mem::zeroed();
+                    if libc::clock_gettime(clock.to_apple()?, &mut now) == -1 {
+                        return Err(LxError::last_apple_error());
+                    }
+                    now
+                }
+            };
             if rqtp.tv_sec < now.tv_sec
                 || (rqtp.tv_sec == now.tv_sec && rqtp.tv_nsec <= now.tv_nsec)
             {
@@ -1398,13 +1513,17 @@ pub unsafe fn sys_clock_nanosleep(
 #[syscall]
 pub unsafe fn sys_clock_gettime(clk_id: ClockId, tp: *mut Timespec) -> Result<(), LxError> {
     unsafe {
-        let mut apple_tp = tp.read().to_apple()?;
-        match libc::clock_gettime(clk_id.to_apple()?, &mut apple_tp) {
-            -1 => Err(LxError::last_apple_error()),
-            _ => {
-                tp.write(Timespec::from_apple(apple_tp)?);
-                Ok(())
-            }
+        tp.write(Timespec::from_apple(rtenv::time::clock_gettime(clk_id)?)?);
+        Ok(())
+    }
+}
+
+#[syscall]
+pub unsafe fn sys_clock_settime(clk_id: ClockId, tp: *const Timespec) -> Result<(), LxError> {
+    unsafe {
+        match clk_id {
+            ClockId::CLOCK_REALTIME => rtenv::time::set_realtime(&tp.read().to_apple()?),
+            _ => Err(LxError::EINVAL),
         }
     }
 }
@@ -1415,30 +1534,24 @@ pub unsafe fn sys_gettimeofday(
     tz: Option<NonNull<Timezone>>,
 ) -> Result<(), LxError> {
     unsafe {
-        let mut tvbuf = std::mem::zeroed();
-        let mut tzbuf: Timezone = std::mem::zeroed();
-        match libc::gettimeofday(&mut tvbuf, (&raw mut tzbuf).cast()) {
-            -1 => Err(LxError::last_apple_error()),
-            _ => {
-                if let Some(tv) = tv {
-                    tv.write(Timeval::from_apple(tvbuf)?);
-                }
-                if let Some(tz) = tz {
-                    tz.write(tzbuf);
-                }
-                Ok(())
-            }
+        if let Some(tv) = tv {
+            tv.write(rtenv::time::timeofday()?);
+        }
+        if let Some(tz) = tz {
+            tz.write(rtenv::time::timezone()?);
         }
+        Ok(())
     }
 }
 
 #[syscall]
-pub unsafe fn sys_time(time: *mut i64) -> Result<(), LxError> {
+pub unsafe fn sys_time(time: *mut i64) -> Result<i64, LxError> {
     unsafe {
-        match libc::time(time) {
-            -1 => Err(LxError::last_apple_error()),
-            _ => Ok(()),
+        let now = rtenv::time::realtime()?;
+        if !time.is_null() {
+            time.write(now.tv_sec);
         }
+        Ok(now.tv_sec)
     }
 }
 
@@ -1453,6 +1566,7 @@ pub unsafe fn sys_futex(
     uaddr2: *mut u32,
     val3: u32,
 ) -> Result<usize, LxError> {
+    rtenv::sync::futex::check_clock(op)?;
     match op.cmd() {
         FutexCmd::FUTEX_WAIT => unsafe {
             rtenv::sync::futex::wait(uaddr, val, utime, op.opts()).map(|()| 0)
@@ -1462,16 +1576,26 @@ pub unsafe fn sys_futex(
             rtenv::sync::futex::wake_op(uaddr, val, utime as usize as u32, uaddr2, val3, op.opts())
         },
         FutexCmd::FUTEX_LOCK_PI => unsafe {
-            rtenv::sync::pi_futex::lock(&mut *uaddr.cast());
-            Ok(0)
+            let deadline = match utime.is_null() {
+                true => None,
+                false => Some(rtenv::sync::pi_futex::deadline(utime.read())?),
+            };
+            rtenv::sync::pi_futex::lock(uaddr, deadline).map(|()| 0)
         },
-        FutexCmd::FUTEX_UNLOCK_PI => unsafe {
-            rtenv::sync::pi_futex::unlock(&mut *uaddr.cast())?;
-            Ok(0)
+        FutexCmd::FUTEX_UNLOCK_PI => unsafe { rtenv::sync::pi_futex::unlock(uaddr).map(|()| 0) },
+        FutexCmd::FUTEX_TRYLOCK_PI => unsafe { rtenv::sync::pi_futex::try_lock(uaddr).map(|()| 0) },
+        FutexCmd::FUTEX_REQUEUE => unsafe {
+            rtenv::sync::futex::requeue(uaddr, val, utime as usize as u32, uaddr2, None, op.opts())
         },
-        FutexCmd::FUTEX_TRYLOCK_PI => unsafe {
-            rtenv::sync::pi_futex::try_lock(&mut *uaddr.cast())?;
-            Ok(0)
+        FutexCmd::FUTEX_CMP_REQUEUE => unsafe {
+            rtenv::sync::futex::requeue(
+                uaddr,
+                val,
+                utime as usize as u32,
+                uaddr2,
+                Some(val3),
+                op.opts(),
+            )
         },
         FutexCmd::FUTEX_WAIT_BITSET => unsafe {
             rtenv::sync::futex::wait_bitset(uaddr, val, utime, op.opts(), val3).map(|()| 0)
@@ -1488,6 +1612,25 @@ pub unsafe fn sys_set_robust_list(head: *mut u8, size: usize) -> Result<(), LxEr
     rtenv::thread::set_robust_list(head, size)
 }
 
+#[syscall]
+pub unsafe fn sys_unshare(flags: CloneFlags) -> Result<(), LxError> {
+    rtenv::process::unshare(flags)
+}
+
+#[syscall]
+pub unsafe fn sys_setns(fd: c_int, nstype: CloneFlags) -> Result<(), LxError> {
+    rtenv::process::setns(fd, nstype)
+}
+
+#[syscall]
+pub unsafe fn sys_membarrier(
+    cmd: MembarrierCmd,
+    flags: u32,
+    _cpu_id: c_int,
+) -> Result<c_int, LxError> {
+    rtenv::sync::membarrier::membarrier(cmd, flags)
+}
+
 #[syscall]
 pub unsafe fn sys_rseq(
     _rseq: *mut RSeq,
@@ -1517,8 +1660,8 @@ pub unsafe fn sys_tkill(tid: i32, signum: SigNum) -> Result<(), LxError> {
 }
 
 #[syscall]
-pub unsafe fn sys_tgkill(_tgid: i32, tid: i32, signum: SigNum) -> Result<(), LxError> {
-    rtenv::thread::kill(tid, signum)
+pub unsafe fn sys_tgkill(tgid: i32, tid: i32, signum: SigNum) -> Result<(), LxError> {
+    rtenv::thread::tgkill(tgid, tid, signum)
 }
 
 #[syscall]
@@ -1552,13 +1695,12 @@ pub unsafe fn sys_setpgid(pid: i32, pgid: i32) -> Result<(), LxError> {
 
 #[syscall]
 pub unsafe fn sys_setsid() -> Result<i32, LxError> {
-    // FIXME: This is not converting the native/linux PID.
-    unsafe {
-        match libc::setsid() {
-            -1 => Err(LxError::last_apple_error()),
-            n => Ok(n),
-        }
-    }
+    rtenv::process::setsid()
+}
+
+#[syscall]
+pub unsafe fn sys_getsid(pid: i32) -> Result<i32, LxError> {
+    rtenv::process::sid(pid)
 }
 
 #[syscall]
@@ -1566,6 +1708,16 @@ pub unsafe fn sys_getppid() -> c_int {
     rtenv::process::ppid()
 }
 
+#[syscall]
+pub unsafe fn sys_pidfd_open(pid: i32, flags: PidFdFlags) -> Result<c_int, LxError> {
+    rtenv::process::pidfd_open(pid, flags)
+}
+
+#[syscall]
+pub unsafe fn sys_pidfd_getfd(pidfd: c_int, targetfd: c_int, flags: u32) -> Result<c_int, LxError> {
+    rtenv::process::pidfd_getfd(pidfd, targetfd, flags)
+}
+
 #[syscall]
 pub unsafe fn sys_kill(pid: i32, signum: SigNum) -> Result<(), LxError> {
     rtenv::process::kill(pid, signum)
@@ -1605,30 +1757,34 @@ pub unsafe fn sys_prlimit64(
     new: Option<NonNull<RLimit64>>,
     old: Option<NonNull<RLimit64>>,
 ) -> Result<(), LxError> {
-    if ![0, -1, rtenv::process::pid()].contains(&pid) {
-        return Err(LxError::EPERM);
+    if pid < 0 {
+        return Err(LxError::EINVAL);
     }
-    let Ok(res) = res.to_apple() else {
-        return Ok(());
-    };
-    unsafe {
-        if let Some(old) = old {
-            let mut buf = std::mem::zeroed();
-            if libc::getrlimit(res, &mut buf) == -1 {
-                return Err(LxError::last_apple_error());
-            }
-            old.write(RLimit64::from_apple(buf));
-        }
-        if let Some(new) = new {
-            let buf = new.read().to_apple();
-            if libc::setrlimit(res, &buf) == -1 {
-                return Err(LxError::last_apple_error());
-            }
+    let prev = rtenv::process::prlimit(pid, res, new.map(|x| unsafe { x.read() }))?;
+    if let Some(old) = old {
+        unsafe {
+            old.write(prev);
         }
     }
     Ok(())
 }
 
+#[syscall]
+pub unsafe fn sys_getrlimit(res: RLimitable, old: *mut RLimit) -> Result<(), LxError> {
+    let prev = rtenv::process::prlimit(0, res, None)?;
+    unsafe {
+        old.write(prev.into());
+        Ok(())
+    }
+}
+
+#[syscall]
+pub unsafe fn sys_setrlimit(res: RLimitable, new: *mut RLimit) -> Result<(), LxError> {
+    let new = unsafe { new.read() };
+    rtenv::process::prlimit(0, res, Some(new.into()))?;
+    Ok(())
+}
+
 #[syscall]
 pub unsafe fn sys_wait4(
     pid: i32,
@@ -1656,11 +1812,7 @@ pub unsafe fn sys_wait4(
 #[syscall]
 pub unsafe fn sys_getrusage(who: RUsageWho, rusage: *mut RUsage) -> Result<(), LxError> {
     unsafe {
-        let mut buf = std::mem::zeroed();
-        if libc::getrusage(who.to_apple()?, &mut buf) == -1 {
-            return Err(LxError::last_apple_error());
-        }
-        rusage.write(RUsage::from_apple(buf)?);
+        rusage.write(rtenv::process::rusage(who)?);
         Ok(())
     }
 }
@@ -1673,20 +1825,25 @@ pub unsafe fn sys_prctl(
     _arg2: usize,
     _arg3: usize,
     _arg4: usize,
-) -> Result<(), LxError> {
+) -> Result<c_int, LxError> {
     match op {
-        PrctlOp::PR_SET_KEEPCAPS => Ok(()),
+        PrctlOp::PR_GET_DUMPABLE => rtenv::process::dumpable(0).map(|x| x as _),
+        PrctlOp::PR_SET_DUMPABLE => match arg0 {
+            0 | 1 => rtenv::process::set_dumpable(arg0 == 1).map(|_| 0),
+            _ => Err(LxError::EINVAL),
+        },
+        PrctlOp::PR_SET_KEEPCAPS => Ok(0),
         PrctlOp::PR_SET_NAME => unsafe {
             rtenv::thread::set_name((arg0 as *const [u8; 16]).read());
-            Ok(())
+            Ok(0)
         },
         PrctlOp::PR_GET_NAME => unsafe {
             (arg0 as *mut [u8; 16]).write(rtenv::thread::get_name());
-            Ok(())
+            Ok(0)
         },
         PrctlOp::PR_GET_TID_ADDRESS => unsafe {
             (arg0 as *mut Option<NonNull<u32>>).write(rtenv::thread::get_clear_tid());
-            Ok(())
+            Ok(0)
         },
         _ => Err(LxError::EINVAL),
     }
@@ -1694,6 +1851,9 @@ pub unsafe fn sys_prctl(
 
 #[syscall]
 pub unsafe fn sys_exit_group(code: c_int) {
+    if rtenv::switches::telemetry() {
+        rtenv::telemetry::dump();
+    }
     std::process::exit(code);
 }
 
@@ -1704,26 +1864,66 @@ pub unsafe fn sys_sched_yield() {
     std::thread::yield_now();
 }
 
+#[syscall]
+pub unsafe fn sys_sched_setparam(pid: i32, param: *const SchedParam) -> Result<(), LxError> {
+    if param.is_null() {
+        return Err(LxError::EINVAL);
+    }
+    let (policy, _) = rtenv::thread::scheduler(pid)?;
+    unsafe { rtenv::thread::set_scheduler(pid, policy, param.read().sched_priority) }
+}
+
+#[syscall]
+pub unsafe fn sys_sched_getparam(pid: i32, param: *mut SchedParam) -> Result<(), LxError> {
+    if param.is_null() {
+        return Err(LxError::EINVAL);
+    }
+    let (_, sched_priority) = rtenv::thread::scheduler(pid)?;
+    unsafe {
+        param.write(SchedParam { sched_priority });
+        Ok(())
+    }
+}
+
+#[syscall]
+pub unsafe fn sys_sched_setscheduler(
+    pid: i32,
+    policy: u32,
+    param: *const SchedParam,
+) -> Result<(), LxError> {
+    if param.is_null() {
+        return Err(LxError::EINVAL);
+    }
+    let policy = SchedPolicy(policy & !SchedPolicy::SCHED_RESET_ON_FORK);
+    unsafe { rtenv::thread::set_scheduler(pid, policy, param.read().sched_priority) }
+}
+
+#[syscall]
+pub unsafe fn sys_sched_getscheduler(pid: i32) -> Result<u32, LxError> {
+    rtenv::thread::scheduler(pid).map(|(policy, _)| policy.0)
+}
+
+#[syscall]
+pub unsafe fn sys_sched_get_priority_max(policy: u32) -> Result<c_int, LxError> {
+    SchedPolicy(policy).priority_range().map(|(_, max)| max)
+}
+
+#[syscall]
+pub unsafe fn sys_sched_get_priority_min(policy: u32) -> Result<c_int, LxError> {
+    SchedPolicy(policy).priority_range().map(|(min, _)| min)
+}
+
 #[syscall]
 pub unsafe fn sys_sched_getaffinity(
     _pid: i32,
     cpusetsize: usize,
     cpuset: *mut u8,
-) -> Result<(), LxError> {
+) -> Result<usize, LxError> {
     let cpus = std::thread::available_parallelism()
         .map(NonZero::get)
         .unwrap_or(8);
-    let min_size = cpus.div_ceil(size_of::<u8>());
-    let last = 0xff << (cpus % size_of::<u8>());
-    if cpusetsize < min_size {
-        return Err(LxError::EINVAL);
-    }
-    unsafe {
-        cpuset.write_bytes(0, cpusetsize);
-        cpuset.write_bytes(0xff, min_size - 1);
-        cpuset.add(min_size - 1).write(last);
-    }
-    Ok(())
+    let mask = crate::util::cpu_mask(cpus, cpusetsize)?;
+    unsafe { crate::util::ret_buf(&mask, cpuset, cpusetsize) }
 }
 
 #[syscall]
@@ -1733,7 +1933,10 @@ pub unsafe fn sys_sched_setaffinity(
     _cpuset: *const u8,
 ) -> Result<(), LxError> {
     // TODO: We cannot really implement it on macOS.
-    Err(LxError::EPERM)
+    Err(rtenv::error_report::explain(
+        LxError::EPERM,
+        "CPU affinity cannot be set on macOS",
+    ))
 }
 
 // -== Multi-user Support ==-
@@ -1818,6 +2021,9 @@ pub unsafe fn sys_setfsgid(gid: u32) -> Result<(), LxError> {
 
 #[syscall]
 pub unsafe fn sys_getgroups(len: c_int, list: *mut u32) -> Result<u32, LxError> {
+    if len < 0 {
+        return Err(LxError::EINVAL);
+    }
     let groups = rtenv::security::groups();
     if len == 0 {
         return Ok(groups.len() as _);
@@ -1831,6 +2037,18 @@ pub unsafe fn sys_getgroups(len: c_int, list: *mut u32) -> Result<u32, LxError>
     Ok(groups.len() as _)
 }
 
+#[syscall]
+pub unsafe fn sys_setgroups(len: c_int, list: *mut u32) -> Result<(), LxError> {
+    if len < 0 {
+        return Err(LxError::EINVAL);
+    }
+    let groups = match len {
+        0 => Vec::new(),
+        len => unsafe { std::slice::from_raw_parts(list, len as usize).to_vec() },
+    };
+    rtenv::security::setgroups(groups)
+}
+
 #[syscall]
 pub unsafe fn sys_capget() -> Result<(), LxError> {
     Ok(())
@@ -1876,7 +2094,13 @@ pub unsafe fn sys_invalid(uctx: &mut libc::ucontext_t) {
         rtenv::emuctx::leave_emulated();
         if rtenv::switches::ignore_unsupported_syscalls() {
             log::warn!("ignored unsupported syscall {}", uctx.sysno());
-            uctx.ret(-(LxError::ENOSYS.0 as isize) as usize);
+            let sysret = -(rtenv::error_report::explain(
+                LxError::ENOSYS,
+                "the system call is not supported by MacTux",
+            )
+            .0 as isize) as usize;
+            rtenv::error_report::finish("sys_invalid", sysret);
+            uctx.ret(sysret);
             rtenv::emuctx::enter_emulated();
             return;
         }
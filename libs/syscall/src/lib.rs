@@ -7,17 +7,17 @@ mod util;
 
 use std::{ffi::CStr, ptr::NonNull};
 use structures::{
-    FromApple,
     device::DeviceNumber,
     error::LxError,
-    fs::{AccessFlags, AtFlags, OpenFlags, UmountFlags},
-    io::{CloseRangeFlags, EventFdFlags, FcntlCmd, FlockOp, IoctlCmd, Whence},
+    fs::{AccessFlags, AtFlags, MountFlags, OpenFlags, UmountFlags},
+    io::{CloseRangeFlags, EventFdFlags, Fadvice, FcntlCmd, FlockOp, IoctlCmd, RwfFlags, Whence},
+    io_uring::{IoUringEnterFlags, IoUringRegisterOp},
     misc::{GrndFlags, SyslogAction},
     mm::{Madvice, MmapFlags, MmapProt, MremapFlags, MsyncFlags},
     net::{Domain, MsgFlags, Protocol, ShutdownHow, SockOptLevel, SocketFlags, SocketType},
-    process::{PrctlOp, RLimitable, RUsageWho, WaitOptions},
+    process::{CloneFlags, PidFdFlags, PrctlOp, RLimitable, RUsageWho, WaitOptions},
     signal::{MaskHowto, SigNum},
-    sync::FutexOp,
+    sync::{FutexOp, MembarrierCmd},
     time::{ClockId, TimerFlags},
 };
 
@@ -124,12 +124,12 @@ impl_from_to_sys_plain!(i8; u8; i16; u16; i32; u32; i64; u64; isize; usize);
 impl_from_to_sys_bitflags!(
     MmapFlags; OpenFlags; AtFlags; MmapProt; GrndFlags; AccessFlags; WaitOptions; MsyncFlags;
     MremapFlags; SocketFlags; EventFdFlags; TimerFlags; UmountFlags; CloseRangeFlags; FlockOp;
-    MsgFlags
+    MsgFlags; PidFdFlags; MountFlags; CloneFlags; RwfFlags; IoUringEnterFlags
 );
 impl_from_to_sys_newtype!(
     Whence; FcntlCmd; IoctlCmd; FutexOp; ClockId; MaskHowto; SigNum; Domain; SocketType; Protocol;
     ShutdownHow; Madvice; RLimitable; RUsageWho; PrctlOp; SockOptLevel; DeviceNumber;
-    SyslogAction
+    SyslogAction; MembarrierCmd; Fadvice; IoUringRegisterOp
 );
 impl<T> FromSyscall for *const T {
     fn from_syscall(value: usize) -> Self {
@@ -188,7 +188,7 @@ impl<T: ToSysret> ToSysret for std::io::Result<T> {
             Err(err) => {
                 (-(err
                     .raw_os_error()
-                    .map(|x| LxError::from_apple(x).unwrap_or(LxError::EIO))
+                    .map(LxError::from_apple_errno)
                     .unwrap_or(LxError::EIO)
                     .0 as i32)) as usize
             }
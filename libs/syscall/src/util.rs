@@ -42,3 +42,60 @@ pub unsafe fn ret_buf(buf: &[u8], ptr: *mut u8, len: usize) -> Result<usize, LxE
         Ok(buf.len())
     }
 }
+
+/// Returns the CPU mask of `sched_getaffinity` with the first `cpus` CPUs set, for a user buffer of `cpusetsize`
+/// bytes.
+///
+/// Like Linux, the mask is made of `unsigned long`s, so the buffer must be a multiple of their size and large enough
+/// for all CPUs, and only as many of them as needed are returned.
+pub fn cpu_mask(cpus: usize, cpusetsize: usize) -> Result<Vec<u8>, LxError> {
+    if cpusetsize.saturating_mul(8) < cpus || cpusetsize % size_of::<u64>() != 0 {
+        return Err(LxError::EINVAL);
+    }
+    let mut mask = vec![0; cpus.div_ceil(u64::BITS as usize) * size_of::<u64>()];
+    mask[..cpus / 8].fill(0xff);
+    if cpus % 8 != 0 {
+        mask[cpus / 8] = (1 << (cpus % 8)) - 1;
+    }
+    Ok(mask)
+}
+
+/// Builds a slice of I/O vectors from a user-provided array, checking it against `UIO_MAXIOV`.
+pub unsafe fn iovec_slice<'a>(
+    vec: *const libc::iovec,
+    vlen: usize,
+) -> Result<&'a [libc::iovec], LxError> {
+    const UIO_MAXIOV: usize = 1024;
+
+    if vlen > UIO_MAXIOV {
+        return Err(LxError::EINVAL);
+    }
+    if vlen == 0 {
+        return Ok(&[]);
+    }
+    unsafe { Ok(std::slice::from_raw_parts(vec, vlen)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_mask_sets_one_bit_per_cpu() {
+        assert_eq!(cpu_mask(8, 8), Ok(vec![0xff, 0, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(cpu_mask(10, 128), Ok(vec![0xff, 0x03, 0, 0, 0, 0, 0, 0]));
+
+        let mask = cpu_mask(65, 16).unwrap();
+        assert_eq!(mask.len(), 16);
+        assert!(mask[..8].iter().all(|x| *x == 0xff));
+        assert_eq!(mask[8..], [0x01, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn cpu_mask_needs_whole_longs_for_all_cpus() {
+        assert_eq!(cpu_mask(8, 4), Err(LxError::EINVAL));
+        assert_eq!(cpu_mask(8, 12), Err(LxError::EINVAL));
+        assert_eq!(cpu_mask(65, 8), Err(LxError::EINVAL));
+        assert_eq!(cpu_mask(8, 0), Err(LxError::EINVAL));
+    }
+}
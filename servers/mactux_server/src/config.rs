@@ -39,6 +39,11 @@ impl WorkDir {
     pub fn net(&self) -> PathBuf {
         self.0.join("net")
     }
+
+    /// Copy of the host's timezone data last written to `/etc/localtime` of the root filesystem.
+    pub fn bridged_localtime(&self) -> PathBuf {
+        self.0.join("bridged_localtime")
+    }
 }
 
 fn init_work_dir(dir: &WorkDir) -> anyhow::Result<()> {
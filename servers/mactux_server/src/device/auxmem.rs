@@ -5,7 +5,7 @@ use crate::{
     vfd::Stream,
 };
 use std::{path::PathBuf, sync::Arc};
-use structures::error::LxError;
+use structures::{error::LxError, io::Whence};
 
 struct Zero;
 impl Stream for Zero {
@@ -46,6 +46,10 @@ impl Stream for Full {
     fn write(&self, _buf: &[u8], _off: &mut i64) -> Result<usize, LxError> {
         Err(LxError::ENOSPC)
     }
+
+    fn seek(&self, _orig_off: i64, _whence: Whence, _off: i64) -> Result<i64, LxError> {
+        Ok(0)
+    }
 }
 impl Device for Full {}
 
@@ -2,6 +2,7 @@
 
 mod auxmem;
 mod loopdev;
+mod rtc;
 mod term;
 
 #[cfg(feature = "audio")]
@@ -47,6 +48,7 @@ impl DeviceTable {
 
     pub fn discover(&self) {
         auxmem::discover(self);
+        rtc::discover(self);
         term::discover(self);
 
         #[cfg(feature = "audio")]
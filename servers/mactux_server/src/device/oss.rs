@@ -6,14 +6,24 @@ use crate::{
     vfd::Stream,
 };
 use rodio::cpal::SampleFormat;
-use std::{ffi::c_int, sync::Arc};
+use std::{
+    ffi::c_int,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use structures::{
     error::LxError,
     fs::OpenFlags,
     internal::mactux_ipc::CtrlOutput,
-    io::{IoctlCmd, VfdAvailCtrl},
+    io::{AudioBufInfo, IoctlCmd, VfdAvailCtrl},
 };
 
+/// Default size of a fragment, in bytes.
+const DEFAULT_FRAGMENT_SIZE: usize = 4096;
+
+/// Default number of fragments in the playback buffer.
+const DEFAULT_FRAGMENTS: usize = 4;
+
 /// The `/dev/dsp` device.
 #[derive(Debug)]
 struct Dsp;
@@ -25,6 +35,7 @@ impl Device for Dsp {
 
 struct DspFd {
     output: Option<Arc<AudioOutput>>,
+    buffer: Mutex<PlaybackBuffer>,
 }
 impl DspFd {
     fn new(flags: OpenFlags) -> Result<Arc<Self>, LxError> {
@@ -38,27 +49,65 @@ impl DspFd {
             None
         };
 
-        Ok(Arc::new(Self { output }))
+        Ok(Arc::new(Self {
+            output,
+            buffer: Mutex::new(PlaybackBuffer::new()),
+        }))
     }
 
     fn output(&self) -> Result<Arc<AudioOutput>, LxError> {
         self.output.clone().ok_or(LxError::EBADF)
     }
+
+    /// Submits a fragment to the audio output, waiting until there is free space in the playback buffer.
+    fn submit(
+        &self,
+        output: &AudioOutput,
+        buffer: &PlaybackBuffer,
+        fragment: &[u8],
+    ) -> Result<(), LxError> {
+        while output.player.len() >= buffer.fragments {
+            std::thread::sleep(play_time(output, buffer.fragment_size));
+        }
+        output.write_samples(fragment)?;
+        Ok(())
+    }
+
+    fn get_ospace(&self) -> Result<AudioBufInfo, LxError> {
+        let output = self.output()?;
+        let buffer = self.buffer.lock().unwrap();
+        let fragments = buffer.fragments - output.player.len().min(buffer.fragments);
+        let bytes = (fragments * buffer.fragment_size).saturating_sub(buffer.pending.len());
+        Ok(AudioBufInfo {
+            fragments: fragments as _,
+            fragstotal: buffer.fragments as _,
+            fragsize: buffer.fragment_size as _,
+            bytes: bytes as _,
+        })
+    }
+
+    fn sync(&self) -> Result<(), LxError> {
+        let output = self.output()?;
+        let mut buffer = self.buffer.lock().unwrap();
+        let pending = std::mem::take(&mut buffer.pending);
+        if !pending.is_empty() {
+            self.submit(&output, &buffer, &pending)?;
+        }
+        drop(buffer);
+        output.player.sleep_until_end();
+        Ok(())
+    }
 }
 impl Stream for DspFd {
     fn write(&self, buf: &[u8], _off: &mut i64) -> Result<usize, LxError> {
         let output = self.output()?;
-        let nbytes = output.write_samples(buf)?;
-        let nsamples = nbytes / output.sample_format.load().sample_size();
-        let time_to_sleep = std::time::Duration::from_secs(1) / output.sample_rate()
-            * (nsamples as u32)
-            / output.channels() as _;
-
-        if output.player.len() > 2 {
-            std::thread::sleep(time_to_sleep);
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.pending.extend_from_slice(buf);
+        while buffer.pending.len() >= buffer.fragment_size {
+            let fragment: Vec<u8> = buffer.pending.drain(..buffer.fragment_size).collect();
+            self.submit(&output, &buffer, &fragment)?;
         }
-
-        Ok(nbytes)
+        Ok(buf.len())
     }
 
     fn ioctl_query(&self, cmd: IoctlCmd) -> Result<VfdAvailCtrl, LxError> {
@@ -71,11 +120,40 @@ impl Stream for DspFd {
             IoctlCmd::SNDCTL_DSP_SETFMT => Ok(AVAIL_CTRL),
             IoctlCmd::SNDCTL_DSP_SPEED => Ok(AVAIL_CTRL),
             IoctlCmd::SNDCTL_DSP_STEREO => Ok(AVAIL_CTRL),
-            _ => Err(LxError::EINVAL),
+            IoctlCmd::SNDCTL_DSP_SETFRAGMENT => Ok(AVAIL_CTRL),
+            IoctlCmd::SNDCTL_DSP_GETBLKSIZE => Ok(AVAIL_CTRL),
+            IoctlCmd::SNDCTL_DSP_SYNC => Ok(VfdAvailCtrl {
+                in_size: 0,
+                out_size: 0,
+            }),
+            IoctlCmd::SNDCTL_DSP_GETOSPACE => Ok(VfdAvailCtrl {
+                in_size: 0,
+                out_size: size_of::<AudioBufInfo>(),
+            }),
+            IoctlCmd::SNDCTL_DSP_GETISPACE => Err(LxError::EOPNOTSUPP),
+            _ => Err(LxError::ENOTTY),
         }
     }
 
     fn ioctl(&self, cmd: IoctlCmd, data: &[u8]) -> Result<CtrlOutput, LxError> {
+        match cmd {
+            IoctlCmd::SNDCTL_DSP_SYNC => {
+                self.sync()?;
+                return Ok(CtrlOutput {
+                    status: 0,
+                    blob: Vec::new(),
+                });
+            }
+            IoctlCmd::SNDCTL_DSP_GETOSPACE => {
+                return Ok(CtrlOutput {
+                    status: 0,
+                    blob: self.get_ospace()?.to_bytes(),
+                });
+            }
+            IoctlCmd::SNDCTL_DSP_GETISPACE => return Err(LxError::EOPNOTSUPP),
+            _ => (),
+        }
+
         let mut buf = [0u8; size_of::<c_int>()];
         if data.len() != buf.len() {
             return Err(LxError::EINVAL);
@@ -125,11 +203,55 @@ impl Stream for DspFd {
                     blob: data.to_vec(),
                 })
             }
+            IoctlCmd::SNDCTL_DSP_SETFRAGMENT => {
+                let mut buffer = self.buffer.lock().unwrap();
+                let size_selector = (value & 0xffff).clamp(4, 16);
+                let max_fragments = ((value >> 16) & 0x7fff).clamp(2, 32);
+                buffer.fragment_size = 1 << size_selector;
+                buffer.fragments = max_fragments as _;
+                Ok(CtrlOutput {
+                    status: 0,
+                    blob: data.to_vec(),
+                })
+            }
+            IoctlCmd::SNDCTL_DSP_GETBLKSIZE => {
+                let fragment_size = self.buffer.lock().unwrap().fragment_size as c_int;
+                Ok(CtrlOutput {
+                    status: 0,
+                    blob: fragment_size.to_ne_bytes().to_vec(),
+                })
+            }
             _ => Err(LxError::EINVAL),
         }
     }
 }
 
+/// Playback buffer of a `/dev/dsp` file descriptor.
+///
+/// Writes are collected until a whole fragment is available, then submitted to the audio output. At most `fragments`
+/// fragments are queued in the audio output at the same time.
+#[derive(Debug)]
+struct PlaybackBuffer {
+    fragment_size: usize,
+    fragments: usize,
+    pending: Vec<u8>,
+}
+impl PlaybackBuffer {
+    fn new() -> Self {
+        Self {
+            fragment_size: DEFAULT_FRAGMENT_SIZE,
+            fragments: DEFAULT_FRAGMENTS,
+            pending: Vec::with_capacity(DEFAULT_FRAGMENT_SIZE),
+        }
+    }
+}
+
+/// Returns time needed to play `nbytes` bytes of samples on the audio output.
+fn play_time(output: &AudioOutput, nbytes: usize) -> Duration {
+    let nsamples = nbytes / output.sample_format.load().sample_size();
+    Duration::from_secs(1) / output.sample_rate() * (nsamples as u32) / output.channels() as _
+}
+
 pub fn discover(devices: &DeviceTable) {
     devices.add_chr_fixed(14, 3, || Arc::new(Dsp));
 }
@@ -0,0 +1,78 @@
+//! Implementation of `/dev/rtc0`, the real-time clock.
+
+use crate::{
+    device::{Device, DeviceTable},
+    vfd::Stream,
+};
+use std::sync::Arc;
+use structures::{
+    FromApple,
+    error::LxError,
+    fs::OpenFlags,
+    internal::mactux_ipc::CtrlOutput,
+    io::{IoctlCmd, VfdAvailCtrl},
+    time::RtcTime,
+};
+
+/// Major number of RTC devices. This is allocated dynamically by Linux, and commonly ends up being this one.
+const RTC_MAJOR: u32 = 248;
+
+/// The `/dev/rtc0` device.
+///
+/// The clock is read from the system clock of macOS, and cannot be set. Interrupts and alarms are not supported.
+struct Rtc;
+impl Device for Rtc {
+    fn open(&self, _flags: OpenFlags) -> Result<Arc<dyn Stream + Send + Sync>, LxError> {
+        Ok(Arc::new(RtcFd))
+    }
+}
+
+struct RtcFd;
+impl Stream for RtcFd {
+    fn ioctl_query(&self, cmd: IoctlCmd) -> Result<VfdAvailCtrl, LxError> {
+        match cmd {
+            IoctlCmd::RTC_RD_TIME => Ok(VfdAvailCtrl {
+                in_size: 0,
+                out_size: size_of::<RtcTime>(),
+            }),
+            IoctlCmd::RTC_SET_TIME => Ok(VfdAvailCtrl {
+                in_size: size_of::<RtcTime>() as _,
+                out_size: 0,
+            }),
+            IoctlCmd::RTC_AIE_ON
+            | IoctlCmd::RTC_AIE_OFF
+            | IoctlCmd::RTC_UIE_ON
+            | IoctlCmd::RTC_UIE_OFF
+            | IoctlCmd::RTC_ALM_SET
+            | IoctlCmd::RTC_ALM_READ => Err(LxError::EINVAL),
+            _ => Err(LxError::ENOTTY),
+        }
+    }
+
+    fn ioctl(&self, cmd: IoctlCmd, _data: &[u8]) -> Result<CtrlOutput, LxError> {
+        match cmd {
+            IoctlCmd::RTC_RD_TIME => Ok(CtrlOutput {
+                status: 0,
+                blob: read_time()?.to_bytes(),
+            }),
+            IoctlCmd::RTC_SET_TIME => Err(LxError::EPERM),
+            _ => Err(LxError::EINVAL),
+        }
+    }
+}
+
+/// Returns current time of the system clock, broken down in UTC.
+fn read_time() -> Result<RtcTime, LxError> {
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm = std::mem::zeroed();
+        if libc::gmtime_r(&now, &mut tm).is_null() {
+            return Err(LxError::last_apple_error());
+        }
+        RtcTime::from_apple(tm)
+    }
+}
+
+pub fn discover(devices: &DeviceTable) {
+    devices.add_chr_fixed(RTC_MAJOR, 0, || Arc::new(Rtc));
+}
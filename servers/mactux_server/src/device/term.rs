@@ -2,6 +2,8 @@
 
 use crate::{
     device::{Device, DeviceTable},
+    task::process::Process,
+    util::Shared,
     vfd::Stream,
 };
 use std::{
@@ -9,18 +11,28 @@ use std::{
     path::PathBuf,
     sync::Arc,
 };
-use structures::error::LxError;
+use structures::{
+    error::LxError,
+    fs::OpenFlags,
+    internal::mactux_ipc::CtrlOutput,
+    io::{IoctlCmd, VfdAvailCtrl},
+    terminal::WinSize,
+};
 
+/// The `/dev/tty` device, which refers to the controlling terminal of the process that opens it.
+///
+/// Sessions and controlling terminals of Linux processes are the ones of their native processes, so opening the macOS
+/// `/dev/tty` in the caller reaches the right terminal, and fails with `ENXIO` if the caller has none.
 struct Tty;
-impl Stream for Tty {
-    fn read(&self, _: &mut [u8], _: &mut i64) -> Result<usize, LxError> {
-        Err(LxError::EOPNOTSUPP)
-    }
-}
 impl Device for Tty {
     fn macos_device(&self) -> Option<PathBuf> {
         Some(PathBuf::from("/dev/tty"))
     }
+
+    fn open(&self, _flags: OpenFlags) -> Result<Arc<dyn Stream + Send + Sync>, LxError> {
+        // The server has no controlling terminal of its own to hand out.
+        Err(LxError::ENXIO)
+    }
 }
 
 struct Console;
@@ -32,9 +44,67 @@ impl Stream for Console {
     fn write(&self, buf: &[u8], _: &mut i64) -> Result<usize, LxError> {
         Ok(std::io::stdout().write(buf)?)
     }
+
+    fn ioctl_query(&self, cmd: IoctlCmd) -> Result<VfdAvailCtrl, LxError> {
+        match cmd {
+            IoctlCmd::TIOCGWINSZ => Ok(VfdAvailCtrl {
+                in_size: 0,
+                out_size: size_of::<WinSize>(),
+            }),
+            IoctlCmd::TIOCSWINSZ => Ok(VfdAvailCtrl {
+                in_size: size_of::<WinSize>() as _,
+                out_size: 0,
+            }),
+            _ => Err(LxError::ENOTTY),
+        }
+    }
+
+    fn ioctl(&self, cmd: IoctlCmd, data: &[u8]) -> Result<CtrlOutput, LxError> {
+        match cmd {
+            IoctlCmd::TIOCGWINSZ => Ok(CtrlOutput {
+                status: 0,
+                blob: console_winsize()?.to_bytes(),
+            }),
+            IoctlCmd::TIOCSWINSZ => {
+                let winsize = WinSize::from_bytes(data)?;
+                let changed = console_winsize()? != winsize;
+                unsafe {
+                    if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCSWINSZ, &winsize.to_apple()) == -1
+                    {
+                        return Err(LxError::last_apple_error());
+                    }
+                }
+
+                // The console has no foreground process group, so we notify the process group of the caller.
+                if changed {
+                    let apple_pid = Shared::id(&Process::current()) as libc::pid_t;
+                    unsafe {
+                        libc::killpg(libc::getpgid(apple_pid), libc::SIGWINCH);
+                    }
+                }
+
+                Ok(CtrlOutput {
+                    status: 0,
+                    blob: Vec::new(),
+                })
+            }
+            _ => Err(LxError::ENOTTY),
+        }
+    }
 }
 impl Device for Console {}
 
+/// Returns window size of the terminal that the console is attached to.
+fn console_winsize() -> Result<WinSize, LxError> {
+    unsafe {
+        let mut winsize: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut winsize) == -1 {
+            return Err(LxError::last_apple_error());
+        }
+        Ok(winsize.into())
+    }
+}
+
 pub fn discover(devices: &DeviceTable) {
     devices.add_chr_fixed(5, 0, || Arc::new(Tty));
     devices.add_chr_fixed(5, 1, || Arc::new(Console));
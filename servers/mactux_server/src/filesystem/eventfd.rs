@@ -3,8 +3,13 @@ use crate::{
     vfd::{PollToken, Stream, Vfd, VfdContent},
 };
 use crossbeam::channel::Sender;
-use rustc_hash::FxHashSet;
-use std::sync::{Arc, Mutex};
+use std::{
+    ops::Deref,
+    sync::{
+        Arc, Mutex,
+        atomic::{self, AtomicBool},
+    },
+};
 use structures::{
     error::LxError,
     io::{EventFdFlags, PollEvents, Whence},
@@ -12,87 +17,111 @@ use structures::{
 
 pub fn open(count: u64, flags: EventFdFlags) -> Result<Vfd, LxError> {
     Ok(Vfd::new(
-        Arc::new(EventFd {
+        Arc::new(EventFd(Arc::new(Counter {
             inner: Watch::new(count),
             flags,
+            nonblocking: AtomicBool::new(flags.contains(EventFdFlags::EFD_NONBLOCK)),
             senders: Mutex::new(Vec::new()),
-        }),
+        }))),
         flags.open_flags(),
     ))
 }
 
+/// Maximum value of the counter of an eventfd.
+const MAX_COUNT: u64 = u64::MAX - 1;
+
+/// An eventfd.
+///
+/// Like on Linux, duplicates of an eventfd, including the ones inherited by forked children, refer to the same counter.
+#[derive(Debug)]
+struct EventFd(Arc<Counter>);
+impl Deref for EventFd {
+    type Target = Counter;
+
+    fn deref(&self) -> &Counter {
+        &self.0
+    }
+}
+
 #[derive(Debug)]
-struct EventFd {
+struct Counter {
     inner: Watch<u64>,
     flags: EventFdFlags,
+    nonblocking: AtomicBool,
     senders: Mutex<Vec<Sender<PollEvents>>>,
 }
+impl Counter {
+    /// Sends `events` to polling clients, removing the ones that are gone.
+    fn notify(&self, events: PollEvents) {
+        self.senders
+            .lock()
+            .unwrap()
+            .retain(|sender| sender.send(events).is_ok());
+    }
+}
 impl Stream for EventFd {
     fn read(&self, buf: &mut [u8], _: &mut i64) -> Result<usize, LxError> {
-        if buf.len() != size_of::<u64>() {
+        if buf.len() < size_of::<u64>() {
             return Err(LxError::EINVAL);
         }
+
+        // In semaphore mode, a read takes 1 from the counter. Otherwise, it takes the whole counter.
         let mut val = 0;
-        if self.flags.contains(EventFdFlags::EFD_NONBLOCK) {
-            if self.inner.get() == 0 {
+        let mut take = |cur: &mut u64| {
+            if *cur == 0 {
+                return false;
+            }
+            val = match self.flags.contains(EventFdFlags::EFD_SEMAPHORE) {
+                true => 1,
+                false => *cur,
+            };
+            *cur -= val;
+            true
+        };
+        if self.nonblocking.load(atomic::Ordering::Relaxed) {
+            let mut taken = false;
+            self.inner.update(|cur| taken = take(cur));
+            if !taken {
                 return Err(LxError::EAGAIN);
             }
-            self.inner.update(|cur| {
-                val = *cur;
-                if self.flags.contains(EventFdFlags::EFD_SEMAPHORE) {
-                    *cur -= 1;
-                } else {
-                    *cur = 0;
-                }
-            });
         } else {
-            self.inner.wait_until(|cur| match cur {
-                0 => false,
-                other => {
-                    val = *other;
-                    if self.flags.contains(EventFdFlags::EFD_SEMAPHORE) {
-                        *other -= 1;
-                    } else {
-                        *other = 0;
-                    }
-                    true
-                }
-            });
+            self.inner.wait_until(&mut take);
         }
-        buf.copy_from_slice(&val.to_ne_bytes());
+        buf[..size_of::<u64>()].copy_from_slice(&val.to_ne_bytes());
+        self.notify(PollEvents::POLLOUT);
 
         Ok(size_of::<u64>())
     }
 
     fn write(&self, buf: &[u8], _: &mut i64) -> Result<usize, LxError> {
-        // Ensure that the argument is valid
-        if buf.len() != size_of::<u64>() {
+        if buf.len() < size_of::<u64>() {
             return Err(LxError::EINVAL);
         }
-
-        // Get added value
         let mut val = [0; size_of::<u64>()];
-        val.copy_from_slice(buf);
+        val.copy_from_slice(&buf[..size_of::<u64>()]);
         let val = u64::from_ne_bytes(val);
+        if val == u64::MAX {
+            return Err(LxError::EINVAL);
+        }
 
-        // Update the internal value
-        self.inner.update(|x| *x += val);
-
-        // Notify polling clients
-        let mut senders = self.senders.lock().unwrap();
-        let mut invalid = FxHashSet::default();
-        for (id, sender) in senders.iter().enumerate() {
-            if sender.send(PollEvents::POLLIN).is_err() {
-                invalid.insert(id);
+        // A write that would overflow the counter waits until enough is read.
+        let add = |cur: &mut u64| {
+            if MAX_COUNT - *cur < val {
+                return false;
             }
+            *cur += val;
+            true
+        };
+        if self.nonblocking.load(atomic::Ordering::Relaxed) {
+            let mut added = false;
+            self.inner.update(|cur| added = add(cur));
+            if !added {
+                return Err(LxError::EAGAIN);
+            }
+        } else {
+            self.inner.wait_until(add);
         }
-
-        // Remove invalidated polling clients
-        let mut id = 0;
-        senders.retain(|_| {
-            id += 1;
-            !invalid.contains(&(id - 1))
-        });
+        self.notify(PollEvents::POLLIN);
 
         Ok(size_of::<u64>())
     }
@@ -102,7 +131,7 @@ impl Stream for EventFd {
         if interest.contains(PollEvents::POLLIN) && self.inner.get() > 0 {
             _ = tx.send(PollEvents::POLLIN);
         }
-        if interest.contains(PollEvents::POLLOUT) {
+        if interest.contains(PollEvents::POLLOUT) && self.inner.get() < MAX_COUNT {
             _ = tx.send(PollEvents::POLLOUT);
         }
         self.senders.lock().unwrap().push(tx);
@@ -113,8 +142,77 @@ impl Stream for EventFd {
         })
     }
 
+    // Like on Linux, seeking an eventfd succeeds without doing anything, and its offset always stays at 0.
     fn seek(&self, _: i64, _: Whence, _: i64) -> Result<i64, LxError> {
         Ok(0)
     }
+
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking
+            .store(nonblocking, atomic::Ordering::Relaxed);
+    }
+}
+impl VfdContent for EventFd {
+    fn dup(&self) -> Result<Arc<dyn VfdContent>, LxError> {
+        Ok(Arc::new(Self(Arc::clone(&self.0))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfd::VfdTable;
+
+    fn read(vfd: &Vfd) -> Result<u64, LxError> {
+        let mut buf = [0; size_of::<u64>()];
+        vfd.read(&mut buf)?;
+        Ok(u64::from_ne_bytes(buf))
+    }
+
+    fn write(vfd: &Vfd, val: u64) -> Result<usize, LxError> {
+        vfd.write(&val.to_ne_bytes())
+    }
+
+    #[test]
+    fn read_takes_whole_counter() {
+        let vfd = open(3, EventFdFlags::EFD_NONBLOCK).unwrap();
+        write(&vfd, 4).unwrap();
+        assert_eq!(read(&vfd), Ok(7));
+        assert_eq!(read(&vfd), Err(LxError::EAGAIN));
+    }
+
+    #[test]
+    fn semaphore_read_takes_one() {
+        let vfd = open(2, EventFdFlags::EFD_SEMAPHORE | EventFdFlags::EFD_NONBLOCK).unwrap();
+        assert_eq!(read(&vfd), Ok(1));
+        assert_eq!(read(&vfd), Ok(1));
+        assert_eq!(read(&vfd), Err(LxError::EAGAIN));
+    }
+
+    #[test]
+    fn overflowing_write_fails_with_eagain() {
+        let vfd = open(MAX_COUNT - 1, EventFdFlags::EFD_NONBLOCK).unwrap();
+        assert_eq!(write(&vfd, 2), Err(LxError::EAGAIN));
+        assert_eq!(write(&vfd, 1), Ok(size_of::<u64>()));
+        assert_eq!(write(&vfd, 1), Err(LxError::EAGAIN));
+        assert_eq!(write(&vfd, u64::MAX), Err(LxError::EINVAL));
+        assert_eq!(read(&vfd), Ok(MAX_COUNT));
+        assert_eq!(vfd.read(&mut [0; 4]), Err(LxError::EINVAL));
+    }
+
+    #[test]
+    fn forked_child_signals_parent() {
+        let parent = VfdTable::new();
+        let id = parent.register(Arc::new(open(0, EventFdFlags::EFD_NONBLOCK).unwrap()));
+        let child = parent.fork();
+
+        let vfd = parent.get(id).unwrap();
+        let token = vfd.poll(PollEvents::POLLIN).unwrap();
+        assert!(token.receiver.try_recv().is_err());
+
+        write(&child.get(id).unwrap(), 5).unwrap();
+        assert!(token.ready(token.receiver.try_recv().unwrap()));
+        assert_eq!(read(&vfd), Ok(5));
+        assert_eq!(read(&child.get(id).unwrap()), Err(LxError::EAGAIN));
+    }
 }
-impl VfdContent for EventFd {}
@@ -3,6 +3,8 @@
 pub mod eventfd;
 pub mod invalidfd;
 pub mod nativefs;
+pub mod nsfd;
+pub mod pidfd;
 pub mod procfs;
 pub mod sysfs;
 pub mod tmpfs;
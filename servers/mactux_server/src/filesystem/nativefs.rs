@@ -5,8 +5,8 @@ use crate::{
         VPath,
         vfs::{Filesystem, LPath, MakeFilesystem, NewlyOpen},
     },
-    task::process::Process,
-    util::symlink_abs,
+    task::{native_credentials, process::Process},
+    util::{Shared, symlink_abs},
     vfd::{Stream, Vfd, VfdContent},
 };
 use libc::c_int;
@@ -22,8 +22,8 @@ use structures::{
     device::DeviceNumber,
     error::LxError,
     fs::{
-        AccessFlags, Dirent64, FileMode, MountFlags, OpenFlags, OpenHow, OpenResolve, StatFs,
-        Statx, StatxMask,
+        AccessFlags, AtFlags, Dirent64, FileMode, FileType, MountFlags, OpenFlags, OpenHow,
+        OpenResolve, StatFs, Statx, StatxMask,
     },
     time::Timespec,
 };
@@ -31,24 +31,42 @@ use structures::{
 /// A nativefs mount.
 pub struct NativeFs {
     base: NBase,
+    flags: MountFlags,
 }
 impl NativeFs {
     /// Creates a new [`NativeFs`] mount.
-    pub fn new(dev: &[u8]) -> Result<Arc<Self>, LxError> {
+    pub fn new(dev: &[u8], flags: MountFlags) -> Result<Arc<Self>, LxError> {
         let dev = str::from_utf8(dev).map_err(|_| LxError::EINVAL)?;
         let path = dev.strip_prefix("native=").ok_or(LxError::EACCES)?;
         let base = NBase::new(Path::new(path))?;
         log::debug!("mounted filesystem \"{dev}\" with dirfd={}.", base.dirfd);
-        Ok(Arc::new(Self { base }))
+        Ok(Arc::new(Self { base, flags }))
+    }
+
+    /// Fails with [`LxError::EROFS`] if the filesystem is mounted read-only.
+    fn will_write(&self) -> Result<(), LxError> {
+        match self.flags.contains(MountFlags::MS_RDONLY) {
+            true => Err(LxError::EROFS),
+            false => Ok(()),
+        }
     }
 }
 impl Filesystem for NativeFs {
     fn open(self: Arc<Self>, path: LPath, how: OpenHow) -> Result<NewlyOpen, LxError> {
+        if how.flags().is_writable() {
+            self.will_write()?;
+        }
         match NPath::resolve(&self.base, path)? {
             NPath::Direct(dst) => unsafe {
                 let mut statbuf = std::mem::zeroed();
                 match posix_result(libc::lstat(dst.as_ptr(), &mut statbuf)) {
-                    Ok(()) | Err(LxError::ENOENT) => (),
+                    Ok(()) => (),
+
+                    // Like on Linux, `O_CREAT` only fails on a read-only filesystem if the file would be created.
+                    Err(LxError::ENOENT) if how.flags().contains(OpenFlags::O_CREAT) => {
+                        self.will_write()?
+                    }
+                    Err(LxError::ENOENT) => (),
                     Err(err) => return Err(err),
                 }
                 if statbuf.st_mode & libc::S_IFMT == libc::S_IFDIR {
@@ -58,7 +76,7 @@ impl Filesystem for NativeFs {
                 Ok(NewlyOpen::Native(dst.into_bytes()))
             },
             NPath::HasSymlink(symexpr) => Process::current()
-                .mnt
+                .mnt()
                 .locate(&symexpr.into_vpath())?
                 .open(how),
             NPath::IsSymlink(sympath, content) => {
@@ -68,49 +86,55 @@ impl Filesystem for NativeFs {
                 if how.resolve.contains(OpenResolve::RESOLVE_NO_SYMLINKS) {
                     return Ok(NewlyOpen::Native(sympath.into_bytes()));
                 }
-                Process::current().mnt.locate(&content)?.open(how)
+                Process::current().mnt().locate(&content)?.open(how)
             }
         }
     }
 
-    fn access(&self, path: LPath, mode: AccessFlags) -> Result<(), LxError> {
+    fn access(&self, path: LPath, mode: AccessFlags, flags: AtFlags) -> Result<(), LxError> {
+        if mode.contains(AccessFlags::W_OK) {
+            self.will_write()?;
+        }
         match NPath::resolve(&self.base, path)? {
-            NPath::Direct(dst) => unsafe {
-                posix_result(libc::access(dst.as_ptr(), mode.to_apple()?))
-            },
+            NPath::Direct(dst) => access_as_caller(&dst, mode, flags),
             NPath::HasSymlink(symexpr) => Process::current()
-                .mnt
+                .mnt()
                 .locate(&symexpr.into_vpath())?
-                .access(mode),
-            NPath::IsSymlink(_, content) => Process::current().mnt.locate(&content)?.access(mode),
+                .access(mode, flags),
+            NPath::IsSymlink(_, content) => Process::current()
+                .mnt()
+                .locate(&content)?
+                .access(mode, flags),
         }
     }
 
     fn symlink(&self, dst: LPath, content: &[u8]) -> Result<(), LxError> {
+        self.will_write()?;
         match NPath::resolve(&self.base, dst)? {
             NPath::Direct(dst) | NPath::IsSymlink(dst, _) => unsafe {
                 let content = bytes_to_cstring(content.to_vec())?;
                 posix_result(libc::symlink(content.as_ptr(), dst.as_ptr()))
             },
             NPath::HasSymlink(symexpr) => Process::current()
-                .mnt
+                .mnt()
                 .locate(&symexpr.into_vpath())?
                 .symlink(content),
         }
     }
 
     fn rmdir(&self, path: LPath) -> Result<(), LxError> {
+        self.will_write()?;
         match NPath::resolve(&self.base, path.clone())? {
             NPath::Direct(dst) => unsafe { posix_result(libc::rmdir(dst.as_ptr())) },
             NPath::HasSymlink(symexpr) => Process::current()
-                .mnt
+                .mnt()
                 .locate(&symexpr.into_vpath())?
                 .rmdir(),
             NPath::IsSymlink(_, content) => {
                 if !path.relative.slash_suffix {
                     return Err(LxError::ENOTDIR);
                 }
-                Process::current().mnt.locate(&content)?.rmdir()
+                Process::current().mnt().locate(&content)?.rmdir()
             }
         }
     }
@@ -120,6 +144,7 @@ impl Filesystem for NativeFs {
     }
 
     fn link(&self, src: LPath, dst: LPath) -> Result<(), LxError> {
+        self.will_write()?;
         let src_solved = NPath::resolve(&self.base, src.clone())?;
         let dst_solved = NPath::resolve(&self.base, dst.clone())?;
         match dst_solved {
@@ -131,17 +156,17 @@ impl Filesystem for NativeFs {
                     }
                 },
                 NPath::HasSymlink(symexpr) => {
-                    let src_location = Process::current().mnt.locate(&symexpr.into_vpath())?;
+                    let src_location = Process::current().mnt().locate(&symexpr.into_vpath())?;
                     Process::current()
-                        .mnt
+                        .mnt()
                         .locate(&dst.expand())?
                         .link_to(src_location)
                 }
             },
             NPath::HasSymlink(symexpr) => {
-                let src_location = Process::current().mnt.locate(&src.expand())?;
+                let src_location = Process::current().mnt().locate(&src.expand())?;
                 Process::current()
-                    .mnt
+                    .mnt()
                     .locate(&symexpr.into_vpath())?
                     .link_to(src_location)
             }
@@ -150,10 +175,11 @@ impl Filesystem for NativeFs {
     }
 
     fn mkdir(&self, path: LPath, mode: FileMode) -> Result<(), LxError> {
+        self.will_write()?;
         match NPath::resolve(&self.base, path)? {
             NPath::Direct(dst) => unsafe { posix_result(libc::mkdir(dst.as_ptr(), mode.0 as _)) },
             NPath::HasSymlink(symexpr) => Process::current()
-                .mnt
+                .mnt()
                 .locate(&symexpr.into_vpath())?
                 .mkdir(mode),
             NPath::IsSymlink(_, _) => Err(LxError::EEXIST),
@@ -161,6 +187,7 @@ impl Filesystem for NativeFs {
     }
 
     fn rename(&self, src: LPath, dst: LPath) -> Result<(), LxError> {
+        self.will_write()?;
         let src_solved = NPath::resolve(&self.base, src.clone())?;
         let dst_solved = NPath::resolve(&self.base, dst.clone())?;
         match dst_solved {
@@ -172,17 +199,17 @@ impl Filesystem for NativeFs {
                     }
                 },
                 NPath::HasSymlink(symexpr) => {
-                    let src_location = Process::current().mnt.locate(&symexpr.into_vpath())?;
+                    let src_location = Process::current().mnt().locate(&symexpr.into_vpath())?;
                     Process::current()
-                        .mnt
+                        .mnt()
                         .locate(&dst.expand())?
                         .rename_to(src_location)
                 }
             },
             NPath::HasSymlink(symexpr) => {
-                let src_location = Process::current().mnt.locate(&src.expand())?;
+                let src_location = Process::current().mnt().locate(&src.expand())?;
                 Process::current()
-                    .mnt
+                    .mnt()
                     .locate(&symexpr.into_vpath())?
                     .rename_to(src_location)
             }
@@ -190,25 +217,27 @@ impl Filesystem for NativeFs {
     }
 
     fn unlink(&self, path: LPath) -> Result<(), LxError> {
+        self.will_write()?;
         match NPath::resolve(&self.base, path)? {
             NPath::Direct(dst) | NPath::IsSymlink(dst, _) => unsafe {
                 posix_result(libc::unlink(dst.as_ptr()))
             },
             NPath::HasSymlink(symexpr) => Process::current()
-                .mnt
+                .mnt()
                 .locate(&symexpr.into_vpath())?
                 .unlink(),
         }
     }
 
     fn mknod(&self, path: LPath, mode: FileMode, dev: DeviceNumber) -> Result<(), LxError> {
+        self.will_write()?;
         let apple_dev = libc::makedev(dev.major() as _, dev.minor() as _);
         match NPath::resolve(&self.base, path)? {
             NPath::Direct(path) => unsafe {
                 posix_result(libc::mknod(path.as_ptr(), mode.to_apple()?, apple_dev))
             },
             NPath::HasSymlink(symexpr) => Process::current()
-                .mnt
+                .mnt()
                 .locate(&symexpr.into_vpath())?
                 .mknod(mode, dev),
             NPath::IsSymlink(_, _) => Err(LxError::EEXIST),
@@ -221,6 +250,7 @@ impl Filesystem for NativeFs {
             posix_result(libc::fstatfs(self.base.dirfd, &mut *apple))?;
             let mut result = StatFs::from_apple(apple)?;
             result.f_fsid = crate::util::fsid(self);
+            result.f_flags = self.flags.statfs_flags();
             Ok(result)
         }
     }
@@ -231,10 +261,10 @@ impl MakeFilesystem for MakeNativefs {
     fn make_filesystem(
         &self,
         dev: &[u8],
-        _: MountFlags,
+        flags: MountFlags,
         _: &[u8],
     ) -> Result<Arc<dyn Filesystem>, LxError> {
-        NativeFs::new(dev).map(|x| x as _)
+        NativeFs::new(dev, flags).map(|x| x as _)
     }
 }
 
@@ -451,9 +481,104 @@ fn readlinkat(dirfd: c_int, path: &CStr) -> Result<Vec<u8>, LxError> {
     Ok(buf)
 }
 
+/// Checks whether the calling process may access the native file at `path`, against its effective IDs with
+/// `AT_EACCESS` in `flags`, or its real IDs otherwise.
+///
+/// The check is left to macOS if the server runs with the same IDs. Otherwise, permission bits are checked like Linux
+/// does, against the user and the primary group only, since supplementary groups of other processes are not known.
+fn access_as_caller(path: &CStr, mode: AccessFlags, flags: AtFlags) -> Result<(), LxError> {
+    let caller = native_credentials(Shared::id(&Process::current()) as _)?;
+    let (uid, gid) = match flags.contains(AtFlags::AT_EACCESS) {
+        true => (caller.pbi_uid, caller.pbi_gid),
+        false => (caller.pbi_ruid, caller.pbi_rgid),
+    };
+    unsafe {
+        if uid == libc::geteuid() && gid == libc::getegid() {
+            return posix_result(libc::faccessat(
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                mode.to_apple()?,
+                libc::AT_EACCESS,
+            ));
+        }
+        let mut stat: libc::stat = std::mem::zeroed();
+        posix_result(libc::stat(path.as_ptr(), &mut stat))?;
+        let file_mode = FileMode(stat.st_mode as _);
+        match permitted(file_mode, stat.st_uid, stat.st_gid, mode, uid, gid) {
+            true => Ok(()),
+            false => Err(LxError::EACCES),
+        }
+    }
+}
+
+/// Returns `true` if permission bits of a file with `file_mode`, owned by `owner` and `group`, grant `mode` to a process
+/// with `uid` and `gid`.
+///
+/// Like Linux, the superuser may read and write any file, and execute directories and files with any execute bit.
+fn permitted(
+    file_mode: FileMode,
+    owner: u32,
+    group: u32,
+    mode: AccessFlags,
+    uid: u32,
+    gid: u32,
+) -> bool {
+    let perm = file_mode.permbits();
+    if uid == 0 {
+        return !mode.contains(AccessFlags::X_OK)
+            || file_mode.file_type() == FileType::Directory
+            || perm & 0o111 != 0;
+    }
+    let class = match (owner == uid, group == gid) {
+        (true, _) => perm >> 6,
+        (false, true) => perm >> 3,
+        (false, false) => perm,
+    };
+    let wanted = mode.bits() as u16;
+    class & wanted == wanted
+}
+
 fn posix_result(value: c_int) -> Result<(), LxError> {
     match value {
         -1 => Err(LxError::last_apple_error()),
         _ => Ok(()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: u16 = 0o100000;
+    const DIR: u16 = 0o040000;
+
+    #[test]
+    fn permission_class_is_chosen_by_ownership() {
+        let mode = FileMode(FILE | 0o754);
+        let check = |access, uid, gid| permitted(mode, 501, 20, access, uid, gid);
+
+        assert!(check(
+            AccessFlags::R_OK | AccessFlags::W_OK | AccessFlags::X_OK,
+            501,
+            0
+        ));
+        assert!(check(AccessFlags::R_OK | AccessFlags::X_OK, 502, 20));
+        assert!(!check(AccessFlags::W_OK, 502, 20));
+        assert!(check(AccessFlags::R_OK, 502, 21));
+        assert!(!check(AccessFlags::X_OK, 502, 21));
+
+        // The owner class applies to the owner even if the group class grants more.
+        let mode = FileMode(FILE | 0o070);
+        assert!(!permitted(mode, 501, 20, AccessFlags::R_OK, 501, 20));
+        assert!(permitted(mode, 501, 20, AccessFlags::F_OK, 501, 20));
+    }
+
+    #[test]
+    fn superuser_needs_an_execute_bit_for_files_only() {
+        let check = |mode, access| permitted(FileMode(mode), 501, 20, access, 0, 0);
+        assert!(check(FILE, AccessFlags::R_OK | AccessFlags::W_OK));
+        assert!(!check(FILE | 0o644, AccessFlags::X_OK));
+        assert!(check(FILE | 0o001, AccessFlags::X_OK));
+        assert!(check(DIR, AccessFlags::X_OK));
+    }
+}
@@ -0,0 +1,21 @@
+//! Implementation of namespace file descriptors, as are opened from `/proc/[pid]/ns`.
+
+use crate::vfd::{Stream, VfdContent};
+use std::sync::Arc;
+use structures::{error::LxError, process::NamespaceKind};
+
+pub fn new(kind: NamespaceKind, id: u64) -> Arc<dyn VfdContent> {
+    Arc::new(NsFd { kind, id })
+}
+
+#[derive(Debug)]
+struct NsFd {
+    kind: NamespaceKind,
+    id: u64,
+}
+impl Stream for NsFd {}
+impl VfdContent for NsFd {
+    fn namespace(&self) -> Result<(NamespaceKind, u64), LxError> {
+        Ok((self.kind, self.id))
+    }
+}
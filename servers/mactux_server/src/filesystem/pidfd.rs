@@ -0,0 +1,20 @@
+//! Implementation of process file descriptors.
+
+use crate::vfd::{Stream, Vfd, VfdContent};
+use std::sync::Arc;
+use structures::{error::LxError, process::PidFdFlags};
+
+pub fn open(native_pid: i32, flags: PidFdFlags) -> Result<Vfd, LxError> {
+    Ok(Vfd::new(Arc::new(PidFd { native_pid }), flags.open_flags()))
+}
+
+#[derive(Debug)]
+struct PidFd {
+    native_pid: i32,
+}
+impl Stream for PidFd {}
+impl VfdContent for PidFd {
+    fn pidfd(&self) -> Result<i32, LxError> {
+        Ok(self.native_pid)
+    }
+}
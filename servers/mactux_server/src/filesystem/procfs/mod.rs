@@ -2,7 +2,9 @@
 //!
 //! Actually, it is a special kind of `tmpfs`.
 
+mod net;
 mod pid;
+mod region;
 mod sysinfo;
 
 use crate::{
@@ -10,7 +12,7 @@ use crate::{
     filesystem::{
         VPath,
         tmpfs::{DynFile, Tmpfs},
-        vfs::{Filesystem, LPath, MakeFilesystem},
+        vfs::{Filesystem, LPath, MakeFilesystem, Mount},
     },
     task::{PidNamespace, process::Process, thread::Thread},
     util::Shared,
@@ -19,6 +21,7 @@ use std::sync::Arc;
 use structures::{
     error::LxError,
     fs::{FileMode, FsMagic, MountFlags},
+    process::NamespaceKind,
 };
 
 pub fn new() -> Result<Arc<Tmpfs>, LxError> {
@@ -33,6 +36,13 @@ pub fn new() -> Result<Arc<Tmpfs>, LxError> {
     create_dynfile_ro(&tmpfs, "/uptime", sysinfo::uptime, 0o444)?;
     create_dynfile_ro(&tmpfs, "/filesystems", sysinfo::filesystems, 0o444)?;
 
+    create_dir(&tmpfs, "/net", 0o555)?;
+    create_dynfile_ro(&tmpfs, "/net/tcp", net::tcp, 0o444)?;
+    create_dynfile_ro(&tmpfs, "/net/tcp6", net::tcp6, 0o444)?;
+    create_dynfile_ro(&tmpfs, "/net/udp", net::udp, 0o444)?;
+    create_dynfile_ro(&tmpfs, "/net/udp6", net::udp6, 0o444)?;
+    create_dynfile_ro(&tmpfs, "/net/unix", net::unix, 0o444)?;
+
     tmpfs.create_dynlink(VPath::parse(b"/self"), || {
         current_linux_ids().0.to_string().into_bytes()
     })?;
@@ -103,16 +113,20 @@ fn fill_proc_or_thread(
         pid::cmdline(native_pid),
         0o444,
     )?;
+    tmpfs.create_dynfile(
+        VPath::parse(format!("{relpath}/comm").as_bytes()),
+        DynFile::new(pid::comm(native_pid), pid::set_comm(native_pid), 0o644),
+    )?;
     create_dynfile_ro(
         tmpfs,
-        &format!("{relpath}/comm"),
-        pid::comm(native_pid),
+        &format!("{relpath}/stat"),
+        pid::stat(native_pid),
         0o444,
     )?;
     create_dynfile_ro(
         tmpfs,
-        &format!("{relpath}/stat"),
-        pid::stat(native_pid),
+        &format!("{relpath}/status"),
+        pid::status(native_pid),
         0o444,
     )?;
     create_dynfile_ro(
@@ -139,9 +153,42 @@ fn fill_proc_or_thread(
         pid::maps(native_pid),
         0o444,
     )?;
+    create_dynfile_ro(
+        tmpfs,
+        &format!("{relpath}/smaps"),
+        pid::smaps(native_pid),
+        0o444,
+    )?;
+    create_dynfile_ro(
+        tmpfs,
+        &format!("{relpath}/smaps_rollup"),
+        pid::smaps_rollup(native_pid),
+        0o444,
+    )?;
+    tmpfs.create_dynlink(
+        VPath::parse(format!("{relpath}/exe").as_bytes()),
+        pid::exe(native_pid),
+    )?;
+    tmpfs.create_dynlink(
+        VPath::parse(format!("{relpath}/cwd").as_bytes()),
+        pid::cwd(native_pid),
+    )?;
 
     if !thread {
         create_dir(tmpfs, &format!("{relpath}/task"), 0o777)?;
+        create_dir(tmpfs, &format!("{relpath}/ns"), 0o555)?;
+        for kind in [
+            NamespaceKind::Mount,
+            NamespaceKind::Pid,
+            NamespaceKind::Uts,
+            NamespaceKind::Net,
+        ] {
+            tmpfs.create_special(
+                VPath::parse(format!("{relpath}/ns/{}", kind.name()).as_bytes()),
+                pid::ns(native_pid, kind),
+                0o444,
+            )?;
+        }
     }
 
     Ok(())
@@ -160,6 +207,7 @@ where
 fn create_dir(tmpfs: &Tmpfs, path: &str, permbits: u16) -> Result<(), LxError> {
     let lpath = LPath {
         mountpoint: VPath::parse(b"/"),
+        root_depth: 0,
         relative: VPath::parse(path.as_bytes()),
     };
     tmpfs.mkdir(lpath, FileMode(permbits))
@@ -181,3 +229,43 @@ fn current_linux_ids() -> (i32, i32) {
     let linux_tid = current.process.pid.ntol(native_tid).unwrap_or(native_tid);
     (linux_pid, linux_tid)
 }
+
+/// Converts a native path into the path of the same file in `mounts`.
+///
+/// Only nativefs mounts have native paths. If none of them covers `native`, it is returned unchanged.
+fn linux_path(native: &[u8], mounts: &[Mount]) -> Vec<u8> {
+    let mut best: Option<(usize, Vec<u8>)> = None;
+    for mount in mounts {
+        let Some(base) = mount.source.strip_prefix(b"native=") else {
+            continue;
+        };
+        let base = unsafe { std::ffi::OsStr::from_encoded_bytes_unchecked(base) };
+        let Ok(base) = std::fs::canonicalize(base) else {
+            continue;
+        };
+        let mut root = base.into_os_string().into_encoded_bytes();
+        root.extend_from_slice(&mount.root.express());
+        while root.len() > 1 && root.last() == Some(&b'/') {
+            root.pop();
+        }
+        let Some(rest) = native.strip_prefix(&root[..]) else {
+            continue;
+        };
+        if !rest.is_empty() && !rest.starts_with(b"/") {
+            continue;
+        }
+        if best.as_ref().is_some_and(|(len, _)| *len >= root.len()) {
+            continue;
+        }
+        let mut path = mount.mountpoint.express();
+        if path.last() == Some(&b'/') {
+            path.pop();
+        }
+        path.extend_from_slice(rest);
+        if path.is_empty() {
+            path.push(b'/');
+        }
+        best = Some((root.len(), path));
+    }
+    best.map(|x| x.1).unwrap_or_else(|| native.to_vec())
+}
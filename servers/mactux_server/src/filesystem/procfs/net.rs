@@ -0,0 +1,227 @@
+//! Socket tables in `/proc/net`.
+//!
+//! Sockets are collected from file descriptors of processes in the network namespace of the reader, so that the
+//! inode numbers match the ones shown in `/proc/[pid]/fd`.
+
+use super::linux_path;
+use crate::{app, task::process::Process, util::Shared};
+use libproc::{
+    bsd_info::BSDInfo,
+    file_info::{ListFDs, ProcFDType},
+    net_info::{InSIAddr, InSockInfo, SocketFDInfo, SocketInfo, SocketInfoKind},
+};
+use rustc_hash::FxHashSet;
+use std::{
+    ffi::{CStr, OsStr, c_int},
+    io::Write,
+    path::Path,
+};
+use structures::{error::LxError, process::NamespaceKind};
+
+const TCP_HEADER: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode";
+const TCP6_HEADER: &str = "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode";
+const UDP_HEADER: &str = "   sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops";
+const UDP6_HEADER: &str = "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops";
+const UNIX_HEADER: &str = "Num       RefCount Protocol Flags    Type St Inode Path";
+
+const TCP_ESTABLISHED: u8 = 0x01;
+const TCP_CLOSE: u8 = 0x07;
+const TCP_LISTEN: u8 = 0x0a;
+
+const SS_ISCONNECTED: c_int = 0x2;
+const SS_ISCONNECTING: c_int = 0x4;
+const SS_ISDISCONNECTING: c_int = 0x8;
+
+/// The `__SO_ACCEPTCON` flag of Linux, shown for listening sockets in `/proc/net/unix`.
+const SO_ACCEPTCON: u32 = 1 << 16;
+
+pub fn tcp() -> Result<Vec<u8>, LxError> {
+    inet_table(TCP_HEADER, libc::AF_INET, libc::IPPROTO_TCP)
+}
+
+pub fn tcp6() -> Result<Vec<u8>, LxError> {
+    inet_table(TCP6_HEADER, libc::AF_INET6, libc::IPPROTO_TCP)
+}
+
+pub fn udp() -> Result<Vec<u8>, LxError> {
+    inet_table(UDP_HEADER, libc::AF_INET, libc::IPPROTO_UDP)
+}
+
+pub fn udp6() -> Result<Vec<u8>, LxError> {
+    inet_table(UDP6_HEADER, libc::AF_INET6, libc::IPPROTO_UDP)
+}
+
+pub fn unix() -> Result<Vec<u8>, LxError> {
+    let viewer = Process::current();
+    let mounts = viewer.mnt().mounts();
+    let net = viewer.net();
+
+    let mut s = Vec::with_capacity(4096);
+    writeln!(&mut s, "{UNIX_HEADER}").unwrap();
+    for socket in sockets(&viewer) {
+        if socket.soi_family != libc::AF_UNIX
+            || !matches!(SocketInfoKind::from(socket.soi_kind), SocketInfoKind::Un)
+        {
+            continue;
+        }
+        let flags = match socket.soi_options as c_int & libc::SO_ACCEPTCONN {
+            0 => 0,
+            _ => SO_ACCEPTCON,
+        };
+        let state = match socket.soi_state as c_int {
+            x if x & SS_ISCONNECTED != 0 => 3,
+            x if x & SS_ISCONNECTING != 0 => 2,
+            x if x & SS_ISDISCONNECTING != 0 => 4,
+            _ => 1,
+        };
+        let sock_type = match socket.soi_type {
+            libc::SOCK_STREAM => 1,
+            libc::SOCK_DGRAM => 2,
+            libc::SOCK_SEQPACKET => 5,
+            other => other,
+        };
+        write!(
+            &mut s,
+            "0000000000000000: 00000002 00000000 {flags:08X} {sock_type:04X} {state:02X} {:5}",
+            socket.soi_stat.vst_ino
+        )
+        .unwrap();
+
+        let sun_path =
+            unsafe { CStr::from_ptr(socket.soi_proto.pri_un.unsi_addr.ua_sun.sun_path.as_ptr()) };
+        if !sun_path.is_empty() {
+            let native = sun_path.to_bytes();
+            let native_path = Path::new(unsafe { OsStr::from_encoded_bytes_unchecked(native) });
+            s.push(b' ');
+            match net.abs.name_by_sock(native_path) {
+                Some(name) => {
+                    s.push(b'@');
+                    s.extend_from_slice(&name);
+                }
+                None => s.extend_from_slice(&linux_path(native, &mounts)),
+            }
+        }
+        s.push(b'\n');
+    }
+    Ok(s)
+}
+
+/// Formats a table of Internet sockets of the given family and protocol.
+fn inet_table(header: &str, family: c_int, protocol: c_int) -> Result<Vec<u8>, LxError> {
+    let viewer = Process::current();
+    let mut s = Vec::with_capacity(4096);
+    writeln!(&mut s, "{header}").unwrap();
+    let mut sl = 0;
+    for socket in sockets(&viewer) {
+        if socket.soi_family != family || socket.soi_protocol != protocol {
+            continue;
+        }
+        let (inet, state) = match SocketInfoKind::from(socket.soi_kind) {
+            SocketInfoKind::Tcp => unsafe {
+                let tcp = &socket.soi_proto.pri_tcp;
+                (&tcp.tcpsi_ini, tcp_state(tcp.tcpsi_state))
+            },
+            SocketInfoKind::In => unsafe {
+                let inet = &socket.soi_proto.pri_in;
+                let state = match inet.insi_fport {
+                    0 => TCP_CLOSE,
+                    _ => TCP_ESTABLISHED,
+                };
+                (inet, state)
+            },
+            _ => continue,
+        };
+        let (local, remote) = inet_addrs(inet, family);
+        let (tx_queue, rx_queue) = match state {
+            TCP_LISTEN => (socket.soi_qlimit as u32, socket.soi_qlen as u32),
+            _ => (socket.soi_snd.sbi_cc, socket.soi_rcv.sbi_cc),
+        };
+        let uid = socket.soi_stat.vst_uid;
+        let inode = socket.soi_stat.vst_ino;
+        match protocol {
+            libc::IPPROTO_TCP => write!(
+                &mut s,
+                "{sl:4}: {local} {remote} {state:02X} {tx_queue:08X}:{rx_queue:08X} 00:00000000 00000000 {uid:5}        0 {inode} 1 0000000000000000 100 0 0 10 0"
+            ),
+            _ => write!(
+                &mut s,
+                "{sl:5}: {local} {remote} {state:02X} {tx_queue:08X}:{rx_queue:08X} 00:00000000 00000000 {uid:5}        0 {inode} 2 0000000000000000 0"
+            ),
+        }
+        .unwrap();
+        s.push(b'\n');
+        sl += 1;
+    }
+    Ok(s)
+}
+
+/// Returns sockets open in processes that share the network namespace of `viewer`.
+///
+/// A socket shared by several file descriptors, for example after a `fork`, is only returned once.
+fn sockets(viewer: &Shared<Process>) -> Vec<SocketInfo> {
+    let net = viewer.namespace_id(NamespaceKind::Net);
+    let mut seen = FxHashSet::default();
+    let mut sockets = Vec::new();
+    for process in app().processes.list() {
+        if process.namespace_id(NamespaceKind::Net) != net {
+            continue;
+        }
+        let pid = Shared::id(&process) as libc::pid_t;
+        let Ok(bsd_info) = libproc::proc_pid::pidinfo::<BSDInfo>(pid, 0) else {
+            continue;
+        };
+        let Ok(fds) = libproc::proc_pid::listpidinfo::<ListFDs>(pid, bsd_info.pbi_nfiles as _)
+        else {
+            continue;
+        };
+        for fd in fds {
+            if !matches!(ProcFDType::from(fd.proc_fdtype), ProcFDType::Socket) {
+                continue;
+            }
+            let Ok(info) = libproc::file_info::pidfdinfo::<SocketFDInfo>(pid, fd.proc_fd) else {
+                continue;
+            };
+            if seen.insert(info.psi.soi_so) {
+                sockets.push(info.psi);
+            }
+        }
+    }
+    sockets
+}
+
+/// Formats local and remote addresses of an Internet socket, in the hexadecimal format of `/proc/net/tcp`.
+fn inet_addrs(inet: &InSockInfo, family: c_int) -> (String, String) {
+    let port = |port: c_int| u16::from_be(port as u16);
+    let addr = |addr: &InSIAddr| unsafe {
+        match family {
+            libc::AF_INET6 => addr
+                .ina_6
+                .s6_addr
+                .chunks(4)
+                .map(|x| format!("{:08X}", u32::from_ne_bytes([x[0], x[1], x[2], x[3]])))
+                .collect::<String>(),
+            _ => format!("{:08X}", addr.ina_46.i46a_addr4.s_addr),
+        }
+    };
+    (
+        format!("{}:{:04X}", addr(&inet.insi_laddr), port(inet.insi_lport)),
+        format!("{}:{:04X}", addr(&inet.insi_faddr), port(inet.insi_fport)),
+    )
+}
+
+/// Converts a macOS TCP state into the one shown in `/proc/net/tcp`.
+fn tcp_state(apple: c_int) -> u8 {
+    match apple {
+        1 => TCP_LISTEN,
+        2 => 0x02, // SYN_SENT
+        3 => 0x03, // SYN_RECV
+        4 => TCP_ESTABLISHED,
+        5 => 0x08,  // CLOSE_WAIT
+        6 => 0x04,  // FIN_WAIT1
+        7 => 0x0b,  // CLOSING
+        8 => 0x09,  // LAST_ACK
+        9 => 0x05,  // FIN_WAIT2
+        10 => 0x06, // TIME_WAIT
+        _ => TCP_CLOSE,
+    }
+}
@@ -1,41 +1,53 @@
-use crate::{app, util::sysctl_read};
+use super::region;
+use crate::{
+    app,
+    filesystem::vfs::Mount,
+    task::process::Process,
+    util::{Shared, sysctl_read},
+    vfd::VfdContent,
+};
 use libproc::{bsd_info::BSDInfo, task_info::TaskInfo};
-use std::io::Write;
+use std::{io::Write, sync::Arc};
 use structures::{
+    device::DeviceNumber,
     error::LxError,
     files::{Fstab, FstabEntry},
+    fs::OpenFlags,
+    process::{NamespaceKind, RLimitable},
 };
 
 pub fn mounts(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + Clone {
     move || {
-        let mounts = app()
+        let mnt = app()
             .processes
             .get(apple_pid as _)
             .ok_or(LxError::ENOENT)?
-            .mnt
-            .mounts();
-        let mut fstab = Fstab(Vec::with_capacity(mounts.len()));
-
-        for mount in mounts {
-            let fs_type = mount
-                .filesystem
-                .statfs()
-                .ok()
-                .and_then(|x| x.f_type.name())
-                .unwrap_or("unknown")
-                .to_string();
-            fstab.0.push(FstabEntry {
-                device: String::from_utf8_lossy(&mount.source).to_string(),
-                mount_point: String::from_utf8_lossy(&mount.mountpoint.express()).to_string(),
-                fs_type,
-                options: "defaults".into(),
-                dump: 0,
-                pass: 0,
-            });
-        }
+            .mnt();
+        Ok(mnt.render(render_mounts))
+    }
+}
 
-        Ok(fstab.to_string().into_bytes())
+/// Renders mounts in the format of `/proc/[pid]/mounts`.
+fn render_mounts(mounts: &[Mount]) -> Vec<u8> {
+    let mut fstab = Fstab(Vec::with_capacity(mounts.len()));
+    for mount in mounts {
+        let fs_type = mount
+            .filesystem
+            .statfs()
+            .ok()
+            .and_then(|x| x.f_type.name())
+            .unwrap_or("unknown")
+            .to_string();
+        fstab.0.push(FstabEntry {
+            device: String::from_utf8_lossy(&mount.source).to_string(),
+            mount_point: String::from_utf8_lossy(&mount.mountpoint.express()).to_string(),
+            fs_type,
+            options: mount.options(),
+            dump: 0,
+            pass: 0,
+        });
     }
+    fstab.to_string().into_bytes()
 }
 
 pub fn comm(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + Clone {
@@ -50,20 +62,55 @@ pub fn comm(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + C
             .clone()
         {
             comm.truncate(15);
-            comm.push(0);
+            comm.push(b'\n');
             return Ok(comm);
         }
         let cmdline = argv_from_mactux_exec(apple_argv(apple_pid)?);
         let Some(arg0) = cmdline.get(0) else {
-            return Ok(vec![0]);
+            return Ok(vec![b'\n']);
         };
         let mut comm = arg0.rsplit(|x| *x == b'/').next().unwrap_or(&[]).to_vec();
         comm.truncate(15);
-        comm.push(0);
+        comm.push(b'\n');
         Ok(comm)
     }
 }
 
+pub fn set_comm(apple_pid: libc::pid_t) -> impl Fn(Vec<u8>) -> Result<usize, LxError> + Clone {
+    move |mut name| {
+        let len = name.len();
+        let thread = app().threads.get(apple_pid as _).ok_or(LxError::ESRCH)?;
+        if Shared::id(&thread.process) != Shared::id(&Process::current()) {
+            return Err(LxError::EINVAL);
+        }
+        if name.last() == Some(&b'\n') {
+            name.pop();
+        }
+        thread.set_comm(name);
+        Ok(len)
+    }
+}
+
+pub fn exe(native: libc::pid_t) -> impl Fn() -> Vec<u8> + Clone {
+    move || {
+        app()
+            .threads
+            .get(native as _)
+            .map(|x| x.process.exe())
+            .unwrap_or_default()
+    }
+}
+
+pub fn cwd(native: libc::pid_t) -> impl Fn() -> Vec<u8> + Clone {
+    move || {
+        app()
+            .threads
+            .get(native as _)
+            .map(|x| x.process.cwd())
+            .unwrap_or_else(|| vec![b'/'])
+    }
+}
+
 pub fn cmdline(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + Clone {
     move || {
         let mut cmdline = argv_from_mactux_exec(apple_argv(apple_pid)?);
@@ -78,6 +125,7 @@ pub fn cmdline(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError>
 
 pub fn environ(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + Clone {
     move || {
+        check_access(apple_pid)?;
         let mut envp = envp_from_mactux_exec(apple_argv(apple_pid)?);
         let mut data = Vec::with_capacity(envp.len() * 32);
         for entry in &mut envp {
@@ -101,7 +149,17 @@ pub fn stat(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + C
         let bsd_info = libproc::proc_pid::pidinfo::<BSDInfo>(apple_pid, apple_pid as _)
             .map_err(|_| LxError::EPERM)?;
         let ppid = bsd_info.pbi_ppid;
-        let pgid = bsd_info.pbi_pgid;
+
+        // Sessions, process groups and controlling terminals belong to native processes, so they survive `execve` and
+        // are read from the native process, like `getsid` and `getpgid` do.
+        let viewer = Process::current();
+        let to_linux = |native: libc::pid_t| viewer.pid.ntol(native).unwrap_or(0);
+        let pgid = to_linux(bsd_info.pbi_pgid as _);
+        let session = to_linux(unsafe { libc::getsid(apple_pid) });
+        let (tty_nr, tpgid) = match bsd_info.e_tdev {
+            NODEV => (0, -1),
+            tdev => (tty_nr(tdev), to_linux(bsd_info.e_tpgid as _)),
+        };
         let start_time = bsd_info.pbi_start_tvusec / 1000;
 
         let task_info = libproc::proc_pid::pidinfo::<TaskInfo>(apple_pid, apple_pid as _)
@@ -123,7 +181,7 @@ pub fn stat(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + C
 
         let mut s = Vec::new();
         write!(&mut s, "{pid} ({comm}) {state} {ppid} {pgid} ").unwrap();
-        write!(&mut s, "1 0 0 0 ").unwrap();
+        write!(&mut s, "{session} {tty_nr} {tpgid} 0 ").unwrap();
         write!(&mut s, "{min_flt} {cmin_flt} {maj_flt} {cmaj_flt} ").unwrap();
         write!(&mut s, "{utime} {stime} {cutime} {cstime} ").unwrap();
         write!(&mut s, "{priority} {nice} ").unwrap();
@@ -139,6 +197,69 @@ pub fn stat(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + C
     }
 }
 
+/// The `e_tdev` of processes without a controlling terminal.
+const NODEV: u32 = u32::MAX;
+
+/// Encodes the native device number of a terminal like the `tty_nr` field of `/proc/[pid]/stat`.
+fn tty_nr(tdev: u32) -> u64 {
+    let tdev = tdev as libc::dev_t;
+    DeviceNumber::new(libc::major(tdev) as _, libc::minor(tdev) as _).0
+}
+
+pub fn status(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + Clone {
+    move || {
+        let thread = app().threads.get(apple_pid as _).ok_or(LxError::ESRCH)?;
+        let process = &thread.process;
+        let native_pid = Shared::id(process) as libc::pid_t;
+        let viewer = Process::current();
+        let to_linux = |native| viewer.pid.ntol(native).unwrap_or(0);
+
+        let mut name = comm(apple_pid)()?;
+        name.pop();
+        let name = String::from_utf8_lossy(&name);
+
+        let bsd_info =
+            libproc::proc_pid::pidinfo::<BSDInfo>(native_pid, 0).map_err(|_| LxError::EPERM)?;
+        let state = match bsd_info.pbi_status {
+            3 => "S (sleeping)",
+            4 => "T (stopped)",
+            5 => "Z (zombie)",
+            _ => "R (running)",
+        };
+        let task_info =
+            libproc::proc_pid::pidinfo::<TaskInfo>(native_pid, 0).map_err(|_| LxError::EPERM)?;
+        let sigpending = process.rlimit(RLimitable::RLIMIT_SIGPENDING).rlim_cur;
+
+        let mut s = Vec::with_capacity(512);
+        writeln!(&mut s, "Name:\t{name}").unwrap();
+        writeln!(&mut s, "Umask:\t{:04o}", process.umask()).unwrap();
+        writeln!(&mut s, "State:\t{state}").unwrap();
+        writeln!(&mut s, "Tgid:\t{}", to_linux(native_pid)).unwrap();
+        writeln!(&mut s, "Ngid:\t0").unwrap();
+        writeln!(&mut s, "Pid:\t{}", to_linux(apple_pid)).unwrap();
+        writeln!(&mut s, "PPid:\t{}", to_linux(bsd_info.pbi_ppid as _)).unwrap();
+        writeln!(&mut s, "TracerPid:\t0").unwrap();
+        writeln!(
+            &mut s,
+            "Uid:\t{}\t{}\t{}\t{}",
+            bsd_info.pbi_ruid, bsd_info.pbi_uid, bsd_info.pbi_svuid, bsd_info.pbi_uid
+        )
+        .unwrap();
+        writeln!(
+            &mut s,
+            "Gid:\t{}\t{}\t{}\t{}",
+            bsd_info.pbi_rgid, bsd_info.pbi_gid, bsd_info.pbi_svgid, bsd_info.pbi_gid
+        )
+        .unwrap();
+        writeln!(&mut s, "VmSize:\t{} kB", task_info.pti_virtual_size / 1024).unwrap();
+        writeln!(&mut s, "VmRSS:\t{} kB", task_info.pti_resident_size / 1024).unwrap();
+        writeln!(&mut s, "Threads:\t{}", process.threads.len()).unwrap();
+        writeln!(&mut s, "SigQ:\t0/{sigpending}").unwrap();
+
+        Ok(s)
+    }
+}
+
 pub fn statm(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + Clone {
     move || {
         let mut s = Vec::with_capacity(64);
@@ -158,8 +279,84 @@ pub fn statm(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> +
     }
 }
 
-pub fn maps(_apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + Clone {
-    move || Ok(Vec::new())
+pub fn maps(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + Clone {
+    move || {
+        let regions = process_regions(apple_pid)?;
+        let mut s = Vec::with_capacity(regions.len() * 96);
+        for region in &regions {
+            region.write_maps_line(&mut s);
+        }
+        Ok(s)
+    }
+}
+
+pub fn smaps(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + Clone {
+    move || {
+        let regions = process_regions(apple_pid)?;
+        let mut s = Vec::with_capacity(regions.len() * 1024);
+        for region in &regions {
+            region.write_maps_line(&mut s);
+            region.usage().write(&mut s, true);
+            let perm = |bit, name| (region.protection & bit != 0).then_some(name);
+            let vm_flags: Vec<&str> = [
+                perm(libc::VM_PROT_READ as u32, "rd"),
+                perm(libc::VM_PROT_WRITE as u32, "wr"),
+                perm(libc::VM_PROT_EXECUTE as u32, "ex"),
+                region.is_shared().then_some("sh"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            writeln!(&mut s, "THPeligible:    0").unwrap();
+            writeln!(&mut s, "VmFlags: {} ", vm_flags.join(" ")).unwrap();
+        }
+        Ok(s)
+    }
+}
+
+pub fn smaps_rollup(apple_pid: libc::pid_t) -> impl Fn() -> Result<Vec<u8>, LxError> + Clone {
+    move || {
+        let regions = process_regions(apple_pid)?;
+        let mut usage = region::Usage::default();
+        regions.iter().for_each(|x| usage.add(&x.usage()));
+        let start = regions.first().map(|x| x.start).unwrap_or_default();
+        let end = regions.last().map(|x| x.end).unwrap_or_default();
+
+        let mut s = Vec::with_capacity(1024);
+        let mut header = Vec::with_capacity(96);
+        write!(&mut header, "{start:08x}-{end:08x} ---p 00000000 00:00 0").unwrap();
+        header.resize(73, b' ');
+        s.extend_from_slice(&header);
+        s.extend_from_slice(b"[rollup]\n");
+        usage.write(&mut s, false);
+        Ok(s)
+    }
+}
+
+/// Returns memory regions of the process that `apple_pid` belongs to.
+fn process_regions(apple_pid: libc::pid_t) -> Result<Vec<region::Region>, LxError> {
+    check_access(apple_pid)?;
+    let thread = app().threads.get(apple_pid as _).ok_or(LxError::ESRCH)?;
+    let native_pid = Shared::id(&thread.process) as libc::pid_t;
+    let mounts = thread.process.mnt().mounts();
+    Ok(region::regions(native_pid, &mounts))
+}
+
+/// Checks whether the current process may read internals of the process that `apple_pid` belongs to.
+///
+/// Like Linux, internals of a process that is not dumpable are only readable by itself and privileged processes.
+fn check_access(apple_pid: libc::pid_t) -> Result<(), LxError> {
+    let thread = app().threads.get(apple_pid as _).ok_or(LxError::ESRCH)?;
+    let current = Process::current();
+    if thread.process.dumpable() || Shared::id(&thread.process) == Shared::id(&current) {
+        return Ok(());
+    }
+    let viewer = libproc::proc_pid::pidinfo::<BSDInfo>(Shared::id(&current) as _, 0)
+        .map_err(|_| LxError::EACCES)?;
+    match viewer.pbi_uid {
+        0 => Ok(()),
+        _ => Err(LxError::EACCES),
+    }
 }
 
 fn apple_argv(apple_pid: libc::pid_t) -> Result<Vec<Vec<u8>>, LxError> {
@@ -262,3 +459,65 @@ fn envp_from_mactux_exec(apple: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
 
     envp
 }
+
+pub fn ns(
+    apple_pid: libc::pid_t,
+    kind: NamespaceKind,
+) -> impl Fn(OpenFlags) -> Result<Arc<dyn VfdContent>, LxError> + Clone {
+    move |_| {
+        let process = app().processes.get(apple_pid as _).ok_or(LxError::ESRCH)?;
+        Ok(crate::filesystem::nsfd::new(
+            kind,
+            process.namespace_id(kind),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::thread::Thread;
+    use std::{os::unix::process::CommandExt, process::Command, time::Duration};
+
+    #[test]
+    fn tty_nr_encodes_terminal_device() {
+        assert_eq!(tty_nr(libc::makedev(16, 3) as _), (16 << 8) | 3);
+    }
+
+    #[test]
+    fn session_and_process_group_survive_exec() {
+        crate::init_for_tests();
+        let mut command = Command::new("/bin/sh");
+        command.args(["-c", "exec /bin/sleep 30"]);
+        unsafe {
+            command.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+        let mut child = command.spawn().unwrap();
+        let apple_pid = child.id() as libc::pid_t;
+        let server = Thread::current();
+        crate::task::configure()
+            .apple_pid(apple_pid)
+            .exec()
+            .unwrap();
+        Thread::set_current(server);
+
+        while libproc::proc_pid::name(apple_pid).unwrap_or_default() != "sleep" {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let stat = stat(apple_pid)().unwrap();
+        let stat = String::from_utf8(stat).unwrap();
+        let (_, fields) = stat.rsplit_once(") ").unwrap();
+        let fields: Vec<&str> = fields.split(' ').collect();
+        let pid = apple_pid.to_string();
+        assert_eq!(fields[2], pid);
+        assert_eq!(fields[3], pid);
+        assert_eq!(fields[4], "0");
+        assert_eq!(fields[5], "-1");
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
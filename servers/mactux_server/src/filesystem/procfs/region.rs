@@ -0,0 +1,235 @@
+//! Memory regions of native processes, as are shown in `/proc/[pid]/maps` and `/proc/[pid]/smaps`.
+
+use super::linux_path;
+use crate::{filesystem::vfs::Mount, sysinfo::page_size};
+use libproc::proc_pid::{PIDInfo, PidInfoFlavor};
+use std::{ffi::CStr, io::Write};
+
+const SM_SHARED: u32 = 4;
+const SM_TRUESHARED: u32 = 5;
+const SM_SHARED_ALIASED: u32 = 7;
+
+/// A memory region of a native process.
+pub struct Region {
+    pub start: u64,
+    pub end: u64,
+    pub protection: u32,
+    pub offset: u64,
+    pub share_mode: u32,
+    pub user_tag: u32,
+    pub ref_count: u32,
+    pub dev: u32,
+    pub ino: u64,
+    pub path: Vec<u8>,
+
+    pub pages_resident: u64,
+    pub pages_dirtied: u64,
+    pub pages_swapped_out: u64,
+}
+impl Region {
+    /// Returns `true` if pages of the region are shared with other mappings.
+    pub fn is_shared(&self) -> bool {
+        matches!(
+            self.share_mode,
+            SM_SHARED | SM_TRUESHARED | SM_SHARED_ALIASED
+        )
+    }
+
+    /// Writes the line of the region in `/proc/[pid]/maps`.
+    pub fn write_maps_line(&self, s: &mut Vec<u8>) {
+        let perm = |bit, ch| if self.protection & bit != 0 { ch } else { '-' };
+        let r = perm(libc::VM_PROT_READ as u32, 'r');
+        let w = perm(libc::VM_PROT_WRITE as u32, 'w');
+        let x = perm(libc::VM_PROT_EXECUTE as u32, 'x');
+        let p = if self.is_shared() { 's' } else { 'p' };
+        let (major, minor) = (self.dev >> 24, self.dev & 0xffffff);
+        let mut line = Vec::with_capacity(128);
+        write!(
+            &mut line,
+            "{:08x}-{:08x} {r}{w}{x}{p} {:08x} {major:02x}:{minor:02x} {}",
+            self.start, self.end, self.offset, self.ino
+        )
+        .unwrap();
+        let name: &[u8] = match self.user_tag as i32 {
+            _ if !self.path.is_empty() => &self.path,
+            libc::VM_MEMORY_STACK => b"[stack]",
+            _ => b"",
+        };
+        if !name.is_empty() {
+            line.resize(line.len().max(73), b' ');
+            line.extend_from_slice(name);
+        }
+        s.extend_from_slice(&line);
+        s.push(b'\n');
+    }
+
+    /// Returns memory usage of the region.
+    pub fn usage(&self) -> Usage {
+        let page_size = page_size() as u64;
+        let rss = self.pages_resident * page_size;
+        let dirty = self.pages_dirtied.min(self.pages_resident) * page_size;
+        let mut usage = Usage {
+            size: self.end - self.start,
+            rss,
+            referenced: rss,
+            swap: self.pages_swapped_out * page_size,
+            ..Default::default()
+        };
+        if self.is_shared() {
+            // Pages of shared regions are accounted evenly to all mappings of the underlying object.
+            usage.pss = rss / self.ref_count.max(1) as u64;
+            usage.shared_dirty = dirty;
+            usage.shared_clean = rss - dirty;
+        } else {
+            // macOS does not tell how many pages of a private region are shared with other processes, for example after
+            // a fork, so they are all counted as private.
+            usage.pss = rss;
+            usage.private_dirty = dirty;
+            usage.private_clean = rss - dirty;
+            if self.path.is_empty() {
+                usage.anonymous = rss;
+            }
+        }
+        usage
+    }
+}
+
+/// Memory usage of one or more regions, as is shown in `/proc/[pid]/smaps`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub size: u64,
+    pub rss: u64,
+    pub pss: u64,
+    pub shared_clean: u64,
+    pub shared_dirty: u64,
+    pub private_clean: u64,
+    pub private_dirty: u64,
+    pub referenced: u64,
+    pub anonymous: u64,
+    pub swap: u64,
+}
+impl Usage {
+    /// Adds usage of another region to this one.
+    pub fn add(&mut self, other: &Self) {
+        self.size += other.size;
+        self.rss += other.rss;
+        self.pss += other.pss;
+        self.shared_clean += other.shared_clean;
+        self.shared_dirty += other.shared_dirty;
+        self.private_clean += other.private_clean;
+        self.private_dirty += other.private_dirty;
+        self.referenced += other.referenced;
+        self.anonymous += other.anonymous;
+        self.swap += other.swap;
+    }
+
+    /// Writes the usage in the format of `/proc/[pid]/smaps`. `Size` is omitted if `with_size` is `false`, like in
+    /// `/proc/[pid]/smaps_rollup`.
+    pub fn write(&self, s: &mut Vec<u8>, with_size: bool) {
+        let page_size = page_size() as u64;
+        let mut field = |name: &str, bytes: u64| {
+            writeln!(s, "{:<16}{:>8} kB", format!("{name}:"), bytes / 1024).unwrap();
+        };
+        if with_size {
+            field("Size", self.size);
+            field("KernelPageSize", page_size);
+            field("MMUPageSize", page_size);
+        }
+        field("Rss", self.rss);
+        field("Pss", self.pss);
+        field("Shared_Clean", self.shared_clean);
+        field("Shared_Dirty", self.shared_dirty);
+        field("Private_Clean", self.private_clean);
+        field("Private_Dirty", self.private_dirty);
+        field("Referenced", self.referenced);
+        field("Anonymous", self.anonymous);
+        field("LazyFree", 0);
+        field("AnonHugePages", 0);
+        field("ShmemPmdMapped", 0);
+        field("FilePmdMapped", 0);
+        field("Shared_Hugetlb", 0);
+        field("Private_Hugetlb", 0);
+        field("Swap", self.swap);
+        field("SwapPss", self.swap);
+        field("Locked", 0);
+    }
+}
+
+/// Returns all memory regions of a native process, with paths of mapped files expressed in `mounts`.
+///
+/// Regions are listed until `proc_pidinfo` fails, so that a process exiting in the middle yields the regions found so
+/// far instead of an error.
+pub fn regions(apple_pid: libc::pid_t, mounts: &[Mount]) -> Vec<Region> {
+    let mut regions = Vec::new();
+    let mut address = 0;
+    while let Ok(info) = libproc::proc_pid::pidinfo::<RegionWithPathInfo>(apple_pid, address) {
+        let region = &info.prp_prinfo;
+        let vnode = &info.prp_vip.vip_vi.vi_stat;
+        let native_path = unsafe { CStr::from_ptr(info.prp_vip.vip_path.as_ptr().cast()) };
+        let path = match native_path.is_empty() {
+            true => Vec::new(),
+            false => linux_path(native_path.to_bytes(), mounts),
+        };
+        regions.push(Region {
+            start: region.pri_address,
+            end: region.pri_address + region.pri_size,
+            protection: region.pri_protection,
+            offset: region.pri_offset,
+            share_mode: region.pri_share_mode,
+            user_tag: region.pri_user_tag,
+            ref_count: region.pri_ref_count,
+            dev: if path.is_empty() { 0 } else { vnode.vst_dev },
+            ino: if path.is_empty() { 0 } else { vnode.vst_ino },
+            path,
+            pages_resident: region.pri_pages_resident as _,
+            pages_dirtied: region.pri_pages_dirtied as _,
+            pages_swapped_out: region.pri_pages_swapped_out as _,
+        });
+        let Some(next) = region.pri_address.checked_add(region.pri_size) else {
+            break;
+        };
+        if next <= address {
+            break;
+        }
+        address = next;
+    }
+    regions
+}
+
+/// The `struct proc_regioninfo` structure from `<sys/proc_info.h>`.
+#[repr(C)]
+struct RegionInfo {
+    pri_protection: u32,
+    _pri_max_protection: u32,
+    _pri_inheritance: u32,
+    _pri_flags: u32,
+    pri_offset: u64,
+    _pri_behavior: u32,
+    _pri_user_wired_count: u32,
+    pri_user_tag: u32,
+    pri_pages_resident: u32,
+    _pri_pages_shared_now_private: u32,
+    pri_pages_swapped_out: u32,
+    pri_pages_dirtied: u32,
+    pri_ref_count: u32,
+    _pri_shadow_depth: u32,
+    pri_share_mode: u32,
+    _pri_private_pages_resident: u32,
+    _pri_shared_pages_resident: u32,
+    _pri_obj_id: u32,
+    _pri_depth: u32,
+    pri_address: u64,
+    pri_size: u64,
+}
+
+/// The `struct proc_regionwithpathinfo` structure from `<sys/proc_info.h>`.
+#[repr(C)]
+struct RegionWithPathInfo {
+    prp_prinfo: RegionInfo,
+    prp_vip: libc::vnode_info_path,
+}
+impl PIDInfo for RegionWithPathInfo {
+    fn flavor() -> PidInfoFlavor {
+        PidInfoFlavor::RegionPathInfo
+    }
+}
@@ -83,7 +83,7 @@ pub fn loadavg() -> Result<Vec<u8>, LxError> {
     let loadavg = ProcLoadavg {
         loadavg: crate::sysinfo::loadavg()?,
         proc_running: 0,
-        proc_total: 0,
+        proc_total: app().threads.len() as _,
         last_pid_running: Shared::id(&Thread::current().process()) as _,
     };
     Ok(loadavg.to_string().into_bytes())
@@ -28,11 +28,12 @@ use structures::{
     device::DeviceNumber,
     error::LxError,
     fs::{
-        AccessFlags, Dirent64, Dirent64Hdr, FileMode, FileType, FsMagic, MountFlags, OpenFlags,
-        OpenHow, OpenResolve, StatFs, StatFsFlags, Statx, StatxAttrs, StatxMask,
+        AccessFlags, AtFlags, Dirent64, Dirent64Hdr, FileMode, FileType, FsMagic, MountFlags,
+        OpenFlags, OpenHow, OpenResolve, StatFs, StatFsFlags, Statx, StatxAttrs, StatxMask,
     },
     internal::mactux_ipc::CtrlOutput,
     io::{IoctlCmd, PollEvents, VfdAvailCtrl, Whence},
+    process::NamespaceKind,
     time::Timespec,
 };
 
@@ -132,7 +133,7 @@ impl Filesystem for Tmpfs {
                         return Err(LxError::ELOOP);
                     }
                     Process::current()
-                        .mnt
+                        .mnt()
                         .locate(&symlink.solve(path))?
                         .open(how)
                 }
@@ -153,22 +154,34 @@ impl Filesystem for Tmpfs {
                 );
                 Ok(map_virtual(file.open_vfd(how.flags())?))
             }
-            Location::MidSymlink(vpath) => Process::current().mnt.locate(&vpath)?.open(how),
+            Location::MidSymlink(vpath) => Process::current().mnt().locate(&vpath)?.open(how),
         }
     }
 
-    fn access(&self, path: LPath, mode: AccessFlags) -> Result<(), LxError> {
+    fn access(&self, path: LPath, mode: AccessFlags, flags: AtFlags) -> Result<(), LxError> {
         match self.locate(path.clone())? {
             Location::Direct(_, Some(node)) => match node {
                 Node::Dir(_) => Ok(()),
+                Node::File(file) if mode.contains(AccessFlags::X_OK) => {
+                    // Ownership is not tracked per user, so like for privileged callers, any execute bit suffices.
+                    let stat = file
+                        .open_vfd(OpenFlags::O_PATH)?
+                        .stat(StatxMask::STATX_MODE)?;
+                    match stat.stx_mode.permbits() & 0o111 {
+                        0 => Err(LxError::EACCES),
+                        _ => Ok(()),
+                    }
+                }
                 Node::File(_) => Ok(()),
                 Node::Symlink(symlink) => Process::current()
-                    .mnt
+                    .mnt()
                     .locate(&symlink.solve(path))?
-                    .access(mode),
+                    .access(mode, flags),
             },
             Location::Direct(_, None) => Err(LxError::ENOENT),
-            Location::MidSymlink(vpath) => Process::current().mnt.locate(&vpath)?.access(mode),
+            Location::MidSymlink(vpath) => {
+                Process::current().mnt().locate(&vpath)?.access(mode, flags)
+            }
         }
     }
 
@@ -177,7 +190,7 @@ impl Filesystem for Tmpfs {
     }
 
     fn link(&self, src: LPath, dst: LPath) -> Result<(), LxError> {
-        let vlocation = |x| Process::current().mnt.locate(x);
+        let vlocation = |x| Process::current().mnt().locate(x);
         let src_location = self.locate(src.clone())?;
         let dst_location = self.locate(dst.clone())?;
         let src_node = match src_location {
@@ -215,12 +228,12 @@ impl Filesystem for Tmpfs {
                 );
                 Ok(())
             }
-            Location::MidSymlink(vpath) => Process::current().mnt.locate(&vpath)?.mkdir(mode),
+            Location::MidSymlink(vpath) => Process::current().mnt().locate(&vpath)?.mkdir(mode),
         }
     }
 
     fn rename(&self, src: LPath, dst: LPath) -> Result<(), LxError> {
-        let vlocation = |x| Process::current().mnt.locate(x);
+        let vlocation = |x| Process::current().mnt().locate(x);
         let src_location = self.locate(src.clone())?;
         let dst_location = self.locate(dst.clone())?;
         let src_filename = src.relative.parts.last().ok_or(LxError::EISDIR)?.clone();
@@ -256,7 +269,7 @@ impl Filesystem for Tmpfs {
                 Ok(())
             }
             Location::Direct(_, None) => Err(LxError::ENOENT),
-            Location::MidSymlink(vpath) => Process::current().mnt.locate(&vpath)?.rmdir(),
+            Location::MidSymlink(vpath) => Process::current().mnt().locate(&vpath)?.rmdir(),
         }
     }
 
@@ -271,7 +284,9 @@ impl Filesystem for Tmpfs {
                 );
                 Ok(())
             }
-            Location::MidSymlink(vpath) => Process::current().mnt.locate(&vpath)?.symlink(content),
+            Location::MidSymlink(vpath) => {
+                Process::current().mnt().locate(&vpath)?.symlink(content)
+            }
         }
     }
 
@@ -286,7 +301,7 @@ impl Filesystem for Tmpfs {
                 Ok(())
             }
             Location::Direct(_, None) => Err(LxError::ENOENT),
-            Location::MidSymlink(vpath) => Process::current().mnt.locate(&vpath)?.unlink(),
+            Location::MidSymlink(vpath) => Process::current().mnt().locate(&vpath)?.unlink(),
         }
     }
 
@@ -309,7 +324,9 @@ impl Filesystem for Tmpfs {
                 );
                 Ok(())
             }
-            Location::MidSymlink(vpath) => Process::current().mnt.locate(&vpath)?.mknod(mode, dev),
+            Location::MidSymlink(vpath) => {
+                Process::current().mnt().locate(&vpath)?.mknod(mode, dev)
+            }
         }
     }
 
@@ -338,6 +355,7 @@ impl Tmpfs {
     {
         let lpath = LPath {
             mountpoint: VPath::parse(b"/"),
+            root_depth: 0,
             relative: path.clone(),
         };
         match self.locate(lpath)? {
@@ -353,12 +371,35 @@ impl Tmpfs {
         }
     }
 
+    pub fn create_special<F>(&self, path: VPath, open: F, permbits: u16) -> Result<(), LxError>
+    where
+        F: Fn(OpenFlags) -> Result<Arc<dyn VfdContent>, LxError> + Send + Sync + 'static,
+    {
+        let lpath = LPath {
+            mountpoint: VPath::parse(b"/"),
+            root_depth: 0,
+            relative: path.clone(),
+        };
+        match self.locate(lpath)? {
+            Location::Direct(_, Some(_)) => Err(LxError::EEXIST),
+            Location::Direct(dir, None) => {
+                dir.children.insert(
+                    path.parts.last().ok_or(LxError::EEXIST)?.clone(),
+                    Node::File(Arc::new(SpecialFile::new(open, permbits))),
+                );
+                Ok(())
+            }
+            Location::MidSymlink(_) => Err(LxError::EXDEV),
+        }
+    }
+
     pub fn create_dynlink<F>(&self, path: VPath, f: F) -> Result<(), LxError>
     where
         F: Fn() -> Vec<u8> + Send + Sync + 'static,
     {
         let lpath = LPath {
             mountpoint: VPath::parse(b"/"),
+            root_depth: 0,
             relative: path.clone(),
         };
         match self.locate(lpath)? {
@@ -377,6 +418,7 @@ impl Tmpfs {
     pub fn rmdir_all(&self, path: VPath) -> Result<(), LxError> {
         let lpath = LPath {
             mountpoint: VPath::parse(b"/"),
+            root_depth: 0,
             relative: path.clone(),
         };
         match self.locate(lpath)? {
@@ -437,6 +479,10 @@ impl Stream for WrapVfdContent {
     fn poll(&self, interest: PollEvents) -> Result<PollToken, LxError> {
         self.content.poll(interest)
     }
+
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.content.set_nonblocking(nonblocking)
+    }
 }
 impl VfdContent for WrapVfdContent {
     fn stat(&self, mask: StatxMask) -> Result<Statx, LxError> {
@@ -487,6 +533,10 @@ impl VfdContent for WrapVfdContent {
     fn filesystem(&self) -> Result<Arc<dyn Filesystem>, LxError> {
         Ok(self.filesystem.clone())
     }
+
+    fn namespace(&self) -> Result<(NamespaceKind, u64), LxError> {
+        self.content.namespace()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -603,6 +653,16 @@ impl VfdContent for DirFd {
 
         Ok(statx)
     }
+
+    fn chmod(&self, mode: u16) -> Result<(), LxError> {
+        self.metadata.chmod(mode);
+        Ok(())
+    }
+
+    fn chown(&self, uid: u32, gid: u32) -> Result<(), LxError> {
+        self.metadata.chown(uid, gid);
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -670,6 +730,12 @@ impl Stream for DevFd {
     fn ioctl(&self, cmd: IoctlCmd, data: &[u8]) -> Result<CtrlOutput, LxError> {
         self.device.as_ref().ok_or(LxError::EBADF)?.ioctl(cmd, data)
     }
+
+    fn set_nonblocking(&self, nonblocking: bool) {
+        if let Some(device) = &self.device {
+            device.set_nonblocking(nonblocking);
+        }
+    }
 }
 impl VfdContent for DevFd {
     fn stat(&self, mask: StatxMask) -> Result<Statx, LxError> {
@@ -682,6 +748,16 @@ impl VfdContent for DevFd {
 
         Ok(statx)
     }
+
+    fn chmod(&self, mode: u16) -> Result<(), LxError> {
+        self.metadata.chmod(mode);
+        Ok(())
+    }
+
+    fn chown(&self, uid: u32, gid: u32) -> Result<(), LxError> {
+        self.metadata.chown(uid, gid);
+        Ok(())
+    }
 }
 
 pub trait DynFileReadFn: Fn() -> Result<Vec<u8>, LxError> + Send + Sync + 'static {}
@@ -761,6 +837,34 @@ impl<R, W> Debug for DynFile<R, W> {
     }
 }
 
+/// A file that opens to a VFD created by the given function.
+struct SpecialFile<F> {
+    metadata: Metadata,
+    open: F,
+}
+impl<F> SpecialFile<F> {
+    fn new(open: F, permbits: u16) -> Self {
+        let metadata = Metadata::new();
+        metadata.permbits.store(permbits, atomic::Ordering::Relaxed);
+        Self { metadata, open }
+    }
+}
+impl<F> File for SpecialFile<F>
+where
+    F: Fn(OpenFlags) -> Result<Arc<dyn VfdContent>, LxError> + Send + Sync + 'static,
+{
+    fn open_vfd(self: Arc<Self>, flags: OpenFlags) -> Result<Arc<dyn VfdContent>, LxError> {
+        (self.open)(flags)
+    }
+}
+impl<F> Debug for SpecialFile<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpecialFile")
+            .field("metadata", &self.metadata)
+            .finish_non_exhaustive()
+    }
+}
+
 struct Symlink {
     metadata: Metadata,
     target: Box<dyn Fn() -> Vec<u8> + Send + Sync + 'static>,
@@ -812,6 +916,11 @@ impl VfdContent for Symlink {
 
         Ok(stat)
     }
+
+    fn chown(&self, uid: u32, gid: u32) -> Result<(), LxError> {
+        self.metadata.chown(uid, gid);
+        Ok(())
+    }
 }
 impl Symlink {
     fn solve(&self, lpath: LPath) -> VPath {
@@ -891,10 +1000,7 @@ impl Metadata {
     }
 
     fn fork(&self, mode: FileMode) -> Arc<Self> {
-        let permbits = match mode.file_type() {
-            FileType::Directory => 0o777,
-            _ => 0o666,
-        };
+        let permbits = mode.permbits();
         Arc::new(Self {
             xattrs: self.xattrs.clone(),
             uid: AtomicU32::new(self.uid.load(atomic::Ordering::Relaxed)),
@@ -912,4 +1018,21 @@ impl Metadata {
         *self.atime.write().unwrap() = times[0];
         *self.mtime.write().unwrap() = times[1];
     }
+
+    fn chmod(&self, mode: u16) {
+        self.permbits
+            .store(mode & !FileMode::S_IFMT, atomic::Ordering::Relaxed);
+        *self.ctime.write().unwrap() = Timespec::now();
+    }
+
+    /// Changes owner of the node. An ID of `-1` leaves the corresponding owner unchanged.
+    fn chown(&self, uid: u32, gid: u32) {
+        if uid != u32::MAX {
+            self.uid.store(uid, atomic::Ordering::Relaxed);
+        }
+        if gid != u32::MAX {
+            self.gid.store(gid, atomic::Ordering::Relaxed);
+        }
+        *self.ctime.write().unwrap() = Timespec::now();
+    }
 }
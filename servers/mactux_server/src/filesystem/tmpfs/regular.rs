@@ -35,7 +35,7 @@ impl Stream for Reg {
     }
 
     fn write(&self, buf: &[u8], off: &mut i64) -> Result<usize, LxError> {
-        let ret = self.buf.write(buf, *off as u64);
+        let ret = self.buf.write(buf, *off as u64)?;
         *off += ret as i64;
         Ok(ret)
     }
@@ -62,9 +62,12 @@ impl VfdContent for Reg {
     }
 
     fn chmod(&self, mode: u16) -> Result<(), LxError> {
-        self.metadata
-            .permbits
-            .store(mode, std::sync::atomic::Ordering::Relaxed);
+        self.metadata.chmod(mode);
+        Ok(())
+    }
+
+    fn chown(&self, uid: u32, gid: u32) -> Result<(), LxError> {
+        self.metadata.chown(uid, gid);
         Ok(())
     }
 }
@@ -91,19 +94,70 @@ impl RegBuf {
 
     pub fn read(&self, buf: &mut [u8], off: u64) -> usize {
         let data = self.inner.read().unwrap();
-        let bytes_to_read = (buf.len() as u64).min(data.len() as u64 - off);
-        let actual_read = (bytes_to_read as usize).min(buf.len());
-        buf[..actual_read].copy_from_slice(&data[off as usize..off as usize + actual_read]);
+        if off >= data.len() as u64 {
+            return 0;
+        }
+        let off = off as usize;
+        let actual_read = buf.len().min(data.len() - off);
+        buf[..actual_read].copy_from_slice(&data[off..off + actual_read]);
         actual_read
     }
 
-    pub fn write(&self, buf: &[u8], off: u64) -> usize {
+    /// Writes `buf` at `off`, filling the gap between the old end of the file and `off` with zeros. Fails with `ENOSPC`
+    /// if memory for the grown file cannot be allocated.
+    pub fn write(&self, buf: &[u8], off: u64) -> Result<usize, LxError> {
+        let end = off
+            .checked_add(buf.len() as u64)
+            .filter(|&end| end <= i64::MAX as u64)
+            .ok_or(LxError::EFBIG)?;
+        let (off, end) = (off as usize, end as usize);
         let mut data = self.inner.write().unwrap();
-        if data.len() < buf.len() + off as usize {
-            let adding = buf.len() + off as usize - data.len();
-            data.extend(std::iter::repeat_n(0, adding));
+        if data.len() < end {
+            let additional = end - data.len();
+            data.try_reserve(additional).map_err(|_| LxError::ENOSPC)?;
+            data.resize(end, 0);
         }
-        data[off as usize..off as usize + buf.len()].copy_from_slice(&buf);
-        buf.len()
+        data[off..end].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_past_end_fills_hole_with_zeros() {
+        let buf = RegBuf::new();
+        assert_eq!(buf.write(b"head", 0), Ok(4));
+        assert_eq!(buf.write(b"tail", 1024 * 1024), Ok(4));
+        assert_eq!(buf.size(), 1024 * 1024 + 4);
+
+        let mut hole = vec![0xff; 4096];
+        assert_eq!(buf.read(&mut hole, 4), 4096);
+        assert!(hole.iter().all(|&x| x == 0));
+
+        let mut tail = [0; 8];
+        assert_eq!(buf.read(&mut tail, 1024 * 1024), 4);
+        assert_eq!(&tail[..4], b"tail");
+    }
+
+    #[test]
+    fn read_at_or_past_end_returns_nothing() {
+        let buf = RegBuf::new();
+        buf.write(b"data", 0).unwrap();
+        let mut out = [0; 4];
+        assert_eq!(buf.read(&mut out, 4), 0);
+        assert_eq!(buf.read(&mut out, 1024 * 1024), 0);
+        assert_eq!(buf.read(&mut out, u64::MAX), 0);
+    }
+
+    #[test]
+    fn write_at_huge_offset_fails() {
+        let buf = RegBuf::new();
+        assert_eq!(buf.write(b"x", u64::MAX), Err(LxError::EFBIG));
+        assert_eq!(buf.write(b"x", i64::MAX as u64), Err(LxError::EFBIG));
+        assert_eq!(buf.write(b"x", 1 << 62), Err(LxError::ENOSPC));
+        assert_eq!(buf.size(), 0);
     }
 }
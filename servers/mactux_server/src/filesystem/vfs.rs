@@ -3,14 +3,22 @@
 use crate::{app, filesystem::VPath, vfd::Vfd};
 use rustc_hash::FxHashMap;
 use std::{
+    ffi::OsStr,
     fmt::Write,
-    path::PathBuf,
-    sync::{Arc, RwLock},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, LazyLock, Mutex, RwLock, RwLockWriteGuard,
+        atomic::{self, AtomicU64},
+    },
 };
 use structures::{
     device::DeviceNumber,
     error::LxError,
-    fs::{AccessFlags, FileMode, MountFlags, OpenFlags, OpenHow, OpenResolve, StatFs, UmountFlags},
+    fs::{
+        AccessFlags, AtFlags, FileMode, FileType, MountFlags, OpenFlags, OpenHow, OpenResolve,
+        StatFs, StatxMask, UmountFlags,
+    },
 };
 
 /// Registry of all supported mountable filesystems in the kernel.
@@ -66,12 +74,52 @@ impl FsRegistry {
 /// A mount namespace.
 pub struct MountNamespace {
     mounts: RwLock<Vec<Mount>>,
+    generation: AtomicU64,
+    rendered: Mutex<Option<(u64, Vec<u8>)>>,
 }
 impl MountNamespace {
     /// Creates a new, empty mount namespace.
     pub fn new() -> Self {
         Self {
             mounts: RwLock::new(Vec::with_capacity(16)),
+            generation: AtomicU64::new(0),
+            rendered: Mutex::new(None),
+        }
+    }
+
+    /// Creates a new mount namespace with a copy of mounts in this mount namespace.
+    ///
+    /// Shared mounts in the new mount namespace are peers of the corresponding ones in this mount namespace.
+    pub fn fork(&self) -> Self {
+        Self {
+            mounts: RwLock::new(self.mounts()),
+            generation: AtomicU64::new(0),
+            rendered: Mutex::new(None),
+        }
+    }
+
+    /// Locks the mount table for writing. The generation of the mount table is bumped, so cached renderings of it are
+    /// invalidated.
+    fn mounts_mut(&self) -> RwLockWriteGuard<'_, Vec<Mount>> {
+        let mounts = self.mounts.write().unwrap();
+        self.generation.fetch_add(1, atomic::Ordering::Relaxed);
+        mounts
+    }
+
+    /// Returns the mount table rendered by `render`, like what `/proc/[pid]/mounts` shows.
+    ///
+    /// The rendering is cached, and `render` is only called if the mount table has changed since it was last rendered.
+    pub fn render(&self, render: impl FnOnce(&[Mount]) -> Vec<u8>) -> Vec<u8> {
+        let mounts = self.mounts.read().unwrap();
+        let generation = self.generation.load(atomic::Ordering::Relaxed);
+        let mut rendered = self.rendered.lock().unwrap();
+        match &*rendered {
+            Some((cached, content)) if *cached == generation => content.clone(),
+            _ => {
+                let content = render(&mounts);
+                *rendered = Some((generation, content.clone()));
+                content
+            }
         }
     }
 
@@ -85,36 +133,220 @@ impl MountNamespace {
         data: &[u8],
     ) -> Result<(), LxError> {
         let target = target.clearize()?;
+        if flags.contains(MountFlags::MS_REMOUNT) {
+            return self.remount(&target, flags, data);
+        }
+        if flags.intersects(MountFlags::PROPAGATION) {
+            return self.set_propagation(&target, flags);
+        }
+
         let is_root = target.parts.is_empty();
-        let path_exists = crate::util::test_path(
-            self,
-            &target,
-            OpenHow {
-                flags: OpenFlags::O_PATH.bits() as _,
-                mode: 0,
-                resolve: OpenResolve::RESOLVE_NO_SYMLINKS,
-            },
-        );
-        if !path_exists && !is_root {
+        if !self.exists(&target) && !is_root {
             return Err(LxError::ENOENT);
         }
 
-        let filesystem = app().filesystems.mount(fs, source, flags, data)?;
-
         let mut mountpoint = target.clone();
         mountpoint.slash_suffix = false;
 
+        if flags.contains(MountFlags::MS_MOVE) {
+            return self.move_mount(&VPath::parse(source), mountpoint);
+        }
+        if flags.contains(MountFlags::MS_BIND) {
+            return self.bind(&VPath::parse(source), mountpoint, flags);
+        }
+
+        let filesystem = app().filesystems.mount(fs, source, flags, data)?;
+
         let mount = Mount {
             source: source.to_vec(),
             mountpoint,
+            mounted: MountRef::new(&filesystem),
             filesystem,
-            flags,
+            root: VPath::parse(b"/"),
+            flags: flags & MountFlags::PER_MOUNT,
+            data: data.to_vec(),
+            propagation: Propagation::Private,
         };
-        self.mounts.write().unwrap().push(mount);
+        self.attach(vec![mount]);
+
+        Ok(())
+    }
+
+    /// Grafts the subtree at `source` to `mountpoint`. If `MS_REC` is specified, submounts of `source` are grafted,
+    /// too.
+    fn bind(&self, source: &VPath, mountpoint: VPath, flags: MountFlags) -> Result<(), LxError> {
+        let source = source.clearize()?;
+        if !self.exists(&source) {
+            return Err(LxError::ENOENT);
+        }
+
+        let mounts = self.mounts.read().unwrap();
+        let parent = covering(&mounts, &source).ok_or(LxError::ENOENT)?;
+        if parent.propagation == Propagation::Unbindable {
+            return Err(LxError::EINVAL);
+        }
+        let mut root = parent.root.clone();
+        root.parts
+            .extend_from_slice(&source.parts[parent.mountpoint.parts.len()..]);
+
+        let mut binds = vec![Mount {
+            source: parent.source.clone(),
+            mountpoint: mountpoint.clone(),
+            filesystem: parent.filesystem.clone(),
+            mounted: MountRef::new(&parent.filesystem),
+            root,
+            flags: parent.flags,
+            data: parent.data.clone(),
+            propagation: parent.propagation.of_bind(),
+        }];
+        if flags.contains(MountFlags::MS_REC) {
+            for mount in mounts.iter() {
+                if mount.mountpoint.parts.len() > source.parts.len()
+                    && mount.mountpoint.parts.starts_with(&source.parts)
+                    && mount.propagation != Propagation::Unbindable
+                {
+                    let mut submount = mount.clone();
+                    submount.mountpoint = mountpoint.clone();
+                    submount
+                        .mountpoint
+                        .parts
+                        .extend_from_slice(&mount.mountpoint.parts[source.parts.len()..]);
+                    submount.propagation = mount.propagation.of_bind();
+                    binds.push(submount);
+                }
+            }
+        }
+        drop(mounts);
+        self.attach(binds);
 
         Ok(())
     }
 
+    /// Relocates the topmost mount at `source`, along with its submounts, to `mountpoint`.
+    fn move_mount(&self, source: &VPath, mountpoint: VPath) -> Result<(), LxError> {
+        let source = source.clearize()?;
+        if source.parts.is_empty() {
+            return Err(LxError::EINVAL);
+        }
+        if mountpoint.parts.starts_with(&source.parts) {
+            return Err(LxError::ELOOP);
+        }
+
+        let mut mounts = self.mounts_mut();
+        let index = mounts
+            .iter()
+            .rposition(|x| x.mountpoint.parts == source.parts)
+            .ok_or(LxError::EINVAL)?;
+        let mut moved = Vec::new();
+        let mut i = index;
+        while i < mounts.len() {
+            if mounts[i].mountpoint.parts.starts_with(&source.parts) {
+                moved.push(mounts.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        for mount in moved.iter_mut() {
+            let mut new_mountpoint = mountpoint.clone();
+            new_mountpoint
+                .parts
+                .extend_from_slice(&mount.mountpoint.parts[source.parts.len()..]);
+            mount.mountpoint = new_mountpoint;
+        }
+        mounts.append(&mut moved);
+
+        Ok(())
+    }
+
+    /// Changes flags of the topmost mount at `target`. If `MS_BIND` is specified, filesystem-specific data is kept.
+    fn remount(&self, target: &VPath, flags: MountFlags, data: &[u8]) -> Result<(), LxError> {
+        let mut mounts = self.mounts_mut();
+        let mount = mounts
+            .iter_mut()
+            .rev()
+            .find(|x| x.mountpoint.parts == target.parts)
+            .ok_or(LxError::EINVAL)?;
+        mount.flags = flags & MountFlags::PER_MOUNT;
+        if !flags.contains(MountFlags::MS_BIND) {
+            mount.data = data.to_vec();
+        }
+        Ok(())
+    }
+
+    /// Changes propagation type of the topmost mount at `target`. If `MS_REC` is specified, propagation types of its
+    /// submounts are changed, too.
+    fn set_propagation(&self, target: &VPath, flags: MountFlags) -> Result<(), LxError> {
+        let propagation_flags = flags & MountFlags::PROPAGATION;
+        if propagation_flags.bits().count_ones() != 1 {
+            return Err(LxError::EINVAL);
+        }
+        let recursive = flags.contains(MountFlags::MS_REC);
+
+        let mut mounts = self.mounts_mut();
+        let mut found = false;
+        for mount in mounts.iter_mut().rev() {
+            let matched = match recursive {
+                true => mount.mountpoint.parts.starts_with(&target.parts),
+                false => mount.mountpoint.parts == target.parts,
+            };
+            if !matched {
+                continue;
+            }
+            found = true;
+            mount.propagation = mount.propagation.change(propagation_flags);
+            if !recursive {
+                break;
+            }
+        }
+
+        match found {
+            true => Ok(()),
+            false => Err(LxError::EINVAL),
+        }
+    }
+
+    /// Adds new mounts to the mount namespace, propagating them to peers of their parents.
+    fn attach(&self, new_mounts: Vec<Mount>) {
+        let mut events = Vec::new();
+        let mut mounts = self.mounts_mut();
+        for mut mount in new_mounts {
+            let parent = covering(&mounts, &mount.mountpoint);
+            if let Some((parent, Propagation::Shared(group))) = parent.map(|x| (x, x.propagation)) {
+                if mount.propagation == Propagation::Private {
+                    mount.propagation = Propagation::new_shared();
+                }
+                let mut fs_path = parent.root.parts.clone();
+                fs_path.extend_from_slice(&mount.mountpoint.parts[parent.mountpoint.parts.len()..]);
+                events.push(PropagationEvent {
+                    group,
+                    parent_fs: parent.filesystem.clone(),
+                    parent_mountpoint: parent.mountpoint.clone(),
+                    fs_path,
+                    mount: mount.clone(),
+                });
+            }
+            mounts.push(mount);
+        }
+        drop(mounts);
+
+        for event in events {
+            event.propagate(self);
+        }
+    }
+
+    /// Returns `true` if the given path exists in the mount namespace.
+    fn exists(&self, path: &VPath) -> bool {
+        crate::util::test_path(
+            self,
+            path,
+            OpenHow {
+                flags: OpenFlags::O_PATH.bits() as _,
+                mode: 0,
+                resolve: OpenResolve::RESOLVE_NO_SYMLINKS,
+            },
+        )
+    }
+
     /// Unmounts a filesystem.
     pub fn umount(&self, path: &VPath, _flags: UmountFlags) -> Result<(), LxError> {
         let submount_busy = |p: &VPath, m: &Mount| {
@@ -125,9 +357,9 @@ impl MountNamespace {
         let path = path.clearize()?;
         let mut nelem = None;
 
-        let mut mounts = self.mounts.write().unwrap();
+        let mut mounts = self.mounts_mut();
 
-        for (n, mount) in mounts.iter().rev().enumerate() {
+        for (n, mount) in mounts.iter().enumerate().rev() {
             if submount_busy(&path, mount) {
                 return Err(LxError::EBUSY);
             }
@@ -139,7 +371,10 @@ impl MountNamespace {
 
         match nelem {
             Some(i) => {
-                if Arc::strong_count(&mounts[i].filesystem) > 1 {
+                // Mounts in other mount namespaces, like the ones copied by `unshare(CLONE_NEWNS)`, do not make the
+                // filesystem busy.
+                let filesystem = &mounts[i].filesystem;
+                if Arc::strong_count(filesystem) > MountRef::count(filesystem) {
                     return Err(LxError::EBUSY);
                 }
                 mounts.remove(i);
@@ -153,35 +388,137 @@ impl MountNamespace {
     pub fn locate(&self, full_path: &VPath) -> Result<Location, LxError> {
         let full_path = full_path.clearize()?;
         let mounts = self.mounts.read().unwrap();
-        for mount in mounts.iter().rev() {
-            if full_path.parts.len() < mount.mountpoint.parts.len() {
-                continue;
-            }
+        let mount = covering(&mounts, &full_path).ok_or(LxError::ENOENT)?;
+        let mut parts = mount.root.parts.clone();
+        parts.extend_from_slice(&full_path.parts[mount.mountpoint.parts.len()..]);
+        let relative = VPath {
+            slash_prefix: true,
+            slash_suffix: full_path.slash_suffix && !parts.is_empty(),
+            parts,
+        };
+        let lpath = LPath {
+            mountpoint: mount.mountpoint.clone(),
+            root_depth: mount.root.parts.len(),
+            relative,
+        };
+        Ok(Location {
+            filesystem: mount.filesystem.clone(),
+            path: lpath,
+            mount_flags: mount.flags,
+        })
+    }
 
-            if full_path.parts[..mount.mountpoint.parts.len()] == mount.mountpoint.parts {
-                let parts = full_path.parts[mount.mountpoint.parts.len()..].to_vec();
-                let relative = VPath {
-                    slash_prefix: true,
-                    slash_suffix: full_path.slash_suffix && !parts.is_empty(),
-                    parts,
+    /// Lists all mounts in the VFS tree.
+    pub fn mounts(&self) -> Vec<Mount> {
+        self.mounts.read().unwrap().clone()
+    }
+}
+
+/// Returns the topmost mount that covers `path`.
+fn covering<'a>(mounts: &'a [Mount], path: &VPath) -> Option<&'a Mount> {
+    mounts.iter().rev().find(|mount| {
+        path.parts.len() >= mount.mountpoint.parts.len()
+            && path.parts[..mount.mountpoint.parts.len()] == mount.mountpoint.parts
+    })
+}
+
+/// A mount event that should be propagated to peers of the parent mount.
+struct PropagationEvent {
+    group: u64,
+    parent_fs: Arc<dyn Filesystem>,
+    parent_mountpoint: VPath,
+    fs_path: Vec<Vec<u8>>,
+    mount: Mount,
+}
+impl PropagationEvent {
+    /// Replicates the mount under all mounts that receive events from the peer group, except the parent in `origin`.
+    fn propagate(self, origin: &MountNamespace) {
+        for ns in app().namespaces.mount.list() {
+            let is_origin = std::ptr::eq(&**ns, origin);
+            let mut mounts = ns.mounts_mut();
+            let mut copies = Vec::new();
+            for peer in mounts.iter() {
+                if peer.propagation.receives_from() != Some(self.group)
+                    || !Arc::ptr_eq(&peer.filesystem, &self.parent_fs)
+                    || (is_origin && peer.mountpoint.parts == self.parent_mountpoint.parts)
+                {
+                    continue;
+                }
+                let Some(rest) = self.fs_path.strip_prefix(peer.root.parts.as_slice()) else {
+                    continue;
                 };
-                let lpath = LPath {
-                    mountpoint: mount.mountpoint.clone(),
-                    relative,
+                let mut copy = self.mount.clone();
+                copy.mountpoint = peer.mountpoint.clone();
+                copy.mountpoint.parts.extend_from_slice(rest);
+                copy.propagation = match (peer.propagation, self.mount.propagation) {
+                    (Propagation::Slave(_), Propagation::Shared(group)) => {
+                        Propagation::Slave(group)
+                    }
+                    (Propagation::Slave(_), _) => Propagation::Private,
+                    (_, propagation) => propagation,
                 };
-                return Ok(Location {
-                    filesystem: mount.filesystem.clone(),
-                    path: lpath,
-                    mount_flags: mount.flags,
-                });
+                copies.push(copy);
             }
+            mounts.append(&mut copies);
         }
-        Err(LxError::ENOENT)
     }
+}
 
-    /// Lists all mounts in the VFS tree.
-    pub fn mounts(&self) -> Vec<Mount> {
-        self.mounts.read().unwrap().clone()
+/// Propagation type of a mount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagation {
+    /// Mount events do not propagate into or out of the mount.
+    Private,
+
+    /// Mount events propagate among members of the peer group.
+    Shared(u64),
+
+    /// Mount events propagate into the mount from the master peer group, but not out of it.
+    Slave(u64),
+
+    /// Same as [`Propagation::Private`], but the mount cannot be bind mounted.
+    Unbindable,
+}
+impl Propagation {
+    /// Creates a shared propagation type with a new peer group.
+    fn new_shared() -> Self {
+        static NEXT_GROUP: AtomicU64 = AtomicU64::new(1);
+        Self::Shared(NEXT_GROUP.fetch_add(1, atomic::Ordering::Relaxed))
+    }
+
+    /// Returns the peer group that the mount receives mount events from.
+    fn receives_from(self) -> Option<u64> {
+        match self {
+            Self::Shared(group) | Self::Slave(group) => Some(group),
+            Self::Private | Self::Unbindable => None,
+        }
+    }
+
+    /// Returns propagation type of a bind mount whose source has this propagation type.
+    fn of_bind(self) -> Self {
+        match self {
+            Self::Shared(_) | Self::Slave(_) => self,
+            Self::Private | Self::Unbindable => Self::Private,
+        }
+    }
+
+    /// Returns the propagation type after applying one of the propagation flags.
+    fn change(self, flags: MountFlags) -> Self {
+        if flags.contains(MountFlags::MS_SHARED) {
+            match self {
+                Self::Shared(_) => self,
+                _ => Self::new_shared(),
+            }
+        } else if flags.contains(MountFlags::MS_SLAVE) {
+            match self {
+                Self::Shared(group) | Self::Slave(group) => Self::Slave(group),
+                Self::Private | Self::Unbindable => Self::Private,
+            }
+        } else if flags.contains(MountFlags::MS_PRIVATE) {
+            Self::Private
+        } else {
+            Self::Unbindable
+        }
     }
 }
 
@@ -191,7 +528,67 @@ pub struct Mount {
     pub source: Vec<u8>,
     pub mountpoint: VPath,
     pub filesystem: Arc<dyn Filesystem>,
+
+    /// Path in the filesystem that is mounted at the mountpoint, which is not `/` for bind mounts.
+    pub root: VPath,
     pub flags: MountFlags,
+    pub data: Vec<u8>,
+    pub propagation: Propagation,
+    mounted: MountRef,
+}
+impl Mount {
+    /// Returns the mount option string, as is represented in `/proc/mounts`.
+    pub fn options(&self) -> String {
+        let mut options = self.flags.options();
+        if !self.data.is_empty() {
+            options.push(',');
+            options.push_str(&String::from_utf8_lossy(&self.data));
+        }
+        options
+    }
+}
+
+/// Number of [`Mount`]s that refer to each filesystem, keyed by its address, in all mount namespaces.
+static MOUNT_REFS: LazyLock<Mutex<FxHashMap<usize, usize>>> =
+    LazyLock::new(|| Mutex::new(FxHashMap::default()));
+
+/// Counts a [`Mount`] as a user of its filesystem, so that uses by mount tables are told from other uses, like open
+/// files, which make the filesystem busy.
+struct MountRef(usize);
+impl MountRef {
+    fn new(filesystem: &Arc<dyn Filesystem>) -> Self {
+        let addr = Arc::as_ptr(filesystem).cast::<()>().addr();
+        *MOUNT_REFS.lock().unwrap().entry(addr).or_default() += 1;
+        Self(addr)
+    }
+
+    /// Returns number of mounts that refer to `filesystem`.
+    fn count(filesystem: &Arc<dyn Filesystem>) -> usize {
+        let addr = Arc::as_ptr(filesystem).cast::<()>().addr();
+        MOUNT_REFS
+            .lock()
+            .unwrap()
+            .get(&addr)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+impl Clone for MountRef {
+    fn clone(&self) -> Self {
+        *MOUNT_REFS.lock().unwrap().entry(self.0).or_default() += 1;
+        Self(self.0)
+    }
+}
+impl Drop for MountRef {
+    fn drop(&mut self) {
+        let mut refs = MOUNT_REFS.lock().unwrap();
+        if let Some(count) = refs.get_mut(&self.0) {
+            *count -= 1;
+            if *count == 0 {
+                refs.remove(&self.0);
+            }
+        }
+    }
 }
 
 /// A path containing both the located mountpoint [`VPath`] and the relative [`VPath`].
@@ -200,13 +597,19 @@ pub struct Mount {
 #[derive(Debug, Clone)]
 pub struct LPath {
     pub mountpoint: VPath,
+
+    /// Number of leading parts of `relative` that belong to root of the mount.
+    pub root_depth: usize,
+
     pub relative: VPath,
 }
 impl LPath {
     /// Expands the located path to a full [`VPath`] including the mountpoint and the relative path.
     pub fn expand(mut self) -> VPath {
         self.mountpoint.slash_suffix = self.relative.slash_suffix;
-        self.mountpoint.parts.append(&mut self.relative.parts);
+        self.mountpoint
+            .parts
+            .extend(self.relative.parts.into_iter().skip(self.root_depth));
         self.mountpoint
     }
 }
@@ -223,6 +626,19 @@ impl Location {
             self.will_write()?;
         }
 
+        // Like on Linux, `O_CREAT` on a read-only mount only fails if the file would be created.
+        if how.flags().contains(OpenFlags::O_CREAT) && self.will_write().is_err() {
+            match self
+                .filesystem
+                .access(self.path.clone(), AccessFlags::F_OK, AtFlags::empty())
+            {
+                Ok(()) if how.flags().contains(OpenFlags::O_EXCL) => return Err(LxError::EEXIST),
+                Ok(()) => (),
+                Err(LxError::ENOENT) => return Err(LxError::EROFS),
+                Err(err) => return Err(err),
+            }
+        }
+
         self.filesystem.open(self.path.clone(), how).inspect(|x| {
             if let NewlyOpen::Virtual(vfd) = x {
                 // We allow the filesystem driver to set the original path ahead of this.
@@ -230,16 +646,27 @@ impl Location {
                 // And for symlinks, we just make the `orig_path` be the finally solved one, matching the Linux behavior
                 // better.
                 _ = vfd.set_orig_path(self.path.expand().express());
+
+                // Bind mounts share nodes with their source, so this is the only place to tell whether metadata of
+                // the node may be modified through this VFD.
+                if self.mount_flags.contains(MountFlags::MS_RDONLY) {
+                    vfd.set_read_only_mount();
+                }
             }
         })
     }
 
-    pub fn access(self, mode: AccessFlags) -> Result<(), LxError> {
+    /// Checks whether the calling process may access the location, like `faccessat2`. Permissions are checked against
+    /// its effective IDs with `AT_EACCESS` in `flags`, or its real IDs otherwise.
+    pub fn access(self, mode: AccessFlags, flags: AtFlags) -> Result<(), LxError> {
         if mode.contains(AccessFlags::W_OK) {
             self.will_write()?;
         }
+        if mode.contains(AccessFlags::X_OK) {
+            self.will_exec()?;
+        }
 
-        self.filesystem.access(self.path, mode)
+        self.filesystem.access(self.path, mode, flags)
     }
 
     pub fn unlink(self) -> Result<(), LxError> {
@@ -297,12 +724,50 @@ impl Location {
             Ok(())
         }
     }
+
+    /// Fails with [`LxError::EACCES`] if the location is a regular file on a `noexec` mount.
+    ///
+    /// Directories stay searchable, and symbolic links are checked against the mount of their targets when solved.
+    fn will_exec(&self) -> Result<(), LxError> {
+        if !self.mount_flags.contains(MountFlags::MS_NOEXEC) {
+            return Ok(());
+        }
+        match self.file_type()? {
+            FileType::RegularFile => Err(LxError::EACCES),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns type of the file at the location, without following symbolic links.
+    fn file_type(&self) -> Result<FileType, LxError> {
+        let how = OpenHow {
+            flags: (OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW).bits() as _,
+            mode: 0,
+            resolve: OpenResolve::RESOLVE_NO_SYMLINKS,
+        };
+        match self.filesystem.clone().open(self.path.clone(), how)? {
+            NewlyOpen::Native(path) => {
+                let file_type =
+                    std::fs::symlink_metadata(Path::new(OsStr::from_bytes(&path)))?.file_type();
+                Ok(if file_type.is_file() {
+                    FileType::RegularFile
+                } else if file_type.is_dir() {
+                    FileType::Directory
+                } else if file_type.is_symlink() {
+                    FileType::Symlink
+                } else {
+                    FileType::Unknown
+                })
+            }
+            NewlyOpen::Virtual(vfd) => Ok(vfd.stat(StatxMask::STATX_TYPE)?.stx_mode.file_type()),
+        }
+    }
 }
 
 /// Content of a filesystem.
 pub trait Filesystem: Send + Sync {
     fn open(self: Arc<Self>, path: LPath, how: OpenHow) -> Result<NewlyOpen, LxError>;
-    fn access(&self, path: LPath, mode: AccessFlags) -> Result<(), LxError>;
+    fn access(&self, path: LPath, mode: AccessFlags, flags: AtFlags) -> Result<(), LxError>;
     fn unlink(&self, path: LPath) -> Result<(), LxError>;
     fn rmdir(&self, path: LPath) -> Result<(), LxError>;
     fn symlink(&self, dst: LPath, content: &[u8]) -> Result<(), LxError>;
@@ -335,3 +800,86 @@ pub enum NewlyOpen {
     Native(Vec<u8>),
     Virtual(Vfd),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmpfs_namespace() -> MountNamespace {
+        crate::init_for_tests();
+        let mnt = MountNamespace::new();
+        mnt.mount(
+            b"tmpfs",
+            &VPath::parse(b"/"),
+            "tmpfs",
+            MountFlags::empty(),
+            &[],
+        )
+        .unwrap();
+        mnt
+    }
+
+    #[test]
+    fn copied_mounts_do_not_make_filesystem_busy() {
+        let mnt = tmpfs_namespace();
+        let forked = mnt.fork();
+        mnt.umount(&VPath::parse(b"/"), UmountFlags::empty())
+            .unwrap();
+        forked
+            .umount(&VPath::parse(b"/"), UmountFlags::empty())
+            .unwrap();
+    }
+
+    #[test]
+    fn used_filesystem_is_busy() {
+        let mnt = tmpfs_namespace();
+        let forked = mnt.fork();
+        let location = forked.locate(&VPath::parse(b"/")).unwrap();
+        assert_eq!(
+            mnt.umount(&VPath::parse(b"/"), UmountFlags::empty()),
+            Err(LxError::EBUSY)
+        );
+        drop(location);
+        mnt.umount(&VPath::parse(b"/"), UmountFlags::empty())
+            .unwrap();
+    }
+
+    #[test]
+    fn creating_open_on_read_only_mount_fails_only_when_creating() {
+        let mnt = tmpfs_namespace();
+        let open = |path: &[u8], flags: OpenFlags| {
+            let how = OpenHow {
+                flags: flags.bits() as _,
+                mode: 0o644,
+                resolve: OpenResolve::empty(),
+            };
+            mnt.locate(&VPath::parse(path))?.open(how).map(|_| ())
+        };
+        open(b"/file", OpenFlags::O_CREAT | OpenFlags::O_WRONLY).unwrap();
+        mnt.mount(
+            b"",
+            &VPath::parse(b"/"),
+            "",
+            MountFlags::MS_REMOUNT | MountFlags::MS_RDONLY,
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            open(b"/file", OpenFlags::O_CREAT | OpenFlags::O_RDONLY),
+            Ok(())
+        );
+        assert_eq!(
+            open(b"/file", OpenFlags::O_CREAT | OpenFlags::O_EXCL),
+            Err(LxError::EEXIST)
+        );
+        assert_eq!(
+            open(b"/file", OpenFlags::O_CREAT | OpenFlags::O_WRONLY),
+            Err(LxError::EROFS)
+        );
+        assert_eq!(
+            open(b"/missing", OpenFlags::O_CREAT | OpenFlags::O_RDONLY),
+            Err(LxError::EROFS)
+        );
+    }
+}
@@ -2,7 +2,7 @@ use crate::{
     app,
     filesystem::{VPath, vfs::NewlyOpen},
     syslog::WriteLogRequest,
-    task::{process::Process, thread::Thread},
+    task::{native_credentials, process::Process, thread::Thread},
     util::Shared,
     vfd::Vfd,
 };
@@ -11,82 +11,104 @@ use structures::{
     device::DeviceNumber,
     error::LxError,
     fs::{
-        AccessFlags, Dirent64, FileMode, OpenFlags, OpenHow, StatFs, Statx, StatxMask, UmountFlags,
+        AccessFlags, AtFlags, Dirent64, FileMode, MountFlags, OpenFlags, OpenHow, StatFs, Statx,
+        StatxMask, UmountFlags,
     },
     io::{FcntlCmd, IoctlCmd, VfdAvailCtrl, Whence},
-    misc::{LogLevel, SysInfo},
+    misc::{HOST_NAME_MAX, LogLevel, SysInfo},
+    process::{CloneFlags, PidFdFlags, RLimit64, RLimitable},
+    thread::TID_MIN,
     time::Timespec,
 };
 use structures::{
-    internal::mactux_ipc::{CtrlOutput, NetworkNames, Response},
+    internal::{
+        mactux_ipc::{CtrlOutput, NetworkNames, Response},
+        shared_buffer::{SHARED_BUFFER_LEN, SharedBuffer},
+    },
     io::EventFdFlags,
 };
 
-pub fn open(path: Vec<u8>, how: OpenHow) -> Result<NewlyOpen, LxError> {
-    Process::current()
-        .mnt
-        .locate(&VPath::parse(&path))?
-        .open(how)
+pub fn open(path: Vec<u8>, mut how: OpenHow) -> Result<NewlyOpen, LxError> {
+    let process = Process::current();
+    let flags = how.flags();
+    if flags.contains(OpenFlags::O_CREAT) || flags.contains(OpenFlags::O_TMPFILE) {
+        how.mode &= !(process.umask() as u64);
+    }
+    process.mnt().locate(&VPath::parse(&path))?.open(how)
 }
 
-pub fn access(path: Vec<u8>, flags: AccessFlags) -> Result<(), LxError> {
+pub fn access(path: Vec<u8>, mode: AccessFlags, flags: AtFlags) -> Result<(), LxError> {
     Process::current()
-        .mnt
+        .mnt()
         .locate(&VPath::parse(&path))?
-        .access(flags)
+        .access(mode, flags)
 }
 
 pub fn unlink(path: Vec<u8>) -> Result<(), LxError> {
     Process::current()
-        .mnt
+        .mnt()
         .locate(&VPath::parse(&path))?
         .unlink()
 }
 
 pub fn rmdir(path: Vec<u8>) -> Result<(), LxError> {
-    Process::current().mnt.locate(&VPath::parse(&path))?.rmdir()
+    Process::current()
+        .mnt()
+        .locate(&VPath::parse(&path))?
+        .rmdir()
 }
 
 pub fn mkdir(path: Vec<u8>, mode: FileMode) -> Result<(), LxError> {
-    Process::current()
-        .mnt
-        .locate(&VPath::parse(&path))?
-        .mkdir(mode)
+    let process = Process::current();
+    let mode = FileMode(mode.0 & !process.umask());
+    process.mnt().locate(&VPath::parse(&path))?.mkdir(mode)
 }
 
 pub fn mknod(path: Vec<u8>, mode: FileMode, dev: DeviceNumber) -> Result<(), LxError> {
-    Process::current()
-        .mnt
-        .locate(&VPath::parse(&path))?
-        .mknod(mode, dev)
+    let process = Process::current();
+    let mode = FileMode(mode.0 & !process.umask());
+    process.mnt().locate(&VPath::parse(&path))?.mknod(mode, dev)
 }
 
 pub fn symlink(src: &[u8], dst: &[u8]) -> Result<(), LxError> {
     Process::current()
-        .mnt
+        .mnt()
         .locate(&VPath::parse(dst))?
         .symlink(src)
 }
 
 pub fn link(src: &[u8], dst: &[u8]) -> Result<(), LxError> {
-    let dst = Process::current().mnt.locate(&VPath::parse(dst))?;
-    let src = Process::current().mnt.locate(&VPath::parse(src))?;
+    let dst = Process::current().mnt().locate(&VPath::parse(dst))?;
+    let src = Process::current().mnt().locate(&VPath::parse(src))?;
     dst.link_to(src)
 }
 
 pub fn rename(src: &[u8], dst: &[u8]) -> Result<(), LxError> {
-    let dst = Process::current().mnt.locate(&VPath::parse(dst))?;
-    let src = Process::current().mnt.locate(&VPath::parse(src))?;
+    let dst = Process::current().mnt().locate(&VPath::parse(dst))?;
+    let src = Process::current().mnt().locate(&VPath::parse(src))?;
     dst.rename_to(src)
 }
 
+pub fn mount(
+    source: &[u8],
+    target: &[u8],
+    fs: &str,
+    flags: MountFlags,
+    data: &[u8],
+) -> Result<(), LxError> {
+    Process::current()
+        .mnt()
+        .mount(source, &VPath::parse(target), fs, flags, data)
+}
+
 pub fn umount(path: &[u8], flags: UmountFlags) -> Result<(), LxError> {
-    Process::current().mnt.umount(&VPath::parse(path), flags)
+    Process::current().mnt().umount(&VPath::parse(path), flags)
 }
 
 pub fn get_sock_path(path: Vec<u8>, create: bool) -> Result<Response, LxError> {
     if path.get(0).copied() == Some(0) {
-        let abs = &Process::current().net.abs;
+        let net = Process::current().net();
+        let abs = &net.abs;
         let id = if create {
             abs.sock_by_id(abs.create_named(&path[1..])?)
         } else {
@@ -97,7 +119,7 @@ pub fn get_sock_path(path: Vec<u8>, create: bool) -> Result<Response, LxError> {
         ));
     }
     Process::current()
-        .mnt
+        .mnt()
         .locate(&VPath::parse(&path))?
         .get_sock_path(create)
         .map(|path| Response::NativePath(path.into_os_string().into_encoded_bytes()))
@@ -107,8 +129,35 @@ pub fn get_thread_id() -> Response {
     Response::Pid(Thread::current().tid())
 }
 
+/// Makes the thread `tid` of the calling process current for this connection. The thread created for the connection is
+/// released, while the thread `tid` stays alive as long as the connection it is reattached from is not yet closed.
+pub fn reattach(tid: i32) -> Result<(), LxError> {
+    let current = Thread::current();
+    let thread = app().threads.get(tid as _).ok_or(LxError::ESRCH)?;
+    if Shared::id(&thread.process) != Shared::id(&current.process) {
+        return Err(LxError::EPERM);
+    }
+    Thread::set_current(thread);
+    Ok(())
+}
+
+/// Returns PID of the process that the thread `tid` belongs to. Both IDs are in the caller's PID namespace.
+pub fn get_thread_group(tid: i32) -> Result<Response, LxError> {
+    let current = Process::current();
+    let native = current.pid.lton(tid).map_err(|_| LxError::ESRCH)?;
+    let thread = app().threads.get(native as _).ok_or(LxError::ESRCH)?;
+    let tgid = current
+        .pid
+        .ntol(Shared::id(&thread.process) as _)
+        .map_err(|_| LxError::ESRCH)?;
+    Ok(Response::Pid(tgid))
+}
+
+/// Duplicates a VFD, like `dup`. As on Linux, the duplicate does not inherit the close-on-exec flag.
 pub fn vfd_dup(vfd: u64) -> Result<Arc<Vfd>, LxError> {
-    Ok(Process::current().vfd.get(vfd).ok_or(LxError::EBADF)?.dup())
+    let new_vfd = Process::current().vfd.get(vfd).ok_or(LxError::EBADF)?.dup();
+    new_vfd.set_cloexec(false);
+    Ok(new_vfd)
 }
 
 pub fn vfd_read(vfd: u64, bufsiz: usize) -> Result<Response, LxError> {
@@ -133,6 +182,24 @@ pub fn vfd_pread(vfd: u64, bufsiz: usize, off: i64) -> Result<Response, LxError>
     Ok(Response::Bytes(buf))
 }
 
+pub fn vfd_read_shared(vfd: u64, buf: &mut [u8]) -> Result<Response, LxError> {
+    Process::current()
+        .vfd
+        .get(vfd)
+        .ok_or(LxError::EBADF)?
+        .read(buf)
+        .map(Response::Length)
+}
+
+pub fn vfd_pread_shared(vfd: u64, buf: &mut [u8], off: i64) -> Result<Response, LxError> {
+    Process::current()
+        .vfd
+        .get(vfd)
+        .ok_or(LxError::EBADF)?
+        .pread(buf, off)
+        .map(Response::Length)
+}
+
 pub fn vfd_write(vfd: u64, buf: &[u8]) -> Result<Response, LxError> {
     Process::current()
         .vfd
@@ -151,6 +218,15 @@ pub fn vfd_pwrite(vfd: u64, buf: &[u8], off: i64) -> Result<Response, LxError> {
         .map(Response::Length)
 }
 
+pub fn vfd_append(vfd: u64, buf: &[u8], update_offset: bool) -> Result<Response, LxError> {
+    Process::current()
+        .vfd
+        .get(vfd)
+        .ok_or(LxError::EBADF)?
+        .append(buf, update_offset)
+        .map(Response::Length)
+}
+
 pub fn vfd_lseek(vfd: u64, whence: Whence, off: i64) -> Result<Response, LxError> {
     Process::current()
         .vfd
@@ -244,7 +320,7 @@ pub fn vfd_listxattr(vfd: u64) -> Result<Response, LxError> {
 }
 
 pub fn get_network_names() -> Result<NetworkNames, LxError> {
-    let uts = &Process::current().uts;
+    let uts = Process::current().uts();
     Ok(NetworkNames {
         nodename: uts.nodename(),
         domainname: uts.domainname(),
@@ -252,7 +328,10 @@ pub fn get_network_names() -> Result<NetworkNames, LxError> {
 }
 
 pub fn set_network_names(set: NetworkNames) -> Result<(), LxError> {
-    let uts = &Process::current().uts;
+    if set.nodename.len() > HOST_NAME_MAX || set.domainname.len() > HOST_NAME_MAX {
+        return Err(LxError::EINVAL);
+    }
+    let uts = Process::current().uts();
     uts.set_nodename(set.nodename)?;
     uts.set_domainname(set.domainname)?;
     Ok(())
@@ -310,8 +389,24 @@ pub fn after_exec() {
     Process::current().on_exec();
 }
 
+pub fn set_exe(path: Vec<u8>) -> Result<(), LxError> {
+    if !path.starts_with(b"/") {
+        return Err(LxError::EINVAL);
+    }
+    Process::current().set_exe(path);
+    Ok(())
+}
+
+pub fn set_cwd(path: Vec<u8>) -> Result<(), LxError> {
+    if !path.starts_with(b"/") {
+        return Err(LxError::EINVAL);
+    }
+    Process::current().set_cwd(path);
+    Ok(())
+}
+
 pub fn set_thread_name(name: Vec<u8>) {
-    *Thread::current().comm.write().unwrap() = Some(name);
+    Thread::current().set_comm(name);
 }
 
 pub fn get_thread_name() -> Result<Response, LxError> {
@@ -338,16 +433,17 @@ pub fn write_syslog(level: LogLevel, mut content: Vec<u8>) {
     app().syslog.write(WriteLogRequest { level, content });
 }
 
-pub fn set_mnt_namespace(_ns: u64) -> Result<(), LxError> {
-    Ok(())
+pub fn set_namespace(vfd: u64, nstype: CloneFlags) -> Result<(), LxError> {
+    let process = Process::current();
+    let (kind, id) = process.vfd.get(vfd).ok_or(LxError::EBADF)?.namespace()?;
+    if !nstype.is_empty() && nstype.bits() != kind.clone_flag().bits() {
+        return Err(LxError::EINVAL);
+    }
+    process.set_namespace(kind, id)
 }
 
-pub fn set_pid_namespace(_ns: u64) -> Result<(), LxError> {
-    Ok(())
-}
-
-pub fn set_uts_namespace(_ns: u64) -> Result<(), LxError> {
-    Ok(())
+pub fn unshare(flags: CloneFlags) -> Result<(), LxError> {
+    Process::current().unshare(flags)
 }
 
 pub fn eventfd(count: u64, flags: EventFdFlags) -> Result<Vfd, LxError> {
@@ -358,6 +454,103 @@ pub fn invalidfd(flags: OpenFlags) -> Result<Vfd, LxError> {
     crate::filesystem::invalidfd::open(flags)
 }
 
+pub fn pidfd_open(pid: i32, flags: PidFdFlags) -> Result<Vfd, LxError> {
+    let native = Process::current()
+        .pid
+        .lton(pid)
+        .map_err(|_| LxError::ESRCH)?;
+    if native >= TID_MIN && !flags.contains(PidFdFlags::PIDFD_THREAD) {
+        return Err(LxError::EINVAL);
+    }
+    crate::filesystem::pidfd::open(native, flags)
+}
+
+pub fn pidfd_get_pid(vfd: u64) -> Result<Response, LxError> {
+    let native = Process::current()
+        .vfd
+        .get(vfd)
+        .ok_or(LxError::EBADF)?
+        .pidfd()?;
+    let thread = app().threads.get(native as _).ok_or(LxError::ESRCH)?;
+    Ok(Response::Pid(Shared::id(&thread.process) as _))
+}
+
+pub fn get_umask() -> Response {
+    Response::Umask(Process::current().umask())
+}
+
+pub fn set_umask(mask: u16) -> Response {
+    Response::Umask(Process::current().set_umask(mask))
+}
+
+/// Returns whether the process with the given native PID, or the current process if it is `0`, is dumpable.
+pub fn get_dumpable(native: i32) -> Result<Response, LxError> {
+    let process = match native {
+        0 => Process::current(),
+        _ => app()
+            .threads
+            .get(native as _)
+            .ok_or(LxError::ESRCH)?
+            .process(),
+    };
+    Ok(Response::Dumpable(process.dumpable()))
+}
+
+pub fn set_dumpable(dumpable: bool) -> Response {
+    Process::current().set_dumpable(dumpable);
+    Response::Nothing
+}
+
+pub fn get_rlimit(pid: i32, res: RLimitable) -> Result<Response, LxError> {
+    let (target, _) = rlimit_target(pid)?;
+    Ok(Response::RLimit(target.rlimit(res)))
+}
+
+pub fn set_rlimit(pid: i32, res: RLimitable, limit: RLimit64) -> Result<Response, LxError> {
+    if limit.rlim_cur > limit.rlim_max {
+        return Err(LxError::EINVAL);
+    }
+    let (target, privileged) = rlimit_target(pid)?;
+    let old = target.set_rlimit(res, limit, |old| {
+        if limit.rlim_max > old.rlim_max && !privileged {
+            return Err(LxError::EPERM);
+        }
+        Ok(())
+    })?;
+    Ok(Response::RLimit(old))
+}
+
+pub fn sync_rlimits(native: Vec<(RLimitable, RLimit64)>) -> Response {
+    Response::RLimits(Process::current().sync_rlimits(native))
+}
+
+/// Returns the process whose resource limits are referred to by `pid`, which is in the caller's PID namespace, and
+/// whether the caller is privileged.
+///
+/// Like Linux, limits of another process may only be accessed if its real, effective and saved user and group IDs
+/// all match the real ones of the caller, or if the caller has effective UID 0.
+fn rlimit_target(pid: i32) -> Result<(Shared<Process>, bool), LxError> {
+    let current = Process::current();
+    let caller = native_credentials(Shared::id(&current) as _)?;
+    let privileged = caller.pbi_uid == 0;
+    if pid == 0 {
+        return Ok((current, privileged));
+    }
+    let native = current.pid.lton(pid).map_err(|_| LxError::ESRCH)?;
+    let thread = app().threads.get(native as _).ok_or(LxError::ESRCH)?;
+    let target = thread.process();
+    if !privileged && Shared::id(&target) != Shared::id(&current) {
+        let target = native_credentials(Shared::id(&target) as _)?;
+        let uids = [target.pbi_ruid, target.pbi_uid, target.pbi_svuid];
+        let gids = [target.pbi_rgid, target.pbi_gid, target.pbi_svgid];
+        if uids.iter().any(|x| *x != caller.pbi_ruid) || gids.iter().any(|x| *x != caller.pbi_rgid)
+        {
+            return Err(LxError::EPERM);
+        }
+    }
+    Ok((target, privileged))
+}
+
 pub fn pid_linux_to_native(linux: i32) -> Result<Response, LxError> {
     Process::current().pid.lton(linux).map(Response::Pid)
 }
@@ -366,6 +559,13 @@ pub fn pid_native_to_linux(native: i32) -> Result<Response, LxError> {
     Process::current().pid.ntol(native).map(Response::Pid)
 }
 
+pub fn map_shared_buffer(name: &[u8], len: usize) -> Result<SharedBuffer, LxError> {
+    if len > SHARED_BUFFER_LEN {
+        return Err(LxError::EINVAL);
+    }
+    Ok(SharedBuffer::open(name, len)?)
+}
+
 pub trait IntoResponse {
     fn into_response(self) -> Response;
 }
@@ -451,3 +651,99 @@ impl IntoResponse for StatFs {
         Response::StatFs(Box::new(self))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    /// Registers a native child process of the test as a MacTux process, returning its PID.
+    fn spawn_child() -> (std::process::Child, i32) {
+        crate::init_for_tests();
+        let child = Command::new("/bin/sleep").arg("30").spawn().unwrap();
+        let apple_pid = child.id() as i32;
+        let server = Thread::current();
+        crate::task::configure()
+            .apple_pid(apple_pid)
+            .exec()
+            .unwrap();
+        Thread::set_current(server);
+        (child, apple_pid)
+    }
+
+    #[test]
+    fn nofile_limit_of_child_is_enforced() {
+        let (mut sleeper, pid) = spawn_child();
+        let limit = RLimit64 {
+            rlim_cur: 64,
+            rlim_max: 128,
+        };
+        set_rlimit(pid, RLimitable::RLIMIT_NOFILE, limit).unwrap();
+        let Response::RLimit(recorded) = get_rlimit(pid, RLimitable::RLIMIT_NOFILE).unwrap() else {
+            panic!("unexpected response");
+        };
+        assert_eq!(recorded, limit);
+
+        // The child applies recorded limits natively when it starts running a program.
+        let child = app().threads.get(pid as _).unwrap().process();
+        let native = vec![(RLimitable::RLIMIT_NOFILE, RLimit64::UNLIMITED)];
+        assert!(
+            child
+                .sync_rlimits(native)
+                .contains(&(RLimitable::RLIMIT_NOFILE, limit))
+        );
+
+        if unsafe { libc::geteuid() } != 0 {
+            let raised = RLimit64 {
+                rlim_cur: 64,
+                rlim_max: 256,
+            };
+            assert!(matches!(
+                set_rlimit(pid, RLimitable::RLIMIT_NOFILE, raised),
+                Err(LxError::EPERM)
+            ));
+        }
+        assert!(matches!(
+            set_rlimit(
+                pid,
+                RLimitable::RLIMIT_NOFILE,
+                RLimit64 {
+                    rlim_cur: 256,
+                    rlim_max: 128,
+                }
+            ),
+            Err(LxError::EINVAL)
+        ));
+
+        sleeper.kill().unwrap();
+        sleeper.wait().unwrap();
+    }
+
+    #[test]
+    fn limits_of_other_users_are_protected() {
+        crate::init_for_tests();
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+
+        // launchd runs as root.
+        let server = Thread::current();
+        crate::task::configure().apple_pid(1).exec().unwrap();
+        Thread::set_current(server);
+        assert!(matches!(
+            get_rlimit(1, RLimitable::RLIMIT_NOFILE),
+            Err(LxError::EPERM)
+        ));
+        assert!(matches!(
+            set_rlimit(
+                1,
+                RLimitable::RLIMIT_NOFILE,
+                RLimit64 {
+                    rlim_cur: 1,
+                    rlim_max: 1
+                }
+            ),
+            Err(LxError::EPERM)
+        ));
+    }
+}
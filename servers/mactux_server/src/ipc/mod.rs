@@ -10,22 +10,28 @@ use std::{
     io::{Read, Write},
     os::unix::net::UnixStream,
 };
-use structures::internal::mactux_ipc::{HandshakeRequest, HandshakeResponse};
+use structures::internal::mactux_ipc::{Capabilities, HandshakeRequest, HandshakeResponse};
 
 #[derive(Debug)]
-pub struct RegChannel(UnixStream);
+pub struct RegChannel(UnixStream, Capabilities);
 impl RegChannel {
     pub fn new(st: UnixStream) -> anyhow::Result<Self> {
-        let this = Self(st);
+        let mut this = Self(st, Capabilities::empty());
         let mut buf = Vec::new();
         let handshake_req = this.recv::<HandshakeRequest>(&mut buf)?;
-        if handshake_req != HandshakeRequest::new() {
-            return Err(anyhow::anyhow!("invalid handshake request"));
-        }
-        this.send(&HandshakeResponse::new(), &mut buf)?;
+        let handshake_resp = HandshakeResponse::new(&handshake_req);
+        // Clients of other versions are answered as well, so that they can report the mismatch themselves.
+        this.send(&handshake_resp, &mut buf)?;
+        handshake_req.check()?;
+        this.1 = handshake_resp.capabilities;
         Ok(this)
     }
 
+    /// Returns the capabilities enabled for this channel at handshake.
+    pub fn capabilities(&self) -> Capabilities {
+        self.1
+    }
+
     pub fn send_bytes(&self, data: &[u8]) -> anyhow::Result<()> {
         let len = (data.len() as u64).to_le_bytes();
         (&self.0).write_all(&len)?;
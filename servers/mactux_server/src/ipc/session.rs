@@ -5,7 +5,13 @@ use crate::{
 };
 use anyhow::anyhow;
 use std::os::unix::net::UnixStream;
-use structures::internal::mactux_ipc::Request;
+use structures::{
+    error::LxError,
+    internal::{
+        mactux_ipc::{Capabilities, Request, Response},
+        shared_buffer::SharedBuffer,
+    },
+};
 
 #[derive(Debug)]
 pub struct RegSession(RegChannel);
@@ -37,64 +43,33 @@ impl RegSession {
 
     pub fn run(self) -> anyhow::Result<()> {
         let mut buf = Vec::with_capacity(512);
+        let mut shared = None;
+        let capabilities = self.0.capabilities();
 
         while let Ok(req) = self.0.recv::<Request>(&mut buf) {
             let resp = match req {
-                Request::SetMntNamespace(ns) => set_mnt_namespace(ns).into_response(),
-                Request::SetPidNamespace(ns) => set_pid_namespace(ns).into_response(),
-                Request::SetUtsNamespace(ns) => set_uts_namespace(ns).into_response(),
-                Request::Open(path, how) => open(path, how).into_response(),
-                Request::Access(path, flags) => access(path, flags).into_response(),
-                Request::Unlink(path) => unlink(path).into_response(),
-                Request::Rmdir(path) => rmdir(path).into_response(),
-                Request::Mkdir(path, mode) => mkdir(path, mode).into_response(),
-                Request::Mknod(path, mode, dev) => mknod(path, mode, dev).into_response(),
-                Request::Symlink(src, dst) => symlink(&src, &dst).into_response(),
-                Request::Link(src, dst) => link(&src, &dst).into_response(),
-                Request::Rename(src, dst) => rename(&src, &dst).into_response(),
-                Request::GetSockPath(path, create) => get_sock_path(path, create).into_response(),
-                Request::Umount(path, flags) => umount(&path, flags).into_response(),
-                Request::VfdDup(vfd) => vfd_dup(vfd).into_response(),
-                Request::VfdStat(vfd, mask) => vfd_stat(vfd, mask).into_response(),
-                Request::VfdRead(vfd, bufsiz) => vfd_read(vfd, bufsiz).into_response(),
-                Request::VfdPread(vfd, off, bufsiz) => vfd_pread(vfd, bufsiz, off).into_response(),
-                Request::VfdWrite(vfd, buf) => vfd_write(vfd, &buf).into_response(),
-                Request::VfdPwrite(vfd, off, buf) => vfd_pwrite(vfd, &buf, off).into_response(),
-                Request::VfdSeek(vfd, whence, off) => vfd_lseek(vfd, whence, off).into_response(),
-                Request::VfdGetdent(vfd) => vfd_getdent(vfd).into_response(),
-                Request::VfdReadlink(vfd) => vfd_readlink(vfd).into_response(),
-                Request::VfdTruncate(vfd, len) => vfd_truncate(vfd, len).into_response(),
-                Request::VfdChown(vfd, uid, gid) => vfd_chown(vfd, uid, gid).into_response(),
-                Request::VfdChmod(vfd, mode) => vfd_chmod(vfd, mode).into_response(),
-                Request::VfdClose(vfd) => vfd_close(vfd).into_response(),
-                Request::VfdSync(vfd) => vfd_sync(vfd).into_response(),
-                Request::VfdOrigPath(vfd) => vfd_orig_path(vfd).into_response(),
-                Request::VfdIoctlQuery(vfd, cmd) => vfd_ioctl_query(vfd, cmd).into_response(),
-                Request::VfdIoctl(vfd, cmd, data) => vfd_ioctl(vfd, cmd, &data).into_response(),
-                Request::VfdFcntl(vfd, cmd, data) => vfd_fcntl(vfd, cmd, &data).into_response(),
-                Request::VfdUtimeNs(vfd, times) => vfd_utimens(vfd, times).into_response(),
-                Request::VfdStatFs(vfd) => vfd_statfs(vfd).into_response(),
-                Request::VfdListXattr(vfd) => vfd_listxattr(vfd).into_response(),
-                Request::GetNetworkNames => get_network_names().into_response(),
-                Request::SetNetworkNames(set) => set_network_names(set).into_response(),
-                Request::SysInfo => sysinfo().into_response(),
-                Request::AfterFork(npid) => after_fork(npid).into_response(),
-                Request::AfterExec => after_exec().into_response(),
-                Request::ReadSyslogAll(bufsiz) => read_syslog_all(bufsiz).into_response(),
-                Request::WriteSyslog(level, content) => {
-                    write_syslog(level, content).into_response()
+                Request::MapSharedBuffer(..) | Request::Batch(_)
+                    if !capabilities.contains(required_capabilities(&req)) =>
+                {
+                    Response::Error(LxError::EOPNOTSUPP)
+                }
+                Request::MapSharedBuffer(name, len) => match map_shared_buffer(&name, len) {
+                    Ok(buffer) => {
+                        shared = Some(buffer);
+                        Response::Nothing
+                    }
+                    Err(err) => Response::Error(err),
+                },
+                Request::AfterExec => {
+                    after_exec();
+                    Response::Capabilities(capabilities)
                 }
-                Request::GetThreadName => get_thread_name().into_response(),
-                Request::SetThreadName(name) => set_thread_name(name).into_response(),
-                Request::GetThreadId => get_thread_id().into_response(),
-                Request::PidLinuxToNative(pid) => pid_linux_to_native(pid).into_response(),
-                Request::PidNativeToLinux(pid) => pid_native_to_linux(pid).into_response(),
-                Request::EventFd(count, flags) => eventfd(count, flags).into_response(),
-                Request::InvalidFd(flags) => invalidfd(flags).into_response(),
+                Request::Batch(reqs) => handle_batch(reqs, |x| handle(x, shared.as_ref())),
                 Request::CallInterruptible(req) => {
                     InterruptibleSession::new(self.0.0, req).run();
                     return Ok(());
                 }
+                req => handle(req, shared.as_ref()),
             };
             self.0.send(&resp, &mut buf)?;
         }
@@ -102,3 +77,160 @@ impl RegSession {
         Ok(())
     }
 }
+
+/// Handles the requests of a batch in order with `handle`. Nested batches and interruptible requests cannot be batched,
+/// so they are answered with `EINVAL`.
+fn handle_batch(reqs: Vec<Request>, mut handle: impl FnMut(Request) -> Response) -> Response {
+    Response::Batch(
+        reqs.into_iter()
+            .map(|req| match req {
+                Request::Batch(_) | Request::CallInterruptible(_) => {
+                    Response::Error(LxError::EINVAL)
+                }
+                req => handle(req),
+            })
+            .collect(),
+    )
+}
+
+/// Handles a request that is neither a batch nor interruptible, with the shared buffer of the connection, if any.
+fn handle(req: Request, shared: Option<&SharedBuffer>) -> Response {
+    match req {
+        Request::SetNamespace(vfd, nstype) => set_namespace(vfd, nstype).into_response(),
+        Request::Unshare(flags) => unshare(flags).into_response(),
+        Request::Open(path, how) => open(path, how).into_response(),
+        Request::Access(path, mode, flags) => access(path, mode, flags).into_response(),
+        Request::Unlink(path) => unlink(path).into_response(),
+        Request::Rmdir(path) => rmdir(path).into_response(),
+        Request::Mkdir(path, mode) => mkdir(path, mode).into_response(),
+        Request::Mknod(path, mode, dev) => mknod(path, mode, dev).into_response(),
+        Request::Symlink(src, dst) => symlink(&src, &dst).into_response(),
+        Request::Link(src, dst) => link(&src, &dst).into_response(),
+        Request::Rename(src, dst) => rename(&src, &dst).into_response(),
+        Request::GetSockPath(path, create) => get_sock_path(path, create).into_response(),
+        Request::Mount(source, target, fs, flags, data) => {
+            mount(&source, &target, &fs, flags, &data).into_response()
+        }
+        Request::Umount(path, flags) => umount(&path, flags).into_response(),
+        Request::VfdDup(vfd) => vfd_dup(vfd).into_response(),
+        Request::VfdStat(vfd, mask) => vfd_stat(vfd, mask).into_response(),
+        Request::VfdRead(vfd, bufsiz) => vfd_read(vfd, bufsiz).into_response(),
+        Request::VfdPread(vfd, off, bufsiz) => vfd_pread(vfd, bufsiz, off).into_response(),
+        Request::VfdWrite(vfd, buf) => vfd_write(vfd, &buf).into_response(),
+        Request::VfdPwrite(vfd, off, buf) => vfd_pwrite(vfd, &buf, off).into_response(),
+        Request::VfdAppend(vfd, buf, update_offset) => {
+            vfd_append(vfd, &buf, update_offset).into_response()
+        }
+        Request::VfdReadShared(vfd, len) => shared_buf(shared, len)
+            .and_then(|buf| vfd_read_shared(vfd, buf))
+            .into_response(),
+        Request::VfdPreadShared(vfd, off, len) => shared_buf(shared, len)
+            .and_then(|buf| vfd_pread_shared(vfd, buf, off))
+            .into_response(),
+        Request::VfdWriteShared(vfd, len) => shared_buf(shared, len)
+            .and_then(|buf| vfd_write(vfd, buf))
+            .into_response(),
+        Request::VfdPwriteShared(vfd, off, len) => shared_buf(shared, len)
+            .and_then(|buf| vfd_pwrite(vfd, buf, off))
+            .into_response(),
+        Request::VfdSeek(vfd, whence, off) => vfd_lseek(vfd, whence, off).into_response(),
+        Request::VfdGetdent(vfd) => vfd_getdent(vfd).into_response(),
+        Request::VfdReadlink(vfd) => vfd_readlink(vfd).into_response(),
+        Request::VfdTruncate(vfd, len) => vfd_truncate(vfd, len).into_response(),
+        Request::VfdChown(vfd, uid, gid) => vfd_chown(vfd, uid, gid).into_response(),
+        Request::VfdChmod(vfd, mode) => vfd_chmod(vfd, mode).into_response(),
+        Request::VfdClose(vfd) => vfd_close(vfd).into_response(),
+        Request::VfdSync(vfd) => vfd_sync(vfd).into_response(),
+        Request::VfdOrigPath(vfd) => vfd_orig_path(vfd).into_response(),
+        Request::VfdIoctlQuery(vfd, cmd) => vfd_ioctl_query(vfd, cmd).into_response(),
+        Request::VfdIoctl(vfd, cmd, data) => vfd_ioctl(vfd, cmd, &data).into_response(),
+        Request::VfdFcntl(vfd, cmd, data) => vfd_fcntl(vfd, cmd, &data).into_response(),
+        Request::VfdUtimeNs(vfd, times) => vfd_utimens(vfd, times).into_response(),
+        Request::VfdStatFs(vfd) => vfd_statfs(vfd).into_response(),
+        Request::VfdListXattr(vfd) => vfd_listxattr(vfd).into_response(),
+        Request::GetNetworkNames => get_network_names().into_response(),
+        Request::SetNetworkNames(set) => set_network_names(set).into_response(),
+        Request::SysInfo => sysinfo().into_response(),
+        Request::AfterFork(npid) => after_fork(npid).into_response(),
+        Request::SetExe(path) => set_exe(path).into_response(),
+        Request::SetCwd(path) => set_cwd(path).into_response(),
+        Request::ReadSyslogAll(bufsiz) => read_syslog_all(bufsiz).into_response(),
+        Request::WriteSyslog(level, content) => write_syslog(level, content).into_response(),
+        Request::GetThreadName => get_thread_name().into_response(),
+        Request::SetThreadName(name) => set_thread_name(name).into_response(),
+        Request::GetThreadId => get_thread_id().into_response(),
+        Request::Reattach(tid) => reattach(tid).into_response(),
+        Request::GetThreadGroup(tid) => get_thread_group(tid).into_response(),
+        Request::GetUmask => get_umask(),
+        Request::SetUmask(mask) => set_umask(mask),
+        Request::GetDumpable(npid) => get_dumpable(npid).into_response(),
+        Request::SetDumpable(dumpable) => set_dumpable(dumpable),
+        Request::GetRLimit(pid, res) => get_rlimit(pid, res).into_response(),
+        Request::SetRLimit(pid, res, limit) => set_rlimit(pid, res, limit).into_response(),
+        Request::SyncRLimits(native) => sync_rlimits(native),
+        Request::PidLinuxToNative(pid) => pid_linux_to_native(pid).into_response(),
+        Request::PidNativeToLinux(pid) => pid_native_to_linux(pid).into_response(),
+        Request::EventFd(count, flags) => eventfd(count, flags).into_response(),
+        Request::InvalidFd(flags) => invalidfd(flags).into_response(),
+        Request::PidFdOpen(pid, flags) => pidfd_open(pid, flags).into_response(),
+        Request::PidFdGetPid(vfd) => pidfd_get_pid(vfd).into_response(),
+        Request::MapSharedBuffer(..)
+        | Request::AfterExec
+        | Request::Batch(_)
+        | Request::CallInterruptible(_) => Response::Error(LxError::EINVAL),
+    }
+}
+
+/// Returns the capabilities that must be enabled for a connection to make `req`.
+fn required_capabilities(req: &Request) -> Capabilities {
+    match req {
+        Request::MapSharedBuffer(..) => Capabilities::SHARED_BUFFER,
+        Request::Batch(_) => Capabilities::BATCH,
+        _ => Capabilities::empty(),
+    }
+}
+
+/// Returns the first `len` bytes of the shared buffer of the connection.
+fn shared_buf(shared: Option<&SharedBuffer>, len: usize) -> Result<&mut [u8], LxError> {
+    shared
+        .and_then(|x| unsafe { x.get(len) })
+        .ok_or(LxError::EINVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structures::internal::mactux_ipc::InterruptibleRequest;
+
+    #[test]
+    fn batch_responses_are_in_order() {
+        let reqs = (0..16).map(Request::PidNativeToLinux).collect();
+        let resp = handle_batch(reqs, |req| match req {
+            Request::PidNativeToLinux(pid) => Response::Pid(pid),
+            _ => unreachable!(),
+        });
+        let Response::Batch(resps) = resp else {
+            panic!("expected a batch response");
+        };
+        assert_eq!(resps.len(), 16);
+        for (i, resp) in resps.into_iter().enumerate() {
+            assert!(matches!(resp, Response::Pid(pid) if pid == i as i32));
+        }
+    }
+
+    #[test]
+    fn nested_batch_is_rejected() {
+        let reqs = vec![
+            Request::Batch(vec![Request::GetThreadId]),
+            Request::CallInterruptible(InterruptibleRequest::VfdPoll(Vec::new(), None)),
+            Request::VfdReadShared(0, 1),
+        ];
+        let Response::Batch(resps) = handle_batch(reqs, |req| handle(req, None)) else {
+            panic!("expected a batch response");
+        };
+        assert_eq!(resps.len(), 3);
+        for resp in resps {
+            assert!(matches!(resp, Response::Error(LxError::EINVAL)));
+        }
+    }
+}
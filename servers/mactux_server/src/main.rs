@@ -25,10 +25,8 @@ use crate::{
     syslog::Syslog,
     task::{InitPid, PidNamespace, process::Process, thread::Thread},
     util::{ReclaimRegistry, Shared},
-    vfd::VfdTable,
 };
 use anyhow::{Context, anyhow};
-use dashmap::DashSet;
 use std::{path::PathBuf, sync::OnceLock};
 use structures::{fs::MountFlags, misc::LogLevel};
 
@@ -178,6 +176,10 @@ struct Cli {
 
     #[arg(long)]
     record_loglevel: Option<u32>,
+
+    /// Do not provide the host's timezone as `/etc/localtime` of the root filesystem.
+    #[arg(long)]
+    no_timezone_bridge: bool,
 }
 
 fn main() {
@@ -196,7 +198,7 @@ fn main() {
         app().syslog.config.console_loglevel.store(LogLevel(level));
     }
 
-    if let Err(err) = init_env() {
+    if let Err(err) = init_env(&cli) {
         log::error!("cannot initialize Linux environment: {err}");
         std::process::exit(1);
     }
@@ -220,14 +222,12 @@ fn init_app(cli: &Cli) -> anyhow::Result<()> {
 
     let server_proc: Shared<Process> = app().processes.intervene(
         std::process::id() as _,
-        Process {
-            mnt: app().namespaces.init_mnt(),
-            uts: app().namespaces.init_uts(),
-            pid: app().namespaces.init_pid(),
-            net: app().namespaces.init_net(),
-            vfd: VfdTable::new(),
-            threads: DashSet::default(),
-        },
+        Process::new(
+            app().namespaces.init_mnt(),
+            app().namespaces.init_uts(),
+            app().namespaces.init_pid(),
+            app().namespaces.init_net(),
+        ),
     );
     let server_thrd = Thread::builder().process(server_proc).is_main().build()?;
     _ = app().server_thread.set(server_thrd);
@@ -239,12 +239,47 @@ fn init_app(cli: &Cli) -> anyhow::Result<()> {
 ///
 /// We tend to initialize the most thing inside the Linux environment, however, we need some initializations
 /// to ensure a simple Linux program could be executed. Thus, we put them here.
-fn init_env() -> anyhow::Result<()> {
+fn init_env(cli: &Cli) -> anyhow::Result<()> {
     app().devices.discover();
+    if !cli.no_timezone_bridge
+        && let Err(err) = init_localtime()
+    {
+        log::warn!("failed to bridge the host's timezone: {err}");
+    }
     init_mounts()?;
     Ok(())
 }
 
+/// Provides the host's timezone as `/etc/localtime` of the root filesystem, so that programs show the host's local
+/// time without timezone data of their own.
+///
+/// A `/etc/localtime` provided by the guest is respected. The bridged file is recognized by the copy kept in the
+/// working directory, and is refreshed on each start, so that it follows changes of the host's timezone.
+fn init_localtime() -> anyhow::Result<()> {
+    let localtime = app().work_dir.rootfs().join("etc/localtime");
+    let bridged = app().work_dir.bridged_localtime();
+    match std::fs::symlink_metadata(&localtime) {
+        Ok(meta)
+            if meta.is_symlink()
+                || std::fs::read(&localtime).ok() != std::fs::read(&bridged).ok() =>
+        {
+            return Ok(());
+        }
+        Ok(_) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    let host = std::fs::read("/etc/localtime").context("failed to read the host's timezone")?;
+    if let Some(parent) = localtime.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    _ = std::fs::remove_file(&localtime);
+    std::fs::write(&localtime, &host)?;
+    std::fs::write(&bridged, &host)?;
+    Ok(())
+}
+
 /// Initializes mounts listed in `/etc/fstab`.
 fn init_mounts() -> anyhow::Result<()> {
     let fstab = app().work_dir.rootfs().join("etc/fstab");
@@ -261,12 +296,13 @@ fn init_mounts() -> anyhow::Result<()> {
         &[],
     )?;
     for entry in fstab.0 {
+        let (flags, data) = MountFlags::parse_options(&entry.options);
         let mount_result = init_mnt.mount(
             entry.device.as_bytes(),
             &VPath::parse(entry.mount_point.as_bytes()),
             &entry.fs_type,
-            MountFlags::empty(),
-            &[],
+            flags,
+            data.as_bytes(),
         );
         if let Err(err) = mount_result {
             log::warn!(
@@ -276,7 +312,6 @@ fn init_mounts() -> anyhow::Result<()> {
                 err
             );
         }
-        // TODO Support mount flags
     }
     Ok(())
 }
@@ -285,3 +320,20 @@ fn init_mounts() -> anyhow::Result<()> {
 fn app() -> &'static App {
     APP.get().unwrap()
 }
+
+/// Initializes the global application state in a temporary working directory, for tests that need it.
+#[cfg(test)]
+fn init_for_tests() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        let cli = Cli {
+            work_dir: Some(
+                std::env::temp_dir().join(format!("mactux-test-{}", std::process::id())),
+            ),
+            console_loglevel: None,
+            record_loglevel: None,
+            no_timezone_bridge: true,
+        };
+        init_app(&cli).expect("cannot initialize application for tests");
+    });
+}
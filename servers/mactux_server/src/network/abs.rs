@@ -1,6 +1,10 @@
+use rustc_hash::FxHashMap;
 use std::{
-    path::PathBuf,
-    sync::atomic::{self, AtomicU64},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{self, AtomicU64},
+    },
 };
 use structures::error::LxError;
 
@@ -8,6 +12,7 @@ use structures::error::LxError;
 pub struct AbstractNamespace {
     path: PathBuf,
     next_id: AtomicU64,
+    names: Mutex<FxHashMap<u64, Vec<u8>>>,
 }
 impl AbstractNamespace {
     pub fn new(path: PathBuf) -> std::io::Result<Self> {
@@ -17,6 +22,7 @@ impl AbstractNamespace {
         Ok(Self {
             path,
             next_id: AtomicU64::new(1),
+            names: Mutex::new(FxHashMap::default()),
         })
     }
 
@@ -25,6 +31,7 @@ impl AbstractNamespace {
         let escaped = escape_abstract_name(name);
         let map_file = self.path.join(format!("{escaped}.map"));
         std::fs::write(map_file, id.to_string())?;
+        self.names.lock().unwrap().insert(id, name.to_vec());
         Ok(id)
     }
 
@@ -44,6 +51,13 @@ impl AbstractNamespace {
     pub fn sock_by_name(&self, name: &[u8]) -> Result<PathBuf, LxError> {
         Ok(self.sock_by_id(self.id_by_name(name)?))
     }
+
+    /// Returns the abstract name that the native socket file at `path` is bound to, if any.
+    pub fn name_by_sock(&self, path: &Path) -> Option<Vec<u8>> {
+        let file_name = path.strip_prefix(&self.path).ok()?.to_str()?;
+        let id = file_name.strip_suffix(".sock")?.parse().ok()?;
+        self.names.lock().unwrap().get(&id).cloned()
+    }
 }
 
 fn escape_abstract_name(before: &[u8]) -> String {
@@ -10,7 +10,11 @@ use mach2::{
     vm_statistics::vm_statistics64_data_t,
 };
 use std::sync::RwLock;
-use structures::{error::LxError, misc::SysInfo, time::Timespec};
+use structures::{
+    error::LxError,
+    misc::{SI_LOAD_SHIFT, SysInfo},
+    time::Timespec,
+};
 
 pub trait UtsNamespace: Send + Sync {
     fn nodename(&self) -> Vec<u8>;
@@ -81,6 +85,14 @@ pub struct CustomUts {
     nodename: RwLock<Vec<u8>>,
     domainname: RwLock<Vec<u8>>,
 }
+impl CustomUts {
+    pub fn new(nodename: Vec<u8>, domainname: Vec<u8>) -> Self {
+        Self {
+            nodename: RwLock::new(nodename),
+            domainname: RwLock::new(domainname),
+        }
+    }
+}
 impl UtsNamespace for CustomUts {
     fn nodename(&self) -> Vec<u8> {
         self.nodename.read().unwrap().clone()
@@ -101,12 +113,14 @@ impl UtsNamespace for CustomUts {
     }
 }
 
-/// Retrieves [`SysInfo`] information.
+/// Returns system information, like `sysinfo`.
+///
+/// Values are consistent with the ones in `/proc/meminfo`, `/proc/uptime` and `/proc/loadavg`, and `mem_unit` is always
+/// 1, so memory sizes are in bytes. Like Linux, `procs` counts threads rather than processes.
 pub fn sysinfo() -> Result<SysInfo, LxError> {
     let mem_info = MemInfo::acquire()?;
     let boottime = boot_time()?;
-    let loads = loadavg()?;
-    let loads = [loads[0] as _, loads[1] as _, loads[2] as _];
+    let loads = loadavg()?.map(|x| (x * (1 << SI_LOAD_SHIFT) as f64) as u64);
 
     Ok(SysInfo {
         uptime: Timespec::now().tv_sec - boottime.tv_sec,
@@ -117,7 +131,7 @@ pub fn sysinfo() -> Result<SysInfo, LxError> {
         bufferram: 0,
         totalswap: mem_info.swap_usage.xsu_total,
         freeswap: mem_info.swap_usage.xsu_avail,
-        procs: app().processes.len() as _,
+        procs: app().threads.len().min(u16::MAX as usize) as _,
         totalhigh: 0,
         freehigh: 0,
         mem_unit: 1,
@@ -8,10 +8,15 @@ use crate::{
     task::thread::Thread,
     util::Shared,
 };
+use libproc::bsd_info::BSDInfo;
 use process::Process;
-use std::sync::Arc;
+use rustc_hash::FxHashMap;
+use std::sync::{Arc, RwLock};
 use structures::{error::LxError, thread::TID_MIN};
 
+/// Maximum nesting level of PID namespaces.
+const MAX_PID_NS_LEVEL: usize = 32;
+
 /// A pid namespace.
 ///
 /// Note that "native pid" represents to both macOS PIDs and MacTux thread IDs.
@@ -41,9 +46,6 @@ pub trait PidNamespace: Send + Sync {
     /// This would return `None` if this is the initial PID namespace.
     fn parent(&self) -> Option<Shared<Box<dyn PidNamespace>>>;
 
-    /// Creates a child namespace of this.
-    fn child(&self) -> Shared<Box<dyn PidNamespace>>;
-
     /// Returns `procfs` instance associated with this pid namespace.
     fn procfs(&self) -> Result<Arc<dyn Filesystem>, LxError>;
 }
@@ -93,8 +95,71 @@ impl PidNamespace for InitPid {
         None
     }
 
-    fn child(&self) -> Shared<Box<dyn PidNamespace>> {
-        todo!()
+    fn procfs(&self) -> Result<Arc<dyn Filesystem>, LxError> {
+        Ok(self.procfs.clone())
+    }
+}
+
+/// A nested pid namespace.
+///
+/// Processes registered in a child namespace are registered in all its ancestors as well, so they stay visible from
+/// the enclosing namespaces under their own PIDs there.
+pub struct ChildPid {
+    parent: Shared<Box<dyn PidNamespace>>,
+    procfs: Arc<Tmpfs>,
+    table: RwLock<PidTable>,
+}
+impl ChildPid {
+    pub fn new(parent: Shared<Box<dyn PidNamespace>>) -> Result<Self, LxError> {
+        Ok(Self {
+            parent,
+            procfs: procfs::new()?,
+            table: RwLock::new(PidTable::new()),
+        })
+    }
+}
+impl PidNamespace for ChildPid {
+    fn ntol(&self, native: i32) -> Result<i32, LxError> {
+        let table = self.table.read().unwrap();
+        table.ntol.get(&native).copied().ok_or(LxError::ENOENT)
+    }
+
+    fn lton(&self, linux: i32) -> Result<i32, LxError> {
+        let table = self.table.read().unwrap();
+        table.lton.get(&linux).copied().ok_or(LxError::ENOENT)
+    }
+
+    fn register(&self, native: i32) -> Result<i32, LxError> {
+        if self.ntol(native).is_ok() {
+            return Err(LxError::EEXIST);
+        }
+        self.parent.register(native)?;
+        let linux = self.table.write().unwrap().alloc(native);
+        if native < TID_MIN {
+            procfs::add_proc(&self.procfs, native, linux)?;
+        }
+        procfs::add_thread(self, &self.procfs, native)?;
+        Ok(linux)
+    }
+
+    fn unregister(&self, native_pid: i32, native_tid: i32) -> Result<(), LxError> {
+        let mut table = self.table.write().unwrap();
+        let linux_tid = table.ntol.remove(&native_tid).ok_or(LxError::ENOENT)?;
+        table.lton.remove(&linux_tid);
+        let linux_pid = table.ntol.get(&native_pid).copied().unwrap_or(linux_tid);
+        drop(table);
+
+        self.parent.unregister(native_pid, native_tid)?;
+        if native_tid < TID_MIN {
+            procfs::del_proc(&self.procfs, linux_tid)?;
+        } else {
+            procfs::del_thread(&self.procfs, linux_pid, linux_tid)?;
+        }
+        Ok(())
+    }
+
+    fn parent(&self) -> Option<Shared<Box<dyn PidNamespace>>> {
+        Some(self.parent.clone())
     }
 
     fn procfs(&self) -> Result<Arc<dyn Filesystem>, LxError> {
@@ -102,6 +167,74 @@ impl PidNamespace for InitPid {
     }
 }
 
+/// Mapping between native PIDs and Linux PIDs of a [`ChildPid`].
+struct PidTable {
+    ntol: FxHashMap<i32, i32>,
+    lton: FxHashMap<i32, i32>,
+    next: i32,
+}
+impl PidTable {
+    fn new() -> Self {
+        Self {
+            ntol: FxHashMap::default(),
+            lton: FxHashMap::default(),
+            next: 1,
+        }
+    }
+
+    /// Allocates a Linux PID for `native`. Like Linux, the first process registered gets PID 1.
+    fn alloc(&mut self, native: i32) -> i32 {
+        while self.lton.contains_key(&self.next) {
+            self.next = self.next.checked_add(1).unwrap_or(2);
+        }
+        let linux = self.next;
+        self.next = self.next.checked_add(1).unwrap_or(2);
+        self.ntol.insert(native, linux);
+        self.lton.insert(linux, native);
+        linux
+    }
+}
+
+/// Creates a child namespace of `parent`.
+pub fn child_pid_ns(
+    parent: Shared<Box<dyn PidNamespace>>,
+) -> Result<Shared<Box<dyn PidNamespace>>, LxError> {
+    let mut level = 1;
+    let mut ancestor = parent.parent();
+    while let Some(ns) = ancestor {
+        level += 1;
+        ancestor = ns.parent();
+    }
+    if level >= MAX_PID_NS_LEVEL {
+        return Err(LxError::EUSERS);
+    }
+    let child = ChildPid::new(parent)?;
+    Ok(app().namespaces.pid.register(Box::new(child)))
+}
+
+/// Returns `true` if `ns` is `ancestor` or one of its descendants.
+///
+/// Like Linux, a process may only move its children into such PID namespaces, so that they cannot escape from the
+/// namespace of the process.
+pub fn pid_ns_within(
+    ns: &Shared<Box<dyn PidNamespace>>,
+    ancestor: &Shared<Box<dyn PidNamespace>>,
+) -> bool {
+    let mut current = Some(ns.clone());
+    while let Some(ns) = current {
+        if Shared::id(&ns) == Shared::id(ancestor) {
+            return true;
+        }
+        current = ns.parent();
+    }
+    false
+}
+
+/// Returns native credentials of the process with the given native PID.
+pub fn native_credentials(apple_pid: i32) -> Result<BSDInfo, LxError> {
+    libproc::proc_pid::pidinfo::<BSDInfo>(apple_pid, 0).map_err(|_| LxError::ESRCH)
+}
+
 pub fn configure() -> Configuration {
     Configuration::new()
 }
@@ -141,9 +274,47 @@ impl Configuration {
                 thread_builder.is_main();
             }
             let thread = thread_builder.build()?;
-            self.parent.pid.register(thread.tid())?;
+            thread.process.pid.register(thread.tid())?;
             Thread::set_current(thread);
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_process_is_pid_1() {
+        let mut table = PidTable::new();
+        assert_eq!(table.alloc(4242), 1);
+        assert_eq!(table.alloc(4243), 2);
+        assert_eq!(table.ntol[&4242], 1);
+        assert_eq!(table.lton[&1], 4242);
+    }
+
+    #[test]
+    fn process_keeps_its_pid_in_parent_namespace() {
+        let mut parent = PidTable::new();
+        let mut child = PidTable::new();
+        parent.alloc(100);
+
+        // Like `ChildPid::register`, the process is registered in the parent namespace, then in the child.
+        assert_eq!(parent.alloc(4242), 2);
+        assert_eq!(child.alloc(4242), 1);
+        assert_eq!(parent.ntol[&4242], 2);
+        assert_eq!(child.ntol[&4242], 1);
+        assert_eq!(parent.lton[&1], 100);
+    }
+
+    #[test]
+    fn pids_wrap_around_skipping_used_ones() {
+        let mut table = PidTable::new();
+        table.alloc(100);
+        table.alloc(101);
+        table.next = i32::MAX;
+        assert_eq!(table.alloc(102), i32::MAX);
+        assert_eq!(table.alloc(103), 3);
+    }
+}
@@ -1,24 +1,63 @@
 use crate::{
+    app,
     filesystem::vfs::MountNamespace,
     network::NetNamespace,
-    sysinfo::UtsNamespace,
-    task::{PidNamespace, thread::Thread},
+    sysinfo::{CustomUts, UtsNamespace},
+    task::{PidNamespace, child_pid_ns, pid_ns_within, thread::Thread},
     util::Shared,
     vfd::VfdTable,
 };
 use dashmap::DashSet;
-use rustc_hash::FxBuildHasher;
-use structures::error::LxError;
+use rustc_hash::{FxBuildHasher, FxHashMap};
+use std::sync::{
+    RwLock,
+    atomic::{self, AtomicBool, AtomicU16},
+};
+use structures::{
+    error::LxError,
+    process::{CloneFlags, NamespaceKind, RLimit64, RLimitable},
+};
+
+/// The umask of processes that are not forked from another MacTux process.
+const DEFAULT_UMASK: u16 = 0o022;
 
 pub struct Process {
-    pub mnt: Shared<MountNamespace>,
-    pub uts: Shared<Box<dyn UtsNamespace>>,
+    mnt: RwLock<Shared<MountNamespace>>,
+    uts: RwLock<Shared<Box<dyn UtsNamespace>>>,
     pub pid: Shared<Box<dyn PidNamespace>>,
-    pub net: Shared<NetNamespace>,
+    pid_for_children: RwLock<Option<Shared<Box<dyn PidNamespace>>>>,
+    net: RwLock<Shared<NetNamespace>>,
     pub vfd: VfdTable,
     pub threads: DashSet<i32, FxBuildHasher>,
+    umask: AtomicU16,
+    exe: RwLock<Vec<u8>>,
+    cwd: RwLock<Vec<u8>>,
+    rlimits: RwLock<FxHashMap<RLimitable, RLimit64>>,
+    dumpable: AtomicBool,
 }
 impl Process {
+    pub fn new(
+        mnt: Shared<MountNamespace>,
+        uts: Shared<Box<dyn UtsNamespace>>,
+        pid: Shared<Box<dyn PidNamespace>>,
+        net: Shared<NetNamespace>,
+    ) -> Self {
+        Self {
+            mnt: RwLock::new(mnt),
+            uts: RwLock::new(uts),
+            pid,
+            pid_for_children: RwLock::new(None),
+            net: RwLock::new(net),
+            vfd: VfdTable::new(),
+            threads: DashSet::default(),
+            umask: AtomicU16::new(DEFAULT_UMASK),
+            exe: RwLock::new(Vec::new()),
+            cwd: RwLock::new(vec![b'/']),
+            rlimits: RwLock::new(FxHashMap::default()),
+            dumpable: AtomicBool::new(true),
+        }
+    }
+
     pub fn server() -> Shared<Self> {
         Thread::server().process()
     }
@@ -29,17 +68,200 @@ impl Process {
 
     pub(super) fn _child(&self) -> Self {
         Self {
-            mnt: self.mnt.clone(),
-            uts: self.uts.clone(),
-            pid: self.pid.clone(),
-            net: self.net.clone(),
+            mnt: RwLock::new(self.mnt()),
+            uts: RwLock::new(self.uts()),
+            pid: self.pid_for_children(),
+            pid_for_children: RwLock::new(None),
+            net: RwLock::new(self.net()),
             vfd: self.vfd.fork(),
             threads: DashSet::default(),
+            umask: AtomicU16::new(self.umask()),
+            exe: RwLock::new(self.exe()),
+            cwd: RwLock::new(self.cwd()),
+            rlimits: RwLock::new(self.rlimits.read().unwrap().clone()),
+            dumpable: AtomicBool::new(self.dumpable()),
+        }
+    }
+
+    /// Returns path of the executable file that the process is running.
+    pub fn exe(&self) -> Vec<u8> {
+        self.exe.read().unwrap().clone()
+    }
+
+    /// Records path of the executable file that the process is running.
+    pub fn set_exe(&self, path: Vec<u8>) {
+        *self.exe.write().unwrap() = path;
+    }
+
+    /// Returns the current working directory of the process.
+    pub fn cwd(&self) -> Vec<u8> {
+        self.cwd.read().unwrap().clone()
+    }
+
+    /// Records the current working directory of the process.
+    pub fn set_cwd(&self, path: Vec<u8>) {
+        *self.cwd.write().unwrap() = path;
+    }
+
+    /// Returns the file mode creation mask of the process.
+    pub fn umask(&self) -> u16 {
+        self.umask.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Sets the file mode creation mask of the process, returning the previous one.
+    pub fn set_umask(&self, mask: u16) -> u16 {
+        self.umask.swap(mask & 0o777, atomic::Ordering::Relaxed)
+    }
+
+    /// Returns `true` if the process is dumpable, as is set by `PR_SET_DUMPABLE`.
+    ///
+    /// Internals of processes that are not dumpable may only be accessed by themselves and privileged processes.
+    pub fn dumpable(&self) -> bool {
+        self.dumpable.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Sets whether the process is dumpable.
+    pub fn set_dumpable(&self, dumpable: bool) {
+        self.dumpable.store(dumpable, atomic::Ordering::Relaxed);
+    }
+
+    /// Returns a resource limit of the process. Limits that were never recorded are unlimited.
+    pub fn rlimit(&self, res: RLimitable) -> RLimit64 {
+        self.rlimits
+            .read()
+            .unwrap()
+            .get(&res)
+            .copied()
+            .unwrap_or(RLimit64::UNLIMITED)
+    }
+
+    /// Records a resource limit of the process, returning the previous one.
+    ///
+    /// `check` is called with the previous limit first, and the limit is only changed if it succeeds. Nothing else may
+    /// change the limit in the meantime.
+    pub fn set_rlimit(
+        &self,
+        res: RLimitable,
+        limit: RLimit64,
+        check: impl FnOnce(RLimit64) -> Result<(), LxError>,
+    ) -> Result<RLimit64, LxError> {
+        let mut rlimits = self.rlimits.write().unwrap();
+        let old = rlimits.get(&res).copied().unwrap_or(RLimit64::UNLIMITED);
+        check(old)?;
+        rlimits.insert(res, limit);
+        Ok(old)
+    }
+
+    /// Records native resource limits of the process for resources that have no recorded limits yet, returning all
+    /// recorded limits.
+    pub fn sync_rlimits(&self, native: Vec<(RLimitable, RLimit64)>) -> Vec<(RLimitable, RLimit64)> {
+        let mut rlimits = self.rlimits.write().unwrap();
+        for (res, limit) in native {
+            rlimits.entry(res).or_insert(limit);
         }
+        rlimits.iter().map(|(res, limit)| (*res, *limit)).collect()
+    }
+
+    /// Returns the mount namespace of the process.
+    pub fn mnt(&self) -> Shared<MountNamespace> {
+        self.mnt.read().unwrap().clone()
+    }
+
+    /// Returns the UTS namespace of the process.
+    pub fn uts(&self) -> Shared<Box<dyn UtsNamespace>> {
+        self.uts.read().unwrap().clone()
+    }
+
+    /// Returns the network namespace of the process.
+    pub fn net(&self) -> Shared<NetNamespace> {
+        self.net.read().unwrap().clone()
+    }
+
+    /// Returns the PID namespace that children of the process would be in.
+    pub fn pid_for_children(&self) -> Shared<Box<dyn PidNamespace>> {
+        self.pid_for_children
+            .read()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| self.pid.clone())
+    }
+
+    /// Returns ID of the namespace of given kind that the process is in.
+    pub fn namespace_id(&self, kind: NamespaceKind) -> u64 {
+        match kind {
+            NamespaceKind::Mount => Shared::id(&self.mnt()),
+            NamespaceKind::Pid => Shared::id(&self.pid),
+            NamespaceKind::Uts => Shared::id(&self.uts()),
+            NamespaceKind::Net => Shared::id(&self.net()),
+        }
+    }
+
+    /// Moves the process into an existing namespace.
+    ///
+    /// Like [`Process::unshare`], joining a PID namespace only affects children of the process. The namespace must be
+    /// that of the process or one of its descendants.
+    pub fn set_namespace(&self, kind: NamespaceKind, id: u64) -> Result<(), LxError> {
+        let namespaces = &app().namespaces;
+        match kind {
+            NamespaceKind::Mount => {
+                *self.mnt.write().unwrap() = namespaces.mount.get(id).ok_or(LxError::EINVAL)?;
+            }
+            NamespaceKind::Pid => {
+                let ns = namespaces.pid.get(id).ok_or(LxError::EINVAL)?;
+                if !pid_ns_within(&ns, &self.pid) {
+                    return Err(LxError::EINVAL);
+                }
+                *self.pid_for_children.write().unwrap() = Some(ns);
+            }
+            NamespaceKind::Uts => {
+                *self.uts.write().unwrap() = namespaces.uts.get(id).ok_or(LxError::EINVAL)?;
+            }
+            NamespaceKind::Net => {
+                *self.net.write().unwrap() = namespaces.net.get(id).ok_or(LxError::EINVAL)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the process into new namespaces, as is specified by `flags`.
+    ///
+    /// Like Linux, `CLONE_NEWPID` does not change PID namespace of the process itself, but that of its children.
+    pub fn unshare(&self, flags: CloneFlags) -> Result<(), LxError> {
+        let supported = CloneFlags::CLONE_FILES
+            | CloneFlags::CLONE_FS
+            | CloneFlags::CLONE_SYSVSEM
+            | CloneFlags::CLONE_NEWNS
+            | CloneFlags::CLONE_NEWUTS
+            | CloneFlags::CLONE_NEWPID
+            | CloneFlags::CLONE_NEWNET;
+        if !supported.contains(flags) {
+            return Err(LxError::EINVAL);
+        }
+
+        let namespaces = &app().namespaces;
+        if flags.contains(CloneFlags::CLONE_NEWPID) {
+            let child = child_pid_ns(self.pid_for_children())?;
+            *self.pid_for_children.write().unwrap() = Some(child);
+        }
+        if flags.contains(CloneFlags::CLONE_NEWNET) {
+            let net = NetNamespace::new().map_err(|_| LxError::ENOMEM)?;
+            *self.net.write().unwrap() = namespaces.net.register(net);
+        }
+        if flags.contains(CloneFlags::CLONE_NEWNS) {
+            let mnt = self.mnt().fork();
+            *self.mnt.write().unwrap() = namespaces.mount.register(mnt);
+        }
+        if flags.contains(CloneFlags::CLONE_NEWUTS) {
+            let uts = self.uts();
+            let uts = CustomUts::new(uts.nodename(), uts.domainname());
+            *self.uts.write().unwrap() = namespaces.uts.register(Box::new(uts));
+        }
+        Ok(())
     }
 
     pub fn on_exec(&self) {
         self.vfd.on_exec();
+        self.set_dumpable(true);
     }
 }
 
@@ -49,3 +271,60 @@ pub fn after_fork(apple_pid: libc::pid_t) -> Result<(), LxError> {
         .apple_pid(apple_pid)
         .exec()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unshared_hostname_is_private() {
+        crate::init_for_tests();
+        let parent = Process::server();
+        let child = parent._child();
+        let hostname = parent.uts().nodename();
+
+        child.unshare(CloneFlags::CLONE_NEWUTS).unwrap();
+        assert_eq!(child.uts().nodename(), hostname);
+        child.uts().set_nodename(b"mactux-test".to_vec()).unwrap();
+        assert_eq!(child.uts().nodename(), b"mactux-test");
+        assert_eq!(parent.uts().nodename(), hostname);
+    }
+
+    #[test]
+    fn only_descendant_pid_namespaces_are_joined() {
+        crate::init_for_tests();
+        let parent = Process::server();
+        let first = parent._child();
+        first.unshare(CloneFlags::CLONE_NEWPID).unwrap();
+        let second = parent._child();
+        second.unshare(CloneFlags::CLONE_NEWPID).unwrap();
+        let inner = first._child();
+        let outer = parent._child();
+        let id = |ns: &Shared<Box<dyn PidNamespace>>| Shared::id(ns);
+
+        // Into the namespace of the process itself, or one of its children.
+        assert_eq!(
+            inner.set_namespace(NamespaceKind::Pid, id(&inner.pid)),
+            Ok(())
+        );
+        assert_eq!(
+            first.set_namespace(NamespaceKind::Pid, id(&inner.pid)),
+            Ok(())
+        );
+        assert_eq!(
+            outer.set_namespace(NamespaceKind::Pid, id(&second.pid_for_children())),
+            Ok(())
+        );
+
+        // Out to an ancestor, or into a sibling.
+        assert_eq!(
+            inner.set_namespace(NamespaceKind::Pid, id(&parent.pid)),
+            Err(LxError::EINVAL)
+        );
+        assert_eq!(
+            inner.set_namespace(NamespaceKind::Pid, id(&second.pid_for_children())),
+            Err(LxError::EINVAL)
+        );
+        assert_eq!(Shared::id(&inner.pid_for_children()), id(&inner.pid));
+    }
+}
@@ -40,6 +40,15 @@ impl Thread {
     pub fn tid(&self) -> i32 {
         self.tid
     }
+
+    /// Sets name of the thread. The name is truncated at the first NUL byte, and to at most 15 bytes.
+    pub fn set_comm(&self, mut name: Vec<u8>) {
+        if let Some(len) = name.iter().position(|x| *x == 0) {
+            name.truncate(len);
+        }
+        name.truncate(15);
+        *self.comm.write().unwrap() = Some(name);
+    }
 }
 impl Drop for Thread {
     fn drop(&mut self) {
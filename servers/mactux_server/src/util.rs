@@ -78,6 +78,11 @@ impl<T> ReclaimRegistry<T> {
     pub fn len(&self) -> usize {
         self.table.len()
     }
+
+    /// Returns all values in the registry.
+    pub fn list(&self) -> Vec<Shared<T>> {
+        self.table.iter().map(|x| x.value().clone()).collect()
+    }
 }
 
 pub struct Shared<T: 'static> {
@@ -143,9 +148,13 @@ impl<T: Clone> Watch<T> {
         self.condvar.notify_all();
     }
 
-    pub fn wait_until(&self, f: impl FnMut(&mut T) -> bool) -> T {
+    /// Waits until `f` returns `true` for the value, which it may also modify. The value after `f` returns `true` is
+    /// returned.
+    pub fn wait_until(&self, mut f: impl FnMut(&mut T) -> bool) -> T {
         let guard = self.mutex.lock().unwrap();
-        self.condvar.wait_while(guard, f).unwrap().clone()
+        let value = self.condvar.wait_while(guard, |x| !f(x)).unwrap().clone();
+        self.condvar.notify_all();
+        value
     }
 }
 
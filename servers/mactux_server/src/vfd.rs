@@ -7,8 +7,8 @@ use rustc_hash::FxBuildHasher;
 use std::{
     path::PathBuf,
     sync::{
-        Arc, OnceLock,
-        atomic::{self, AtomicI64, AtomicU64},
+        Arc, Mutex, OnceLock,
+        atomic::{self, AtomicBool, AtomicI64, AtomicU64},
     },
 };
 use structures::{
@@ -16,51 +16,96 @@ use structures::{
     fs::{Dirent64, OpenFlags, StatFs, Statx, StatxMask, XATTR_NAMESPACE_PREFIXES},
     internal::mactux_ipc::CtrlOutput,
     io::{FcntlCmd, FdFlags, IoctlCmd, PollEvents, VfdAvailCtrl, Whence},
+    process::NamespaceKind,
     time::Timespec,
 };
 
 pub struct Vfd {
     content: Arc<dyn VfdContent>,
-    open_flags: AtomicCell<OpenFlags>,
-    offset: AtomicI64,
+    description: Arc<OpenDescription>,
+    cloexec: AtomicBool,
     orig_path: OnceLock<Vec<u8>>,
+    read_only_mount: AtomicBool,
 }
 impl Vfd {
     pub fn new(content: Arc<dyn VfdContent>, open_flags: OpenFlags) -> Self {
+        // Like on 64-bit Linux, `O_LARGEFILE` is always set, while creation flags are not kept.
+        let status =
+            (open_flags - OpenFlags::CREATION - OpenFlags::O_CLOEXEC) | OpenFlags::O_LARGEFILE;
         Self {
             content,
-            open_flags: AtomicCell::new(open_flags),
-            offset: AtomicI64::new(0),
+            description: Arc::new(OpenDescription {
+                flags: AtomicCell::new(status),
+                offset: AtomicI64::new(0),
+                append_lock: Mutex::new(()),
+            }),
+            cloexec: AtomicBool::new(open_flags.contains(OpenFlags::O_CLOEXEC)),
             orig_path: OnceLock::new(),
+            read_only_mount: AtomicBool::new(false),
         }
     }
 
+    /// Returns the access mode and status flags of the open file description, as are reported by `F_GETFL`.
+    fn open_flags(&self) -> OpenFlags {
+        self.description.flags.load()
+    }
+
     pub fn read(&self, buf: &mut [u8]) -> Result<usize, LxError> {
-        if !self.open_flags.load().is_readable() {
+        if !self.open_flags().is_readable() {
             return Err(LxError::EBADF);
         }
 
-        let mut off = self.offset.load(atomic::Ordering::Relaxed);
+        let offset = &self.description.offset;
+        let mut off = offset.load(atomic::Ordering::Relaxed);
         let stat = self.content.read(buf, &mut off);
-        self.offset.store(off, atomic::Ordering::Relaxed);
+        offset.store(off, atomic::Ordering::Relaxed);
         stat
     }
 
     pub fn write(&self, buf: &[u8]) -> Result<usize, LxError> {
-        if !self.open_flags.load().is_writable() {
+        if self.open_flags().contains(OpenFlags::O_APPEND) {
+            return self.append(buf, true);
+        }
+        if !self.open_flags().is_writable() {
+            return Err(LxError::EBADF);
+        }
+
+        let offset = &self.description.offset;
+        let mut off = offset.load(atomic::Ordering::Relaxed);
+        let stat = self.content.write(buf, &mut off);
+        offset.store(off, atomic::Ordering::Relaxed);
+        stat
+    }
+
+    /// Appends `buf` to the end of the file, whether the VFD is opened with `O_APPEND` or not.
+    ///
+    /// Appending writes through the same open file description are serialized, so that writes through duplicated VFDs
+    /// never overwrite each other.
+    pub fn append(&self, buf: &[u8], update_offset: bool) -> Result<usize, LxError> {
+        if !self.open_flags().is_writable() {
             return Err(LxError::EBADF);
         }
 
-        let mut off = self.offset.load(atomic::Ordering::Relaxed);
+        let _guard = self.description.append_lock.lock().unwrap();
+        let offset = &self.description.offset;
+        let orig_off = offset.load(atomic::Ordering::Relaxed);
+        let mut off = match self.content.seek(orig_off, Whence::SEEK_END, 0) {
+            Ok(off) => off,
+            Err(LxError::ESPIPE | LxError::EOPNOTSUPP) => orig_off,
+            Err(err) => return Err(err),
+        };
         let stat = self.content.write(buf, &mut off);
-        self.offset.store(off, atomic::Ordering::Relaxed);
+        if update_offset {
+            offset.store(off, atomic::Ordering::Relaxed);
+        }
         stat
     }
 
     pub fn seek(&self, whence: Whence, off: i64) -> Result<i64, LxError> {
-        let orig_off = self.offset.load(atomic::Ordering::Relaxed);
+        let offset = &self.description.offset;
+        let orig_off = offset.load(atomic::Ordering::Relaxed);
         let new_off = self.content.seek(orig_off, whence, off)?;
-        self.offset.store(new_off, atomic::Ordering::Relaxed);
+        offset.store(new_off, atomic::Ordering::Relaxed);
         Ok(new_off)
     }
 
@@ -69,17 +114,27 @@ impl Vfd {
     }
 
     pub fn pread(&self, buf: &mut [u8], mut off: i64) -> Result<usize, LxError> {
-        if !self.open_flags.load().is_readable() {
+        if !self.open_flags().is_readable() {
             return Err(LxError::EBADF);
         }
+        if off < 0 {
+            return Err(LxError::EINVAL);
+        }
 
         self.content.read(buf, &mut off)
     }
 
     pub fn pwrite(&self, buf: &[u8], mut off: i64) -> Result<usize, LxError> {
-        if !self.open_flags.load().is_writable() {
+        // Like on Linux, data is appended regardless of `off` if the VFD is opened with `O_APPEND`.
+        if self.open_flags().contains(OpenFlags::O_APPEND) {
+            return self.append(buf, false);
+        }
+        if !self.open_flags().is_writable() {
             return Err(LxError::EBADF);
         }
+        if off < 0 {
+            return Err(LxError::EINVAL);
+        }
 
         self.content.write(buf, &mut off)
     }
@@ -95,11 +150,31 @@ impl Vfd {
     pub fn fcntl(&self, cmd: FcntlCmd, data: &[u8]) -> Result<CtrlOutput, LxError> {
         match cmd {
             FcntlCmd::F_GETFL => Ok(CtrlOutput {
-                status: self.open_flags.load().bits() as _,
+                status: self.open_flags().bits() as _,
                 blob: Vec::new(),
             }),
+            FcntlCmd::F_SETFL => {
+                let mut flags = [0u8; size_of::<u64>()];
+                flags.copy_from_slice(data);
+                let flags = OpenFlags::from_bits_truncate(u64::from_ne_bytes(flags) as _);
+                let old_flags = self
+                    .description
+                    .flags
+                    .fetch_update(|x| Some((x - OpenFlags::SETFL) | (flags & OpenFlags::SETFL)))
+                    .unwrap();
+                if old_flags.contains(OpenFlags::O_NONBLOCK)
+                    != flags.contains(OpenFlags::O_NONBLOCK)
+                {
+                    self.content
+                        .set_nonblocking(flags.contains(OpenFlags::O_NONBLOCK));
+                }
+                Ok(CtrlOutput {
+                    status: 0,
+                    blob: Vec::new(),
+                })
+            }
             FcntlCmd::F_GETFD => {
-                if self.open_flags.load().contains(OpenFlags::O_CLOEXEC) {
+                if self.cloexec.load(atomic::Ordering::Relaxed) {
                     Ok(CtrlOutput {
                         status: FdFlags::FD_CLOEXEC.bits() as _,
                         blob: Vec::new(),
@@ -118,10 +193,7 @@ impl Vfd {
                 let Some(fd_flags) = FdFlags::from_bits(fd_flags as _) else {
                     return Err(LxError::EINVAL);
                 };
-                if fd_flags.contains(FdFlags::FD_CLOEXEC) {
-                    self.open_flags
-                        .store(self.open_flags.load() | OpenFlags::O_CLOEXEC);
-                }
+                self.set_cloexec(fd_flags.contains(FdFlags::FD_CLOEXEC));
                 Ok(CtrlOutput {
                     status: 0,
                     blob: Vec::new(),
@@ -135,6 +207,15 @@ impl Vfd {
         self.content.getdent()
     }
 
+    /// Sets or clears the close-on-exec flag of this VFD.
+    pub fn set_cloexec(&self, cloexec: bool) {
+        self.cloexec.store(cloexec, atomic::Ordering::Relaxed);
+    }
+
+    /// Duplicates the VFD, as is done by `dup` and `fork`.
+    ///
+    /// The duplicate shares the open file description, including the file offset and status flags, with this one, while
+    /// the close-on-exec flag is copied.
     pub fn dup(self: &Arc<Self>) -> Arc<Self> {
         let content = match self.content.dup() {
             Ok(content) => Arc::clone(&content),
@@ -142,24 +223,27 @@ impl Vfd {
         };
         Arc::new(Self {
             content,
-            open_flags: AtomicCell::new(self.open_flags.load()),
-            offset: AtomicI64::new(self.offset.load(atomic::Ordering::Relaxed)),
+            description: Arc::clone(&self.description),
+            cloexec: AtomicBool::new(self.cloexec.load(atomic::Ordering::Relaxed)),
             orig_path: self.orig_path.clone(),
+            read_only_mount: AtomicBool::new(self.read_only_mount.load(atomic::Ordering::Relaxed)),
         })
     }
 
     pub fn truncate(&self, len: u64) -> Result<(), LxError> {
-        if !self.open_flags.load().is_writable() {
+        if !self.open_flags().is_writable() {
             return Err(LxError::EBADF);
         }
         self.content.truncate(len)
     }
 
     pub fn chown(&self, uid: u32, gid: u32) -> Result<(), LxError> {
+        self.will_modify_metadata()?;
         self.content.chown(uid, gid)
     }
 
     pub fn chmod(&self, mode: u16) -> Result<(), LxError> {
+        self.will_modify_metadata()?;
         self.content.chmod(mode)
     }
 
@@ -178,6 +262,7 @@ impl Vfd {
     pub fn setxattr(&self, name: &[u8], value: &[u8], flags: u32) -> Result<(), LxError> {
         for prefix in XATTR_NAMESPACE_PREFIXES.iter() {
             if name.starts_with(prefix) {
+                self.will_modify_metadata()?;
                 return self.content.setxattr(name, value, flags);
             }
         }
@@ -185,6 +270,7 @@ impl Vfd {
     }
 
     pub fn removexattr(&self, name: &[u8]) -> Result<(), LxError> {
+        self.will_modify_metadata()?;
         self.content.removexattr(name)
     }
 
@@ -193,6 +279,7 @@ impl Vfd {
     }
 
     pub fn utimens(&self, times: [Timespec; 2]) -> Result<(), LxError> {
+        self.will_modify_metadata()?;
         self.content.utimens(times)
     }
 
@@ -205,6 +292,19 @@ impl Vfd {
         self.orig_path.get().map(|x| &**x)
     }
 
+    /// Marks this VFD as opened through a read-only mount, so that modifications to metadata of the file fail with
+    /// [`LxError::EROFS`], like they would through the path.
+    pub fn set_read_only_mount(&self) {
+        self.read_only_mount.store(true, atomic::Ordering::Relaxed);
+    }
+
+    fn will_modify_metadata(&self) -> Result<(), LxError> {
+        match self.read_only_mount.load(atomic::Ordering::Relaxed) {
+            true => Err(LxError::EROFS),
+            false => Ok(()),
+        }
+    }
+
     /// Sets the original path of this VFD. Fails if it has already been set.
     ///
     /// On failure, the original path remains unchanged.
@@ -215,6 +315,22 @@ impl Vfd {
     pub fn poll(&self, events: PollEvents) -> Result<PollToken, LxError> {
         self.content.poll(events)
     }
+
+    pub fn pidfd(&self) -> Result<i32, LxError> {
+        self.content.pidfd()
+    }
+
+    pub fn namespace(&self) -> Result<(NamespaceKind, u64), LxError> {
+        self.content.namespace()
+    }
+}
+
+/// State shared by a VFD and its duplicates, like an open file description on Linux.
+struct OpenDescription {
+    flags: AtomicCell<OpenFlags>,
+    offset: AtomicI64,
+    /// Serializes appending writes, so that finding the end of the file and writing there is atomic.
+    append_lock: Mutex<()>,
 }
 
 pub trait Stream {
@@ -226,21 +342,29 @@ pub trait Stream {
         Err(LxError::EOPNOTSUPP)
     }
 
+    /// Computes the new offset of a seek from `orig_off`.
+    ///
+    /// Streams that are not seekable, like pipes and sockets, fail with `ESPIPE`, which is the default.
     fn seek(&self, _orig_off: i64, _whence: Whence, _off: i64) -> Result<i64, LxError> {
-        Err(LxError::EOPNOTSUPP)
+        Err(LxError::ESPIPE)
     }
 
     fn ioctl_query(&self, _cmd: IoctlCmd) -> Result<VfdAvailCtrl, LxError> {
-        Err(LxError::EOPNOTSUPP)
+        Err(LxError::ENOTTY)
     }
 
     fn ioctl(&self, _cmd: IoctlCmd, _data: &[u8]) -> Result<CtrlOutput, LxError> {
-        Err(LxError::EOPNOTSUPP)
+        Err(LxError::ENOTTY)
     }
 
     fn poll(&self, _interest: PollEvents) -> Result<PollToken, LxError> {
         Err(LxError::EOPNOTSUPP)
     }
+
+    /// Called when `O_NONBLOCK` of the open file description is changed by `F_SETFL`.
+    ///
+    /// Streams that may block in [`Stream::read`] or [`Stream::write`] should stop or start blocking accordingly.
+    fn set_nonblocking(&self, _nonblocking: bool) {}
 }
 
 pub trait VfdContent: Stream + Send + Sync {
@@ -306,6 +430,16 @@ pub trait VfdContent: Stream + Send + Sync {
     fn filesystem(&self) -> Result<Arc<dyn Filesystem>, LxError> {
         Err(LxError::EOPNOTSUPP)
     }
+
+    /// Returns native PID of the process this VFD refers to, if this is a pidfd.
+    fn pidfd(&self) -> Result<i32, LxError> {
+        Err(LxError::EBADF)
+    }
+
+    /// Returns kind and ID of the namespace this VFD refers to, if this is a namespace file descriptor.
+    fn namespace(&self) -> Result<(NamespaceKind, u64), LxError> {
+        Err(LxError::EINVAL)
+    }
 }
 
 pub struct VfdTable {
@@ -334,6 +468,11 @@ impl VfdTable {
         self.table.remove(&id).map(|(_, v)| v)
     }
 
+    /// Creates the table of a forked child.
+    ///
+    /// Each entry is duplicated with [`Vfd::dup`], so it shares the open file description with the parent. Contents
+    /// that keep shared state, like eventfds, share it in [`VfdContent::dup`], so a write from either process is seen
+    /// by the other.
     pub fn fork(&self) -> Self {
         Self {
             table: self
@@ -347,7 +486,7 @@ impl VfdTable {
 
     pub fn on_exec(&self) {
         self.table
-            .retain(|_, v| !v.open_flags.load().contains(OpenFlags::O_CLOEXEC));
+            .retain(|_, v| !v.cloexec.load(atomic::Ordering::Relaxed));
     }
 }
 
@@ -0,0 +1,433 @@
+//! Launcher configuration files.
+//!
+//! A configuration file is made of sections, each containing one entry per line. Empty lines and lines starting with
+//! `#` are ignored:
+//!
+//! ```text
+//! [env]
+//! PATH=/usr/local/bin:/usr/bin:/bin
+//!
+//! [rlimit]
+//! NOFILE = 1024:4096
+//! STACK = unlimited
+//!
+//! [mount]
+//! tmpfs /tmp tmpfs nosuid,nodev
+//! /home /mnt/home none bind
+//!
+//! [switch]
+//! Strace = 1
+//! ```
+//!
+//! Everything specified here is overridden by the command line and the real environment. Resource limits are
+//! overridden with `--rlimit`, which takes entries like the ones of the `[rlimit]` section.
+
+use std::{ffi::OsStr, path::Path};
+use structures::{
+    error::LxError,
+    fs::MountFlags,
+    process::{CloneFlags, RLimit64, RLimitable},
+};
+
+/// A parsed launcher configuration file.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub env: Vec<Vec<u8>>,
+    pub rlimits: Vec<(RLimitable, RLimit64)>,
+    pub mounts: Vec<MountEntry>,
+    pub switches: Vec<(String, String)>,
+}
+impl Config {
+    /// Loads a configuration file from `path`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        Self::parse(&content)
+    }
+
+    /// Parses a configuration file.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut this = Self::default();
+        let mut section = None;
+        for (lineno, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let result = match line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+                Some(name) => Section::parse(name.trim()).map(|x| section = Some(x)),
+                None => match section {
+                    Some(Section::Env) => this.parse_env(line),
+                    Some(Section::RLimit) => this.parse_rlimit(line),
+                    Some(Section::Mount) => this.parse_mount(line),
+                    Some(Section::Switch) => this.parse_switch(line),
+                    None => Err(String::from("entry outside of any section")),
+                },
+            };
+            result.map_err(|err| format!("line {}: {err}", lineno + 1))?;
+        }
+        Ok(this)
+    }
+
+    /// Sets resource limits given on the command line, which take precedence over the ones of the file.
+    pub fn override_rlimits(&mut self, overrides: &[String]) -> Result<(), String> {
+        for line in overrides {
+            let mut entry = Self::default();
+            entry.parse_rlimit(line)?;
+            for (res, limit) in entry.rlimits {
+                self.rlimits.retain(|(x, _)| *x != res);
+                self.rlimits.push((res, limit));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets switches that are not already present in the environment.
+    ///
+    /// # Safety
+    /// This modifies the process environment, so it must be called before any other thread is spawned.
+    pub unsafe fn apply_switches(&self) {
+        for (name, value) in &self.switches {
+            let name = format!("MacTux_{name}");
+            if std::env::var_os(&name).is_none() {
+                unsafe {
+                    std::env::set_var(name, value);
+                }
+            }
+        }
+    }
+
+    /// Applies resource limits to the current process.
+    pub fn apply_rlimits(&self) -> Result<(), LxError> {
+        for (res, limit) in &self.rlimits {
+            rtenv::process::prlimit(0, *res, Some(*limit))?;
+        }
+        Ok(())
+    }
+
+    /// Performs the configured mounts.
+    ///
+    /// Mounts are made in a new mount namespace, so that they do not pile up in the namespace shared with other
+    /// programs each time one is launched.
+    pub fn apply_mounts(&self) -> Result<(), LxError> {
+        if self.mounts.is_empty() {
+            return Ok(());
+        }
+        rtenv::process::unshare(CloneFlags::CLONE_NEWNS)?;
+        for entry in &self.mounts {
+            rtenv::fs::mount(
+                entry.source.clone(),
+                entry.target.clone(),
+                entry.fs.clone(),
+                entry.flags,
+                entry.data.clone(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Merges environment variables from the configuration file with `overrides`, which take precedence.
+    pub fn merge_env<'a>(&'a self, overrides: &[&'a [u8]]) -> Vec<&'a [u8]> {
+        let mut envp: Vec<&[u8]> = self
+            .env
+            .iter()
+            .map(Vec::as_slice)
+            .filter(|x| !overrides.iter().any(|y| env_key(x) == env_key(y)))
+            .collect();
+        envp.extend_from_slice(overrides);
+        envp
+    }
+
+    fn parse_env(&mut self, line: &str) -> Result<(), String> {
+        if !line.contains('=') {
+            return Err(format!("invalid environment variable \"{line}\""));
+        }
+        self.env.push(line.as_bytes().to_vec());
+        Ok(())
+    }
+
+    fn parse_rlimit(&mut self, line: &str) -> Result<(), String> {
+        let (name, value) = split_key_value(line)?;
+        let res = rlimit_by_name(name).ok_or_else(|| format!("unknown resource \"{name}\""))?;
+        let (cur, max) = value.split_once(':').unwrap_or((value, value));
+        let limit = RLimit64 {
+            rlim_cur: parse_rlimit_value(cur.trim())?,
+            rlim_max: parse_rlimit_value(max.trim())?,
+        };
+        if limit.rlim_cur > limit.rlim_max {
+            return Err(format!("soft limit of \"{name}\" exceeds its hard limit"));
+        }
+        self.rlimits.push((res, limit));
+        Ok(())
+    }
+
+    fn parse_mount(&mut self, line: &str) -> Result<(), String> {
+        let mut parts = line.split_whitespace();
+        let (Some(source), Some(target), Some(fs)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(String::from(
+                "mount entries need a source, a target and a type",
+            ));
+        };
+        let mut flags = MountFlags::empty();
+        let mut data = Vec::new();
+        for option in parts.next().unwrap_or_default().split(',') {
+            match mount_option(option) {
+                Some(flag) => flags |= flag,
+                None if option.is_empty() => (),
+                None => {
+                    if !data.is_empty() {
+                        data.push(b',');
+                    }
+                    data.extend_from_slice(option.as_bytes());
+                }
+            }
+        }
+        if parts.next().is_some() {
+            return Err(String::from("trailing characters in mount entry"));
+        }
+        self.mounts.push(MountEntry {
+            source: source.as_bytes().to_vec(),
+            target: target.as_bytes().to_vec(),
+            fs: fs.to_string(),
+            flags,
+            data,
+        });
+        Ok(())
+    }
+
+    fn parse_switch(&mut self, line: &str) -> Result<(), String> {
+        let (name, value) = split_key_value(line)?;
+        let name = name.strip_prefix("MacTux_").unwrap_or(name);
+        self.switches.push((name.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+/// A mount entry in a configuration file.
+#[derive(Debug)]
+pub struct MountEntry {
+    pub source: Vec<u8>,
+    pub target: Vec<u8>,
+    pub fs: String,
+    pub flags: MountFlags,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Section {
+    Env,
+    RLimit,
+    Mount,
+    Switch,
+}
+impl Section {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "env" => Ok(Self::Env),
+            "rlimit" => Ok(Self::RLimit),
+            "mount" => Ok(Self::Mount),
+            "switch" => Ok(Self::Switch),
+            _ => Err(format!("unknown section \"{name}\"")),
+        }
+    }
+}
+
+fn split_key_value(line: &str) -> Result<(&str, &str), String> {
+    line.split_once('=')
+        .map(|(k, v)| (k.trim(), v.trim()))
+        .ok_or_else(|| format!("expected \"key = value\", found \"{line}\""))
+}
+
+fn env_key(var: &[u8]) -> &OsStr {
+    let key = var.split(|x| *x == b'=').next().unwrap_or_default();
+    unsafe { OsStr::from_encoded_bytes_unchecked(key) }
+}
+
+fn rlimit_by_name(name: &str) -> Option<RLimitable> {
+    let name = name.strip_prefix("RLIMIT_").unwrap_or(name);
+    Some(match name.to_ascii_uppercase().as_str() {
+        "CPU" => RLimitable::RLIMIT_CPU,
+        "FSIZE" => RLimitable::RLIMIT_FSIZE,
+        "DATA" => RLimitable::RLIMIT_DATA,
+        "STACK" => RLimitable::RLIMIT_STACK,
+        "CORE" => RLimitable::RLIMIT_CORE,
+        "NOFILE" => RLimitable::RLIMIT_NOFILE,
+        "MEMLOCK" => RLimitable::RLIMIT_MEMLOCK,
+        "AS" => RLimitable::RLIMIT_AS,
+        "NPROC" => RLimitable::RLIMIT_NPROC,
+        "RSS" => RLimitable::RLIMIT_RSS,
+        "LOCKS" => RLimitable::RLIMIT_LOCKS,
+        "SIGPENDING" => RLimitable::RLIMIT_SIGPENDING,
+        "MSGQUEUE" => RLimitable::RLIMIT_MSGQUEUE,
+        "NICE" => RLimitable::RLIMIT_NICE,
+        "RTPRIO" => RLimitable::RLIMIT_RTPRIO,
+        "RTTIME" => RLimitable::RLIMIT_RTTIME,
+        _ => return None,
+    })
+}
+
+fn parse_rlimit_value(value: &str) -> Result<u64, String> {
+    match value {
+        "unlimited" | "infinity" => Ok(RLimit64::RLIM_INFINITY),
+        _ => value
+            .parse()
+            .map_err(|_| format!("invalid resource limit \"{value}\"")),
+    }
+}
+
+fn mount_option(option: &str) -> Option<MountFlags> {
+    Some(match option {
+        "ro" => MountFlags::MS_RDONLY,
+        "nosuid" => MountFlags::MS_NOSUID,
+        "nodev" => MountFlags::MS_NODEV,
+        "noexec" => MountFlags::MS_NOEXEC,
+        "noatime" => MountFlags::MS_NOATIME,
+        "bind" => MountFlags::MS_BIND,
+        "rbind" => MountFlags::MS_BIND | MountFlags::MS_REC,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+# Defaults for every program.
+[env]
+PATH=/usr/bin:/bin
+LANG=C.UTF-8
+
+[rlimit]
+NOFILE = 1024:4096
+RLIMIT_STACK = unlimited
+core = 0
+
+[mount]
+tmpfs /tmp tmpfs nosuid,nodev,size=64m
+/home /mnt/home none rbind
+
+[switch]
+MacTux_Strace = 1
+";
+
+    #[test]
+    fn sections_are_parsed() {
+        let config = Config::parse(CONFIG).unwrap();
+        assert_eq!(config.env, [&b"PATH=/usr/bin:/bin"[..], b"LANG=C.UTF-8"]);
+        assert_eq!(
+            config.rlimits,
+            [
+                (
+                    RLimitable::RLIMIT_NOFILE,
+                    RLimit64 {
+                        rlim_cur: 1024,
+                        rlim_max: 4096
+                    }
+                ),
+                (RLimitable::RLIMIT_STACK, RLimit64::UNLIMITED),
+                (
+                    RLimitable::RLIMIT_CORE,
+                    RLimit64 {
+                        rlim_cur: 0,
+                        rlim_max: 0
+                    }
+                ),
+            ]
+        );
+
+        assert_eq!(config.mounts.len(), 2);
+        let tmp = &config.mounts[0];
+        assert_eq!(
+            (&tmp.source[..], &tmp.target[..]),
+            (&b"tmpfs"[..], &b"/tmp"[..])
+        );
+        assert_eq!(tmp.fs, "tmpfs");
+        assert_eq!(
+            tmp.flags.bits(),
+            (MountFlags::MS_NOSUID | MountFlags::MS_NODEV).bits()
+        );
+        assert_eq!(tmp.data, b"size=64m");
+        let home = &config.mounts[1];
+        assert_eq!(
+            home.flags.bits(),
+            (MountFlags::MS_BIND | MountFlags::MS_REC).bits()
+        );
+        assert!(home.data.is_empty());
+
+        assert_eq!(
+            config.switches,
+            [(String::from("Strace"), String::from("1"))]
+        );
+    }
+
+    #[test]
+    fn errors_tell_their_lines() {
+        let error = |content| Config::parse(content).unwrap_err();
+        assert_eq!(error("X=1"), "line 1: entry outside of any section");
+        assert_eq!(error("\n[files]"), "line 2: unknown section \"files\"");
+        assert_eq!(
+            error("[env]\nPATH"),
+            "line 2: invalid environment variable \"PATH\""
+        );
+        assert_eq!(
+            error("[rlimit]\nFILES = 1"),
+            "line 2: unknown resource \"FILES\""
+        );
+        assert_eq!(
+            error("[rlimit]\nNOFILE = 10:5"),
+            "line 2: soft limit of \"NOFILE\" exceeds its hard limit"
+        );
+        assert_eq!(
+            error("[rlimit]\nNOFILE = many"),
+            "line 2: invalid resource limit \"many\""
+        );
+        assert_eq!(
+            error("[mount]\ntmpfs /tmp"),
+            "line 2: mount entries need a source, a target and a type"
+        );
+        assert_eq!(
+            error("[mount]\ntmpfs /tmp tmpfs ro extra"),
+            "line 2: trailing characters in mount entry"
+        );
+        assert_eq!(
+            error("[switch]\nStrace"),
+            "line 2: expected \"key = value\", found \"Strace\""
+        );
+    }
+
+    #[test]
+    fn command_line_overrides_file() {
+        let mut config = Config::parse(CONFIG).unwrap();
+        let envp = config.merge_env(&[b"LANG=en_US.UTF-8", b"HOME=/root"]);
+        assert_eq!(
+            envp,
+            [
+                &b"PATH=/usr/bin:/bin"[..],
+                b"LANG=en_US.UTF-8",
+                b"HOME=/root"
+            ]
+        );
+
+        config
+            .override_rlimits(&[String::from("NOFILE = 256:512")])
+            .unwrap();
+        let nofile: Vec<_> = config
+            .rlimits
+            .iter()
+            .filter(|(res, _)| *res == RLimitable::RLIMIT_NOFILE)
+            .collect();
+        assert_eq!(
+            nofile,
+            [&(
+                RLimitable::RLIMIT_NOFILE,
+                RLimit64 {
+                    rlim_cur: 256,
+                    rlim_max: 512
+                }
+            )]
+        );
+        assert_eq!(config.rlimits.len(), 3);
+        assert!(config.override_rlimits(&[String::from("NOFILE")]).is_err());
+    }
+}
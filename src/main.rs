@@ -1,3 +1,6 @@
+mod config;
+
+use config::Config;
 use mimalloc::MiMalloc;
 use std::{ffi::OsString, path::PathBuf};
 
@@ -40,11 +43,36 @@ struct Mactux {
     /// Environment variables passed to the program
     #[arg(short, long)]
     env: Vec<OsString>,
+
+    /// Path of a configuration file providing defaults for environment variables, rlimits, mounts and switches
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Resource limits of the program, like `NOFILE=1024:4096`, overriding the ones of the configuration file
+    #[arg(long)]
+    rlimit: Vec<String>,
 }
 
 fn main() {
     let cmdline: Mactux = clap::Parser::parse();
+    let mut config = match &cmdline.config {
+        Some(path) => Config::load(path).unwrap_or_else(|err| {
+            eprintln!(
+                "mactux: failed to load configuration file \"{}\": {err}",
+                path.display()
+            );
+            std::process::exit(1);
+        }),
+        None => Config::default(),
+    };
+    if let Err(err) = config.override_rlimits(&cmdline.rlimit) {
+        eprintln!("mactux: invalid resource limit: {err}");
+        std::process::exit(1);
+    }
 
+    unsafe {
+        config.apply_switches();
+    }
     setup_environment();
     if let Some(path) = &cmdline.server_sock_path {
         rtenv::ipc_client::set_server_sock_path(path.clone());
@@ -60,6 +88,8 @@ fn main() {
         eprintln!("mactux: failed to initialize cwd: {err:?}",);
         std::process::exit(1);
     }
+    rtenv::fs::sync_umask();
+    rtenv::process::sync_rlimits();
     if let Some(table) = &cmdline.init_vfd_table
         && let Err(err) = rtenv::vfd::fill_table(table)
     {
@@ -67,8 +97,17 @@ fn main() {
         std::process::exit(1);
     }
 
+    if let Err(err) = config.apply_mounts() {
+        eprintln!("mactux: failed to perform configured mounts: {err:?}");
+        std::process::exit(1);
+    }
+    if let Err(err) = config.apply_rlimits() {
+        eprintln!("mactux: failed to apply configured rlimits: {err:?}");
+        std::process::exit(1);
+    }
+
     let args = collect_args(&cmdline);
-    let envp = collect_envp(&cmdline);
+    let envp = config.merge_env(&collect_envp(&cmdline));
     let prog =
         loader::Program::load(cmdline.exec.as_encoded_bytes().into()).unwrap_or_else(|err| {
             eprintln!(